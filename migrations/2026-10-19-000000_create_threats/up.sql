@@ -0,0 +1,6 @@
+CREATE TABLE threats (
+    id SERIAL PRIMARY KEY,
+    message TEXT NOT NULL,
+    threat_type TEXT NOT NULL,
+    timestamp TIMESTAMP
+);
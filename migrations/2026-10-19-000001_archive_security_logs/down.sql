@@ -0,0 +1,6 @@
+DROP INDEX threats_sequence;
+
+ALTER TABLE threats
+    DROP COLUMN severity,
+    DROP COLUMN source,
+    DROP COLUMN sequence;
@@ -0,0 +1,9 @@
+-- Columns the archiver fills from the backend canister's security logs.
+-- `sequence` is the log's position in the canister's hash chain, which rows
+-- are upserted by and archiving resumes after.
+ALTER TABLE threats
+    ADD COLUMN severity TEXT,
+    ADD COLUMN source TEXT,
+    ADD COLUMN sequence BIGINT;
+
+CREATE UNIQUE INDEX threats_sequence ON threats (sequence);
@@ -1,6 +1,7 @@
 use candid::{CandidType, Deserialize};
+use cybersec_log_model::LogRecord;
 
-#[derive(CandidType, Deserialize, Clone, Debug)]
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
 pub struct LogEntry {
     pub timestamp: String,
     pub source_ip: String,
@@ -9,6 +10,31 @@ pub struct LogEntry {
     pub details: String,
 }
 
+// Every field is kept, so converting back yields the original entry
+impl From<LogEntry> for LogRecord {
+    fn from(entry: LogEntry) -> Self {
+        LogRecord {
+            original_timestamp: Some(entry.timestamp),
+            source_ip: Some(entry.source_ip),
+            action: Some(entry.action),
+            outcome: Some(entry.status),
+            ..LogRecord::new(entry.details)
+        }
+    }
+}
+
+impl From<LogRecord> for LogEntry {
+    fn from(record: LogRecord) -> Self {
+        LogEntry {
+            timestamp: record.original_timestamp.unwrap_or_default(),
+            source_ip: record.source_ip.unwrap_or_default(),
+            action: record.action.unwrap_or_default(),
+            status: record.outcome.unwrap_or_default(),
+            details: record.message,
+        }
+    }
+}
+
 #[derive(CandidType, Deserialize)]
 pub struct HttpRequest {
     pub method: String,
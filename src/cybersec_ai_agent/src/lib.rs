@@ -1,12 +1,20 @@
 use candid::{CandidType, Deserialize};
+use cybersec_log_model::timestamp::TimestampConfig;
+use cybersec_log_model::LogRecord;
 use ic_cdk::api::management_canister::http_request::{HttpHeader, HttpResponse};
 use ic_cdk_macros::{init, query, update};
+use std::cell::Cell;
 
 // Import your modules
 mod api;
 mod threat_detection;
 mod wallet;
 
+thread_local! {
+    // Zone assumed for timestamps that carry none, in minutes east of UTC
+    static DEFAULT_UTC_OFFSET_MINUTES: Cell<i32> = const { Cell::new(0) };
+}
+
 #[derive(CandidType, Deserialize)]
 struct HttpRequest {
     method: String,
@@ -60,7 +68,7 @@ fn handle_http_request(req: HttpRequest, is_update: bool) -> HttpResponse {
         // Parse the request body as LogEntry array
         match serde_json::from_slice::<Vec<api::LogEntry>>(&req.body) {
             Ok(logs) => {
-                let results = threat_detection::detect_threats(logs);
+                let results = threat_detection::detect_threats(to_records(logs));
                 let response_body = serde_json::to_vec(&results).unwrap_or_default();
 
                 HttpResponse {
@@ -110,8 +118,47 @@ fn handle_http_request(req: HttpRequest, is_update: bool) -> HttpResponse {
 // Direct canister method for threat detection
 #[update]
 fn detect_threats(logs: Vec<api::LogEntry>) -> Vec<String> {
-    threat_detection::detect_threats(logs)
+    threat_detection::detect_threats(to_records(logs))
+}
+
+#[query]
+fn get_default_utc_offset_minutes() -> i32 {
+    DEFAULT_UTC_OFFSET_MINUTES.with(Cell::get)
+}
+
+#[update]
+fn set_default_utc_offset_minutes(minutes: i32) -> Result<(), String> {
+    if !ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        return Err("Only controllers of the canister can call this".to_string());
+    }
+    if minutes.abs() > 14 * 60 {
+        return Err(format!("UTC offset {} minutes is out of range", minutes));
+    }
+    DEFAULT_UTC_OFFSET_MINUTES.with(|offset| offset.set(minutes));
+    Ok(())
+}
+
+// Converts incoming entries to canonical records with their timestamps parsed
+fn to_records(logs: Vec<api::LogEntry>) -> Vec<LogRecord> {
+    let config = TimestampConfig {
+        default_utc_offset_minutes: DEFAULT_UTC_OFFSET_MINUTES.with(Cell::get),
+        reference_time: Some(ic_cdk::api::time()),
+    };
+    logs.into_iter()
+        .map(|entry| {
+            let mut record = LogRecord::from(entry);
+            record.resolve_timestamp(&config);
+            record
+        })
+        .collect()
 }
 
 // Export Candid interface
 ic_cdk::export_candid!();
+
+// The tests under tests/ name the crate as an integration test would
+#[cfg(test)]
+extern crate self as cybersec_ai_agent;
+#[cfg(test)]
+#[path = "../tests/mod.rs"]
+mod tests;
@@ -1,18 +1,20 @@
-use crate::api::LogEntry;
+use cybersec_log_model::LogRecord;
 
-pub fn detect_threats(logs: Vec<LogEntry>) -> Vec<String> {
+pub fn detect_threats(logs: Vec<LogRecord>) -> Vec<String> {
     let mut threats = Vec::new();
 
     for log in logs {
+        let source_ip = log.source_ip.as_deref().unwrap_or_default();
+
         // Simple threat detection logic
-        if log.status == "FAILED" && log.action == "LOGIN" {
-            threats.push(format!("Failed login attempt from IP: {}", log.source_ip));
+        if log.outcome.as_deref() == Some("FAILED") && log.action.as_deref() == Some("LOGIN") {
+            threats.push(format!("Failed login attempt from IP: {}", source_ip));
         }
 
-        if log.details.contains("SQL injection") || log.details.contains("XSS") {
+        if log.message.contains("SQL injection") || log.message.contains("XSS") {
             threats.push(format!(
                 "Potential attack detected: {} from IP: {}",
-                log.details, log.source_ip
+                log.message, source_ip
             ));
         }
     }
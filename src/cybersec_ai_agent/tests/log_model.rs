@@ -0,0 +1,21 @@
+// tests/log_model.rs
+use cybersec_ai_agent::api::LogEntry;
+use cybersec_log_model::LogRecord;
+
+#[test]
+fn test_log_entry_round_trip() {
+    let entry = LogEntry {
+        timestamp: "2024-03-01T10:00:10Z".to_string(),
+        source_ip: "203.0.113.45".to_string(),
+        action: "login".to_string(),
+        status: "failure".to_string(),
+        details: "Failed password for admin".to_string(),
+    };
+    let record = LogRecord::from(entry.clone());
+
+    assert_eq!(record.original_timestamp.as_deref(), Some("2024-03-01T10:00:10Z"));
+    assert_eq!(record.source_ip.as_deref(), Some("203.0.113.45"));
+    assert_eq!(record.outcome.as_deref(), Some("failure"));
+    assert_eq!(record.message, "Failed password for admin");
+    assert_eq!(LogEntry::from(record), entry);
+}
@@ -0,0 +1,4 @@
+// tests/mod.rs
+// Compiled into the library's unit test harness: the canister only links
+// as a wasm cdylib, so these cannot run as integration tests
+mod log_model;
@@ -1,3 +1,4 @@
+use crate::api::LogEntry;
 use candid::{CandidType, Deserialize};
 use ic_cdk::api::management_canister::http_request::{
     http_request, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformArgs,
@@ -9,13 +10,6 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::error::Error;
 
-#[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
-pub struct LogEntry {
-    pub message: String,
-    pub timestamp: u64,
-    pub source: String,
-}
-
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug)]
 pub struct ThreatDetectionResult {
     pub is_threat: bool,
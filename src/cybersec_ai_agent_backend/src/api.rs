@@ -1,4 +1,5 @@
 use candid::{CandidType, Deserialize, Func, Principal};
+use cybersec_log_model::LogRecord;
 use ic_cdk::api::management_canister::http_request::HttpResponse;
 use serde::{Serialize, Serializer};
 use serde_bytes;
@@ -81,4 +82,32 @@ impl From<HttpResponse> for CustomHttpResponse {
             upgrade: None,
         }
     }
-}
\ No newline at end of file
+}
+
+// The backend's legacy log shape
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq)]
+pub struct LogEntry {
+    pub message: String,
+    pub timestamp: u64,
+    pub source: String,
+}
+
+impl From<LogEntry> for LogRecord {
+    fn from(entry: LogEntry) -> Self {
+        LogRecord {
+            timestamp: Some(entry.timestamp),
+            source: Some(entry.source),
+            ..LogRecord::new(entry.message)
+        }
+    }
+}
+
+impl From<LogRecord> for LogEntry {
+    fn from(record: LogRecord) -> Self {
+        LogEntry {
+            message: record.message,
+            timestamp: record.timestamp.unwrap_or_default(),
+            source: record.source.unwrap_or_default(),
+        }
+    }
+}
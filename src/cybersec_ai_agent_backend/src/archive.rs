@@ -0,0 +1,459 @@
+use crate::detection::NANOS_PER_SECOND;
+use crate::security_logs::SecurityLogStore;
+use crate::{require_controller, SecurityLog, ARCHIVE, SECURITY_LOGS};
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::call::call;
+use ic_cdk::api::management_canister::main::{
+    create_canister, install_code, CanisterInstallMode, CanisterSettings, CreateCanisterArgument,
+    InstallCodeArgument,
+};
+use ic_cdk::{query, update};
+use serde_bytes::ByteBuf;
+use std::cell::Cell;
+use std::time::Duration;
+
+/// Most logs `get_security_log_range` covers in one call.
+pub const MAX_LOG_RANGE: u64 = 1_000;
+// Stay well below the 2 MiB message limit when appending to an archive
+const MAX_BATCH_BYTES: usize = 1_500_000;
+// Candid framing of a log on top of its strings
+const LOG_OVERHEAD_BYTES: usize = 96;
+// How long archiving waits after a failed run before trying again
+const RETRY_INTERVAL_NANOS: u64 = 5 * 60 * NANOS_PER_SECOND;
+
+// Neither survives an upgrade; a run interrupted by one is started over by
+// the next security log recorded
+thread_local! {
+    static ARCHIVING: Cell<bool> = const { Cell::new(false) };
+    static SCHEDULED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// When and where the oldest security logs are moved out of this canister.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ArchiveOptions {
+    /// Stored security logs at which archiving starts; 0 turns it off
+    pub trigger_threshold: u64,
+    /// Most logs moved per run, oldest first
+    pub num_logs_to_archive: u64,
+    /// Logs one archive canister holds before the next is created
+    pub max_logs_per_archive: u64,
+    /// Cycles each new archive canister is created with
+    pub cycles_for_archive_creation: u64,
+    /// Controllers of new archive canisters besides this canister
+    pub controllers: Vec<Principal>,
+}
+
+impl Default for ArchiveOptions {
+    fn default() -> Self {
+        ArchiveOptions {
+            trigger_threshold: 1_000_000,
+            num_logs_to_archive: 100_000,
+            max_logs_per_archive: 500_000,
+            cycles_for_archive_creation: 2_000_000_000_000,
+            controllers: Vec::new(),
+        }
+    }
+}
+
+/// An archive canister and the sequence numbers `from..from + length` it
+/// covers. Logs retention evicted before they were archived leave gaps, so
+/// it may store fewer.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ArchiveNode {
+    pub canister_id: Principal,
+    pub from: u64,
+    pub length: u64,
+    pub stored: u64,
+}
+
+candid::define_function!(pub GetLogsCallback : (u64, u64) -> (Vec<SecurityLog>) query);
+
+/// Archived security logs `from..from + length`; call `callback` with
+/// `(from, length)` to fetch them from the archive holding them. A large
+/// range may come back in several replies, each carrying on after the last
+/// log of the one before.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchivedRange {
+    pub from: u64,
+    pub length: u64,
+    pub callback: GetLogsCallback,
+}
+
+/// Security logs in a range of sequence numbers: those this canister still
+/// stores, and pointers to the archives holding the older ones.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SecurityLogRange {
+    /// Logs ever recorded
+    pub chain_length: u64,
+    pub logs: Vec<SecurityLog>,
+    pub archived: Vec<ArchivedRange>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchiveStatus {
+    pub options: ArchiveOptions,
+    pub archives: Vec<ArchiveNode>,
+    /// Sequence numbers below this have been moved to archives
+    pub archived_through: u64,
+    pub has_wasm: bool,
+    pub running: bool,
+    pub last_error: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct ArchiveFailure {
+    time: u64,
+    error: String,
+}
+
+// Passed to the archive canister's init
+#[derive(CandidType)]
+struct ArchiveInit {
+    main_canister: Principal,
+    capacity: u64,
+}
+
+/// The archive canisters, oldest first, which together hold a contiguous
+/// prefix of the security log chain.
+#[derive(CandidType, Deserialize, Default)]
+pub struct ArchiveIndex {
+    options: ArchiveOptions,
+    nodes: Vec<ArchiveNode>,
+    /// Code installed in new archives, uploaded by a controller
+    wasm: Option<ByteBuf>,
+    /// Created but not yet installed; the next run installs it instead of
+    /// creating another
+    pending: Option<Principal>,
+    last_failure: Option<ArchiveFailure>,
+    /// The first this many archives came with an imported snapshot and
+    /// belong to the canister it was taken from: they are read, never
+    /// appended to
+    imported_nodes: u64,
+}
+
+impl ArchiveIndex {
+    pub fn new(options: ArchiveOptions) -> Self {
+        ArchiveIndex {
+            options,
+            ..Default::default()
+        }
+    }
+
+    pub fn options(&self) -> &ArchiveOptions {
+        &self.options
+    }
+
+    pub fn set_options(&mut self, options: ArchiveOptions) {
+        self.options = options;
+    }
+
+    pub fn nodes(&self) -> &[ArchiveNode] {
+        &self.nodes
+    }
+
+    pub fn set_wasm(&mut self, wasm: Vec<u8>) {
+        self.wasm = Some(ByteBuf::from(wasm));
+    }
+
+    /// Sequence numbers below this are held by archives.
+    pub fn archived_through(&self) -> u64 {
+        self.nodes.last().map_or(0, |node| node.from + node.length)
+    }
+
+    /// The last archive, while it has room for more logs and is this canister's own.
+    pub fn writable(&self) -> Option<&ArchiveNode> {
+        if self.nodes.len() as u64 <= self.imported_nodes {
+            return None;
+        }
+        self.nodes.last().filter(|node| node.stored < self.options.max_logs_per_archive)
+    }
+
+    /// Keeps the archives of an imported snapshot for reading only, and
+    /// forgets the archive the source canister was still creating.
+    pub fn mark_imported(&mut self) {
+        self.imported_nodes = self.nodes.len() as u64;
+        self.pending = None;
+        self.last_failure = None;
+    }
+
+    /// Starts a new archive after the last one.
+    pub fn add_node(&mut self, canister_id: Principal) {
+        let from = self.archived_through();
+        self.nodes.push(ArchiveNode {
+            canister_id,
+            from,
+            length: 0,
+            stored: 0,
+        });
+    }
+
+    /// Records that the last archive now covers everything through sequence
+    /// number `last` and stores `stored` logs.
+    pub fn record_append(&mut self, last: u64, stored: u64) {
+        if let Some(node) = self.nodes.last_mut() {
+            node.length = node.length.max(last + 1 - node.from);
+            node.stored = stored;
+        }
+    }
+
+    /// How many of the oldest of `stored` logs the next run moves: none below
+    /// the threshold, and no more than fit in the archive they go to.
+    pub fn block_size(&self, stored: u64) -> u64 {
+        let options = &self.options;
+        if options.trigger_threshold == 0 || stored < options.trigger_threshold {
+            return 0;
+        }
+        let room = self
+            .writable()
+            .map_or(options.max_logs_per_archive, |node| options.max_logs_per_archive - node.stored);
+        options.num_logs_to_archive.min(room).min(stored)
+    }
+
+    /// The archived parts of `from..from + length`, one per archive, each to
+    /// be fetched with `get_logs` on it.
+    pub fn locate(&self, from: u64, length: u64) -> Vec<ArchivedRange> {
+        let end = from.saturating_add(length);
+        self.nodes
+            .iter()
+            .filter_map(|node| {
+                let start = from.max(node.from);
+                let stop = end.min(node.from + node.length);
+                (start < stop).then(|| ArchivedRange {
+                    from: start,
+                    length: stop - start,
+                    callback: GetLogsCallback::new(node.canister_id, "get_logs".to_string()),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Logs `from..from + length`, at most `MAX_LOG_RANGE` of them, split
+/// between `store` and the archives `index` lists.
+pub fn log_range(store: &SecurityLogStore, index: &ArchiveIndex, from: u64, length: u64) -> SecurityLogRange {
+    let end = from.saturating_add(length.min(MAX_LOG_RANGE));
+    SecurityLogRange {
+        chain_length: store.head().length,
+        logs: store.range(from.max(index.archived_through())..end).cloned().collect(),
+        archived: index.locate(from, end - from),
+    }
+}
+
+/// Splits `logs` into runs small enough to append to an archive in one call.
+pub fn batches(logs: &[SecurityLog]) -> Vec<&[SecurityLog]> {
+    let mut batches = Vec::new();
+    let (mut start, mut bytes) = (0, 0);
+    for (i, log) in logs.iter().enumerate() {
+        let size = LOG_OVERHEAD_BYTES
+            + log.event_type.len()
+            + log.details.len()
+            + log.severity.len()
+            + log.entity.as_ref().map_or(0, String::len)
+            + log.source.as_ref().map_or(0, String::len)
+            + log.hash.as_ref().map_or(0, String::len);
+        if i > start && bytes + size > MAX_BATCH_BYTES {
+            batches.push(&logs[start..i]);
+            (start, bytes) = (i, 0);
+        }
+        bytes += size;
+    }
+    if start < logs.len() {
+        batches.push(&logs[start..]);
+    }
+    batches
+}
+
+/// Whether an archiving run is moving logs.
+pub(crate) fn is_running() -> bool {
+    ARCHIVING.with(Cell::get)
+}
+
+/// Starts an archiving run on a timer when enough logs have piled up, an
+/// archive wasm has been uploaded, no run is underway or recently failed and
+/// no state export is being encoded.
+pub(crate) fn schedule() {
+    if crate::snapshot::export_in_progress() {
+        return;
+    }
+    let stored = SECURITY_LOGS.with(|logs| logs.borrow().len() as u64);
+    let now = ic_cdk::api::time();
+    let due = ARCHIVE.with(|index| {
+        let index = index.borrow();
+        index.wasm.is_some()
+            && index.block_size(stored) > 0
+            && index
+                .last_failure
+                .as_ref()
+                .is_none_or(|failure| now.saturating_sub(failure.time) >= RETRY_INTERVAL_NANOS)
+    });
+    if due && !ARCHIVING.with(Cell::get) && !SCHEDULED.with(|scheduled| scheduled.replace(true)) {
+        ic_cdk_timers::set_timer(Duration::ZERO, || {
+            SCHEDULED.with(|scheduled| scheduled.set(false));
+            ic_cdk::spawn(async {
+                // Keep going while the threshold is still reached
+                if matches!(archive_block().await, Ok(moved) if moved > 0) {
+                    schedule();
+                }
+            });
+        });
+    }
+}
+
+// Clears ARCHIVING when dropped, including when the run traps after an
+// await and its future is dropped during cleanup
+struct ArchivingGuard;
+
+impl ArchivingGuard {
+    fn acquire() -> Option<Self> {
+        (!ARCHIVING.with(|archiving| archiving.replace(true))).then_some(ArchivingGuard)
+    }
+}
+
+impl Drop for ArchivingGuard {
+    fn drop(&mut self) {
+        ARCHIVING.with(|archiving| archiving.set(false));
+    }
+}
+
+// Moves one block of the oldest logs, failing if a run is already underway
+async fn archive_block() -> Result<u64, String> {
+    let Some(guard) = ArchivingGuard::acquire() else {
+        return Err("Archiving is already running".to_string());
+    };
+    let result = move_block().await;
+    drop(guard);
+    ARCHIVE.with(|index| {
+        index.borrow_mut().last_failure = result.as_ref().err().map(|error| ArchiveFailure {
+            time: ic_cdk::api::time(),
+            error: error.clone(),
+        })
+    });
+    result
+}
+
+// Each batch is removed from this canister as soon as the archive has taken
+// it, so a run cut short leaves nothing lost or duplicated
+async fn move_block() -> Result<u64, String> {
+    let stored = SECURITY_LOGS.with(|logs| logs.borrow().len() as u64);
+    let (count, writable) = ARCHIVE.with(|index| {
+        let index = index.borrow();
+        (index.block_size(stored), index.writable().map(|node| node.canister_id))
+    });
+    if count == 0 {
+        return Ok(0);
+    }
+    let canister_id = match writable {
+        Some(canister_id) => canister_id,
+        None => create_archive().await?,
+    };
+    let logs: Vec<SecurityLog> = SECURITY_LOGS.with(|logs| logs.borrow().iter().take(count as usize).cloned().collect());
+    let mut moved = 0;
+    for batch in batches(&logs) {
+        let (result,): (Result<u64, String>,) = call(canister_id, "append_logs", (batch,))
+            .await
+            .map_err(|(code, message)| format!("Appending to archive {} failed: {:?} {}", canister_id, code, message))?;
+        let stored = result?;
+        let last = batch.last().and_then(|log| log.sequence).unwrap_or_default();
+        moved += SECURITY_LOGS.with(|logs| logs.borrow_mut().remove_through(last));
+        ARCHIVE.with(|index| index.borrow_mut().record_append(last, stored));
+        crate::certify();
+    }
+    Ok(moved)
+}
+
+async fn create_archive() -> Result<Principal, String> {
+    let (options, wasm, pending) = ARCHIVE.with(|index| {
+        let index = index.borrow();
+        (index.options.clone(), index.wasm.clone(), index.pending)
+    });
+    let wasm = wasm.ok_or("No archive wasm has been uploaded")?;
+    let canister_id = match pending {
+        Some(canister_id) => canister_id,
+        None => {
+            let settings = CanisterSettings {
+                controllers: Some([vec![ic_cdk::id()], options.controllers.clone()].concat()),
+                ..Default::default()
+            };
+            let argument = CreateCanisterArgument {
+                settings: Some(settings),
+            };
+            let (record,) = create_canister(argument, options.cycles_for_archive_creation as u128)
+                .await
+                .map_err(|(code, message)| format!("Creating an archive canister failed: {:?} {}", code, message))?;
+            ARCHIVE.with(|index| index.borrow_mut().pending = Some(record.canister_id));
+            record.canister_id
+        }
+    };
+    let arg = candid::encode_one(ArchiveInit {
+        main_canister: ic_cdk::id(),
+        capacity: options.max_logs_per_archive,
+    })
+    .map_err(|e| format!("Failed to encode the archive init argument: {}", e))?;
+    install_code(InstallCodeArgument {
+        mode: CanisterInstallMode::Install,
+        canister_id,
+        wasm_module: wasm.into_vec(),
+        arg,
+    })
+    .await
+    .map_err(|(code, message)| format!("Installing archive {} failed: {:?} {}", canister_id, code, message))?;
+    ARCHIVE.with(|index| {
+        let mut index = index.borrow_mut();
+        index.pending = None;
+        index.add_node(canister_id);
+    });
+    Ok(canister_id)
+}
+
+#[query]
+fn get_security_log_range(from: u64, length: u64) -> SecurityLogRange {
+    SECURITY_LOGS.with(|logs| ARCHIVE.with(|index| log_range(&logs.borrow(), &index.borrow(), from, length)))
+}
+
+#[query]
+fn get_archive_status() -> ArchiveStatus {
+    ARCHIVE.with(|index| {
+        let index = index.borrow();
+        ArchiveStatus {
+            options: index.options.clone(),
+            archives: index.nodes.clone(),
+            archived_through: index.archived_through(),
+            has_wasm: index.wasm.is_some(),
+            running: ARCHIVING.with(Cell::get),
+            last_error: index.last_failure.as_ref().map(|failure| failure.error.clone()),
+        }
+    })
+}
+
+#[update]
+fn set_archive_options(options: ArchiveOptions) -> Result<(), String> {
+    require_controller()?;
+    if options.num_logs_to_archive == 0 || options.max_logs_per_archive == 0 {
+        return Err("num_logs_to_archive and max_logs_per_archive must be positive".to_string());
+    }
+    ARCHIVE.with(|index| index.borrow_mut().set_options(options));
+    schedule();
+    Ok(())
+}
+
+/// Sets the code new archive canisters are installed with; archives already
+/// created keep theirs.
+#[update]
+fn set_archive_wasm(wasm: serde_bytes::ByteBuf) -> Result<(), String> {
+    require_controller()?;
+    ARCHIVE.with(|index| {
+        let mut index = index.borrow_mut();
+        index.set_wasm(wasm.into_vec());
+        index.last_failure = None;
+    });
+    schedule();
+    Ok(())
+}
+
+/// Moves one block of the oldest logs now, if the threshold is reached, and
+/// returns how many were moved.
+#[update]
+async fn archive_security_logs() -> Result<u64, String> {
+    require_controller()?;
+    archive_block().await
+}
@@ -0,0 +1,30 @@
+use ic_certification::hash_tree::{labeled_hash, leaf_hash, Hash};
+use ic_certification::{fork, labeled, leaf, pruned, HashTree};
+use serde_bytes::ByteBuf;
+
+pub use cybersec_log_model::certified::{
+    root_hash, security_log_leaf, threat_summary_leaf, CertifiedSecurityLogs, CertifiedThreatSummary,
+    SECURITY_LOGS_LABEL, THREAT_SUMMARY_LABEL,
+};
+
+/// The full witness for a range of the logs tree, as built by `SecurityLogStore::witness`.
+pub fn security_logs_witness(logs_range: HashTree, summary_leaf: &Hash) -> HashTree {
+    fork(
+        labeled(SECURITY_LOGS_LABEL, logs_range),
+        pruned(labeled_hash(THREAT_SUMMARY_LABEL, &leaf_hash(summary_leaf))),
+    )
+}
+
+pub fn threat_summary_witness(logs_root: &Hash, summary_leaf: &Hash) -> HashTree {
+    fork(
+        pruned(labeled_hash(SECURITY_LOGS_LABEL, logs_root)),
+        labeled(THREAT_SUMMARY_LABEL, leaf(summary_leaf.to_vec())),
+    )
+}
+
+pub fn encode_witness(witness: &HashTree) -> Result<ByteBuf, String> {
+    serde_cbor::to_vec(witness)
+        .map(ByteBuf::from)
+        .map_err(|e| format!("Failed to encode witness: {}", e))
+}
+
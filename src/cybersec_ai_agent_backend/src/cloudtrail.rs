@@ -0,0 +1,294 @@
+use crate::decode::{flatten_json, percent_decode};
+use crate::retention::severity_rank;
+use crate::ThreatOutput;
+use cybersec_log_model::timestamp::parse_rfc3339;
+use cybersec_log_model::LogRecord;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+pub const SOURCE: &str = "cloudtrail";
+
+const ADMIN_POLICY_ARN: &str = "arn:aws:iam::aws:policy/AdministratorAccess";
+const POLICY_ATTACHMENTS: [&str; 3] = ["AttachUserPolicy", "AttachRolePolicy", "AttachGroupPolicy"];
+const POLICY_DOCUMENTS: [&str; 5] = [
+    "PutUserPolicy",
+    "PutRolePolicy",
+    "PutGroupPolicy",
+    "CreatePolicy",
+    "CreatePolicyVersion",
+];
+// Open to the world on these is usually deliberate (a public web server)
+const WEB_PORTS: [&str; 2] = ["port 80", "port 443"];
+
+/// Parses CloudTrail events: a single event object, the `{"Records": [...]}`
+/// envelope CloudTrail writes to S3, a JSON array, or one event per line.
+pub fn parse_cloudtrail(text: &str) -> Result<Vec<LogRecord>, String> {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let events: Vec<Value> = match serde_json::from_str::<Value>(trimmed) {
+        Ok(Value::Object(mut object)) => match object.remove("Records") {
+            Some(Value::Array(records)) => records,
+            Some(_) => return Err("CloudTrail Records must be an array".to_string()),
+            None => vec![Value::Object(object)],
+        },
+        Ok(Value::Array(events)) => events,
+        Ok(_) => return Err("CloudTrail input must be an event object or a Records envelope".to_string()),
+        Err(_) => trimmed
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid CloudTrail JSON on line {}: {}", index + 1, e))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    events
+        .iter()
+        .enumerate()
+        .map(|(index, event)| {
+            if event["eventName"].is_string() {
+                Ok(to_record(event))
+            } else {
+                Err(format!("CloudTrail event {} has no eventName", index + 1))
+            }
+        })
+        .collect()
+}
+
+fn to_record(event: &Value) -> LogRecord {
+    let mut attributes = BTreeMap::new();
+    flatten_json("", event, &mut attributes);
+
+    let text = |path: &str| attributes.get(path).cloned();
+    let identity = acting_identity(&attributes);
+    let event_name = text("eventName").unwrap_or_default();
+    let message = format!(
+        "CloudTrail {} {} by {} from {}",
+        text("eventSource").unwrap_or_default(),
+        event_name,
+        identity,
+        text("sourceIPAddress").unwrap_or_else(|| "unknown".to_string())
+    );
+
+    LogRecord {
+        timestamp: text("eventTime").as_deref().and_then(parse_rfc3339),
+        original_timestamp: text("eventTime"),
+        message,
+        source: Some(SOURCE.to_string()),
+        source_ip: text("sourceIPAddress"),
+        destination_ip: None,
+        user: Some(identity),
+        host: text("recipientAccountId"),
+        action: Some(event_name),
+        outcome: Some(text("errorCode").unwrap_or_else(|| "success".to_string())),
+        severity: None,
+        attributes,
+        entities: None,
+    }
+}
+
+// The ARN where there is one; root and federated callers fall back to what CloudTrail gives
+fn acting_identity(attributes: &BTreeMap<String, String>) -> String {
+    let field = |path: &str| attributes.get(path).cloned();
+    field("userIdentity.arn")
+        .or_else(|| field("userIdentity.userName"))
+        .or_else(|| field("userIdentity.principalId"))
+        .or_else(|| field("userIdentity.invokedBy"))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Turns a CloudTrail record into a detection when it matches any of the
+/// built-in rules. When several match, the most severe leads and the others
+/// are added to its details. Other records yield `None`.
+pub fn detection(record: &LogRecord) -> Option<ThreatOutput> {
+    let mut findings = detections(record).into_iter();
+    let mut leading = findings.next()?;
+    for other in findings {
+        leading.details = format!("{}; {}", leading.details, other.details);
+    }
+    Some(leading)
+}
+
+/// Every rule a CloudTrail record matches, most severe first and, within a
+/// severity, most confident first.
+pub fn detections(record: &LogRecord) -> Vec<ThreatOutput> {
+    if record.source.as_deref() != Some(SOURCE) {
+        return Vec::new();
+    }
+
+    let identity = record.user.clone().unwrap_or_else(|| "unknown".to_string());
+    let event_name = record.action.as_deref().unwrap_or_default();
+    let failed = record.attribute("errorCode").map(|code| format!(" (failed: {})", code)).unwrap_or_default();
+    let finding = |category: &str, severity: &str, confidence: f64, details: String| {
+        ThreatOutput::detection(category, severity, confidence, &identity, format!("{}{}", details, failed))
+    };
+
+    let mut findings = Vec::new();
+    if matches!(event_name, "StopLogging" | "DeleteTrail") {
+        findings.push(finding(
+            "cloudtrail_logging_disabled",
+            "CRITICAL",
+            0.95,
+            format!(
+                "{} called {} on trail {}",
+                identity,
+                event_name,
+                record.attribute("requestParameters.name").unwrap_or("unknown")
+            ),
+        ));
+    }
+    if let Some(grant) = admin_grant(record) {
+        findings.push(finding(
+            "cloudtrail_admin_policy",
+            "HIGH",
+            0.9,
+            format!("{} called {} granting {}", identity, event_name, grant),
+        ));
+    }
+    if let Some(target) = access_key_for_other_user(record) {
+        findings.push(finding(
+            "cloudtrail_access_key_for_other_user",
+            "HIGH",
+            0.8,
+            format!("{} created an access key for IAM user {}", identity, target),
+        ));
+    }
+    if let Some((ports, web_only)) = world_open_ingress(record) {
+        findings.push(finding(
+            "cloudtrail_security_group_open",
+            if web_only { "MEDIUM" } else { "HIGH" },
+            if web_only { 0.6 } else { 0.85 },
+            format!(
+                "{} opened security group {} to the internet on {}",
+                identity,
+                record.attribute("requestParameters.groupId").unwrap_or("unknown"),
+                ports
+            ),
+        ));
+    }
+    if record.attribute("userIdentity.type") == Some("Root") && record.attribute("userIdentity.invokedBy").is_none() {
+        findings.push(finding(
+            "cloudtrail_root_usage",
+            "HIGH",
+            0.85,
+            format!(
+                "Root account called {} from {}",
+                event_name,
+                record.source_ip.as_deref().unwrap_or("unknown")
+            ),
+        ));
+    }
+    if event_name == "ConsoleLogin"
+        && record.attribute("responseElements.ConsoleLogin") == Some("Success")
+        && record.attribute("additionalEventData.MFAUsed") == Some("No")
+    {
+        findings.push(finding(
+            "cloudtrail_console_login_without_mfa",
+            "MEDIUM",
+            0.7,
+            format!(
+                "{} signed in to the console without MFA from {}",
+                identity,
+                record.source_ip.as_deref().unwrap_or("unknown")
+            ),
+        ));
+    }
+    let rank = |finding: &ThreatOutput| severity_rank(Some(&finding.severity));
+    findings.sort_by(|a, b| rank(b).cmp(&rank(a)).then(b.confidence.total_cmp(&a.confidence)));
+    findings
+}
+
+// What an attachment or inline policy grants, when it amounts to `*` on `*`
+fn admin_grant(record: &LogRecord) -> Option<String> {
+    let event_name = record.action.as_deref()?;
+    if POLICY_ATTACHMENTS.contains(&event_name) {
+        let policy = record.attribute("requestParameters.policyArn")?;
+        return (policy == ADMIN_POLICY_ARN).then(|| policy.to_string());
+    }
+    if POLICY_DOCUMENTS.contains(&event_name) {
+        let document = record.attribute("requestParameters.policyDocument")?;
+        return grants_everything(document).then(|| "Action * on Resource *".to_string());
+    }
+    None
+}
+
+/// Whether an IAM policy document has an `Allow` statement for action `*` on resource `*`.
+/// CloudTrail records the document as a string, sometimes still URL-encoded.
+pub fn grants_everything(document: &str) -> bool {
+    let decoded = if document.trim_start().starts_with('%') {
+        percent_decode(document)
+    } else {
+        document.to_string()
+    };
+    let Ok(policy) = serde_json::from_str::<Value>(&decoded) else {
+        return false;
+    };
+
+    // "*:*" is the service-qualified spelling of "*"
+    let is_wildcard = |value: &Value| match value {
+        Value::String(item) => item == "*" || item == "*:*",
+        Value::Array(items) => items.iter().any(|item| item == "*" || item == "*:*"),
+        _ => false,
+    };
+    let statements = match &policy["Statement"] {
+        Value::Array(statements) => statements.iter().collect(),
+        statement @ Value::Object(_) => vec![statement],
+        _ => Vec::new(),
+    };
+    statements.iter().any(|statement| {
+        statement["Effect"] == "Allow" && is_wildcard(&statement["Action"]) && is_wildcard(&statement["Resource"])
+    })
+}
+
+fn access_key_for_other_user(record: &LogRecord) -> Option<String> {
+    if record.action.as_deref() != Some("CreateAccessKey") {
+        return None;
+    }
+    // Without a userName the key is for the caller itself
+    let target = record.attribute("requestParameters.userName")?;
+    let caller = record.attribute("userIdentity.userName");
+    let is_own = record.attribute("userIdentity.type") == Some("IAMUser") && caller == Some(target);
+    (!is_own).then(|| target.to_string())
+}
+
+// The ports opened to 0.0.0.0/0 or ::/0, and whether they are all web ports
+fn world_open_ingress(record: &LogRecord) -> Option<(String, bool)> {
+    if record.action.as_deref() != Some("AuthorizeSecurityGroupIngress") {
+        return None;
+    }
+
+    // Keys look like requestParameters.ipPermissions.items.0.ipRanges.items.0.cidrIp
+    let prefix = "requestParameters.ipPermissions.items.";
+    let mut ports = Vec::new();
+    for (key, value) in record.attributes.range(prefix.to_string()..) {
+        let Some(rest) = key.strip_prefix(prefix) else {
+            break;
+        };
+        let is_world = (rest.ends_with(".cidrIp") && value == "0.0.0.0/0")
+            || (rest.ends_with(".cidrIpv6") && value == "::/0");
+        if !is_world {
+            continue;
+        }
+        let index = rest.split('.').next().unwrap_or_default();
+        let permission = |name: &str| record.attribute(&format!("{}{}.{}", prefix, index, name));
+        let range = match (permission("fromPort"), permission("toPort")) {
+            (Some(from), Some(to)) if from == to => format!("port {}", from),
+            (Some(from), Some(to)) => format!("ports {}-{}", from, to),
+            _ => "all ports".to_string(),
+        };
+        if !ports.contains(&range) {
+            ports.push(range);
+        }
+    }
+
+    if ports.is_empty() {
+        return None;
+    }
+    let web_only = ports.iter().all(|port| WEB_PORTS.contains(&port.as_str()));
+    Some((ports.join(", "), web_only))
+}
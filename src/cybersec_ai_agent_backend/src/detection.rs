@@ -0,0 +1,31 @@
+use crate::ThreatOutput;
+use std::collections::HashMap;
+
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+impl ThreatOutput {
+    /// A threat about `entity`, as the built-in detectors report one.
+    pub fn detection(category: &str, severity: &str, confidence: f64, entity: &str, details: String) -> Self {
+        ThreatOutput {
+            is_threat: true,
+            details,
+            category: category.to_string(),
+            confidence,
+            severity: severity.to_string(),
+            entity: Some(entity.to_string()),
+        }
+    }
+}
+
+/// Whether finding `key` has not been reported within `suppression_secs`
+/// before `time`; if so, marks it reported at `time`.
+pub fn first_report(reported: &mut HashMap<String, u64>, key: String, time: u64, suppression_secs: u64) -> bool {
+    let suppression = suppression_secs.saturating_mul(NANOS_PER_SECOND);
+    match reported.get(&key) {
+        Some(last) if time.saturating_sub(*last) < suppression => false,
+        _ => {
+            reported.insert(key, time);
+            true
+        }
+    }
+}
@@ -0,0 +1,488 @@
+use crate::detection::{first_report, NANOS_PER_SECOND};
+use crate::event_time::{EventTimeConfig, EventTimeStats, SlidingWindowCounter};
+use crate::ThreatOutput;
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::LogRecord;
+use std::collections::{HashMap, VecDeque};
+use std::sync::OnceLock;
+
+// Labels of popular, benign domains the bigram model is trained on
+const BENIGN_DOMAINS: &str = include_str!("dns_benign_domains.txt");
+// Letters, digits, hyphen and the start/end markers
+const ALPHABET: &str = "abcdefghijklmnopqrstuvwxyz0123456789-^$";
+// Second-level labels that act as part of the suffix under country TLDs (example.co.uk)
+const SECOND_LEVEL_SUFFIXES: [&str; 8] = ["co", "com", "net", "org", "gov", "edu", "ac", "ne"];
+
+/// A score between 0 and 1 with the reasons behind it.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct DnsScore {
+    pub score: f64,
+    pub explanation: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct DnsDetectorConfig {
+    /// DGA score at or above which a queried domain is reported
+    pub dga_threshold: f64,
+    /// Window the tunneling and NXDOMAIN detectors count queries in
+    pub window_secs: u64,
+    /// Labels at least this long are typical of data encoded into queries
+    pub long_label_length: u64,
+    /// Distinct subdomains of one parent domain within the window that indicate tunneling
+    pub unique_subdomain_threshold: u64,
+    /// Queries to one parent domain within the window before its TXT share is judged
+    pub txt_min_queries: u64,
+    /// Share of TXT (and NULL) queries to one parent domain that indicates tunneling
+    pub txt_ratio_threshold: f64,
+    /// NXDOMAIN answers to one client within the window that make a burst
+    pub nxdomain_burst_threshold: u64,
+    /// The same finding is reported again only after this many seconds
+    pub suppression_secs: u64,
+}
+
+impl Default for DnsDetectorConfig {
+    fn default() -> Self {
+        DnsDetectorConfig {
+            dga_threshold: 0.65,
+            window_secs: 60,
+            long_label_length: 50,
+            unique_subdomain_threshold: 50,
+            txt_min_queries: 20,
+            txt_ratio_threshold: 0.5,
+            nxdomain_burst_threshold: 20,
+            suppression_secs: 3_600,
+        }
+    }
+}
+
+/// A DNS query or answer pulled out of a Zeek or Suricata record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DnsQuery {
+    pub client: String,
+    pub domain: String,
+    pub qtype: Option<String>,
+    pub rcode: Option<String>,
+    /// Whether this is the answer half of a query logged separately (Suricata)
+    pub is_answer: bool,
+}
+
+/// Pulls the DNS query out of a Zeek dns.log or Suricata dns record; other records yield `None`.
+pub fn dns_query(record: &LogRecord) -> Option<DnsQuery> {
+    let (domain, qtype, rcode, is_answer) = match record.source.as_deref() {
+        Some(crate::zeek::SOURCE) if record.action.as_deref() == Some("dns") => (
+            record.attribute("query")?,
+            record.attribute("qtype_name"),
+            record.attribute("rcode_name"),
+            false,
+        ),
+        Some(crate::suricata::SOURCE) if record.action.as_deref() == Some("dns") => (
+            record.attribute("dns.rrname")?,
+            record.attribute("dns.rrtype"),
+            record.attribute("dns.rcode"),
+            record.attribute("dns.type") == Some("answer"),
+        ),
+        _ => return None,
+    };
+    // Suricata logs answers in the server-to-client direction
+    let client = if is_answer { &record.destination_ip } else { &record.source_ip };
+
+    Some(DnsQuery {
+        client: client.clone().unwrap_or_else(|| "unknown".to_string()),
+        domain: domain.trim_end_matches('.').to_ascii_lowercase(),
+        qtype: qtype.map(str::to_ascii_uppercase),
+        rcode: rcode.map(str::to_ascii_uppercase),
+        is_answer,
+    })
+}
+
+/// Splits `domain` into its subdomain part and the registered parent domain,
+/// e.g. `a.b.example.co.uk` into `a.b` and `example.co.uk`.
+pub fn split_domain(domain: &str) -> (&str, &str) {
+    let labels: Vec<&str> = domain.split('.').collect();
+    let mut parent_labels = 2;
+    if labels.len() >= 3 {
+        let tld = labels[labels.len() - 1];
+        let second = labels[labels.len() - 2];
+        if tld.len() == 2 && SECOND_LEVEL_SUFFIXES.contains(&second) {
+            parent_labels = 3;
+        }
+    }
+    if labels.len() <= parent_labels {
+        return ("", domain);
+    }
+    let subdomain_len: usize = labels[..labels.len() - parent_labels].iter().map(|l| l.len() + 1).sum();
+    (&domain[..subdomain_len - 1], &domain[subdomain_len..])
+}
+
+struct BigramModel {
+    log_probabilities: HashMap<(char, char), f64>,
+    unseen: f64,
+    benign_average: f64,
+}
+
+// Character bigram model with add-one smoothing over the labels in BENIGN_DOMAINS
+fn benign_model() -> &'static BigramModel {
+    static MODEL: OnceLock<BigramModel> = OnceLock::new();
+    MODEL.get_or_init(|| {
+        let mut counts: HashMap<(char, char), f64> = HashMap::new();
+        let mut totals: HashMap<char, f64> = HashMap::new();
+        let labels: Vec<&str> = BENIGN_DOMAINS.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        for label in &labels {
+            for pair in bigrams(label) {
+                *counts.entry(pair).or_default() += 1.0;
+                *totals.entry(pair.0).or_default() += 1.0;
+            }
+        }
+
+        let vocabulary = ALPHABET.chars().count() as f64;
+        let mut log_probabilities = HashMap::new();
+        for first in ALPHABET.chars() {
+            let total = totals.get(&first).copied().unwrap_or(0.0) + vocabulary;
+            for second in ALPHABET.chars() {
+                let count = counts.get(&(first, second)).copied().unwrap_or(0.0) + 1.0;
+                log_probabilities.insert((first, second), (count / total).log10());
+            }
+        }
+
+        let mut model = BigramModel {
+            log_probabilities,
+            unseen: (1.0 / vocabulary).log10(),
+            benign_average: 0.0,
+        };
+        model.benign_average = labels.iter().map(|label| model.average_log_probability(label)).sum::<f64>()
+            / labels.len().max(1) as f64;
+        model
+    })
+}
+
+impl BigramModel {
+    fn average_log_probability(&self, label: &str) -> f64 {
+        let pairs: Vec<(char, char)> = bigrams(label).collect();
+        let total: f64 = pairs
+            .iter()
+            .map(|pair| self.log_probabilities.get(pair).copied().unwrap_or(self.unseen))
+            .sum();
+        total / pairs.len().max(1) as f64
+    }
+}
+
+fn bigrams(label: &str) -> impl Iterator<Item = (char, char)> + '_ {
+    let chars: Vec<char> = std::iter::once('^')
+        .chain(label.chars().map(|c| c.to_ascii_lowercase()))
+        .chain(std::iter::once('$'))
+        .collect();
+    (0..chars.len() - 1).map(move |i| (chars[i], chars[i + 1]))
+}
+
+fn entropy(label: &str) -> f64 {
+    let mut counts: HashMap<char, f64> = HashMap::new();
+    for c in label.chars() {
+        *counts.entry(c).or_default() += 1.0;
+    }
+    let length = label.chars().count() as f64;
+    counts
+        .values()
+        .map(|count| {
+            let p = count / length;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+fn longest_consonant_run(label: &str) -> usize {
+    let mut longest = 0;
+    let mut run = 0;
+    for c in label.chars() {
+        if c.is_ascii_alphabetic() && !"aeiouy".contains(c.to_ascii_lowercase()) {
+            run += 1;
+            longest = longest.max(run);
+        } else {
+            run = 0;
+        }
+    }
+    longest
+}
+
+/// Scores how likely `domain` is to come from a domain generation algorithm.
+///
+/// Only the registered label is judged (`xk3v9q` in `www.xk3v9q.com`), on
+/// four signals: character entropy, bigram likelihood against the bundled
+/// benign-domain model, the longest run of consonants and the share of digits.
+/// Labels shorter than eight characters are discounted, as too little to go on.
+pub fn dga_score(domain: &str) -> DnsScore {
+    let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+    let (_, parent) = split_domain(&domain);
+    let label = parent.split('.').next().unwrap_or_default();
+    if label.is_empty() {
+        return DnsScore {
+            score: 0.0,
+            explanation: "No registered label to score".to_string(),
+        };
+    }
+
+    let model = benign_model();
+    let length = label.chars().count();
+    let bits = entropy(label);
+    let likelihood = model.average_log_probability(label);
+    let consonants = longest_consonant_run(label);
+    let digits = label.chars().filter(char::is_ascii_digit).count() as f64 / length as f64;
+
+    let entropy_signal = ((bits - 2.5) / 1.5).clamp(0.0, 1.0);
+    let likelihood_signal = ((model.benign_average - likelihood) / 0.5).clamp(0.0, 1.0);
+    let consonant_signal = ((consonants as f64 - 3.0) / 3.0).clamp(0.0, 1.0);
+    let digit_signal = (digits * 2.5).clamp(0.0, 1.0);
+    let length_factor = (length as f64 / 8.0).min(1.0);
+    let score = length_factor
+        * (0.5 * likelihood_signal + 0.15 * entropy_signal + 0.2 * consonant_signal + 0.15 * digit_signal);
+
+    DnsScore {
+        score,
+        explanation: format!(
+            "'{}': entropy {:.2} bits/char, bigram log-likelihood {:.2} (benign average {:.2}), \
+             longest consonant run {}, {:.0}% digits",
+            label,
+            bits,
+            likelihood,
+            model.benign_average,
+            consonants,
+            digits * 100.0
+        ),
+    }
+}
+
+#[derive(CandidType, Deserialize, Default)]
+struct ParentActivity {
+    // Queries within the window, in time order, with their subdomain and
+    // whether they were TXT or NULL
+    queries: VecDeque<(u64, String, bool)>,
+    // How many of `queries` went to each subdomain, and how many were TXT or NULL
+    subdomains: HashMap<String, u64>,
+    txt: u64,
+}
+
+impl ParentActivity {
+    // Adds a query and forgets those more than `window` before the newest
+    fn record(&mut self, time: u64, subdomain: &str, is_txt: bool, window: u64) {
+        if !subdomain.is_empty() {
+            *self.subdomains.entry(subdomain.to_string()).or_default() += 1;
+        }
+        self.txt += is_txt as u64;
+        let position = self.queries.partition_point(|(t, _, _)| *t <= time);
+        self.queries.insert(position, (time, subdomain.to_string(), is_txt));
+        let newest = self.queries.back().map_or(time, |(t, _, _)| *t);
+        self.trim(newest.saturating_sub(window));
+    }
+
+    // Forgets queries before `horizon`
+    fn trim(&mut self, horizon: u64) {
+        while self.queries.front().is_some_and(|(t, _, _)| *t < horizon) {
+            let Some((_, subdomain, is_txt)) = self.queries.pop_front() else {
+                break;
+            };
+            self.txt -= is_txt as u64;
+            if let Some(count) = self.subdomains.get_mut(&subdomain) {
+                *count -= 1;
+                if *count == 0 {
+                    self.subdomains.remove(&subdomain);
+                }
+            }
+        }
+    }
+}
+
+/// Runs the DNS detectors over queries as they are ingested, keeping per
+/// parent domain and per client activity between batches.
+#[derive(CandidType, Deserialize)]
+pub struct DnsAnalyzer {
+    config: DnsDetectorConfig,
+    watermark: u64,
+    last_eviction: u64,
+    parents: HashMap<String, ParentActivity>,
+    nxdomains: SlidingWindowCounter,
+    reported: HashMap<String, u64>,
+}
+
+impl Default for DnsAnalyzer {
+    fn default() -> Self {
+        DnsAnalyzer::new(DnsDetectorConfig::default())
+    }
+}
+
+impl DnsAnalyzer {
+    /// An analyzer allowing events the default event time config's lateness.
+    pub fn new(config: DnsDetectorConfig) -> Self {
+        let window = config.window_secs.saturating_mul(NANOS_PER_SECOND);
+        let allowed_lateness = EventTimeConfig::default().allowed_lateness_secs * NANOS_PER_SECOND;
+        DnsAnalyzer {
+            nxdomains: SlidingWindowCounter::new(window, allowed_lateness),
+            config,
+            watermark: 0,
+            last_eviction: 0,
+            parents: HashMap::new(),
+            reported: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &DnsDetectorConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: DnsDetectorConfig) {
+        let allowed_lateness = self.nxdomains.allowed_lateness();
+        *self = DnsAnalyzer {
+            parents: std::mem::take(&mut self.parents),
+            reported: std::mem::take(&mut self.reported),
+            ..DnsAnalyzer::new(config)
+        };
+        self.nxdomains.set_allowed_lateness(allowed_lateness);
+    }
+
+    pub fn set_allowed_lateness(&mut self, allowed_lateness_ns: u64) {
+        self.nxdomains.set_allowed_lateness(allowed_lateness_ns);
+    }
+
+    /// Watermark and too-late drops of the NXDOMAIN burst window.
+    pub fn event_time_stats(&self) -> EventTimeStats {
+        self.nxdomains.stats()
+    }
+
+    /// Observes `record` if it is a DNS query or answer and returns the
+    /// strongest finding it completes, with any others added to its details.
+    /// Records without an event time are taken to have happened at `now`.
+    pub fn observe(&mut self, record: &LogRecord, now: u64) -> Option<ThreatOutput> {
+        let query = dns_query(record)?;
+        let time = record.timestamp.unwrap_or(now);
+        if time > self.watermark {
+            self.watermark = time;
+            if self.watermark - self.last_eviction >= self.window() {
+                self.evict();
+            }
+        }
+
+        let mut findings = Vec::new();
+        findings.extend(self.nxdomain_burst(&query, time));
+        if !query.is_answer {
+            findings.extend(self.dga(&query, time));
+            findings.extend(self.tunneling(&query, time));
+        }
+
+        findings.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        let mut findings = findings.into_iter();
+        let mut strongest = findings.next()?;
+        for other in findings {
+            strongest.details = format!("{}; {}", strongest.details, other.details);
+        }
+        Some(strongest)
+    }
+
+    fn window(&self) -> u64 {
+        self.config.window_secs.saturating_mul(NANOS_PER_SECOND)
+    }
+
+    fn dga(&mut self, query: &DnsQuery, time: u64) -> Option<ThreatOutput> {
+        let score = dga_score(&query.domain);
+        if score.score < self.config.dga_threshold {
+            return None;
+        }
+        let (_, parent) = split_domain(&query.domain);
+        if !first_report(&mut self.reported, format!("dga|{}", parent), time, self.config.suppression_secs) {
+            return None;
+        }
+        Some(ThreatOutput::detection(
+            "dns_dga_domain",
+            if score.score >= 0.85 { "HIGH" } else { "MEDIUM" },
+            score.score,
+            &query.client,
+            format!(
+                "{} queried {}, which looks algorithmically generated: {}",
+                query.client, query.domain, score.explanation
+            ),
+        ))
+    }
+
+    fn tunneling(&mut self, query: &DnsQuery, time: u64) -> Option<ThreatOutput> {
+        let (subdomain, parent) = split_domain(&query.domain);
+        let is_txt = matches!(query.qtype.as_deref(), Some("TXT") | Some("NULL"));
+        let window = self.window();
+        let activity = self.parents.entry(parent.to_string()).or_default();
+        activity.record(time, subdomain, is_txt, window);
+        let unique_subdomains = activity.subdomains.len() as u64;
+        let (queries, txt) = (activity.queries.len() as u64, activity.txt);
+        let longest_label = subdomain.split('.').map(str::len).max().unwrap_or(0) as u64;
+
+        let mut indicators = Vec::new();
+        let mut score: f64 = 0.0;
+        if longest_label >= self.config.long_label_length {
+            indicators.push(format!("{}-character label", longest_label));
+            score += 0.4;
+        }
+        if unique_subdomains >= self.config.unique_subdomain_threshold {
+            indicators.push(format!(
+                "{} distinct subdomains within {}s",
+                unique_subdomains, self.config.window_secs
+            ));
+            score += 0.4;
+        }
+        let txt_ratio = txt as f64 / queries.max(1) as f64;
+        if queries >= self.config.txt_min_queries && txt_ratio >= self.config.txt_ratio_threshold {
+            indicators.push(format!("{:.0}% TXT/NULL of {} queries", txt_ratio * 100.0, queries));
+            score += 0.3;
+        }
+        let key = format!("tunnel|{}", parent);
+        if indicators.is_empty() || !first_report(&mut self.reported, key, time, self.config.suppression_secs) {
+            return None;
+        }
+
+        let score = score.min(0.95);
+        Some(ThreatOutput::detection(
+            "dns_tunneling",
+            if score >= 0.7 { "HIGH" } else { "MEDIUM" },
+            score,
+            &query.client,
+            format!(
+                "Possible DNS tunneling to {} from {}: {}",
+                parent,
+                query.client,
+                indicators.join(", ")
+            ),
+        ))
+    }
+
+    fn nxdomain_burst(&mut self, query: &DnsQuery, time: u64) -> Option<ThreatOutput> {
+        if query.rcode.as_deref() != Some("NXDOMAIN") {
+            return None;
+        }
+        let threshold = self.config.nxdomain_burst_threshold;
+        let count = self.nxdomains.observe(&query.client, time)? as u64;
+        let key = format!("nxdomain|{}", query.client);
+        if threshold == 0 || count < threshold || !first_report(&mut self.reported, key, time, self.config.suppression_secs) {
+            return None;
+        }
+
+        // Twice the threshold is certain; at the threshold itself, fairly likely
+        let score = (0.6 + 0.35 * (count as f64 / threshold as f64 - 1.0)).min(0.95);
+        Some(ThreatOutput::detection(
+            "dns_nxdomain_burst",
+            if count >= threshold * 2 { "HIGH" } else { "MEDIUM" },
+            score,
+            &query.client,
+            format!(
+                "{} received {} NXDOMAIN answers within {}s, last for {}",
+                query.client, count, self.config.window_secs, query.domain
+            ),
+        ))
+    }
+
+    fn evict(&mut self) {
+        self.last_eviction = self.watermark;
+        let horizon = self.watermark.saturating_sub(self.window());
+        self.parents.retain(|_, activity| {
+            activity.trim(horizon);
+            !activity.queries.is_empty()
+        });
+        let suppression_horizon = self
+            .watermark
+            .saturating_sub(self.config.suppression_secs.saturating_mul(NANOS_PER_SECOND));
+        self.reported.retain(|_, last| *last >= suppression_horizon);
+    }
+}
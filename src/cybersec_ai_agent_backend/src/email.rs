@@ -0,0 +1,524 @@
+use crate::dns::split_domain;
+use crate::url_risk::{extract_urls, DEFAULT_BRANDS};
+use crate::ThreatOutput;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::net::IpAddr;
+
+// Attachments that run code when opened
+const EXECUTABLE_EXTENSIONS: [&str; 20] = [
+    "exe", "scr", "com", "pif", "bat", "cmd", "js", "jse", "vbs", "vbe", "wsf", "hta", "ps1", "jar", "lnk",
+    "iso", "img", "msi", "docm", "xlsm",
+];
+// Often used to smuggle the above past filters
+const CONTAINER_EXTENSIONS: [&str; 6] = ["zip", "rar", "7z", "gz", "html", "htm"];
+// Score at which a message is judged phishing
+const PHISHING_THRESHOLD: f64 = 0.5;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mailbox {
+    pub display_name: Option<String>,
+    pub address: String,
+}
+
+impl Mailbox {
+    pub fn domain(&self) -> &str {
+        self.address.rsplit_once('@').map(|(_, domain)| domain).unwrap_or_default()
+    }
+}
+
+/// One `Received` header: the host that handed the message over, and to whom.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReceivedHop {
+    pub from: Option<String>,
+    pub by: Option<String>,
+    pub ip: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Link {
+    pub href: String,
+    /// Anchor text for HTML links
+    pub text: Option<String>,
+}
+
+/// The parts of an RFC 5322 message that bear on phishing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct EmailMessage {
+    pub headers: Vec<(String, String)>,
+    pub subject: Option<String>,
+    pub from: Option<Mailbox>,
+    pub reply_to: Option<Mailbox>,
+    pub return_path: Option<String>,
+    /// Newest hop first, as the headers appear
+    pub received: Vec<ReceivedHop>,
+    /// SPF, DKIM and DMARC verdicts from the receiving server's Authentication-Results
+    pub spf: Option<String>,
+    pub dkim: Option<String>,
+    pub dmarc: Option<String>,
+    pub links: Vec<Link>,
+    pub attachments: Vec<String>,
+}
+
+impl EmailMessage {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The first public address in the Received chain, counting from the sender.
+    pub fn originating_ip(&self) -> Option<&str> {
+        self.received
+            .iter()
+            .rev()
+            .filter_map(|hop| hop.ip.as_deref())
+            .find(|ip| is_public(ip))
+    }
+}
+
+/// Parses a raw RFC 5322 message, walking MIME parts for links and attachments.
+pub fn parse_message(raw: &str) -> Result<EmailMessage, String> {
+    let (header_block, body) = split_headers(raw);
+    let headers = parse_headers(header_block);
+    if headers.is_empty() {
+        return Err("Message has no header section".to_string());
+    }
+
+    let mut message = EmailMessage {
+        headers,
+        ..Default::default()
+    };
+    message.subject = message.header("Subject").map(decode_encoded_words);
+    message.from = message.header("From").and_then(parse_mailbox);
+    message.reply_to = message.header("Reply-To").and_then(parse_mailbox);
+    message.return_path = message
+        .header("Return-Path")
+        .map(|path| path.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+        .filter(|path| !path.is_empty());
+    message.received = message
+        .headers
+        .iter()
+        .filter(|(key, _)| key.eq_ignore_ascii_case("Received"))
+        .map(|(_, value)| parse_received(value))
+        .collect();
+
+    // The topmost Authentication-Results is the one our own server added
+    let results = message.header("Authentication-Results").unwrap_or_default().to_string();
+    let verdict = |method: &str| {
+        results
+            .split([';', ' ', '\t'])
+            .find_map(|token| token.trim().strip_prefix(method)?.strip_prefix('='))
+            .map(|verdict| verdict.to_ascii_lowercase())
+    };
+    message.spf = verdict("spf");
+    message.dkim = verdict("dkim");
+    message.dmarc = verdict("dmarc");
+
+    let content_type = message.header("Content-Type").unwrap_or("text/plain").to_string();
+    let encoding = message.header("Content-Transfer-Encoding").map(str::to_string);
+    walk_part(&content_type, encoding.as_deref(), None, body, &mut message);
+    Ok(message)
+}
+
+fn split_headers(raw: &str) -> (&str, &str) {
+    for separator in ["\r\n\r\n", "\n\n"] {
+        if let Some((headers, body)) = raw.split_once(separator) {
+            return (headers, body);
+        }
+    }
+    (raw, "")
+}
+
+// Unfolds continuation lines into their header
+fn parse_headers(block: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in block.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            if !name.is_empty() && !name.contains(' ') {
+                headers.push((name.to_string(), value.trim().to_string()));
+            }
+        }
+    }
+    headers
+}
+
+fn header_parameter(value: &str, name: &str) -> Option<String> {
+    value.split(';').skip(1).find_map(|parameter| {
+        let (key, value) = parameter.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case(name)
+            .then(|| decode_encoded_words(value.trim().trim_matches('"')))
+    })
+}
+
+fn walk_part(content_type: &str, encoding: Option<&str>, disposition: Option<&str>, body: &str, message: &mut EmailMessage) {
+    let media_type = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    let filename = disposition
+        .and_then(|d| header_parameter(d, "filename"))
+        .or_else(|| header_parameter(content_type, "name"));
+    let is_attachment = disposition.is_some_and(|d| d.trim().to_ascii_lowercase().starts_with("attachment"));
+    if is_attachment || filename.is_some() {
+        message.attachments.push(filename.unwrap_or_else(|| "unnamed".to_string()));
+        return;
+    }
+
+    if media_type.starts_with("multipart/") {
+        let Some(boundary) = header_parameter(content_type, "boundary") else {
+            return;
+        };
+        let delimiter = format!("--{}", boundary);
+        for part in body.split(delimiter.as_str()).skip(1) {
+            if part.starts_with("--") {
+                break;
+            }
+            let (part_headers, part_body) = split_headers(part.trim_start_matches(['\r', '\n']));
+            let headers = parse_headers(part_headers);
+            let header = |name: &str| {
+                headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.as_str())
+            };
+            walk_part(
+                header("Content-Type").unwrap_or("text/plain"),
+                header("Content-Transfer-Encoding"),
+                header("Content-Disposition"),
+                part_body,
+                message,
+            );
+        }
+    } else if media_type.starts_with("text/") {
+        let text = decode_body(body, encoding);
+        if media_type == "text/html" {
+            message.links.extend(html_links(&text));
+        } else {
+            for href in extract_urls(&text) {
+                if !message.links.iter().any(|link| link.href == href) {
+                    message.links.push(Link { href, text: None });
+                }
+            }
+        }
+    }
+}
+
+fn decode_body(body: &str, encoding: Option<&str>) -> String {
+    match encoding.map(|e| e.trim().to_ascii_lowercase()).as_deref() {
+        Some("base64") => {
+            let compact: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+            STANDARD
+                .decode(compact)
+                .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                .unwrap_or_default()
+        }
+        Some("quoted-printable") => decode_quoted_printable(body, false),
+        _ => body.to_string(),
+    }
+}
+
+// `underscores` is set for Q-encoded words, where `_` stands for a space
+fn decode_quoted_printable(text: &str, underscores: bool) -> String {
+    let text = text.replace("=\r\n", "").replace("=\n", "");
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3).and_then(|hex| std::str::from_utf8(hex).ok());
+        match (bytes[i], hex.and_then(|hex| u8::from_str_radix(hex, 16).ok())) {
+            (b'=', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'_', _) if underscores => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Decodes RFC 2047 encoded words (`=?UTF-8?B?...?=`) in a header value.
+pub fn decode_encoded_words(value: &str) -> String {
+    let mut decoded = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let word = &rest[start + 2..];
+        let parsed = word.split_once('?').and_then(|(_, word)| {
+            let (encoding, word) = word.split_once('?')?;
+            let (text, tail) = word.split_once("?=")?;
+            let text = match encoding.to_ascii_lowercase().as_str() {
+                "b" => String::from_utf8_lossy(&STANDARD.decode(text).ok()?).into_owned(),
+                "q" => decode_quoted_printable(text, true),
+                _ => return None,
+            };
+            Some((text, tail))
+        });
+        match parsed {
+            Some((text, tail)) => {
+                // Whitespace between adjacent encoded words is not part of the text
+                let before = &rest[..start];
+                if !(after_word && before.trim().is_empty()) {
+                    decoded.push_str(before);
+                }
+                decoded.push_str(&text);
+                rest = tail;
+                after_word = true;
+            }
+            None => {
+                decoded.push_str(&rest[..start + 2]);
+                rest = &rest[start + 2..];
+                after_word = false;
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Parses `"Name" <user@example.com>`, `Name <user@example.com>` or a bare address.
+pub fn parse_mailbox(value: &str) -> Option<Mailbox> {
+    let value = decode_encoded_words(value.trim());
+    let (display_name, address) = match (value.rfind('<'), value.rfind('>')) {
+        (Some(open), Some(close)) if open < close => {
+            let name = value[..open].trim().trim_matches('"').trim();
+            (
+                (!name.is_empty()).then(|| name.to_string()),
+                value[open + 1..close].trim().to_string(),
+            )
+        }
+        _ => (None, value.trim().to_string()),
+    };
+    address.contains('@').then(|| Mailbox {
+        display_name,
+        address: address.to_ascii_lowercase(),
+    })
+}
+
+// "from mail.example.com (mail.example.com [198.51.100.7]) by mx.local with ESMTPS ...; date"
+fn parse_received(value: &str) -> ReceivedHop {
+    let clauses = value.split(';').next().unwrap_or_default();
+    let words: Vec<&str> = clauses.split_whitespace().collect();
+    let after = |keyword: &str| {
+        words
+            .iter()
+            .position(|word| word.eq_ignore_ascii_case(keyword))
+            .and_then(|index| words.get(index + 1))
+            .map(|word| word.to_string())
+    };
+    let ip = clauses
+        .split(['[', ']', '(', ')', ' '])
+        .map(|token| token.trim_start_matches("IPv6:"))
+        .find(|token| token.parse::<IpAddr>().is_ok())
+        .map(str::to_string);
+
+    ReceivedHop {
+        from: after("from"),
+        by: after("by"),
+        ip,
+    }
+}
+
+fn is_public(ip: &str) -> bool {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified()),
+        Ok(IpAddr::V6(ip)) => !(ip.is_loopback() || ip.is_unspecified() || ip.segments()[0] & 0xfe00 == 0xfc00),
+        Err(_) => false,
+    }
+}
+
+// <a href="...">text</a>, tolerating any attribute order and either quote style
+fn html_links(html: &str) -> Vec<Link> {
+    let lower = html.to_ascii_lowercase();
+    let mut links = Vec::new();
+    let mut offset = 0;
+    while let Some(start) = lower[offset..].find("<a ") {
+        let tag_start = offset + start;
+        let Some(tag_len) = lower[tag_start..].find('>') else {
+            break;
+        };
+        let tag = &html[tag_start..tag_start + tag_len];
+        let content_start = tag_start + tag_len + 1;
+        let content_end = lower[content_start..]
+            .find("</a>")
+            .map(|end| content_start + end)
+            .unwrap_or(content_start);
+        offset = content_end.max(content_start);
+
+        let Some(href) = tag_attribute(tag, "href") else {
+            continue;
+        };
+        let text = strip_tags(&html[content_start..content_end]);
+        links.push(Link {
+            href,
+            text: (!text.is_empty()).then_some(text),
+        });
+    }
+    links
+}
+
+fn tag_attribute(tag: &str, name: &str) -> Option<String> {
+    let lower = tag.to_ascii_lowercase();
+    let position = lower.find(&format!("{}=", name))? + name.len() + 1;
+    let value = &tag[position..];
+    let value = match value.chars().next()? {
+        quote @ ('"' | '\'') => value[1..].split(quote).next()?,
+        _ => value.split(|c: char| c.is_whitespace()).next()?,
+    };
+    Some(value.replace("&amp;", "&"))
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut text = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' => in_tag = false,
+            c if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn link_host(url: &str) -> Option<String> {
+    let rest = url.split_once("://")?.1;
+    let authority = rest.split(['/', '?', '#']).next()?;
+    let host = authority.rsplit('@').next()?;
+    let host = host.strip_prefix('[').map_or_else(
+        || host.split(':').next().unwrap_or_default(),
+        |v6| v6.split(']').next().unwrap_or_default(),
+    );
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+fn registered_domain(domain: &str) -> String {
+    split_domain(&domain.to_ascii_lowercase()).1.to_string()
+}
+
+/// Judges a parsed message, returning a phishing verdict with every reason found.
+///
+/// Each finding adds a weight to the score; a score of 0.5 or more is a
+/// phishing verdict. The sender address is the entity.
+pub fn analyze(message: &EmailMessage) -> ThreatOutput {
+    let mut reasons: Vec<(f64, String)> = Vec::new();
+    let from_domain = message.from.as_ref().map(|from| registered_domain(from.domain()));
+
+    match message.dmarc.as_deref() {
+        Some("fail") => reasons.push((0.35, "DMARC failed".to_string())),
+        Some("none") | None if message.spf.as_deref() != Some("pass") && message.dkim.as_deref() != Some("pass") => {
+            reasons.push((0.1, "no passing SPF, DKIM or DMARC result".to_string()))
+        }
+        _ => {}
+    }
+    match message.spf.as_deref() {
+        Some("fail") => reasons.push((0.2, "SPF failed".to_string())),
+        Some("softfail") => reasons.push((0.1, "SPF soft-failed".to_string())),
+        _ => {}
+    }
+    if message.dkim.as_deref() == Some("fail") {
+        reasons.push((0.2, "DKIM signature failed".to_string()));
+    }
+
+    if let (Some(from), Some(from_domain)) = (&message.from, &from_domain) {
+        if let Some(reply_to) = &message.reply_to {
+            if registered_domain(reply_to.domain()) != *from_domain {
+                reasons.push((0.25, format!("Reply-To {} differs from From domain {}", reply_to.address, from_domain)));
+            }
+        }
+        if let Some(return_path) = &message.return_path {
+            let bounce_domain = return_path.rsplit_once('@').map(|(_, domain)| registered_domain(domain));
+            if bounce_domain.is_some_and(|domain| domain != *from_domain) {
+                reasons.push((0.1, format!("Return-Path {} differs from From domain {}", return_path, from_domain)));
+            }
+        }
+        if let Some(name) = &from.display_name {
+            let lowered = name.to_ascii_lowercase();
+            let embedded = parse_mailbox(name).filter(|embedded| registered_domain(embedded.domain()) != *from_domain);
+            if let Some(embedded) = embedded {
+                reasons.push((0.35, format!("display name shows {} but mail is from {}", embedded.address, from.address)));
+            } else if let Some(brand) = DEFAULT_BRANDS.iter().find(|brand| {
+                lowered.replace(' ', "").contains(*brand) && !from_domain.contains(*brand)
+            }) {
+                reasons.push((0.3, format!("display name claims {} but mail is from {}", brand, from.address)));
+            }
+        }
+    }
+
+    for link in &message.links {
+        let Some(host) = link_host(&link.href) else {
+            continue;
+        };
+        if host.parse::<IpAddr>().is_ok() {
+            reasons.push((0.2, format!("link to a bare IP address {}", link.href)));
+        }
+        let shown_host = link.text.as_deref().and_then(|text| link_host(text.trim()));
+        if let Some(shown_host) = shown_host {
+            if registered_domain(&shown_host) != registered_domain(&host) {
+                reasons.push((0.35, format!("link text shows {} but points to {}", shown_host, host)));
+            }
+        }
+    }
+
+    for attachment in &message.attachments {
+        let lowered = attachment.to_ascii_lowercase();
+        let mut extensions = lowered.rsplit('.');
+        let last = extensions.next().unwrap_or_default();
+        let previous = extensions.next().filter(|_| lowered.matches('.').count() >= 2);
+        if EXECUTABLE_EXTENSIONS.contains(&last) {
+            let disguised = previous.is_some_and(|previous| previous.len() <= 4);
+            let weight = if disguised { 0.5 } else { 0.4 };
+            let label = if disguised { "disguised executable attachment" } else { "executable attachment" };
+            reasons.push((weight, format!("{} {}", label, attachment)));
+        } else if CONTAINER_EXTENSIONS.contains(&last) {
+            reasons.push((0.1, format!("archive or HTML attachment {}", attachment)));
+        }
+    }
+
+    let score = reasons.iter().map(|(weight, _)| weight).sum::<f64>().min(0.99);
+    let is_threat = score >= PHISHING_THRESHOLD;
+    let sender = message.from.as_ref().map(|from| from.address.clone());
+    let origin = message
+        .originating_ip()
+        .map(|ip| format!(" (originating IP {})", ip))
+        .unwrap_or_default();
+    let details = if reasons.is_empty() {
+        format!("No phishing indicators{}", origin)
+    } else {
+        let reasons: Vec<&str> = reasons.iter().map(|(_, reason)| reason.as_str()).collect();
+        format!(
+            "{}{}: {}",
+            if is_threat { "Likely phishing" } else { "Suspicious" },
+            origin,
+            reasons.join("; ")
+        )
+    };
+
+    ThreatOutput {
+        is_threat,
+        details,
+        category: if is_threat { "phishing" } else { "email" }.to_string(),
+        confidence: if is_threat { score } else { 1.0 - score },
+        severity: match score {
+            s if s >= 0.8 => "HIGH",
+            s if s >= PHISHING_THRESHOLD => "MEDIUM",
+            s if s > 0.0 => "LOW",
+            _ => "INFO",
+        }
+        .to_string(),
+        entity: sender,
+    }
+}
@@ -0,0 +1,136 @@
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::timestamp::TimestampConfig;
+use std::collections::HashMap;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct EventTimeConfig {
+    /// Zone assumed for timestamps that carry none, in minutes east of UTC
+    pub default_utc_offset_minutes: i32,
+    /// How far behind the newest event time an event may arrive and still be counted
+    pub allowed_lateness_secs: u64,
+}
+
+impl Default for EventTimeConfig {
+    fn default() -> Self {
+        EventTimeConfig {
+            default_utc_offset_minutes: 0,
+            allowed_lateness_secs: 300,
+        }
+    }
+}
+
+impl EventTimeConfig {
+    pub fn timestamp_config(&self, now: u64) -> TimestampConfig {
+        TimestampConfig {
+            default_utc_offset_minutes: self.default_utc_offset_minutes,
+            reference_time: Some(now),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct EventTimeStats {
+    pub watermark: u64,
+    pub dropped_late_events: u64,
+}
+
+impl EventTimeStats {
+    /// The stats of two windows taken together: the newer watermark and every drop.
+    pub fn merge(&self, other: &EventTimeStats) -> EventTimeStats {
+        EventTimeStats {
+            watermark: self.watermark.max(other.watermark),
+            dropped_late_events: self.dropped_late_events + other.dropped_late_events,
+        }
+    }
+}
+
+/// Per-key sliding window over event time rather than arrival order.
+///
+/// The watermark is the newest event time seen. Events older than the
+/// watermark minus the allowed lateness are dropped and counted; anything
+/// newer is slotted into place, so windows reflect when events happened.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SlidingWindowCounter {
+    window_ns: u64,
+    allowed_lateness_ns: u64,
+    watermark: u64,
+    dropped_late: u64,
+    last_eviction: u64,
+    // Event times per key, kept sorted
+    events: HashMap<String, Vec<u64>>,
+}
+
+impl SlidingWindowCounter {
+    pub fn new(window_ns: u64, allowed_lateness_ns: u64) -> Self {
+        SlidingWindowCounter {
+            window_ns,
+            allowed_lateness_ns,
+            watermark: 0,
+            dropped_late: 0,
+            last_eviction: 0,
+            events: HashMap::new(),
+        }
+    }
+
+    pub fn allowed_lateness(&self) -> u64 {
+        self.allowed_lateness_ns
+    }
+
+    pub fn set_allowed_lateness(&mut self, allowed_lateness_ns: u64) {
+        self.allowed_lateness_ns = allowed_lateness_ns;
+    }
+
+    pub fn stats(&self) -> EventTimeStats {
+        EventTimeStats {
+            watermark: self.watermark,
+            dropped_late_events: self.dropped_late,
+        }
+    }
+
+    /// Records an event and returns the largest number of events for `key`
+    /// that share a window with it, or `None` if it arrived too late.
+    pub fn observe(&mut self, key: &str, event_time: u64) -> Option<usize> {
+        if event_time < self.watermark.saturating_sub(self.allowed_lateness_ns) {
+            self.dropped_late += 1;
+            return None;
+        }
+        if event_time > self.watermark {
+            self.watermark = event_time;
+            if self.watermark - self.last_eviction >= self.window_ns {
+                self.evict();
+            }
+        }
+
+        let times = self.events.entry(key.to_string()).or_default();
+        let position = times.partition_point(|t| *t <= event_time);
+        times.insert(position, event_time);
+
+        // Every window holding this event starts somewhere in [event_time - window, event_time]
+        let lower = times.partition_point(|t| *t < event_time.saturating_sub(self.window_ns));
+        let upper = times.partition_point(|t| *t <= event_time.saturating_add(self.window_ns));
+        let candidates = &times[lower..upper];
+        let mut peak = 0;
+        let mut end = 0;
+        for (start, first) in candidates.iter().enumerate().take_while(|(_, t)| **t <= event_time) {
+            while end < candidates.len() && candidates[end] - first <= self.window_ns {
+                end += 1;
+            }
+            peak = peak.max(end - start);
+        }
+        Some(peak)
+    }
+
+    // Forget events that can no longer share a window with an on-time event
+    fn evict(&mut self) {
+        self.last_eviction = self.watermark;
+        let horizon = self
+            .watermark
+            .saturating_sub(self.allowed_lateness_ns)
+            .saturating_sub(self.window_ns);
+        self.events.retain(|_, times| {
+            let expired = times.partition_point(|t| *t < horizon);
+            times.drain(..expired);
+            !times.is_empty()
+        });
+    }
+}
@@ -0,0 +1,629 @@
+use crate::decode::flatten_json;
+use crate::detection::{first_report, NANOS_PER_SECOND};
+use crate::{NetworkScan, ThreatOutput};
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::timestamp::{parse_timestamp, TimestampConfig};
+use cybersec_log_model::LogRecord;
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::hash::Hash;
+use std::net::IpAddr;
+
+pub const SOURCE: &str = "netflow";
+
+// Connection times kept per beacon candidate
+const BEACON_HISTORY: usize = 32;
+// A flow of this many packets got past the handshake, so the port answered
+const MIN_PACKETS_ANSWERED: u64 = 3;
+// Endpoint pairs with connection times kept; past this the least recently seen half is dropped
+const MAX_BEACON_PAIRS: usize = 50_000;
+// Hosts in the network view, likewise, and the ports listed for each
+const MAX_SERVICE_HOSTS: usize = 10_000;
+const MAX_PORTS_PER_HOST: usize = 1_024;
+const MAX_VIEW_PAGE: u64 = 1_000;
+
+// Column names used by the common exporters (nfdump, softflowd, IPFIX element names, VPC flow logs)
+const SRC_IP: [&str; 5] = ["src_ip", "srcaddr", "src", "sa", "sourceIPv4Address"];
+const DST_IP: [&str; 5] = ["dst_ip", "dstaddr", "dst", "da", "destinationIPv4Address"];
+const SRC_PORT: [&str; 4] = ["src_port", "srcport", "sp", "sourceTransportPort"];
+const DST_PORT: [&str; 4] = ["dst_port", "dstport", "dp", "destinationTransportPort"];
+const PROTOCOL: [&str; 4] = ["protocol", "proto", "pr", "protocolIdentifier"];
+const BYTES: [&str; 4] = ["bytes", "ibyt", "in_bytes", "octetDeltaCount"];
+const PACKETS: [&str; 5] = ["packets", "pkts", "ipkt", "in_pkts", "packetDeltaCount"];
+const START: [&str; 5] = ["start", "first", "ts", "start_time", "flowStartMilliseconds"];
+const END: [&str; 5] = ["end", "last", "te", "end_time", "flowEndMilliseconds"];
+
+/// One NetFlow/IPFIX-style flow record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Flow {
+    pub src_ip: String,
+    pub dst_ip: String,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub protocol: String,
+    pub bytes: u64,
+    pub packets: u64,
+    /// Nanoseconds since the Unix epoch
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+}
+
+impl Flow {
+    fn time(&self) -> Option<u64> {
+        self.start.or(self.end)
+    }
+}
+
+impl From<&Flow> for LogRecord {
+    fn from(flow: &Flow) -> Self {
+        let port = |port: Option<u16>| port.map(|p| p.to_string()).unwrap_or_default();
+        let mut attributes = BTreeMap::new();
+        attributes.insert("src_port".to_string(), port(flow.src_port));
+        attributes.insert("dst_port".to_string(), port(flow.dst_port));
+        attributes.insert("protocol".to_string(), flow.protocol.clone());
+        attributes.insert("bytes".to_string(), flow.bytes.to_string());
+        attributes.insert("packets".to_string(), flow.packets.to_string());
+        if let Some(end) = flow.end {
+            attributes.insert("end".to_string(), end.to_string());
+        }
+        attributes.retain(|_, value| !value.is_empty());
+
+        LogRecord {
+            timestamp: flow.time(),
+            message: format!(
+                "Flow {} {}:{} -> {}:{} {} bytes {} packets",
+                flow.protocol,
+                flow.src_ip,
+                port(flow.src_port),
+                flow.dst_ip,
+                port(flow.dst_port),
+                flow.bytes,
+                flow.packets
+            ),
+            source: Some(SOURCE.to_string()),
+            source_ip: Some(flow.src_ip.clone()),
+            destination_ip: Some(flow.dst_ip.clone()),
+            action: Some("flow".to_string()),
+            attributes,
+            ..Default::default()
+        }
+    }
+}
+
+/// Parses flow records from CSV with a header row, JSON lines, or a JSON array.
+///
+/// Column names may follow any of the usual exporters (`src_ip`/`srcaddr`/
+/// `sourceIPv4Address`, `bytes`/`octetDeltaCount`, ...). Times may be epoch
+/// values in any unit or ISO 8601, read in `config`'s zone when they carry
+/// none; numeric protocols are mapped to names.
+pub fn parse_flows(text: &str, config: &TimestampConfig) -> Result<Vec<Flow>, String> {
+    let first = text.trim_start().chars().next();
+    match first {
+        Some('[') => {
+            let entries: Vec<Value> =
+                serde_json::from_str(text).map_err(|e| format!("Invalid flow JSON: {}", e))?;
+            entries
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| from_json(entry, index + 1, config))
+                .collect()
+        }
+        Some('{') => text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                let entry: Value = serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid flow JSON on line {}: {}", index + 1, e))?;
+                from_json(&entry, index + 1, config)
+            })
+            .collect(),
+        Some(_) => parse_csv(text, config),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn from_json(entry: &Value, line: usize, config: &TimestampConfig) -> Result<Flow, String> {
+    let mut fields = BTreeMap::new();
+    flatten_json("", entry, &mut fields);
+    to_flow(&fields, line, config)
+}
+
+fn parse_csv(text: &str, config: &TimestampConfig) -> Result<Vec<Flow>, String> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let header: Vec<String> = match lines.next() {
+        Some((_, line)) => split_csv(line).map(str::to_string).collect(),
+        None => return Ok(Vec::new()),
+    };
+
+    lines
+        .map(|(index, line)| {
+            let values: Vec<&str> = split_csv(line).collect();
+            if values.len() != header.len() {
+                return Err(format!(
+                    "Flow CSV line {} has {} columns but the header declares {}",
+                    index + 1,
+                    values.len(),
+                    header.len()
+                ));
+            }
+            let fields = header
+                .iter()
+                .cloned()
+                .zip(values.into_iter().map(str::to_string))
+                .collect();
+            to_flow(&fields, index + 1, config)
+        })
+        .collect()
+}
+
+fn split_csv(line: &str) -> impl Iterator<Item = &str> {
+    line.split(',').map(|value| value.trim().trim_matches('"'))
+}
+
+fn to_flow(fields: &BTreeMap<String, String>, line: usize, config: &TimestampConfig) -> Result<Flow, String> {
+    let field = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| fields.get(*name))
+            .map(String::as_str)
+            .filter(|value| !value.is_empty() && *value != "-")
+    };
+    let number = |names: &[&str]| -> Result<Option<u64>, String> {
+        field(names)
+            .map(|value| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Flow record {} has a non-numeric {} '{}'", line, names[0], value))
+            })
+            .transpose()
+    };
+    let port = |names: &[&str]| -> Result<Option<u16>, String> {
+        number(names)?
+            .map(|value| u16::try_from(value).map_err(|_| format!("Flow record {} has port {} out of range", line, value)))
+            .transpose()
+    };
+    let time = |names: &[&str]| field(names).and_then(|value| parse_timestamp(value, config));
+
+    let src_ip = field(&SRC_IP).ok_or_else(|| format!("Flow record {} has no source address", line))?;
+    let dst_ip = field(&DST_IP).ok_or_else(|| format!("Flow record {} has no destination address", line))?;
+
+    Ok(Flow {
+        src_ip: src_ip.to_string(),
+        dst_ip: dst_ip.to_string(),
+        src_port: port(&SRC_PORT)?,
+        dst_port: port(&DST_PORT)?,
+        protocol: protocol_name(field(&PROTOCOL).unwrap_or("unknown")),
+        bytes: number(&BYTES)?.unwrap_or(0),
+        packets: number(&PACKETS)?.unwrap_or(0),
+        start: time(&START),
+        end: time(&END),
+    })
+}
+
+fn protocol_name(protocol: &str) -> String {
+    match protocol {
+        "1" => "icmp".to_string(),
+        "6" => "tcp".to_string(),
+        "17" => "udp".to_string(),
+        "58" => "ipv6-icmp".to_string(),
+        other => other.to_ascii_lowercase(),
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct FlowDetectorConfig {
+    /// Window the port scan detectors count distinct hosts and ports in
+    pub scan_window_secs: u64,
+    /// Distinct hosts probed on one port before a horizontal scan is reported
+    pub horizontal_scan_hosts: u64,
+    /// Distinct ports probed on one host before a vertical scan is reported
+    pub vertical_scan_ports: u64,
+    /// Connections between the same endpoints needed before beaconing is considered
+    pub beacon_min_connections: u64,
+    /// Largest spread of inter-arrival times, relative to their mean, that still counts as regular
+    pub beacon_max_jitter: f64,
+    /// Bytes in one flow from an internal to an external address that make it a large transfer
+    pub large_transfer_bytes: u64,
+    /// Flows to observe before ports are judged rare; 0 disables the rare port detector
+    pub rare_port_baseline_flows: u64,
+    /// Share of all flows below which a destination port is rare
+    pub rare_port_max_share: f64,
+    /// The same finding is reported again only after this many seconds
+    pub suppression_secs: u64,
+}
+
+impl Default for FlowDetectorConfig {
+    fn default() -> Self {
+        FlowDetectorConfig {
+            scan_window_secs: 60,
+            horizontal_scan_hosts: 20,
+            vertical_scan_ports: 20,
+            beacon_min_connections: 6,
+            beacon_max_jitter: 0.1,
+            large_transfer_bytes: 100_000_000,
+            rare_port_baseline_flows: 10_000,
+            rare_port_max_share: 0.0001,
+            suppression_secs: 3_600,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+struct Probe {
+    time: u64,
+    dst_ip: String,
+    dst_port: u16,
+}
+
+// Ports seen answering on one host
+#[derive(CandidType, Deserialize, Default)]
+struct ServiceHost {
+    ports: BTreeSet<u16>,
+    last_seen: u64,
+}
+
+/// One source's probes within the scan window, in time order, with the
+/// distinct hosts probed on each port and ports probed on each host.
+#[derive(CandidType, Deserialize, Default)]
+struct ProbeHistory {
+    probes: VecDeque<Probe>,
+    // Probes in `probes` per port and host, both ways round
+    hosts_by_port: HashMap<u16, HashMap<String, u32>>,
+    ports_by_host: HashMap<String, HashMap<u16, u32>>,
+}
+
+impl ProbeHistory {
+    // Adds `probe` and forgets those more than `window` before the newest;
+    // false if `probe` itself is already that old
+    fn record(&mut self, probe: Probe, window: u64) -> bool {
+        let newest = self.probes.back().map_or(probe.time, |last| last.time.max(probe.time));
+        if probe.time.saturating_add(window) < newest {
+            return false;
+        }
+        let hosts = self.hosts_by_port.entry(probe.dst_port).or_default();
+        *hosts.entry(probe.dst_ip.clone()).or_default() += 1;
+        *self.ports_by_host.entry(probe.dst_ip.clone()).or_default().entry(probe.dst_port).or_default() += 1;
+        let position = self.probes.partition_point(|p| p.time <= probe.time);
+        self.probes.insert(position, probe);
+        self.trim(newest.saturating_sub(window));
+        true
+    }
+
+    // Forgets probes before `horizon`
+    fn trim(&mut self, horizon: u64) {
+        while self.probes.front().is_some_and(|probe| probe.time < horizon) {
+            if let Some(probe) = self.probes.pop_front() {
+                forget(&mut self.hosts_by_port, &probe.dst_port, &probe.dst_ip);
+                forget(&mut self.ports_by_host, &probe.dst_ip, &probe.dst_port);
+            }
+        }
+    }
+
+    fn hosts(&self, port: u16) -> u64 {
+        self.hosts_by_port.get(&port).map_or(0, |hosts| hosts.len() as u64)
+    }
+
+    fn ports(&self, host: &str) -> u64 {
+        self.ports_by_host.get(host).map_or(0, |ports| ports.len() as u64)
+    }
+}
+
+// Takes one probe of `inner` off the count under `outer`, dropping emptied entries
+fn forget<K: Eq + Hash, V: Eq + Hash>(counts: &mut HashMap<K, HashMap<V, u32>>, outer: &K, inner: &V) {
+    let Some(inner_counts) = counts.get_mut(outer) else {
+        return;
+    };
+    if let Some(count) = inner_counts.get_mut(inner) {
+        *count -= 1;
+        if *count == 0 {
+            inner_counts.remove(inner);
+        }
+    }
+    if inner_counts.is_empty() {
+        counts.remove(outer);
+    }
+}
+
+/// Keeps the state the flow detectors need between batches: recent probes per
+/// source, connection times per endpoint pair, destination port frequencies
+/// and the ports seen answering on each host.
+#[derive(CandidType, Deserialize, Default)]
+pub struct FlowAnalyzer {
+    config: FlowDetectorConfig,
+    watermark: u64,
+    last_eviction: u64,
+    probes: HashMap<String, ProbeHistory>,
+    connections: HashMap<(String, String, u16), Vec<u64>>,
+    port_counts: HashMap<u16, u64>,
+    total_flows: u64,
+    reported: HashMap<String, u64>,
+    services: BTreeMap<String, ServiceHost>,
+}
+
+impl FlowAnalyzer {
+    pub fn new(config: FlowDetectorConfig) -> Self {
+        FlowAnalyzer {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &FlowDetectorConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: FlowDetectorConfig) {
+        self.config = config;
+    }
+
+    /// Runs every flow detector over `flows` in time order. Flows without a
+    /// start or end time are taken to have happened at `now`.
+    pub fn analyze(&mut self, flows: &[Flow], now: u64) -> Vec<ThreatOutput> {
+        let mut ordered: Vec<&Flow> = flows.iter().collect();
+        ordered.sort_by_key(|flow| flow.time().unwrap_or(now));
+
+        let mut detections = Vec::new();
+        for flow in ordered {
+            let time = flow.time().unwrap_or(now);
+            if time > self.watermark {
+                self.watermark = time;
+                if self.watermark - self.last_eviction >= self.scan_window() {
+                    self.evict();
+                }
+            }
+
+            detections.extend(self.large_transfer(flow, time));
+            if let Some(port) = flow.dst_port {
+                detections.extend(self.rare_port(flow, port, time));
+                detections.extend(self.port_scans(flow, port, time));
+                detections.extend(self.beaconing(flow, port, time));
+                if flow.packets >= MIN_PACKETS_ANSWERED {
+                    self.record_service(&flow.dst_ip, port, time);
+                }
+            }
+        }
+        detections
+    }
+
+    /// A page of the hosts seen answering in the flows so far, by address,
+    /// shaped like the result of an active scan.
+    pub fn network_view(&self, offset: u64, limit: u64) -> Vec<NetworkScan> {
+        self.services
+            .iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_VIEW_PAGE) as usize)
+            .map(|(target, host)| NetworkScan {
+                target: target.clone(),
+                open_ports: host.ports.iter().copied().collect(),
+                services: host.ports.iter().map(|port| service_name(*port)).collect(),
+            })
+            .collect()
+    }
+
+    fn record_service(&mut self, target: &str, port: u16, time: u64) {
+        let host = self.services.entry(target.to_string()).or_default();
+        if host.ports.len() < MAX_PORTS_PER_HOST {
+            host.ports.insert(port);
+        }
+        host.last_seen = host.last_seen.max(time);
+        if self.services.len() > MAX_SERVICE_HOSTS {
+            let cutoff = older_half(self.services.values().map(|host| host.last_seen));
+            self.services.retain(|_, host| host.last_seen > cutoff);
+        }
+    }
+
+    fn scan_window(&self) -> u64 {
+        self.config.scan_window_secs.saturating_mul(NANOS_PER_SECOND)
+    }
+
+    fn port_scans(&mut self, flow: &Flow, port: u16, time: u64) -> Vec<ThreatOutput> {
+        let window = self.scan_window();
+        let history = self.probes.entry(flow.src_ip.clone()).or_default();
+        let probe = Probe {
+            time,
+            dst_ip: flow.dst_ip.clone(),
+            dst_port: port,
+        };
+        let mut detections = Vec::new();
+        if !history.record(probe, window) {
+            return detections;
+        }
+        let (hosts, ports) = (history.hosts(port), history.ports(&flow.dst_ip));
+
+        let threshold = self.config.horizontal_scan_hosts;
+        let key = format!("hscan|{}|{}", flow.src_ip, port);
+        if threshold > 0 && hosts >= threshold && first_report(&mut self.reported, key, time, self.config.suppression_secs) {
+            detections.push(ThreatOutput::detection(
+                "flow_horizontal_scan",
+                scan_severity(hosts, threshold),
+                0.8,
+                &flow.src_ip,
+                format!(
+                    "{} probed port {} on {} hosts within {}s",
+                    flow.src_ip, port, hosts, self.config.scan_window_secs
+                ),
+            ));
+        }
+        let threshold = self.config.vertical_scan_ports;
+        let key = format!("vscan|{}|{}", flow.src_ip, flow.dst_ip);
+        if threshold > 0 && ports >= threshold && first_report(&mut self.reported, key, time, self.config.suppression_secs) {
+            detections.push(ThreatOutput::detection(
+                "flow_vertical_scan",
+                scan_severity(ports, threshold),
+                0.8,
+                &flow.src_ip,
+                format!(
+                    "{} probed {} ports on {} within {}s",
+                    flow.src_ip, ports, flow.dst_ip, self.config.scan_window_secs
+                ),
+            ));
+        }
+        detections
+    }
+
+    // Implants check in on a timer, so their connection times to one endpoint are evenly spaced
+    fn beaconing(&mut self, flow: &Flow, port: u16, time: u64) -> Option<ThreatOutput> {
+        let key = (flow.src_ip.clone(), flow.dst_ip.clone(), port);
+        if self.connections.len() >= MAX_BEACON_PAIRS && !self.connections.contains_key(&key) {
+            let cutoff = older_half(self.connections.values().filter_map(|times| times.last().copied()));
+            self.connections.retain(|_, times| times.last().is_some_and(|t| *t > cutoff));
+        }
+        let times = self.connections.entry(key).or_default();
+        let position = times.partition_point(|t| *t <= time);
+        times.insert(position, time);
+        if times.len() > BEACON_HISTORY {
+            times.remove(0);
+        }
+
+        let min_connections = self.config.beacon_min_connections.max(3) as usize;
+        if times.len() < min_connections {
+            return None;
+        }
+        let intervals: Vec<f64> = times.windows(2).map(|pair| (pair[1] - pair[0]) as f64).collect();
+        let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        if mean < NANOS_PER_SECOND as f64 {
+            return None;
+        }
+        let variance = intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+        let jitter = variance.sqrt() / mean;
+        if jitter > self.config.beacon_max_jitter {
+            return None;
+        }
+
+        let connections = times.len();
+        let key = format!("beacon|{}|{}|{}", flow.src_ip, flow.dst_ip, port);
+        if !first_report(&mut self.reported, key, time, self.config.suppression_secs) {
+            return None;
+        }
+        Some(ThreatOutput::detection(
+            "flow_beaconing",
+            "MEDIUM",
+            0.75,
+            &flow.src_ip,
+            format!(
+                "{} connects to {}:{} every {:.0}s ({} connections, {:.1}% jitter)",
+                flow.src_ip,
+                flow.dst_ip,
+                port,
+                mean / NANOS_PER_SECOND as f64,
+                connections,
+                jitter * 100.0
+            ),
+        ))
+    }
+
+    fn large_transfer(&mut self, flow: &Flow, time: u64) -> Option<ThreatOutput> {
+        let threshold = self.config.large_transfer_bytes;
+        if threshold == 0 || flow.bytes < threshold || !is_internal(&flow.src_ip) || is_internal(&flow.dst_ip) {
+            return None;
+        }
+        let key = format!("outbound|{}|{}", flow.src_ip, flow.dst_ip);
+        if !first_report(&mut self.reported, key, time, self.config.suppression_secs) {
+            return None;
+        }
+        let severity = if flow.bytes >= threshold.saturating_mul(10) { "HIGH" } else { "MEDIUM" };
+        Some(ThreatOutput::detection(
+            "flow_large_outbound_transfer",
+            severity,
+            0.7,
+            &flow.src_ip,
+            format!(
+                "{} sent {} bytes to external address {}",
+                flow.src_ip, flow.bytes, flow.dst_ip
+            ),
+        ))
+    }
+
+    fn rare_port(&mut self, flow: &Flow, port: u16, time: u64) -> Option<ThreatOutput> {
+        let baseline = self.config.rare_port_baseline_flows;
+        let seen = self.port_counts.get(&port).copied().unwrap_or(0);
+        let total = self.total_flows;
+        *self.port_counts.entry(port).or_default() += 1;
+        self.total_flows += 1;
+
+        if baseline == 0 || total < baseline || seen as f64 >= total as f64 * self.config.rare_port_max_share {
+            return None;
+        }
+        let key = format!("rare|{}|{}", flow.src_ip, port);
+        if !first_report(&mut self.reported, key, time, self.config.suppression_secs) {
+            return None;
+        }
+        Some(ThreatOutput::detection(
+            "flow_rare_port",
+            "LOW",
+            0.5,
+            &flow.src_ip,
+            format!(
+                "{} connected to {}:{}, a port seen in {} of {} flows",
+                flow.src_ip, flow.dst_ip, port, seen, total
+            ),
+        ))
+    }
+
+    fn evict(&mut self) {
+        self.last_eviction = self.watermark;
+        let scan_horizon = self.watermark.saturating_sub(self.scan_window());
+        self.probes.retain(|_, history| {
+            history.trim(scan_horizon);
+            !history.probes.is_empty()
+        });
+
+        // Pairs that have gone quiet for the whole suppression period are no longer beaconing
+        let idle_horizon = self
+            .watermark
+            .saturating_sub(self.config.suppression_secs.saturating_mul(NANOS_PER_SECOND));
+        self.connections
+            .retain(|_, times| times.last().is_some_and(|t| *t >= idle_horizon));
+        self.reported.retain(|_, last| *last >= idle_horizon);
+    }
+}
+
+// The time at or before which the least recently seen half of `last_seen` falls
+fn older_half(last_seen: impl Iterator<Item = u64>) -> u64 {
+    let mut last_seen: Vec<u64> = last_seen.collect();
+    let middle = last_seen.len() / 2;
+    *last_seen.select_nth_unstable(middle).1
+}
+
+fn scan_severity(count: u64, threshold: u64) -> &'static str {
+    if count >= threshold.saturating_mul(5) {
+        "HIGH"
+    } else {
+        "MEDIUM"
+    }
+}
+
+// Private, loopback and link-local ranges count as internal
+fn is_internal(ip: &str) -> bool {
+    match ip.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        Ok(IpAddr::V6(ip)) => {
+            let first = ip.segments()[0];
+            ip.is_loopback() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80
+        }
+        Err(_) => false,
+    }
+}
+
+fn service_name(port: u16) -> String {
+    let name = match port {
+        21 => "ftp",
+        22 => "ssh",
+        23 => "telnet",
+        25 => "smtp",
+        53 => "dns",
+        80 => "http",
+        110 => "pop3",
+        143 => "imap",
+        443 => "https",
+        445 => "smb",
+        3306 => "mysql",
+        3389 => "rdp",
+        5432 => "postgresql",
+        6379 => "redis",
+        8080 => "http-alt",
+        _ => return format!("Unknown service on port {}", port),
+    };
+    name.to_string()
+}
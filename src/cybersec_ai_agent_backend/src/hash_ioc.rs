@@ -0,0 +1,204 @@
+use crate::ThreatOutput;
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::LogRecord;
+use std::collections::HashMap;
+
+/// A file hash to watch for, as registered.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct HashIoc {
+    /// MD5, SHA-1 or SHA-256 in hex, either case
+    pub hash: String,
+    /// What the file is, e.g. a malware family
+    pub label: String,
+    /// Where the indicator came from, e.g. a feed name
+    pub source: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct HashMatch {
+    /// Lowercase hex
+    pub hash: String,
+    pub algorithm: String,
+    pub label: String,
+    pub source: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct HashIocStats {
+    pub md5: u64,
+    pub sha1: u64,
+    pub sha256: u64,
+}
+
+enum Digest {
+    Md5([u8; 16]),
+    Sha1([u8; 20]),
+    Sha256([u8; 32]),
+}
+
+fn parse_digest(hash: &str) -> Option<Digest> {
+    fn decode<const N: usize>(hex: &str) -> Option<[u8; N]> {
+        let mut bytes = [0u8; N];
+        for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+        }
+        Some(bytes)
+    }
+
+    let hash = hash.trim();
+    if !hash.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hash.len() {
+        32 => decode(hash).map(Digest::Md5),
+        40 => decode(hash).map(Digest::Sha1),
+        64 => decode(hash).map(Digest::Sha256),
+        _ => None,
+    }
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// Labels and sources repeat across most entries, so each is stored once
+#[derive(CandidType, Deserialize, Clone, Copy)]
+struct Entry {
+    label: u32,
+    source: u32,
+}
+
+/// Registered hashes, keyed by their raw bytes so each SHA-256 costs its 32
+/// bytes plus two string ids rather than a 64-character string.
+#[derive(CandidType, Deserialize, Default)]
+pub struct HashIocStore {
+    md5: HashMap<[u8; 16], Entry>,
+    sha1: HashMap<[u8; 20], Entry>,
+    sha256: HashMap<[u8; 32], Entry>,
+    strings: Vec<String>,
+    string_ids: HashMap<String, u32>,
+}
+
+impl HashIocStore {
+    /// Adds or relabels the given hashes. Nothing is stored unless every hash is valid.
+    pub fn register(&mut self, iocs: &[HashIoc]) -> Result<u64, String> {
+        let mut digests = Vec::with_capacity(iocs.len());
+        for ioc in iocs {
+            let digest = parse_digest(&ioc.hash)
+                .ok_or_else(|| format!("Not an MD5, SHA-1 or SHA-256 hash: {}", ioc.hash))?;
+            digests.push(digest);
+        }
+
+        for (ioc, digest) in iocs.iter().zip(digests) {
+            let entry = Entry {
+                label: self.intern(&ioc.label),
+                source: self.intern(&ioc.source),
+            };
+            match digest {
+                Digest::Md5(bytes) => self.md5.insert(bytes, entry),
+                Digest::Sha1(bytes) => self.sha1.insert(bytes, entry),
+                Digest::Sha256(bytes) => self.sha256.insert(bytes, entry),
+            };
+        }
+        Ok(iocs.len() as u64)
+    }
+
+    /// Removes the given hashes, returning how many were registered.
+    pub fn remove(&mut self, hashes: &[String]) -> u64 {
+        let removed = hashes
+            .iter()
+            .filter(|hash| match parse_digest(hash) {
+                Some(Digest::Md5(bytes)) => self.md5.remove(&bytes).is_some(),
+                Some(Digest::Sha1(bytes)) => self.sha1.remove(&bytes).is_some(),
+                Some(Digest::Sha256(bytes)) => self.sha256.remove(&bytes).is_some(),
+                None => false,
+            })
+            .count();
+        removed as u64
+    }
+
+    pub fn lookup(&self, hash: &str) -> Option<HashMatch> {
+        let (algorithm, bytes, entry) = match parse_digest(hash)? {
+            Digest::Md5(bytes) => ("MD5", bytes.to_vec(), self.md5.get(&bytes)?),
+            Digest::Sha1(bytes) => ("SHA-1", bytes.to_vec(), self.sha1.get(&bytes)?),
+            Digest::Sha256(bytes) => ("SHA-256", bytes.to_vec(), self.sha256.get(&bytes)?),
+        };
+        Some(HashMatch {
+            hash: to_hex(&bytes),
+            algorithm: algorithm.to_string(),
+            label: self.strings[entry.label as usize].clone(),
+            source: self.strings[entry.source as usize].clone(),
+        })
+    }
+
+    /// Registered hashes among the hash-looking tokens of `text`, without repeats.
+    pub fn scan(&self, text: &str) -> Vec<HashMatch> {
+        let mut matches: Vec<HashMatch> = Vec::new();
+        for token in hash_tokens(text) {
+            if let Some(found) = self.lookup(token) {
+                if !matches.iter().any(|existing| existing.hash == found.hash) {
+                    matches.push(found);
+                }
+            }
+        }
+        matches
+    }
+
+    pub fn stats(&self) -> HashIocStats {
+        HashIocStats {
+            md5: self.md5.len() as u64,
+            sha1: self.sha1.len() as u64,
+            sha256: self.sha256.len() as u64,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.md5.is_empty() && self.sha1.is_empty() && self.sha256.is_empty()
+    }
+
+    fn intern(&mut self, value: &str) -> u32 {
+        if let Some(&id) = self.string_ids.get(value) {
+            return id;
+        }
+        let id = self.strings.len() as u32;
+        self.strings.push(value.to_string());
+        self.string_ids.insert(value.to_string(), id);
+        id
+    }
+}
+
+/// Runs of 32, 40 or 64 hex digits not embedded in a longer word, as in
+/// `sha256=9f86d0...`, `MD5:"..."` or Sysmon's `SHA1=...,MD5=...`.
+pub fn hash_tokens(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|token| matches!(token.len(), 32 | 40 | 64) && token.bytes().all(|byte| byte.is_ascii_hexdigit()))
+}
+
+/// Reports registered hashes in a record's message or attributes; other
+/// records yield `None`.
+pub fn detection(record: &LogRecord, store: &HashIocStore) -> Option<ThreatOutput> {
+    if store.is_empty() {
+        return None;
+    }
+    let mut matches = store.scan(&record.message);
+    for value in record.attributes.values() {
+        for found in store.scan(value) {
+            if !matches.iter().any(|existing| existing.hash == found.hash) {
+                matches.push(found);
+            }
+        }
+    }
+
+    let first = matches.first()?;
+    let details: Vec<String> = matches
+        .iter()
+        .map(|found| format!("{} {} {} (source {})", found.label, found.algorithm, found.hash, found.source))
+        .collect();
+    Some(ThreatOutput::detection(
+        "malicious_file_hash",
+        "HIGH",
+        0.95,
+        &first.hash,
+        format!("Malicious file hash: {}", details.join("; ")),
+    ))
+}
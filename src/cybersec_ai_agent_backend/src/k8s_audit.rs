@@ -0,0 +1,372 @@
+use crate::decode::{flatten_json, percent_decode};
+use crate::ThreatOutput;
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::timestamp::parse_rfc3339;
+use cybersec_log_model::LogRecord;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+
+pub const SOURCE: &str = "k8s_audit";
+
+const ANONYMOUS: &str = "system:anonymous";
+const UNAUTHENTICATED: &str = "system:unauthenticated";
+const SERVICE_ACCOUNT_PREFIX: &str = "system:serviceaccount:";
+const WRITE_VERBS: [&str; 3] = ["create", "update", "patch"];
+// Probes that clusters commonly leave open to anonymous callers
+const PUBLIC_PATHS: [&str; 4] = ["/healthz", "/livez", "/readyz", "/version"];
+const MAX_STARTED_SESSIONS: usize = 10_000;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct K8sAuditConfig {
+    /// Service accounts expected to read secrets; a trailing `*` matches any suffix
+    pub secret_reader_allowlist: Vec<String>,
+}
+
+impl Default for K8sAuditConfig {
+    fn default() -> Self {
+        K8sAuditConfig {
+            secret_reader_allowlist: vec!["system:serviceaccount:kube-system:*".to_string()],
+        }
+    }
+}
+
+/// Parses Kubernetes audit events at any audit level: one event per line as
+/// the log backend writes them, a JSON array, a single event, or the
+/// `EventList` the webhook backend posts.
+pub fn parse_audit_log(text: &str) -> Result<Vec<LogRecord>, String> {
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let events: Vec<Value> = match serde_json::from_str::<Value>(trimmed) {
+        Ok(Value::Object(mut object)) if object.get("kind").and_then(Value::as_str) == Some("EventList") => {
+            match object.remove("items") {
+                Some(Value::Array(items)) => items,
+                _ => return Err("Kubernetes EventList has no items array".to_string()),
+            }
+        }
+        Ok(Value::Array(events)) => events,
+        Ok(event @ Value::Object(_)) => vec![event],
+        Ok(_) => return Err("Kubernetes audit input must be an event, array or EventList".to_string()),
+        Err(_) => trimmed
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(index, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid Kubernetes audit JSON on line {}: {}", index + 1, e))
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    events
+        .iter()
+        .enumerate()
+        .map(|(index, event)| {
+            if event["verb"].is_string() {
+                Ok(to_record(event))
+            } else {
+                Err(format!("Kubernetes audit event {} has no verb", index + 1))
+            }
+        })
+        .collect()
+}
+
+fn to_record(event: &Value) -> LogRecord {
+    let mut attributes = BTreeMap::new();
+    flatten_json("", event, &mut attributes);
+
+    let text = |path: &str| attributes.get(path).cloned();
+    let verb = text("verb").unwrap_or_default();
+    let mut resource = text("objectRef.resource").unwrap_or_else(|| text("requestURI").unwrap_or_default());
+    if let Some(subresource) = text("objectRef.subresource") {
+        resource = format!("{}/{}", resource, subresource);
+    }
+    let object = match (text("objectRef.namespace"), text("objectRef.name")) {
+        (Some(namespace), Some(name)) => format!(" {}/{}", namespace, name),
+        (None, Some(name)) => format!(" {}", name),
+        (Some(namespace), None) => format!(" in {}", namespace),
+        (None, None) => String::new(),
+    };
+    let user = text("user.username");
+    let message = format!(
+        "Kubernetes {} {}{} by {}",
+        verb,
+        resource,
+        object,
+        user.as_deref().unwrap_or("unknown")
+    );
+    let time = text("requestReceivedTimestamp").or_else(|| text("stageTimestamp"));
+
+    LogRecord {
+        timestamp: time.as_deref().and_then(parse_rfc3339),
+        original_timestamp: time,
+        message,
+        source: Some(SOURCE.to_string()),
+        source_ip: text("sourceIPs.0"),
+        destination_ip: None,
+        user,
+        host: None,
+        action: Some(verb),
+        outcome: text("responseStatus.code"),
+        severity: None,
+        attributes,
+        entities: None,
+    }
+}
+
+/// Applies the audit rules, remembering the exec and attach sessions already
+/// reported when their connection was upgraded.
+#[derive(CandidType, Deserialize, Default)]
+pub struct K8sAuditDetector {
+    config: K8sAuditConfig,
+    // Audit IDs of sessions reported at ResponseStarted, with their request time
+    started_sessions: HashMap<String, u64>,
+}
+
+impl K8sAuditDetector {
+    pub fn new(config: K8sAuditConfig) -> Self {
+        K8sAuditDetector {
+            config,
+            started_sessions: HashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &K8sAuditConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: K8sAuditConfig) {
+        self.config = config;
+    }
+
+    /// Turns an audit record into a detection when it matches any of the
+    /// built-in rules, the most severe leading. Other records yield `None`.
+    pub fn detection(&mut self, record: &LogRecord) -> Option<ThreatOutput> {
+        let mut findings = self.detections(record).into_iter();
+        let mut leading = findings.next()?;
+        for other in findings {
+            leading.details = format!("{}; {}", leading.details, other.details);
+        }
+        Some(leading)
+    }
+
+    /// Every rule an audit record matches, most severe first.
+    ///
+    /// Only the first stage of a request is skipped: with the default policy
+    /// the same request is also logged at ResponseComplete, with its status.
+    /// Exec and attach sessions are reported at ResponseStarted, once the
+    /// connection is upgraded, and at ResponseComplete unless that audit ID
+    /// was already reported: denied sessions never start, and policies may
+    /// omit the ResponseStarted stage. The privileged pod and cluster-admin
+    /// rules read the request body, so they need events logged at the Request
+    /// level or above.
+    pub fn detections(&mut self, record: &LogRecord) -> Vec<ThreatOutput> {
+        let stage = record.attribute("stage");
+        if record.source.as_deref() != Some(SOURCE) || stage == Some("RequestReceived") {
+            return Vec::new();
+        }
+        let resource = record.attribute("objectRef.resource").unwrap_or_default();
+        let subresource = record.attribute("objectRef.subresource");
+        let mut session_reported = false;
+        if resource == "pods" && matches!(subresource, Some("exec") | Some("attach")) {
+            match (stage, record.attribute("auditID")) {
+                (Some("ResponseStarted"), Some(audit_id)) => {
+                    self.session_started(audit_id, record.timestamp.unwrap_or_default())
+                }
+                (Some("ResponseComplete"), Some(audit_id)) => {
+                    session_reported = self.started_sessions.remove(audit_id).is_some()
+                }
+                _ => {}
+            }
+        }
+        rule_findings(record, &self.config, session_reported)
+    }
+
+    fn session_started(&mut self, audit_id: &str, time: u64) {
+        if self.started_sessions.len() >= MAX_STARTED_SESSIONS {
+            // Sessions whose completion never arrived: forget the earliest half
+            let mut times: Vec<u64> = self.started_sessions.values().copied().collect();
+            let middle = times.len() / 2;
+            let cutoff = *times.select_nth_unstable(middle).1;
+            self.started_sessions.retain(|_, started| *started > cutoff);
+        }
+        self.started_sessions.insert(audit_id.to_string(), time);
+    }
+}
+
+fn rule_findings(record: &LogRecord, config: &K8sAuditConfig, session_reported: bool) -> Vec<ThreatOutput> {
+    let user = record.user.clone().unwrap_or_else(|| "unknown".to_string());
+    let verb = record.action.as_deref().unwrap_or_default();
+    let resource = record.attribute("objectRef.resource").unwrap_or_default();
+    let subresource = record.attribute("objectRef.subresource");
+    let namespace = record.attribute("objectRef.namespace");
+    let object = match (namespace, record.attribute("objectRef.name")) {
+        (Some(namespace), Some(name)) => format!("{}/{}", namespace, name),
+        (None, Some(name)) => name.to_string(),
+        (Some(namespace), None) => format!("all in {}", namespace),
+        (None, None) => "all namespaces".to_string(),
+    };
+    let code = record.outcome.as_deref().and_then(|code| code.parse::<u16>().ok());
+    let denied = code.is_some_and(|code| code >= 400);
+    let suffix = if denied {
+        format!(" (denied: {})", code.unwrap_or_default())
+    } else {
+        String::new()
+    };
+    let finding = |category: &str, severity: &str, confidence: f64, details: String| {
+        ThreatOutput::detection(category, severity, confidence, &user, format!("{}{}", details, suffix))
+    };
+
+    let mut findings = Vec::new();
+    if resource == "pods" && matches!(subresource, Some("exec") | Some("attach")) && !session_reported {
+        findings.push(finding(
+            "k8s_pod_exec",
+            if denied { "LOW" } else { "MEDIUM" },
+            0.7,
+            format!(
+                "{} ran pods/{} on {}{}",
+                user,
+                subresource.unwrap_or_default(),
+                object,
+                record
+                    .attribute("requestURI")
+                    .and_then(exec_command)
+                    .map(|command| format!(": {}", command))
+                    .unwrap_or_default()
+            ),
+        ));
+    }
+    if WRITE_VERBS.contains(&verb) && subresource.is_none() {
+        let risks = privileged_settings(record);
+        if !risks.is_empty() {
+            findings.push(finding(
+                "k8s_privileged_pod",
+                "HIGH",
+                0.85,
+                format!("{}: {} {} {} with {}", user, verb, resource, object, risks.join(", ")),
+            ));
+        }
+    }
+    if matches!(resource, "clusterrolebindings" | "rolebindings")
+        && WRITE_VERBS.contains(&verb)
+        && record.attribute("requestObject.roleRef.name") == Some("cluster-admin")
+    {
+        let subjects = binding_subjects(record);
+        findings.push(finding(
+            "k8s_cluster_admin_binding",
+            "CRITICAL",
+            0.9,
+            format!(
+                "{} bound cluster-admin to {} via {} {}",
+                user,
+                if subjects.is_empty() { "unknown subjects".to_string() } else { subjects.join(", ") },
+                resource.trim_end_matches('s'),
+                object
+            ),
+        ));
+    }
+    if resource == "secrets" && matches!(verb, "get" | "list" | "watch") {
+        if let Some(reason) = unusual_secret_reader(&user, verb, namespace, config) {
+            findings.push(finding(
+                "k8s_unusual_secret_read",
+                if denied { "MEDIUM" } else { "HIGH" },
+                0.75,
+                format!("{} {} secrets {}: {}", user, verb, object, reason),
+            ));
+        }
+    }
+    let anonymous = user == ANONYMOUS
+        || record
+            .attributes
+            .range("user.groups.".to_string()..)
+            .take_while(|(key, _)| key.starts_with("user.groups."))
+            .any(|(_, group)| group == UNAUTHENTICATED);
+    let public_probe = record
+        .attribute("requestURI")
+        .is_some_and(|uri| PUBLIC_PATHS.iter().any(|path| uri.split('?').next() == Some(*path)));
+    if anonymous && !public_probe {
+        findings.push(finding(
+            "k8s_anonymous_request",
+            if denied { "MEDIUM" } else { "HIGH" },
+            if denied { 0.6 } else { 0.85 },
+            format!(
+                "Anonymous {} {} from {}",
+                verb,
+                record.attribute("requestURI").unwrap_or(resource),
+                record.source_ip.as_deref().unwrap_or("unknown")
+            ),
+        ));
+    }
+
+    findings.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+    findings
+}
+
+// Privileged containers and hostPath volumes, in a pod or any workload's pod template
+fn privileged_settings(record: &LogRecord) -> Vec<String> {
+    let mut risks = Vec::new();
+    for (key, value) in record.attributes.range("requestObject.".to_string()..) {
+        if !key.starts_with("requestObject.") {
+            break;
+        }
+        let risk = if key.ends_with(".securityContext.privileged") && value == "true" {
+            Some("privileged container".to_string())
+        } else if key.ends_with(".hostPath.path") {
+            Some(format!("hostPath {}", value))
+        } else {
+            None
+        };
+        if let Some(risk) = risk.filter(|risk| !risks.contains(risk)) {
+            risks.push(risk);
+        }
+    }
+    risks
+}
+
+fn binding_subjects(record: &LogRecord) -> Vec<String> {
+    (0..)
+        .map_while(|index| {
+            let kind = record.attribute(&format!("requestObject.subjects.{}.kind", index))?;
+            let name = record
+                .attribute(&format!("requestObject.subjects.{}.name", index))
+                .unwrap_or_default();
+            Some(format!("{} {}", kind, name))
+        })
+        .collect()
+}
+
+// Service accounts not on the allowlist are unusual readers when they reach
+// outside their own namespace or enumerate secrets rather than fetch one
+fn unusual_secret_reader(user: &str, verb: &str, namespace: Option<&str>, config: &K8sAuditConfig) -> Option<String> {
+    let account = user.strip_prefix(SERVICE_ACCOUNT_PREFIX)?;
+    let allowed = config.secret_reader_allowlist.iter().any(|pattern| match pattern.strip_suffix('*') {
+        Some(prefix) => user.starts_with(prefix),
+        None => user == pattern,
+    });
+    if allowed {
+        return None;
+    }
+
+    let own_namespace = account.split(':').next().unwrap_or_default();
+    match namespace {
+        Some(namespace) if namespace != own_namespace => {
+            Some(format!("service account from namespace {} reading namespace {}", own_namespace, namespace))
+        }
+        None => Some("service account reading secrets across all namespaces".to_string()),
+        Some(_) if verb != "get" => Some(format!("service account enumerating secrets ({})", verb)),
+        Some(_) => None,
+    }
+}
+
+// Exec requests carry the command as repeated `command=` query parameters
+fn exec_command(uri: &str) -> Option<String> {
+    let query = uri.split_once('?')?.1;
+    let words: Vec<String> = query
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix("command="))
+        .map(percent_decode)
+        .collect();
+    (!words.is_empty()).then(|| words.join(" "))
+}
@@ -1,17 +1,65 @@
 use candid::{CandidType, Deserialize, Func, Principal};
+use cybersec_log_model::timestamp::TimestampConfig;
+use cybersec_log_model::LogRecord;
+pub use cybersec_log_model::{log_chain, SecurityLog};
 use num_traits::cast::ToPrimitive;
 use ic_cdk::api::management_canister::http_request::{
     HttpResponse, HttpHeader, HttpMethod, CanisterHttpRequestArgument,
 };
 use ic_cdk::api::call::call;
-use ic_cdk::{query, update};
+use ic_cdk::{init, query, update};
 use serde::{Serialize, Serializer};
 use serde_bytes;
 use std::cell::RefCell;
 use std::collections::HashMap;
-
-mod api;
+use std::time::Duration;
+
+pub mod api;
+pub mod archive;
+pub mod certified;
+pub mod cloudtrail;
+pub mod decode;
+pub mod detection;
+pub mod dns;
+pub mod email;
+pub mod event_time;
+pub mod flows;
+pub mod hash_ioc;
+pub mod k8s_audit;
+pub mod noise_reduction;
+pub mod persistence;
+pub mod retention;
+pub mod search;
+pub mod security_logs;
+pub mod snapshot;
+pub mod suricata;
+pub mod threat_stats;
+mod upload;
+pub mod url_risk;
 mod wallet;
+pub mod web_recon;
+pub mod windows_events;
+pub mod zeek;
+
+use archive::{ArchiveIndex, ArchiveOptions, ArchiveStatus, SecurityLogRange};
+use certified::{CertifiedSecurityLogs, CertifiedThreatSummary};
+use detection::NANOS_PER_SECOND;
+use dns::{DnsAnalyzer, DnsDetectorConfig, DnsScore};
+use event_time::{EventTimeConfig, EventTimeStats};
+use flows::{FlowAnalyzer, FlowDetectorConfig};
+use hash_ioc::{HashIoc, HashIocStats, HashIocStore, HashMatch};
+use k8s_audit::{K8sAuditConfig, K8sAuditDetector};
+use log_chain::{ChainHead, ChainProof, ChainVerification, Checkpoint};
+use noise_reduction::{NoiseReducer, NoiseReductionConfig, NoiseReductionStats};
+use retention::{EvictionSummary, RetentionConfig, RetentionLedger};
+use search::{SearchConfig, SearchHit, SearchIndex, SearchResults, SearchStats};
+use security_logs::{SecurityLogPage, SecurityLogQuery, SecurityLogStore};
+use snapshot::{SnapshotExportStatus, SnapshotImportStatus, SnapshotManifest};
+use threat_stats::{ThreatStats, ThreatStatsConfig, ThreatStatsPage, ThreatStatsQuery};
+use upload::{UploadDetection, UploadProgress};
+use url_risk::{UrlAnalysis, UrlRiskConfig};
+use web_recon::{WebReconConfig, WebReconDetector};
+use windows_events::FailedLogonDetector;
 
 // Define CallError if it's not in wallet module
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -28,12 +76,23 @@ pub struct ThreatInput {
     pub message: String,
 }
 
+impl From<ThreatInput> for LogRecord {
+    fn from(input: ThreatInput) -> Self {
+        LogRecord {
+            source: Some(input.source),
+            ..LogRecord::new(input.message)
+        }
+    }
+}
+
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct ThreatOutput {
     pub is_threat: bool,
     pub details: String,
     pub category: String,
     pub confidence: f64,
+    pub severity: String,
+    pub entity: Option<String>,
 }
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -57,14 +116,6 @@ pub struct CanisterSecurityCheck {
     pub risk_level: String,
 }
 
-#[derive(CandidType, Deserialize, Clone, Debug)]
-pub struct SecurityLog {
-    pub timestamp: u64,
-    pub event_type: String,
-    pub details: String,
-    pub severity: String,
-}
-
 #[derive(CandidType, Deserialize, Clone, Debug)]
 pub struct NetworkScan {
     pub target: String,
@@ -79,11 +130,30 @@ pub struct AnomalyDetectionResult {
     pub explanation: String,
 }
 
+const MAX_LOG_PAGE: u64 = 1_000;
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60);
+
 // Add thread_local variables
 thread_local! {
     static THREATS: RefCell<HashMap<String, u32>> = RefCell::new(HashMap::new());
-    static LOGS: RefCell<Vec<String>> = RefCell::new(Vec::new());
-    static SECURITY_LOGS: RefCell<Vec<SecurityLog>> = RefCell::new(Vec::new());
+    static LOGS: RefCell<Vec<LogRecord>> = RefCell::new(Vec::new());
+    static SECURITY_LOGS: RefCell<SecurityLogStore> = RefCell::new(SecurityLogStore::default());
+    static NOISE_REDUCER: RefCell<NoiseReducer> = RefCell::new(NoiseReducer::new(NoiseReductionConfig::default()));
+    static EVENT_TIME_CONFIG: RefCell<EventTimeConfig> = RefCell::new(EventTimeConfig::default());
+    static FAILED_LOGONS: RefCell<FailedLogonDetector> = RefCell::new(FailedLogonDetector::new(
+        EventTimeConfig::default().allowed_lateness_secs * NANOS_PER_SECOND,
+    ));
+    static DNS_ANALYZER: RefCell<DnsAnalyzer> = RefCell::new(DnsAnalyzer::default());
+    static WEB_RECON: RefCell<WebReconDetector> = RefCell::new(WebReconDetector::new(WebReconConfig::default()));
+    static K8S_AUDIT: RefCell<K8sAuditDetector> = RefCell::new(K8sAuditDetector::new(K8sAuditConfig::default()));
+    static HASH_IOCS: RefCell<HashIocStore> = RefCell::new(HashIocStore::default());
+    static RETENTION_CONFIG: RefCell<RetentionConfig> = RefCell::new(RetentionConfig::default());
+    static RETENTION_LEDGER: RefCell<RetentionLedger> = RefCell::new(RetentionLedger::default());
+    static URL_RISK_CONFIG: RefCell<UrlRiskConfig> = RefCell::new(UrlRiskConfig::default());
+    static FLOW_ANALYZER: RefCell<FlowAnalyzer> = RefCell::new(FlowAnalyzer::new(FlowDetectorConfig::default()));
+    static SEARCH_INDEX: RefCell<SearchIndex> = RefCell::new(SearchIndex::new(SearchConfig::default()));
+    static THREAT_STATS: RefCell<ThreatStats> = RefCell::new(ThreatStats::new(ThreatStatsConfig::default()));
+    static ARCHIVE: RefCell<ArchiveIndex> = RefCell::new(ArchiveIndex::new(ArchiveOptions::default()));
 }
 
 #[derive(CandidType, Deserialize, Clone, Debug)]
@@ -153,34 +223,185 @@ impl From<HttpResponse> for CustomHttpResponse {
     }
 }
 
+// Detections per category since the canister was installed
 #[query]
 fn get_threat_summary() -> HashMap<String, u32> {
     THREATS.with(|threats| threats.borrow().clone())
 }
 
+// Detection counts over time, for trend charts
+#[query]
+fn get_threat_stats(query: ThreatStatsQuery) -> Result<ThreatStatsPage, String> {
+    THREAT_STATS.with(|stats| stats.borrow().query(&query))
+}
+
+#[query]
+fn get_threat_stats_config() -> ThreatStatsConfig {
+    THREAT_STATS.with(|stats| stats.borrow().config().clone())
+}
+
+#[update]
+fn set_threat_stats_config(config: ThreatStatsConfig) -> Result<(), String> {
+    require_controller()?;
+    THREAT_STATS.with(|stats| stats.borrow_mut().set_config(config, ic_cdk::api::time()));
+    Ok(())
+}
+
+fn sorted_threat_summary() -> Vec<(String, u32)> {
+    let mut summary: Vec<(String, u32)> = THREATS.with(|threats| threats.borrow().clone()).into_iter().collect();
+    summary.sort();
+    summary
+}
+
+// Certified counterparts of get_security_logs and get_threat_summary; check
+// them with verify_security_logs and verify_threat_summary from cybersec_log_model's
+// `verify` feature
+#[query]
+fn get_certified_security_logs(from: u64, limit: u64) -> Result<CertifiedSecurityLogs, String> {
+    let certificate = ic_cdk::api::data_certificate().ok_or("Certificates are only available in query calls")?;
+    let limit = limit.clamp(1, MAX_LOG_PAGE) as usize;
+    let (logs, to, range) = SECURITY_LOGS.with(|logs| logs.borrow().certified_page(from, limit));
+    let summary_leaf = certified::threat_summary_leaf(&sorted_threat_summary());
+    Ok(CertifiedSecurityLogs {
+        from,
+        to,
+        logs,
+        certificate: serde_bytes::ByteBuf::from(certificate),
+        witness: certified::encode_witness(&certified::security_logs_witness(range, &summary_leaf))?,
+    })
+}
+
+#[query]
+fn get_certified_threat_summary() -> Result<CertifiedThreatSummary, String> {
+    let certificate = ic_cdk::api::data_certificate().ok_or("Certificates are only available in query calls")?;
+    let summary = sorted_threat_summary();
+    let summary_leaf = certified::threat_summary_leaf(&summary);
+    let logs_root = SECURITY_LOGS.with(|logs| logs.borrow().certified_root());
+    Ok(CertifiedThreatSummary {
+        summary,
+        certificate: serde_bytes::ByteBuf::from(certificate),
+        witness: certified::encode_witness(&certified::threat_summary_witness(&logs_root, &summary_leaf))?,
+    })
+}
+
+// Publishes the root hash over the security logs and threat summary; every
+// change to either must be followed by a call
+fn certify() {
+    let logs_root = SECURITY_LOGS.with(|logs| logs.borrow().certified_root());
+    let summary_leaf = certified::threat_summary_leaf(&sorted_threat_summary());
+    ic_cdk::api::set_certified_data(&certified::root_hash(&logs_root, &summary_leaf));
+}
+
+fn require_controller() -> Result<(), String> {
+    if ic_cdk::api::is_controller(&ic_cdk::caller()) {
+        Ok(())
+    } else {
+        Err("Only controllers of the canister can call this".to_string())
+    }
+}
+
+#[init]
+fn init() {
+    certify();
+    start_retention_timer();
+}
+
+// Timers do not survive upgrades, so post_upgrade calls this too
+fn start_retention_timer() {
+    ic_cdk_timers::set_timer_interval(RETENTION_INTERVAL, || {
+        enforce_retention();
+    });
+}
+
+// Evicted logs shift the indexes the noise reducer folds duplicates into,
+// so it forgets them; the search index drops the same records. Every run
+// that evicts anything leaves a summary. Nothing is evicted while a state
+// export is being encoded
+fn enforce_retention() -> EvictionSummary {
+    if snapshot::export_in_progress() {
+        return EvictionSummary::default();
+    }
+    let config = RETENTION_CONFIG.with(|config| config.borrow().clone());
+    let plan = LOGS.with(|logs| {
+        SECURITY_LOGS.with(|security_logs| {
+            let (mut logs, mut security_logs) = (logs.borrow_mut(), security_logs.borrow_mut());
+            let plan = RETENTION_LEDGER
+                .with(|ledger| ledger.borrow_mut().plan(&logs, &security_logs, &config, ic_cdk::api::time()));
+            plan.apply(&mut logs, &mut security_logs);
+            plan
+        })
+    });
+    SEARCH_INDEX.with(|index| index.borrow_mut().evict(&plan.logs));
+    THREAT_STATS.with(|stats| stats.borrow_mut().prune(ic_cdk::api::time()));
+    let summary = plan.summary;
+    if summary.security_logs > 0 {
+        certify();
+    }
+    if summary.logs > 0 {
+        NOISE_REDUCER.with(|reducer| reducer.borrow_mut().reset());
+    }
+    if summary.total() > 0 {
+        record_security_event("retention_eviction".to_string(), summary.describe(), "INFO".to_string(), None, None);
+    }
+    summary
+}
+
+#[update]
+fn run_retention() -> Result<EvictionSummary, String> {
+    require_controller()?;
+    Ok(enforce_retention())
+}
+
+#[query]
+fn get_retention_config() -> RetentionConfig {
+    RETENTION_CONFIG.with(|config| config.borrow().clone())
+}
+
+#[update]
+fn set_retention_config(config: RetentionConfig) -> Result<(), String> {
+    require_controller()?;
+    RETENTION_CONFIG.with(|current| *current.borrow_mut() = config);
+    Ok(())
+}
+
 #[update]
 fn clear_logs() {
     LOGS.with(|logs| logs.borrow_mut().clear());
+    NOISE_REDUCER.with(|reducer| reducer.borrow_mut().reset());
+    SEARCH_INDEX.with(|index| index.borrow_mut().clear());
+    RETENTION_LEDGER.with(|ledger| ledger.borrow_mut().clear_logs());
 }
 
 #[query]
 fn detect_threats(inputs: Vec<ThreatInput>) -> Vec<ThreatOutput> {
-    inputs.iter().map(|input| {
-        let is_threat = input.message.contains("attack")
-            || input.message.contains("exploit")
-            || input.message.contains("injection");
-
-        ThreatOutput {
-            is_threat,
-            details: if is_threat {
-                format!("Potential threat detected in message from {}", input.source)
-            } else {
-                "No threat detected".to_string()
-            },
-            category: if is_threat { "SECURITY".to_string() } else { "INFO".to_string() },
-            confidence: if is_threat { 0.85 } else { 0.95 },
-        }
-    }).collect()
+    inputs.into_iter().map(|input| classify_threat(&input.into())).collect()
+}
+
+#[query]
+fn detect_threats_in_records(records: Vec<LogRecord>) -> Vec<ThreatOutput> {
+    records.iter().map(classify_threat).collect()
+}
+
+fn classify_threat(record: &LogRecord) -> ThreatOutput {
+    let is_threat = record.message.contains("attack")
+        || record.message.contains("exploit")
+        || record.message.contains("injection");
+
+    ThreatOutput {
+        is_threat,
+        details: if is_threat {
+            format!(
+                "Potential threat detected in message from {}",
+                record.source.as_deref().unwrap_or("unknown")
+            )
+        } else {
+            "No threat detected".to_string()
+        },
+        category: if is_threat { "SECURITY".to_string() } else { "INFO".to_string() },
+        confidence: if is_threat { 0.85 } else { 0.95 },
+        severity: if is_threat { "HIGH".to_string() } else { "INFO".to_string() },
+        entity: None,
+    }
 }
 
 #[update]
@@ -270,19 +491,437 @@ async fn analyze_canister_security(canister_id: Principal) -> CanisterSecurityCh
 
 #[update]
 fn log_security_event(event_type: String, details: String, severity: String) {
+    record_security_event(event_type, details, severity, None, None);
+}
+
+fn record_security_event(
+    event_type: String,
+    details: String,
+    severity: String,
+    entity: Option<String>,
+    source: Option<String>,
+) {
     let log = SecurityLog {
         timestamp: ic_cdk::api::time(),
         event_type,
         details,
         severity,
+        entity,
+        source,
+        sequence: None,
+        hash: None,
     };
     
     SECURITY_LOGS.with(|logs| logs.borrow_mut().push(log));
+    certify();
+    archive::schedule();
+}
+
+// Counts a detection into the threat summary and statistics and records it
+// as a security event, which certifies the new summary too. `source` is the
+// producer of the record it came from, or the detector
+fn record_detection(detection: &ThreatOutput, source: &str) {
+    THREATS.with(|threats| *threats.borrow_mut().entry(detection.category.clone()).or_insert(0) += 1);
+    THREAT_STATS.with(|stats| {
+        stats
+            .borrow_mut()
+            .record(&detection.category, &detection.severity, source, ic_cdk::api::time())
+    });
+    record_security_event(
+        detection.category.clone(),
+        detection.details.clone(),
+        detection.severity.clone(),
+        detection.entity.clone(),
+        Some(source.to_string()),
+    );
+}
+
+// Every ingestion endpoint funnels its records through here: entities are
+// extracted from each message, each record is classified, threats are
+// recorded as security logs and the records are kept, minus repeats and
+// sampled-away noise. Returns the threats found in each record, in order.
+fn ingest_log_records(mut records: Vec<LogRecord>) -> Vec<Vec<ThreatOutput>> {
+    let now = ic_cdk::api::time();
+    let config = timestamp_config();
+    for record in &mut records {
+        record.resolve_timestamp(&config);
+        record.extract_entities();
+    }
+    let findings: Vec<Vec<ThreatOutput>> = DNS_ANALYZER.with(|dns| {
+        WEB_RECON.with(|web| {
+            let (mut dns, mut web) = (dns.borrow_mut(), web.borrow_mut());
+            records
+                .iter()
+                .map(|record| {
+                    let mut findings = classify_record(record);
+                    // Stateful detectors see every record, even ones already flagged, and
+                    // suppress what they return from then on, so each of their findings is kept
+                    findings.extend(dns.observe(record, now));
+                    findings.extend(web.observe(record, now));
+                    findings
+                })
+                .collect()
+        })
+    });
+
+    for (record, findings) in records.iter().zip(&findings) {
+        for output in findings {
+            record_detection(output, record.source.as_deref().unwrap_or("unknown"));
+        }
+    }
+
+    LOGS.with(|logs| {
+        NOISE_REDUCER.with(|reducer| {
+            let mut logs = logs.borrow_mut();
+            let mut reducer = reducer.borrow_mut();
+            for (record, findings) in records.into_iter().zip(&findings) {
+                reducer.admit(record, !findings.is_empty(), now, &mut logs);
+            }
+            SEARCH_INDEX.with(|index| index.borrow_mut().sync(&logs));
+        })
+    });
+
+    findings
+}
+
+// Sensor-specific detections take precedence over the generic keyword check.
+// Hash and URL indicators can turn up in any record, so they are always checked
+fn classify_record(record: &LogRecord) -> Vec<ThreatOutput> {
+    let mut findings: Vec<ThreatOutput> = suricata::alert_detection(record)
+        .or_else(|| zeek::notice_detection(record))
+        .or_else(|| cloudtrail::detection(record))
+        .or_else(|| K8S_AUDIT.with(|detector| detector.borrow_mut().detection(record)))
+        .into_iter()
+        .collect();
+    findings.extend(HASH_IOCS.with(|store| hash_ioc::detection(record, &store.borrow())));
+    findings.extend(URL_RISK_CONFIG.with(|config| url_risk::detection(record, &config.borrow())));
+    if findings.is_empty() {
+        let output = classify_threat(record);
+        if output.is_threat {
+            findings.push(output);
+        }
+    }
+    findings
+}
+
+fn threats_only(findings: Vec<Vec<ThreatOutput>>) -> Vec<ThreatOutput> {
+    findings.into_iter().flatten().collect()
+}
+
+#[update]
+fn ingest_logs(records: Vec<LogRecord>) -> Vec<ThreatOutput> {
+    threats_only(ingest_log_records(records))
+}
+
+#[update]
+fn ingest_suricata_eve(text: String) -> Result<Vec<ThreatOutput>, String> {
+    let records = suricata::parse_eve(&text)?;
+    Ok(threats_only(ingest_log_records(records)))
+}
+
+// Accepts a single event, the S3 `Records` envelope, a JSON array or JSON lines
+#[update]
+fn ingest_cloudtrail(text: String) -> Result<Vec<ThreatOutput>, String> {
+    let records = cloudtrail::parse_cloudtrail(&text)?;
+    Ok(threats_only(ingest_log_records(records)))
+}
+
+// Accepts audit events at any level, as JSON lines, an array or an EventList
+#[update]
+fn ingest_k8s_audit(text: String) -> Result<Vec<ThreatOutput>, String> {
+    let records = k8s_audit::parse_audit_log(&text)?;
+    Ok(threats_only(ingest_log_records(records)))
+}
+
+#[query]
+fn get_k8s_audit_config() -> K8sAuditConfig {
+    K8S_AUDIT.with(|detector| detector.borrow().config().clone())
+}
+
+#[update]
+fn set_k8s_audit_config(config: K8sAuditConfig) -> Result<(), String> {
+    require_controller()?;
+    K8S_AUDIT.with(|detector| detector.borrow_mut().set_config(config));
+    Ok(())
+}
+
+// Hashes are checked against every ingested record's message and attributes
+#[update]
+fn register_hashes(iocs: Vec<HashIoc>) -> Result<u64, String> {
+    require_controller()?;
+    HASH_IOCS.with(|store| store.borrow_mut().register(&iocs))
+}
+
+#[update]
+fn remove_hashes(hashes: Vec<String>) -> Result<u64, String> {
+    require_controller()?;
+    Ok(HASH_IOCS.with(|store| store.borrow_mut().remove(&hashes)))
+}
+
+#[query]
+fn lookup_hashes(hashes: Vec<String>) -> Vec<HashMatch> {
+    HASH_IOCS.with(|store| {
+        let store = store.borrow();
+        hashes.iter().filter_map(|hash| store.lookup(hash)).collect()
+    })
+}
+
+#[query]
+fn get_hash_ioc_stats() -> HashIocStats {
+    HASH_IOCS.with(|store| store.borrow().stats())
+}
+
+#[query]
+fn analyze_url(url: String) -> Result<UrlAnalysis, String> {
+    URL_RISK_CONFIG.with(|config| url_risk::analyze_url(&url, &config.borrow()))
+}
+
+#[query]
+fn get_url_risk_config() -> UrlRiskConfig {
+    URL_RISK_CONFIG.with(|config| config.borrow().clone())
+}
+
+#[update]
+fn set_url_risk_config(config: UrlRiskConfig) -> Result<(), String> {
+    require_controller()?;
+    URL_RISK_CONFIG.with(|current| *current.borrow_mut() = config);
+    Ok(())
+}
+
+// Apache/nginx access logs in the Common or Combined Log Format
+#[update]
+fn ingest_access_log(text: String) -> Result<Vec<ThreatOutput>, String> {
+    let records = web_recon::parse_access_log(&text, &timestamp_config())?;
+    Ok(threats_only(ingest_log_records(records)))
+}
+
+#[query]
+fn get_web_recon_config() -> WebReconConfig {
+    WEB_RECON.with(|detector| detector.borrow().config().clone())
+}
+
+#[update]
+fn set_web_recon_config(config: WebReconConfig) -> Result<(), String> {
+    require_controller()?;
+    WEB_RECON.with(|detector| detector.borrow_mut().set_config(config));
+    Ok(())
+}
+
+// Judges a raw RFC 5322 message; phishing verdicts are recorded as security events
+#[update]
+fn analyze_email(raw: String) -> Result<ThreatOutput, String> {
+    let message = email::parse_message(&raw)?;
+    let verdict = email::analyze(&message);
+    if verdict.is_threat {
+        record_detection(&verdict, "email");
+    }
+    Ok(verdict)
+}
+
+// `path` names the log type (conn, dns, http, ssl, notice) for JSON logs without `_path`
+#[update]
+fn ingest_zeek_log(text: String, path: Option<String>) -> Result<Vec<ThreatOutput>, String> {
+    let records = zeek::parse_log(&text, path.as_deref(), &timestamp_config())?;
+    Ok(threats_only(ingest_log_records(records)))
+}
+
+// Parses exported Windows Security events and records every detection as a security log
+#[update]
+fn ingest_windows_events(xml: String) -> Result<Vec<ThreatOutput>, String> {
+    let events = windows_events::parse_events(&xml)?;
+    let now = ic_cdk::api::time();
+    let detections = FAILED_LOGONS.with(|detector| detector.borrow_mut().detect(&events, now));
+
+    for detection in &detections {
+        record_detection(detection, "windows_events");
+    }
+
+    let records = events.iter().map(LogRecord::from).collect();
+    let mut results = detections;
+    results.extend(threats_only(ingest_log_records(records)));
+    Ok(results)
+}
+
+// Accepts flow records as CSV with a header row, JSON lines or a JSON array
+#[update]
+fn ingest_flows(text: String) -> Result<Vec<ThreatOutput>, String> {
+    let flows = flows::parse_flows(&text, &timestamp_config())?;
+    let detections = FLOW_ANALYZER.with(|analyzer| analyzer.borrow_mut().analyze(&flows, ic_cdk::api::time()));
+
+    for detection in &detections {
+        record_detection(detection, flows::SOURCE);
+    }
+
+    let records = flows.iter().map(LogRecord::from).collect();
+    let mut results = detections;
+    results.extend(threats_only(ingest_log_records(records)));
+    Ok(results)
+}
+
+#[query]
+fn get_flow_detector_config() -> FlowDetectorConfig {
+    FLOW_ANALYZER.with(|analyzer| analyzer.borrow().config().clone())
+}
+
+#[update]
+fn set_flow_detector_config(config: FlowDetectorConfig) -> Result<(), String> {
+    require_controller()?;
+    FLOW_ANALYZER.with(|analyzer| analyzer.borrow_mut().set_config(config));
+    Ok(())
+}
+
+// Hosts and ports seen answering in ingested flows, for the NetworkScan view
+#[query]
+fn get_flow_network_view(offset: u64, limit: u64) -> Vec<NetworkScan> {
+    FLOW_ANALYZER.with(|analyzer| analyzer.borrow().network_view(offset, limit))
+}
+
+// DGA likelihood of a single domain, for triage from the UI
+#[query]
+fn score_domain(domain: String) -> DnsScore {
+    dns::dga_score(&domain)
+}
+
+#[query]
+fn get_dns_detector_config() -> DnsDetectorConfig {
+    DNS_ANALYZER.with(|analyzer| analyzer.borrow().config().clone())
+}
+
+#[update]
+fn set_dns_detector_config(config: DnsDetectorConfig) -> Result<(), String> {
+    require_controller()?;
+    DNS_ANALYZER.with(|analyzer| analyzer.borrow_mut().set_config(config));
+    Ok(())
+}
+
+#[query]
+fn get_noise_reduction_config() -> NoiseReductionConfig {
+    NOISE_REDUCER.with(|reducer| reducer.borrow().config().clone())
+}
+
+#[update]
+fn set_noise_reduction_config(config: NoiseReductionConfig) -> Result<(), String> {
+    require_controller()?;
+    NOISE_REDUCER.with(|reducer| reducer.borrow_mut().set_config(config));
+    Ok(())
+}
+
+#[query]
+fn get_noise_reduction_stats() -> NoiseReductionStats {
+    NOISE_REDUCER.with(|reducer| reducer.borrow().stats())
+}
+
+#[query]
+fn get_log_records(offset: u64, limit: u64) -> Vec<LogRecord> {
+    LOGS.with(|logs| {
+        logs.borrow()
+            .iter()
+            .skip(offset as usize)
+            .take(limit.min(MAX_LOG_PAGE) as usize)
+            .cloned()
+            .collect()
+    })
+}
+
+// Full-text search over stored records, e.g. `"failed password" AND (root OR admin*) -cron`
+#[query]
+fn search_logs(query: String, offset: u64, limit: u64) -> Result<SearchResults, String> {
+    let positions = SEARCH_INDEX.with(|index| index.borrow().search(&query))?;
+    LOGS.with(|logs| {
+        let logs = logs.borrow();
+        let hits = positions
+            .iter()
+            .rev()
+            .skip(offset as usize)
+            .take(limit.min(MAX_LOG_PAGE) as usize)
+            .map(|&position| SearchHit {
+                offset: position as u64,
+                record: logs[position].clone(),
+            })
+            .collect();
+        Ok(SearchResults {
+            total: positions.len() as u64,
+            hits,
+        })
+    })
+}
+
+#[query]
+fn get_search_config() -> SearchConfig {
+    SEARCH_INDEX.with(|index| index.borrow().config().clone())
+}
+
+#[update]
+fn set_search_config(config: SearchConfig) -> Result<(), String> {
+    require_controller()?;
+    SEARCH_INDEX.with(|index| index.borrow_mut().set_config(config));
+    Ok(())
+}
+
+#[query]
+fn get_search_stats() -> SearchStats {
+    SEARCH_INDEX.with(|index| index.borrow().stats())
+}
+
+// How timestamps in ingested logs are read, per the event time config
+fn timestamp_config() -> TimestampConfig {
+    EVENT_TIME_CONFIG.with(|config| config.borrow().timestamp_config(ic_cdk::api::time()))
+}
+
+#[query]
+fn get_event_time_config() -> EventTimeConfig {
+    EVENT_TIME_CONFIG.with(|config| config.borrow().clone())
+}
+
+#[update]
+fn set_event_time_config(config: EventTimeConfig) -> Result<(), String> {
+    require_controller()?;
+    let lateness_ns = config.allowed_lateness_secs.saturating_mul(NANOS_PER_SECOND);
+    FAILED_LOGONS.with(|detector| detector.borrow_mut().set_allowed_lateness(lateness_ns));
+    DNS_ANALYZER.with(|analyzer| analyzer.borrow_mut().set_allowed_lateness(lateness_ns));
+    EVENT_TIME_CONFIG.with(|current| *current.borrow_mut() = config);
+    Ok(())
+}
+
+// Watermark and too-late drops of the windowed detectors
+#[query]
+fn get_event_time_stats() -> EventTimeStats {
+    let failed_logons = FAILED_LOGONS.with(|detector| detector.borrow().stats());
+    let nxdomains = DNS_ANALYZER.with(|analyzer| analyzer.borrow().event_time_stats());
+    failed_logons.merge(&nxdomains)
 }
 
 #[query]
 fn get_security_logs() -> Vec<SecurityLog> {
-    SECURITY_LOGS.with(|logs| logs.borrow().clone())
+    SECURITY_LOGS.with(|logs| logs.borrow().iter().cloned().collect())
+}
+
+// Checks stored security logs `from..=to` (sequence numbers) against their chain hashes
+#[query]
+fn verify_log_chain(from: u64, to: u64) -> Result<ChainVerification, String> {
+    SECURITY_LOGS.with(|logs| logs.borrow().verify(from, to))
+}
+
+// Entries and hashes to check `from..=to` offline against a published head or checkpoint
+#[query]
+fn export_log_chain_proof(from: u64, to: u64) -> Result<ChainProof, String> {
+    SECURITY_LOGS.with(|logs| logs.borrow().proof(from, to))
+}
+
+#[query]
+fn get_log_chain_head() -> ChainHead {
+    SECURITY_LOGS.with(|logs| logs.borrow().head())
+}
+
+#[query]
+fn get_log_chain_checkpoints() -> Vec<Checkpoint> {
+    SECURITY_LOGS.with(|logs| logs.borrow().checkpoints().to_vec())
+}
+
+// Filters by time range, severity, event type, entity and text, a page at a time
+#[query]
+fn query_security_logs(query: SecurityLogQuery) -> Result<SecurityLogPage, String> {
+    SECURITY_LOGS.with(|logs| logs.borrow().query(&query))
 }
 
 #[update]
@@ -325,3 +964,10 @@ fn detect_anomalies(data_points: Vec<f64>) -> AnomalyDetectionResult {
 }
 
 ic_cdk::export_candid!();
+
+// The tests under tests/ name the crate as an integration test would
+#[cfg(test)]
+extern crate self as cybersec_ai_agent_backend;
+#[cfg(test)]
+#[path = "../tests/mod.rs"]
+mod tests;
@@ -0,0 +1,220 @@
+use crate::detection::NANOS_PER_SECOND;
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::LogRecord;
+use std::collections::HashMap;
+
+// Attributes set on a record once duplicates have been folded into it
+pub const OCCURRENCES: &str = "dedup.occurrences";
+pub const FIRST_SEEN: &str = "dedup.first_seen";
+pub const LAST_SEEN: &str = "dedup.last_seen";
+// Set on records kept while their source was being sampled: one kept per N seen
+pub const SAMPLE_RATE: &str = "sample.rate";
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct NoiseReductionConfig {
+    /// Identical messages (after masking) within this many seconds collapse into one record; 0 disables
+    pub dedup_window_secs: u64,
+    /// Low-severity records a source may send per minute before sampling starts; 0 disables
+    pub source_quota_per_minute: u64,
+}
+
+impl Default for NoiseReductionConfig {
+    fn default() -> Self {
+        NoiseReductionConfig {
+            dedup_window_secs: 60,
+            source_quota_per_minute: 1_000,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct NoiseReductionStats {
+    pub deduplicated: u64,
+    pub sampled_out: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct DedupEntry {
+    index: usize,
+    first_seen: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+struct SourceQuota {
+    window_start: u64,
+    seen: u64,
+}
+
+/// Collapses repeated messages and samples chatty sources as records are stored.
+#[derive(CandidType, Deserialize, Default)]
+pub struct NoiseReducer {
+    config: NoiseReductionConfig,
+    stats: NoiseReductionStats,
+    recent: HashMap<String, DedupEntry>,
+    last_pruned: u64,
+    quotas: HashMap<String, SourceQuota>,
+}
+
+impl NoiseReducer {
+    pub fn new(config: NoiseReductionConfig) -> Self {
+        NoiseReducer {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &NoiseReductionConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: NoiseReductionConfig) {
+        self.config = config;
+    }
+
+    pub fn stats(&self) -> NoiseReductionStats {
+        self.stats.clone()
+    }
+
+    /// Forgets which stored records duplicates would fold into, e.g. after the store was cleared.
+    pub fn reset(&mut self) {
+        self.recent.clear();
+        self.quotas.clear();
+    }
+
+    /// Stores `record` in `logs` unless it folds into a recent duplicate or is
+    /// sampled away. Records a detector `flagged` are never sampled, and are
+    /// kept apart from their duplicates so each keeps its own IOCs and entities.
+    pub fn admit(&mut self, mut record: LogRecord, flagged: bool, now: u64, logs: &mut Vec<LogRecord>) {
+        let dedup_window = if flagged {
+            0
+        } else {
+            self.config.dedup_window_secs.saturating_mul(NANOS_PER_SECOND)
+        };
+        let fingerprint = fingerprint(&record);
+
+        if dedup_window > 0 {
+            if now.saturating_sub(self.last_pruned) >= dedup_window {
+                self.recent.retain(|_, entry| now.saturating_sub(entry.first_seen) <= dedup_window);
+                self.last_pruned = now;
+            }
+            let recent = self
+                .recent
+                .get(&fingerprint)
+                .filter(|entry| now.saturating_sub(entry.first_seen) <= dedup_window);
+            if let Some(entry) = recent {
+                if let Some(existing) = logs.get_mut(entry.index) {
+                    let occurrences = existing
+                        .attribute(OCCURRENCES)
+                        .and_then(|count| count.parse::<u64>().ok())
+                        .unwrap_or(1);
+                    existing.attributes.insert(OCCURRENCES.to_string(), (occurrences + 1).to_string());
+                    existing
+                        .attributes
+                        .entry(FIRST_SEEN.to_string())
+                        .or_insert_with(|| entry.first_seen.to_string());
+                    existing.attributes.insert(LAST_SEEN.to_string(), now.to_string());
+                    self.stats.deduplicated += 1;
+                    return;
+                }
+            }
+        }
+
+        if !flagged && is_low_severity(&record) {
+            if let Some(rate) = self.sample_rate(&record, now) {
+                if rate == 0 {
+                    self.stats.sampled_out += 1;
+                    return;
+                }
+                record.attributes.insert(SAMPLE_RATE.to_string(), rate.to_string());
+            }
+        }
+
+        if dedup_window > 0 {
+            self.recent.insert(
+                fingerprint,
+                DedupEntry {
+                    index: logs.len(),
+                    first_seen: now,
+                },
+            );
+        }
+        logs.push(record);
+    }
+
+    // None while the source is under quota; once over, Some(n) keeps this
+    // record as one of every n, and Some(0) drops it. n grows with the
+    // overshoot, so the stored volume stays near the quota however loud the source gets.
+    fn sample_rate(&mut self, record: &LogRecord, now: u64) -> Option<u64> {
+        let quota = self.config.source_quota_per_minute;
+        if quota == 0 {
+            return None;
+        }
+
+        let source = record.source.clone().unwrap_or_default();
+        let counter = self.quotas.entry(source).or_insert(SourceQuota {
+            window_start: now,
+            seen: 0,
+        });
+        if now.saturating_sub(counter.window_start) >= 60 * NANOS_PER_SECOND {
+            counter.window_start = now;
+            counter.seen = 0;
+        }
+        counter.seen += 1;
+
+        if counter.seen <= quota {
+            return None;
+        }
+        let rate = counter.seen.div_ceil(quota);
+        Some(if (counter.seen - quota).is_multiple_of(rate) { rate } else { 0 })
+    }
+}
+
+fn is_low_severity(record: &LogRecord) -> bool {
+    match record.severity.as_deref() {
+        None => true,
+        Some(severity) => ["debug", "info", "informational", "notice", "low"]
+            .contains(&severity.to_ascii_lowercase().as_str()),
+    }
+}
+
+// Source plus the masked message, so "user 1042 logged in" and "user 7 logged in" match
+fn fingerprint(record: &LogRecord) -> String {
+    format!(
+        "{}\u{1f}{}",
+        record.source.as_deref().unwrap_or_default(),
+        mask_message(&record.message)
+    )
+}
+
+/// Replaces numbers and ID-like tokens in `message` with placeholders:
+/// all-digit tokens become `#`, other tokens of four or more characters
+/// that contain a digit (hex IDs, UUID parts, session keys) become `*`.
+pub fn mask_message(message: &str) -> String {
+    let mut masked = String::with_capacity(message.len());
+    let mut token = String::new();
+
+    let flush = |token: &mut String, masked: &mut String| {
+        if token.is_empty() {
+            return;
+        }
+        if token.chars().all(|c| c.is_ascii_digit()) {
+            masked.push('#');
+        } else if token.len() >= 4 && token.chars().any(|c| c.is_ascii_digit()) {
+            masked.push('*');
+        } else {
+            masked.push_str(token);
+        }
+        token.clear();
+    };
+
+    for c in message.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            token.push(c);
+        } else {
+            flush(&mut token, &mut masked);
+            masked.push(c);
+        }
+    }
+    flush(&mut token, &mut masked);
+    masked
+}
@@ -0,0 +1,507 @@
+use crate::archive::ArchiveIndex;
+use crate::detection::NANOS_PER_SECOND;
+use crate::dns::DnsAnalyzer;
+use crate::event_time::EventTimeConfig;
+use crate::flows::{FlowAnalyzer, FlowDetectorConfig};
+use crate::hash_ioc::HashIocStore;
+use crate::k8s_audit::{K8sAuditConfig, K8sAuditDetector};
+use crate::log_chain::ChainState;
+use crate::noise_reduction::{NoiseReducer, NoiseReductionConfig};
+use crate::retention::{RetentionConfig, RetentionLedger};
+use crate::search::{SearchConfig, SearchIndex};
+use crate::security_logs::SecurityLogStore;
+use crate::threat_stats::ThreatStats;
+use crate::upload::{self, UploadSnapshot, UploadSnapshotRef};
+use crate::url_risk::UrlRiskConfig;
+use crate::wallet::WALLET_STORE;
+use crate::web_recon::{WebReconConfig, WebReconDetector};
+use crate::windows_events::FailedLogonDetector;
+use crate::SecurityLog;
+use crate::{
+    ARCHIVE, DNS_ANALYZER, EVENT_TIME_CONFIG, FAILED_LOGONS, FLOW_ANALYZER, HASH_IOCS, K8S_AUDIT, LOGS,
+    NOISE_REDUCER, RETENTION_CONFIG, RETENTION_LEDGER, SEARCH_INDEX, SECURITY_LOGS, THREATS, THREAT_STATS,
+    URL_RISK_CONFIG, WEB_RECON,
+};
+use candid::types::{Compound, Serializer, Type};
+use candid::{CandidType, Deserialize, Principal};
+use cybersec_log_model::LogRecord;
+use ic_cdk::api::stable::{
+    BufferedStableReader, BufferedStableWriter, CanisterStableMemory, StableMemory, StableReader, StableWriter,
+};
+use ic_cdk::{post_upgrade, pre_upgrade};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::thread::LocalKey;
+
+/// Everything the canister keeps, in layout version 1.
+#[derive(CandidType, Deserialize)]
+pub struct StateV1 {
+    pub threats: HashMap<String, u32>,
+    pub logs: Vec<LogRecord>,
+    pub security_logs: Vec<SecurityLog>,
+    pub wallets: HashMap<Principal, String>,
+    pub hash_iocs: HashIocStore,
+    pub noise_reducer: NoiseReducer,
+    pub event_time_config: EventTimeConfig,
+    pub failed_logons: FailedLogonDetector,
+    pub dns_analyzer: DnsAnalyzer,
+    pub web_recon: WebReconDetector,
+    pub k8s_audit: K8sAuditDetector,
+    pub url_risk_config: UrlRiskConfig,
+    pub flow_analyzer: FlowAnalyzer,
+    pub uploads: UploadSnapshot,
+    pub retention_config: RetentionConfig,
+    /// The search index itself is rebuilt from `logs` on restore
+    pub search_config: SearchConfig,
+    pub security_log_chain: ChainState,
+    pub threat_stats: ThreatStats,
+    pub archive: ArchiveIndex,
+}
+
+// The state of a freshly installed canister
+impl Default for StateV1 {
+    fn default() -> Self {
+        StateV1 {
+            threats: HashMap::new(),
+            logs: Vec::new(),
+            security_logs: Vec::new(),
+            wallets: HashMap::new(),
+            hash_iocs: HashIocStore::default(),
+            noise_reducer: NoiseReducer::new(NoiseReductionConfig::default()),
+            event_time_config: EventTimeConfig::default(),
+            failed_logons: FailedLogonDetector::new(
+                EventTimeConfig::default().allowed_lateness_secs * NANOS_PER_SECOND,
+            ),
+            dns_analyzer: DnsAnalyzer::default(),
+            web_recon: WebReconDetector::new(WebReconConfig::default()),
+            k8s_audit: K8sAuditDetector::new(K8sAuditConfig::default()),
+            url_risk_config: UrlRiskConfig::default(),
+            flow_analyzer: FlowAnalyzer::new(FlowDetectorConfig::default()),
+            uploads: UploadSnapshot::default(),
+            retention_config: RetentionConfig::default(),
+            search_config: SearchConfig::default(),
+            security_log_chain: ChainState::default(),
+            threat_stats: ThreatStats::default(),
+            archive: ArchiveIndex::default(),
+        }
+    }
+}
+
+/// What stable memory holds across an upgrade. A later layout is added as a
+/// new variant, and older ones are converted to it on restore.
+#[derive(CandidType, Deserialize)]
+pub enum StableState {
+    V1(StateV1),
+}
+
+fn take<T>(key: &'static LocalKey<RefCell<T>>, empty: T) -> T {
+    key.with(|cell| cell.replace(empty))
+}
+
+/// Moves the live state out, leaving a freshly installed canister behind.
+pub fn take_state() -> StateV1 {
+    let empty = StateV1::default();
+    let (security_logs, security_log_chain) = take(&SECURITY_LOGS, SecurityLogStore::default()).into_parts();
+    StateV1 {
+        threats: take(&THREATS, empty.threats),
+        logs: take(&LOGS, empty.logs),
+        security_logs,
+        wallets: take(&WALLET_STORE, empty.wallets),
+        hash_iocs: take(&HASH_IOCS, empty.hash_iocs),
+        noise_reducer: take(&NOISE_REDUCER, empty.noise_reducer),
+        event_time_config: take(&EVENT_TIME_CONFIG, empty.event_time_config),
+        failed_logons: take(&FAILED_LOGONS, empty.failed_logons),
+        dns_analyzer: take(&DNS_ANALYZER, empty.dns_analyzer),
+        web_recon: take(&WEB_RECON, empty.web_recon),
+        k8s_audit: take(&K8S_AUDIT, empty.k8s_audit),
+        url_risk_config: take(&URL_RISK_CONFIG, empty.url_risk_config),
+        flow_analyzer: take(&FLOW_ANALYZER, empty.flow_analyzer),
+        uploads: upload::take_snapshot(),
+        retention_config: take(&RETENTION_CONFIG, empty.retention_config),
+        search_config: take(&SEARCH_INDEX, SearchIndex::default()).config().clone(),
+        security_log_chain,
+        threat_stats: take(&THREAT_STATS, empty.threat_stats),
+        archive: take(&ARCHIVE, empty.archive),
+    }
+}
+
+/// Replaces the live state with `state`.
+pub fn install(state: StateV1) {
+    THREATS.with(|cell| *cell.borrow_mut() = state.threats);
+    SEARCH_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        index.set_config(state.search_config);
+        index.rebuild(&state.logs);
+    });
+    LOGS.with(|cell| *cell.borrow_mut() = state.logs);
+    SECURITY_LOGS.with(|cell| {
+        *cell.borrow_mut() = SecurityLogStore::restore(state.security_logs, state.security_log_chain)
+    });
+    WALLET_STORE.with(|cell| *cell.borrow_mut() = state.wallets);
+    HASH_IOCS.with(|cell| *cell.borrow_mut() = state.hash_iocs);
+    NOISE_REDUCER.with(|cell| *cell.borrow_mut() = state.noise_reducer);
+    EVENT_TIME_CONFIG.with(|cell| *cell.borrow_mut() = state.event_time_config);
+    FAILED_LOGONS.with(|cell| *cell.borrow_mut() = state.failed_logons);
+    DNS_ANALYZER.with(|cell| *cell.borrow_mut() = state.dns_analyzer);
+    WEB_RECON.with(|cell| *cell.borrow_mut() = state.web_recon);
+    K8S_AUDIT.with(|cell| *cell.borrow_mut() = state.k8s_audit);
+    URL_RISK_CONFIG.with(|cell| *cell.borrow_mut() = state.url_risk_config);
+    FLOW_ANALYZER.with(|cell| *cell.borrow_mut() = state.flow_analyzer);
+    upload::restore_snapshot(state.uploads);
+    RETENTION_CONFIG.with(|cell| *cell.borrow_mut() = state.retention_config);
+    // The next retention run works the ledger out again from the stores
+    RETENTION_LEDGER.with(|cell| *cell.borrow_mut() = RetentionLedger::default());
+    THREAT_STATS.with(|cell| *cell.borrow_mut() = state.threat_stats);
+    ARCHIVE.with(|cell| *cell.borrow_mut() = state.archive);
+}
+
+// Runs `$body` with each name bound to a shared borrow of its thread-local
+macro_rules! with_borrowed {
+    ([] $body:expr) => {
+        $body
+    };
+    ([$name:ident = $key:expr $(, $names:ident = $keys:expr)*] $body:expr) => {
+        $key.with(|cell| {
+            let borrowed = cell.borrow();
+            let $name = &*borrowed;
+            with_borrowed!([$($names = $keys),*] $body)
+        })
+    };
+}
+
+// Encodes like `Vec<SecurityLog>`, in sequence order; `None` as an empty list
+#[derive(Clone, Copy)]
+struct SecurityLogsRef<'a>(Option<&'a SecurityLogStore>);
+
+impl CandidType for SecurityLogsRef<'_> {
+    fn _ty() -> Type {
+        <Vec<SecurityLog>>::ty()
+    }
+
+    fn idl_serialize<S: Serializer>(&self, serializer: S) -> Result<(), S::Error> {
+        let mut list = serializer.serialize_vec(self.0.map_or(0, SecurityLogStore::len))?;
+        for log in self.0.iter().flat_map(|store| store.iter()) {
+            Compound::serialize_element(&mut list, log)?;
+        }
+        Ok(())
+    }
+}
+
+// Encodes like `StateV1`, borrowing the live state
+#[derive(CandidType, Clone, Copy)]
+struct StateV1Ref<'a> {
+    threats: &'a HashMap<String, u32>,
+    logs: &'a [LogRecord],
+    security_logs: SecurityLogsRef<'a>,
+    wallets: &'a HashMap<Principal, String>,
+    hash_iocs: &'a HashIocStore,
+    noise_reducer: &'a NoiseReducer,
+    event_time_config: &'a EventTimeConfig,
+    failed_logons: &'a FailedLogonDetector,
+    dns_analyzer: &'a DnsAnalyzer,
+    web_recon: &'a WebReconDetector,
+    k8s_audit: &'a K8sAuditDetector,
+    url_risk_config: &'a UrlRiskConfig,
+    flow_analyzer: &'a FlowAnalyzer,
+    uploads: UploadSnapshotRef<'a>,
+    retention_config: &'a RetentionConfig,
+    search_config: &'a SearchConfig,
+    security_log_chain: &'a ChainState,
+    threat_stats: &'a ThreatStats,
+    archive: &'a ArchiveIndex,
+}
+
+impl StateV1Ref<'_> {
+    // Everything but the logs, which pre_upgrade writes in chunks after it
+    fn without_logs(self) -> Self {
+        StateV1Ref {
+            logs: &[],
+            security_logs: SecurityLogsRef(None),
+            ..self
+        }
+    }
+}
+
+// Encodes like `StableState` without moving the state
+#[derive(CandidType)]
+enum StableStateRef<'a> {
+    V1(StateV1Ref<'a>),
+}
+
+// Lends the live state to `f` as it would be persisted, without moving it
+fn with_state_ref<R>(f: impl FnOnce(StateV1Ref) -> R) -> R {
+    with_borrowed!([
+        threats = THREATS,
+        logs = LOGS,
+        security_logs = SECURITY_LOGS,
+        wallets = WALLET_STORE,
+        hash_iocs = HASH_IOCS,
+        noise_reducer = NOISE_REDUCER,
+        event_time_config = EVENT_TIME_CONFIG,
+        failed_logons = FAILED_LOGONS,
+        dns_analyzer = DNS_ANALYZER,
+        web_recon = WEB_RECON,
+        k8s_audit = K8S_AUDIT,
+        url_risk_config = URL_RISK_CONFIG,
+        flow_analyzer = FLOW_ANALYZER,
+        retention_config = RETENTION_CONFIG,
+        search_index = SEARCH_INDEX,
+        threat_stats = THREAT_STATS,
+        archive = ARCHIVE
+    ] {
+        let security_log_chain = security_logs.chain_state();
+        upload::with_snapshot_ref(|uploads| {
+            f(StateV1Ref {
+                threats,
+                logs,
+                security_logs: SecurityLogsRef(Some(security_logs)),
+                wallets,
+                hash_iocs,
+                noise_reducer,
+                event_time_config,
+                failed_logons,
+                dns_analyzer,
+                web_recon,
+                k8s_audit,
+                url_risk_config,
+                flow_analyzer,
+                uploads,
+                retention_config,
+                search_config: search_index.config(),
+                security_log_chain: &security_log_chain,
+                threat_stats,
+                archive,
+            })
+        })
+    })
+}
+
+/// Serializes the live state in one piece, as `decode_state` reads it,
+/// without moving it out of the canister.
+pub fn encode_state() -> Result<Vec<u8>, String> {
+    with_state_ref(|state| encode(&StableStateRef::V1(state)))
+}
+
+fn encode<T: CandidType>(value: &T) -> Result<Vec<u8>, String> {
+    candid::encode_one(value).map_err(|e| format!("Failed to encode state: {}", e))
+}
+
+/// Reads state written by `encode_state`, converted to the current layout.
+pub fn decode_state(bytes: &[u8]) -> Result<StateV1, String> {
+    match candid::decode_one(bytes).map_err(|e| format!("Failed to decode state: {}", e))? {
+        StableState::V1(state) => Ok(state),
+    }
+}
+
+// What pre_upgrade leaves in stable memory: STABLE_MAGIC, then the state
+// without its logs, then the logs and the security logs in chunks of
+// LOGS_PER_CHUNK. Every part is a candid blob after its length as a
+// little-endian u64, and each list of chunks ends with an empty part, so
+// neither hook holds more than one chunk's encoding besides the state itself.
+const STABLE_MAGIC: &[u8; 4] = b"CSA1";
+const LOGS_PER_CHUNK: usize = 1_000;
+// Stable memory is written and read through a buffer of this size
+const STABLE_BUFFER_BYTES: usize = 1024 * 1024;
+
+fn write_part(writer: &mut impl Write, part: &[u8]) -> Result<(), String> {
+    writer
+        .write_all(&(part.len() as u64).to_le_bytes())
+        .and_then(|_| writer.write_all(part))
+        .map_err(|e| format!("Failed to write state: {}", e))
+}
+
+fn read_part(reader: &mut impl Read) -> Result<Vec<u8>, String> {
+    let mut length = [0; 8];
+    reader.read_exact(&mut length).map_err(|e| format!("Failed to read state: {}", e))?;
+    let mut part = vec![0; u64::from_le_bytes(length) as usize];
+    reader.read_exact(&mut part).map_err(|e| format!("Failed to read state: {}", e))?;
+    Ok(part)
+}
+
+// Which list of chunks a writer or reader is in
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Logs,
+    SecurityLogs,
+    Done,
+}
+
+/// Writes the live state in `save_state`'s layout a bounded number of parts
+/// at a time, so the encoding can be spread across messages. The logs and
+/// security logs written are those stored when it began; writing fails if
+/// logs it has yet to write have been cleared since.
+pub struct StateWriter {
+    stage: Stage,
+    logs: Range<usize>,
+    security_logs: Range<u64>,
+}
+
+impl StateWriter {
+    /// Writes the header and the state without its logs.
+    pub fn begin(writer: &mut impl Write) -> Result<Self, String> {
+        with_state_ref(|state| {
+            writer.write_all(STABLE_MAGIC).map_err(|e| format!("Failed to write state: {}", e))?;
+            write_part(writer, &encode(&StableStateRef::V1(state.without_logs()))?)?;
+            Ok(StateWriter {
+                stage: Stage::Logs,
+                logs: 0..state.logs.len(),
+                security_logs: 0..state.security_log_chain.length,
+            })
+        })
+    }
+
+    /// Writes up to `max_parts` more parts and returns whether the state is complete.
+    pub fn write_next(&mut self, writer: &mut impl Write, max_parts: usize) -> Result<bool, String> {
+        for _ in 0..max_parts {
+            match self.stage {
+                Stage::Logs => self.write_logs(writer)?,
+                Stage::SecurityLogs => self.write_security_logs(writer)?,
+                Stage::Done => break,
+            }
+        }
+        Ok(self.stage == Stage::Done)
+    }
+
+    fn write_logs(&mut self, writer: &mut impl Write) -> Result<(), String> {
+        if self.logs.is_empty() {
+            self.stage = Stage::SecurityLogs;
+            return write_part(writer, &[]);
+        }
+        let end = self.logs.end.min(self.logs.start + LOGS_PER_CHUNK);
+        LOGS.with(|logs| {
+            let logs = logs.borrow();
+            let chunk = logs
+                .get(self.logs.start..end)
+                .ok_or("The logs were cleared while the state was being written")?;
+            write_part(writer, &encode(&chunk)?)
+        })?;
+        self.logs.start = end;
+        Ok(())
+    }
+
+    fn write_security_logs(&mut self, writer: &mut impl Write) -> Result<(), String> {
+        SECURITY_LOGS.with(|logs| {
+            let logs = logs.borrow();
+            let chunk: Vec<&SecurityLog> = logs.range(self.security_logs.clone()).take(LOGS_PER_CHUNK).collect();
+            match chunk.last() {
+                Some(last) => {
+                    self.security_logs.start = last.sequence.map_or(self.security_logs.end, |sequence| sequence + 1);
+                    write_part(writer, &encode(&chunk)?)
+                }
+                None => {
+                    self.stage = Stage::Done;
+                    write_part(writer, &[])
+                }
+            }
+        })
+    }
+}
+
+/// Reads state as `StateWriter` wrote it a bounded number of parts at a
+/// time, so the decoding can be spread across messages.
+pub struct StateReader {
+    stage: Stage,
+    state: StateV1,
+}
+
+impl StateReader {
+    /// Reads the header and the state without its logs.
+    pub fn begin(reader: &mut impl Read) -> Result<Self, String> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic).map_err(|e| format!("Failed to read state: {}", e))?;
+        if &magic != STABLE_MAGIC {
+            return Err("The data does not hold a saved state".to_string());
+        }
+        Ok(StateReader {
+            stage: Stage::Logs,
+            state: decode_state(&read_part(reader)?)?,
+        })
+    }
+
+    /// Reads up to `max_parts` more parts and returns whether the state is complete.
+    pub fn read_next(&mut self, reader: &mut impl Read, max_parts: usize) -> Result<bool, String> {
+        for _ in 0..max_parts {
+            match self.stage {
+                Stage::Logs => {
+                    if !read_chunk(reader, &mut self.state.logs)? {
+                        self.stage = Stage::SecurityLogs;
+                    }
+                }
+                Stage::SecurityLogs => {
+                    if !read_chunk(reader, &mut self.state.security_logs)? {
+                        self.stage = Stage::Done;
+                    }
+                }
+                Stage::Done => break,
+            }
+        }
+        Ok(self.stage == Stage::Done)
+    }
+
+    /// The state read, once `read_next` has reported it complete.
+    pub fn into_state(self) -> Result<StateV1, String> {
+        match self.stage {
+            Stage::Done => Ok(self.state),
+            _ => Err("The saved state is incomplete".to_string()),
+        }
+    }
+}
+
+// Decodes the next part into `into`; false for the empty part that ends the list
+fn read_chunk<T>(reader: &mut impl Read, into: &mut Vec<T>) -> Result<bool, String>
+where
+    T: for<'de> Deserialize<'de> + CandidType,
+{
+    let part = read_part(reader)?;
+    if part.is_empty() {
+        return Ok(false);
+    }
+    let chunk: Vec<T> = candid::decode_one(&part).map_err(|e| format!("Failed to decode state: {}", e))?;
+    into.extend(chunk);
+    Ok(true)
+}
+
+/// Writes the live state to `writer` in the layout pre_upgrade uses.
+pub fn save_state(writer: &mut impl Write) -> Result<(), String> {
+    StateWriter::begin(writer)?.write_next(writer, usize::MAX)?;
+    Ok(())
+}
+
+/// Reads state from `reader` as pre_upgrade wrote it, converted to the
+/// current layout.
+pub fn load_state(reader: &mut impl Read) -> Result<StateV1, String> {
+    let mut state = StateReader::begin(reader)?;
+    state.read_next(reader, usize::MAX)?;
+    state.into_state()
+}
+
+/// What pre_upgrade does, against `memory`.
+pub fn save_to_stable_memory<M: StableMemory>(memory: M) -> Result<(), String> {
+    let mut writer = BufferedStableWriter::with_writer(STABLE_BUFFER_BYTES, StableWriter::with_memory(memory, 0));
+    save_state(&mut writer)?;
+    writer.flush().map_err(|e| format!("Failed to write state: {}", e))
+}
+
+/// What post_upgrade does to restore the state, against `memory`.
+pub fn restore_from_stable_memory<M: StableMemory>(memory: M) -> Result<(), String> {
+    let mut reader = BufferedStableReader::with_reader(STABLE_BUFFER_BYTES, StableReader::with_memory(memory, 0));
+    install(load_state(&mut reader)?);
+    Ok(())
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    save_to_stable_memory(CanisterStableMemory::default()).expect("Failed to save state to stable memory");
+}
+
+// Timers do not survive the upgrade, so everything driven by one is restarted
+#[post_upgrade]
+fn post_upgrade() {
+    restore_from_stable_memory(CanisterStableMemory::default()).expect("Failed to restore state from stable memory");
+    upload::resume_processing();
+    crate::certify();
+    crate::start_retention_timer();
+    crate::archive::schedule();
+}
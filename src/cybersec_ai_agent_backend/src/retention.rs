@@ -0,0 +1,519 @@
+use crate::detection::NANOS_PER_SECOND;
+use crate::security_logs::SecurityLogStore;
+use crate::SecurityLog;
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::LogRecord;
+use std::collections::{BTreeMap, VecDeque};
+
+// Per-entry bookkeeping beyond the strings themselves: the struct, options and allocations
+const ENTRY_OVERHEAD_BYTES: u64 = 160;
+const ATTRIBUTE_OVERHEAD_BYTES: u64 = 64;
+/// What an evicted run of security logs leaves behind: the hash of its last
+/// entry, its bounds and a map node
+pub const PRUNED_RANGE_BYTES: u64 = 128;
+
+/// Limits for the stored entries a rule matches. A rule without `severity`
+/// or `event_type` matches any value; each entry is governed by the first
+/// rule that matches it, and entries no rule matches are kept.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct RetentionRule {
+    /// Matches case-insensitively against the entry's severity
+    pub severity: Option<String>,
+    /// Matches a security log's event type, or a log record's source
+    pub event_type: Option<String>,
+    pub max_age_secs: Option<u64>,
+    pub max_count: Option<u64>,
+    pub max_bytes: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct RetentionConfig {
+    pub rules: Vec<RetentionRule>,
+    /// Estimated size of all stored entries, and of the pruned ranges evicted
+    /// security logs leave behind, at which memory-pressure eviction starts;
+    /// it then evicts the lowest-severity, oldest entries until the stores are
+    /// back under 90% of this
+    pub memory_budget_bytes: u64,
+    /// Entries evicted per run at most, so a run never exhausts the instruction limit
+    pub max_evictions_per_run: u64,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            rules: vec![
+                RetentionRule {
+                    severity: Some("INFO".to_string()),
+                    max_age_secs: Some(7 * 24 * 3600),
+                    ..Default::default()
+                },
+                RetentionRule {
+                    max_age_secs: Some(90 * 24 * 3600),
+                    max_count: Some(2_000_000),
+                    ..Default::default()
+                },
+            ],
+            memory_budget_bytes: 1 << 30,
+            max_evictions_per_run: 50_000,
+        }
+    }
+}
+
+/// What one enforcement run evicted, and why.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct EvictionSummary {
+    pub logs: u64,
+    pub security_logs: u64,
+    pub by_age: u64,
+    pub by_count: u64,
+    pub by_bytes: u64,
+    pub by_memory_pressure: u64,
+    /// Estimated size of both stores after the run
+    pub remaining_bytes: u64,
+}
+
+impl EvictionSummary {
+    pub fn total(&self) -> u64 {
+        self.logs + self.security_logs
+    }
+
+    pub fn describe(&self) -> String {
+        format!(
+            "Evicted {} log records and {} security logs (age {}, count {}, bytes {}, memory pressure {}); {} bytes remain",
+            self.logs,
+            self.security_logs,
+            self.by_age,
+            self.by_count,
+            self.by_bytes,
+            self.by_memory_pressure,
+            self.remaining_bytes
+        )
+    }
+}
+
+/// The view of a stored entry retention rules work on.
+pub trait Retained {
+    fn time(&self) -> Option<u64>;
+    fn severity(&self) -> Option<&str>;
+    fn event_type(&self) -> Option<&str>;
+    fn estimated_bytes(&self) -> u64;
+}
+
+impl Retained for LogRecord {
+    fn time(&self) -> Option<u64> {
+        self.timestamp
+    }
+
+    fn severity(&self) -> Option<&str> {
+        self.severity.as_deref()
+    }
+
+    fn event_type(&self) -> Option<&str> {
+        self.source.as_deref()
+    }
+
+    fn estimated_bytes(&self) -> u64 {
+        let optional = [
+            &self.original_timestamp,
+            &self.source,
+            &self.source_ip,
+            &self.destination_ip,
+            &self.user,
+            &self.host,
+            &self.action,
+            &self.outcome,
+            &self.severity,
+        ];
+        let strings: usize = optional.iter().map(|value| value.as_ref().map_or(0, String::len)).sum();
+        let attributes: u64 = self
+            .attributes
+            .iter()
+            .map(|(key, value)| (key.len() + value.len()) as u64 + ATTRIBUTE_OVERHEAD_BYTES)
+            .sum();
+        let entities = self.entities.as_ref().map_or(0, |entities| {
+            [
+                &entities.ipv4,
+                &entities.ipv6,
+                &entities.cidrs,
+                &entities.domains,
+                &entities.urls,
+                &entities.emails,
+                &entities.hashes,
+                &entities.cve_ids,
+                &entities.principals,
+                &entities.accounts,
+            ]
+            .iter()
+            .flat_map(|list| list.iter())
+            .map(|value| value.len() as u64 + 24)
+            .sum()
+        });
+        ENTRY_OVERHEAD_BYTES + (self.message.len() + strings) as u64 + attributes + entities
+    }
+}
+
+impl<T: Retained> Retained for &T {
+    fn time(&self) -> Option<u64> {
+        (**self).time()
+    }
+
+    fn severity(&self) -> Option<&str> {
+        (**self).severity()
+    }
+
+    fn event_type(&self) -> Option<&str> {
+        (**self).event_type()
+    }
+
+    fn estimated_bytes(&self) -> u64 {
+        (**self).estimated_bytes()
+    }
+}
+
+impl Retained for SecurityLog {
+    fn time(&self) -> Option<u64> {
+        Some(self.timestamp)
+    }
+
+    fn severity(&self) -> Option<&str> {
+        Some(&self.severity)
+    }
+
+    fn event_type(&self) -> Option<&str> {
+        Some(&self.event_type)
+    }
+
+    fn estimated_bytes(&self) -> u64 {
+        let entity = self.entity.as_ref().map_or(0, String::len);
+        let hash = self.hash.as_ref().map_or(0, String::len);
+        ENTRY_OVERHEAD_BYTES + (self.event_type.len() + self.details.len() + self.severity.len() + entity + hash) as u64
+    }
+}
+
+/// Orders severities from least to most important; unknown values rank lowest.
+pub fn severity_rank(severity: Option<&str>) -> u8 {
+    match severity.map(str::to_ascii_lowercase).as_deref() {
+        Some("critical" | "crit" | "emergency" | "emerg" | "alert" | "fatal") => 4,
+        Some("high" | "error" | "err") => 3,
+        Some("medium" | "warning" | "warn") => 2,
+        Some("low" | "notice") => 1,
+        _ => 0,
+    }
+}
+
+fn matches(rule: &RetentionRule, entry: &impl Retained) -> bool {
+    let field_matches = |expected: &Option<String>, actual: Option<&str>| match expected {
+        Some(expected) => actual.is_some_and(|actual| actual.eq_ignore_ascii_case(expected)),
+        None => true,
+    };
+    field_matches(&rule.severity, entry.severity()) && field_matches(&rule.event_type, entry.event_type())
+}
+
+// What the ledger remembers of a stored entry, worked out once when it first sees it
+struct Tracked {
+    rule: Option<usize>,
+    rank: u8,
+    time: Option<u64>,
+    bytes: u64,
+}
+
+#[derive(Default)]
+struct RuleUsage {
+    // Keys of the entries the rule governs, in store order; may still hold
+    // entries evicted by memory pressure, which are skipped
+    keys: VecDeque<u64>,
+    count: u64,
+    bytes: u64,
+}
+
+// One store's entries: log records are keyed by the order they were stored
+// in, security logs by sequence number
+#[derive(Default)]
+struct StoreLedger {
+    entries: BTreeMap<u64, Tracked>,
+    rules: Vec<RuleUsage>,
+    // Keys by severity rank, in store order, for memory-pressure eviction
+    ranks: [VecDeque<u64>; 5],
+    bytes: u64,
+    next_key: u64,
+    // Runs of keys below `next_key` that are no longer stored; for security
+    // logs, each is a pruned range
+    pruned_ranges: u64,
+}
+
+impl StoreLedger {
+    fn track(&mut self, key: u64, entry: &impl Retained, rules: &[RetentionRule]) {
+        let rule = rules.iter().position(|rule| matches(rule, entry));
+        let tracked = Tracked {
+            rule,
+            rank: severity_rank(entry.severity()),
+            time: entry.time(),
+            bytes: entry.estimated_bytes(),
+        };
+        if let Some(rule) = rule {
+            let usage = &mut self.rules[rule];
+            usage.keys.push_back(key);
+            usage.count += 1;
+            usage.bytes += tracked.bytes;
+        }
+        self.ranks[tracked.rank as usize].push_back(key);
+        self.bytes += tracked.bytes;
+        self.entries.insert(key, tracked);
+        self.next_key = key + 1;
+    }
+
+    fn forget(&mut self, key: u64) -> Option<Tracked> {
+        let tracked = self.entries.remove(&key)?;
+        // The key either starts a run of its own or joins the ones either side
+        let gone = |key: u64| key < self.next_key && !self.entries.contains_key(&key);
+        let joined = (key > 0 && gone(key - 1)) as u64 + gone(key + 1) as u64;
+        self.pruned_ranges = (self.pruned_ranges + 1).saturating_sub(joined);
+        if let Some(rule) = tracked.rule {
+            let usage = &mut self.rules[rule];
+            usage.count -= 1;
+            usage.bytes -= tracked.bytes;
+        }
+        self.bytes -= tracked.bytes;
+        Some(tracked)
+    }
+
+    // The earliest entry the rule still governs
+    fn front(&mut self, rule: usize) -> Option<(u64, &Tracked)> {
+        let usage = &mut self.rules[rule];
+        while let Some(key) = usage.keys.front() {
+            if self.entries.contains_key(key) {
+                break;
+            }
+            usage.keys.pop_front();
+        }
+        let key = *usage.keys.front()?;
+        self.entries.get(&key).map(|tracked| (key, tracked))
+    }
+
+    // The earliest entry of the given severity rank
+    fn front_of_rank(&mut self, rank: usize) -> Option<(u64, Option<u64>)> {
+        let keys = &mut self.ranks[rank];
+        while let Some(key) = keys.front() {
+            if let Some(tracked) = self.entries.get(key) {
+                return Some((*key, tracked.time));
+            }
+            keys.pop_front();
+        }
+        None
+    }
+
+    // Evicts from the front of each rule's entries while it is over a limit
+    fn enforce_rules(
+        &mut self,
+        rules: &[RetentionRule],
+        now: u64,
+        budget: &mut u64,
+        summary: &mut EvictionSummary,
+    ) -> Vec<u64> {
+        let mut evicted = Vec::new();
+        for (index, rule) in rules.iter().enumerate() {
+            let cutoff = rule
+                .max_age_secs
+                .map(|max_age| now.saturating_sub(max_age.saturating_mul(NANOS_PER_SECOND)));
+            while *budget > 0 {
+                let (count, bytes) = (self.rules[index].count, self.rules[index].bytes);
+                let Some((key, tracked)) = self.front(index) else {
+                    break;
+                };
+                let reason = if cutoff.is_some_and(|cutoff| tracked.time.is_some_and(|time| time < cutoff)) {
+                    &mut summary.by_age
+                } else if rule.max_count.is_some_and(|max| count > max) {
+                    &mut summary.by_count
+                } else if rule.max_bytes.is_some_and(|max| bytes > max) {
+                    &mut summary.by_bytes
+                } else {
+                    break;
+                };
+                *reason += 1;
+                *budget -= 1;
+                self.forget(key);
+                evicted.push(key);
+            }
+        }
+        evicted
+    }
+
+    // Drops the keys of evicted entries once they outnumber the live ones
+    fn compact(&mut self) {
+        let entries = &self.entries;
+        for usage in &mut self.rules {
+            if usage.keys.len() as u64 > 2 * usage.count {
+                usage.keys.retain(|key| entries.contains_key(key));
+            }
+        }
+        let live = entries.len();
+        let queued: usize = self.ranks.iter().map(VecDeque::len).sum();
+        if queued > 2 * live {
+            for keys in &mut self.ranks {
+                keys.retain(|key| entries.contains_key(key));
+            }
+        }
+    }
+}
+
+/// What one run evicts: a mask over the log records, in storage order, the
+/// sequence numbers of the security logs, and the summary.
+pub struct RetentionPlan {
+    pub logs: Vec<bool>,
+    pub security_logs: Vec<u64>,
+    pub summary: EvictionSummary,
+}
+
+impl RetentionPlan {
+    pub fn apply(&self, logs: &mut Vec<LogRecord>, security_logs: &mut SecurityLogStore) {
+        if self.logs.contains(&true) {
+            let mut index = 0;
+            logs.retain(|_| {
+                let keep = !self.logs[index];
+                index += 1;
+                keep
+            });
+        }
+        security_logs.remove_logs(&self.security_logs);
+    }
+}
+
+/// Running sizes and counts behind retention. Each stored entry's size,
+/// governing rule and severity are worked out once, when a run first sees
+/// it, so a run only touches what was stored since the last one and what it
+/// evicts. Besides appends and security logs leaving for an archive from
+/// the front, any change to the stores needs `clear_logs` or a fresh ledger.
+#[derive(Default)]
+pub struct RetentionLedger {
+    rules: Vec<RetentionRule>,
+    logs: StoreLedger,
+    security_logs: StoreLedger,
+}
+
+impl RetentionLedger {
+    /// Forgets the log records, after the store was emptied.
+    pub fn clear_logs(&mut self) {
+        self.logs = StoreLedger::default();
+        self.logs.rules.resize_with(self.rules.len(), RuleUsage::default);
+    }
+
+    /// Applies the retention rules to both stores, then evicts by severity
+    /// and age while they are over the memory budget. At most
+    /// `max_evictions_per_run` entries go in one run; the rest wait for the next.
+    pub fn enforce(
+        &mut self,
+        logs: &mut Vec<LogRecord>,
+        security_logs: &mut SecurityLogStore,
+        config: &RetentionConfig,
+        now: u64,
+    ) -> EvictionSummary {
+        let plan = self.plan(logs, security_logs, config, now);
+        plan.apply(logs, security_logs);
+        plan.summary
+    }
+
+    /// Decides what `enforce` evicts and forgets it, leaving the stores for
+    /// the caller to apply the plan to, along with any structures it keeps
+    /// in step with them.
+    pub fn plan(
+        &mut self,
+        logs: &[LogRecord],
+        security_logs: &SecurityLogStore,
+        config: &RetentionConfig,
+        now: u64,
+    ) -> RetentionPlan {
+        self.sync(logs, security_logs, &config.rules);
+        let mut summary = EvictionSummary::default();
+        let mut budget = config.max_evictions_per_run;
+        let mut evicted_logs = self.logs.enforce_rules(&config.rules, now, &mut budget, &mut summary);
+        let mut evicted_security_logs =
+            self.security_logs.enforce_rules(&config.rules, now, &mut budget, &mut summary);
+
+        if self.stored_bytes() > config.memory_budget_bytes {
+            let target = config.memory_budget_bytes / 10 * 9;
+            let mut rank = 0;
+            while self.stored_bytes() > target && budget > 0 && rank < 5 {
+                // Lowest severity first, then the older of the two stores' earliest entries
+                let in_logs = self.logs.front_of_rank(rank);
+                let in_security_logs = self.security_logs.front_of_rank(rank);
+                let take_security_log = match (in_logs, in_security_logs) {
+                    (Some((_, log_time)), Some((_, security_time))) => {
+                        security_time.unwrap_or(0) < log_time.unwrap_or(0)
+                    }
+                    (None, Some(_)) => true,
+                    (_, None) => false,
+                };
+                let (store, key, evicted) = match (take_security_log, in_logs, in_security_logs) {
+                    (true, _, Some((key, _))) => (&mut self.security_logs, key, &mut evicted_security_logs),
+                    (false, Some((key, _)), _) => (&mut self.logs, key, &mut evicted_logs),
+                    _ => {
+                        rank += 1;
+                        continue;
+                    }
+                };
+                store.forget(key);
+                evicted.push(key);
+                budget -= 1;
+                summary.by_memory_pressure += 1;
+            }
+        }
+        self.logs.compact();
+        self.security_logs.compact();
+
+        // Log records are stored in key order, so a record's position is the
+        // number of surviving and evicted keys before its own
+        let mut mask = vec![false; logs.len()];
+        evicted_logs.sort_unstable();
+        let mut surviving = self.logs.entries.keys().peekable();
+        let mut position = 0;
+        for key in &evicted_logs {
+            while surviving.next_if(|surviving| *surviving < key).is_some() {
+                position += 1;
+            }
+            mask[position] = true;
+            position += 1;
+        }
+
+        summary.logs = evicted_logs.len() as u64;
+        summary.security_logs = evicted_security_logs.len() as u64;
+        summary.remaining_bytes = self.stored_bytes();
+        RetentionPlan {
+            logs: mask,
+            security_logs: evicted_security_logs,
+            summary,
+        }
+    }
+
+    fn stored_bytes(&self) -> u64 {
+        self.logs.bytes + self.security_logs.bytes + self.security_logs.pruned_ranges * PRUNED_RANGE_BYTES
+    }
+
+    // Takes in what was stored since the last run, and lets go of security
+    // logs moved to an archive since
+    fn sync(&mut self, logs: &[LogRecord], security_logs: &SecurityLogStore, rules: &[RetentionRule]) {
+        if self.rules != rules || logs.len() < self.logs.entries.len() {
+            self.rules = rules.to_vec();
+            self.logs = StoreLedger::default();
+            self.security_logs = StoreLedger::default();
+            self.logs.rules.resize_with(rules.len(), RuleUsage::default);
+            self.security_logs.rules.resize_with(rules.len(), RuleUsage::default);
+        }
+        for record in &logs[self.logs.entries.len()..] {
+            let key = self.logs.next_key;
+            self.logs.track(key, record, rules);
+        }
+
+        let first_stored = security_logs.iter().next().and_then(|log| log.sequence).unwrap_or(u64::MAX);
+        let archived: Vec<u64> = self.security_logs.entries.range(..first_stored).map(|(key, _)| *key).collect();
+        for key in archived {
+            self.security_logs.forget(key);
+        }
+        let from = self.security_logs.next_key;
+        for log in security_logs.range(from..u64::MAX) {
+            if let Some(sequence) = log.sequence {
+                self.security_logs.track(sequence, log, rules);
+            }
+        }
+        self.security_logs.pruned_ranges = security_logs.pruned_ranges();
+    }
+}
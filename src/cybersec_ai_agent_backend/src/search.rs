@@ -0,0 +1,521 @@
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::LogRecord;
+use std::collections::{BTreeMap, BTreeSet};
+
+const MAX_TOKEN_CHARS: usize = 64;
+// Gap between the fields of a record, so a phrase never spans two fields
+const FIELD_GAP: u32 = 8;
+const MIN_PREFIX_CHARS: usize = 2;
+const MAX_PREFIX_TERMS: usize = 1_000;
+// Rough per-entry costs of the maps and vectors, on top of the data itself
+const TERM_OVERHEAD_BYTES: u64 = 64;
+const POSTING_OVERHEAD_BYTES: u64 = 48;
+const DOC_OVERHEAD_BYTES: u64 = 64;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct SearchConfig {
+    /// Estimated index size at which the oldest records stop being searchable
+    pub memory_budget_bytes: u64,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        SearchConfig {
+            memory_budget_bytes: 256 << 20,
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SearchStats {
+    pub indexed_records: u64,
+    /// Stored records dropped from the index to stay within the memory budget
+    pub unindexed_records: u64,
+    pub terms: u64,
+    pub estimated_bytes: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SearchHit {
+    /// Position of the record, as used by `get_log_records`
+    pub offset: u64,
+    pub record: LogRecord,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SearchResults {
+    /// Matching records in all, not just on this page
+    pub total: u64,
+    /// Newest first
+    pub hits: Vec<SearchHit>,
+}
+
+/// A parsed search expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SearchQuery {
+    Term(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+    And(Vec<SearchQuery>),
+    Or(Vec<SearchQuery>),
+    Not(Box<SearchQuery>),
+}
+
+/// Lowercased alphanumeric runs of `text`; `_` is kept inside tokens, and
+/// tokens longer than 64 characters are skipped.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|token| !token.is_empty() && token.chars().count() <= MAX_TOKEN_CHARS)
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// The searchable text of a record, field by field
+fn fields(record: &LogRecord) -> impl Iterator<Item = &str> {
+    [
+        Some(record.message.as_str()),
+        record.source.as_deref(),
+        record.source_ip.as_deref(),
+        record.destination_ip.as_deref(),
+        record.user.as_deref(),
+        record.host.as_deref(),
+        record.action.as_deref(),
+        record.outcome.as_deref(),
+    ]
+    .into_iter()
+    .flatten()
+}
+
+#[derive(Default)]
+struct Term {
+    text: String,
+    // Document id to the term's positions in that document
+    postings: BTreeMap<u64, Vec<u32>>,
+}
+
+/// Positional inverted index over the stored log records, kept in step with
+/// the log store: records are appended as they are stored and evicted with
+/// the same mask retention applies to the store.
+///
+/// Every stored record gets a document id in storage order. When the index
+/// outgrows its memory budget the oldest records are dropped from it; they
+/// stay in the store but no longer match searches.
+#[derive(Default)]
+pub struct SearchIndex {
+    config: SearchConfig,
+    next_doc: u64,
+    // Document id of each stored record, by position in the store
+    doc_ids: Vec<u64>,
+    term_ids: BTreeMap<String, u32>,
+    terms: Vec<Term>,
+    free_term_ids: Vec<u32>,
+    // Per indexed document: the distinct term ids it contains
+    docs: BTreeMap<u64, Vec<u32>>,
+    estimated_bytes: u64,
+}
+
+impl SearchIndex {
+    pub fn new(config: SearchConfig) -> Self {
+        SearchIndex {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: SearchConfig) {
+        self.config = config;
+        self.enforce_budget();
+    }
+
+    /// Discards the index and indexes `logs` afresh, as after an upgrade.
+    /// Records are indexed newest first and indexing stops at the memory
+    /// budget, so a large store never has more than the budget indexed.
+    pub fn rebuild(&mut self, logs: &[LogRecord]) {
+        *self = SearchIndex::new(self.config.clone());
+        self.doc_ids = (0..logs.len() as u64).collect();
+        self.next_doc = logs.len() as u64;
+        self.estimated_bytes = 8 * logs.len() as u64;
+        for (doc, record) in logs.iter().enumerate().rev() {
+            self.index(doc as u64, record);
+            if self.estimated_bytes > self.config.memory_budget_bytes {
+                self.unindex(doc as u64);
+                break;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.rebuild(&[]);
+    }
+
+    /// Indexes records just appended to the store.
+    pub fn append(&mut self, records: &[LogRecord]) {
+        for record in records {
+            let doc = self.next_doc;
+            self.next_doc += 1;
+            self.doc_ids.push(doc);
+            self.estimated_bytes += 8;
+            self.index(doc, record);
+            self.enforce_budget();
+        }
+    }
+
+    /// Indexes whatever the store `logs` gained since the index last saw it.
+    pub fn sync(&mut self, logs: &[LogRecord]) {
+        if logs.len() > self.doc_ids.len() {
+            let start = self.doc_ids.len();
+            self.append(&logs[start..]);
+        }
+    }
+
+    /// Drops the records whose position in the store is marked in `evict`.
+    pub fn evict(&mut self, evict: &[bool]) {
+        let mut position = 0;
+        let mut removed = Vec::new();
+        self.doc_ids.retain(|doc| {
+            let keep = !evict.get(position).copied().unwrap_or(false);
+            position += 1;
+            if !keep {
+                removed.push(*doc);
+            }
+            keep
+        });
+        self.estimated_bytes -= 8 * removed.len() as u64;
+        for doc in removed {
+            self.unindex(doc);
+        }
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        SearchStats {
+            indexed_records: self.docs.len() as u64,
+            unindexed_records: (self.doc_ids.len() - self.docs.len()) as u64,
+            terms: self.term_ids.len() as u64,
+            estimated_bytes: self.estimated_bytes,
+        }
+    }
+
+    fn index(&mut self, doc: u64, record: &LogRecord) {
+        let mut positions: BTreeMap<String, Vec<u32>> = BTreeMap::new();
+        let mut position = 0u32;
+        for field in fields(record) {
+            for token in tokenize(field) {
+                positions.entry(token).or_default().push(position);
+                position += 1;
+            }
+            position += FIELD_GAP;
+        }
+
+        let mut doc_terms = Vec::with_capacity(positions.len());
+        for (term, term_positions) in positions {
+            let term_id = self.term_id(term);
+            self.estimated_bytes += POSTING_OVERHEAD_BYTES + 4 * term_positions.len() as u64;
+            self.terms[term_id as usize].postings.insert(doc, term_positions);
+            doc_terms.push(term_id);
+        }
+        self.estimated_bytes += DOC_OVERHEAD_BYTES + 4 * doc_terms.len() as u64;
+        self.docs.insert(doc, doc_terms);
+    }
+
+    fn term_id(&mut self, term: String) -> u32 {
+        if let Some(&id) = self.term_ids.get(&term) {
+            return id;
+        }
+        // The text is kept twice, as the lookup key and with the postings
+        self.estimated_bytes += TERM_OVERHEAD_BYTES + 2 * term.len() as u64;
+        let id = match self.free_term_ids.pop() {
+            Some(id) => id,
+            None => {
+                self.terms.push(Term::default());
+                (self.terms.len() - 1) as u32
+            }
+        };
+        self.term_ids.insert(term.clone(), id);
+        self.terms[id as usize].text = term;
+        id
+    }
+
+    fn unindex(&mut self, doc: u64) {
+        let Some(term_ids) = self.docs.remove(&doc) else {
+            return;
+        };
+        self.estimated_bytes -= DOC_OVERHEAD_BYTES + 4 * term_ids.len() as u64;
+        for term_id in term_ids {
+            let term = &mut self.terms[term_id as usize];
+            if let Some(positions) = term.postings.remove(&doc) {
+                self.estimated_bytes -= POSTING_OVERHEAD_BYTES + 4 * positions.len() as u64;
+            }
+            if term.postings.is_empty() {
+                let text = std::mem::take(&mut term.text);
+                self.estimated_bytes -= TERM_OVERHEAD_BYTES + 2 * text.len() as u64;
+                self.term_ids.remove(&text);
+                self.free_term_ids.push(term_id);
+            }
+        }
+    }
+
+    fn enforce_budget(&mut self) {
+        while self.estimated_bytes > self.config.memory_budget_bytes {
+            let Some((&oldest, _)) = self.docs.first_key_value() else {
+                break;
+            };
+            self.unindex(oldest);
+        }
+    }
+
+    /// Positions in the store of the records matching `query`, oldest first.
+    pub fn search(&self, query: &str) -> Result<Vec<usize>, String> {
+        let query = parse_query(query)?;
+        let docs = self.evaluate(&query)?;
+        Ok(docs.into_iter().filter_map(|doc| self.doc_ids.binary_search(&doc).ok()).collect())
+    }
+
+    fn evaluate(&self, query: &SearchQuery) -> Result<BTreeSet<u64>, String> {
+        Ok(match query {
+            SearchQuery::Term(term) => self.docs_with(term).cloned().collect(),
+            SearchQuery::Prefix(prefix) => {
+                if prefix.chars().count() < MIN_PREFIX_CHARS {
+                    return Err(format!("Prefix '{}*' is too short", prefix));
+                }
+                let terms: Vec<u32> = self
+                    .term_ids
+                    .range(prefix.clone()..)
+                    .take_while(|(term, _)| term.starts_with(prefix.as_str()))
+                    .take(MAX_PREFIX_TERMS + 1)
+                    .map(|(_, id)| *id)
+                    .collect();
+                if terms.len() > MAX_PREFIX_TERMS {
+                    return Err(format!("Prefix '{}*' matches too many terms", prefix));
+                }
+                terms
+                    .iter()
+                    .flat_map(|id| self.terms[*id as usize].postings.keys())
+                    .copied()
+                    .collect()
+            }
+            SearchQuery::Phrase(terms) => self.phrase(terms),
+            SearchQuery::And(parts) => {
+                let (negated, required): (Vec<&SearchQuery>, Vec<&SearchQuery>) =
+                    parts.iter().partition(|part| matches!(part, SearchQuery::Not(_)));
+                let mut result = match required.split_first() {
+                    Some((first, rest)) => {
+                        let mut result = self.evaluate(first)?;
+                        for part in rest {
+                            let docs = self.evaluate(part)?;
+                            result.retain(|doc| docs.contains(doc));
+                        }
+                        result
+                    }
+                    None => self.docs.keys().copied().collect(),
+                };
+                for part in negated {
+                    if let SearchQuery::Not(inner) = part {
+                        for doc in self.evaluate(inner)? {
+                            result.remove(&doc);
+                        }
+                    }
+                }
+                result
+            }
+            SearchQuery::Or(parts) => {
+                let mut result = BTreeSet::new();
+                for part in parts {
+                    result.extend(self.evaluate(part)?);
+                }
+                result
+            }
+            SearchQuery::Not(inner) => {
+                let excluded = self.evaluate(inner)?;
+                self.docs.keys().filter(|doc| !excluded.contains(doc)).copied().collect()
+            }
+        })
+    }
+
+    fn docs_with(&self, term: &str) -> impl Iterator<Item = &u64> {
+        self.term_ids
+            .get(term)
+            .into_iter()
+            .flat_map(|id| self.terms[*id as usize].postings.keys())
+    }
+
+    fn phrase(&self, terms: &[String]) -> BTreeSet<u64> {
+        let postings: Option<Vec<&BTreeMap<u64, Vec<u32>>>> = terms
+            .iter()
+            .map(|term| self.term_ids.get(term).map(|id| &self.terms[*id as usize].postings))
+            .collect();
+        let Some(postings) = postings else {
+            return BTreeSet::new();
+        };
+        let Some(rarest) = postings.iter().min_by_key(|postings| postings.len()) else {
+            return BTreeSet::new();
+        };
+        rarest
+            .keys()
+            .filter(|doc| {
+                let Some(positions) = postings
+                    .iter()
+                    .map(|postings| postings.get(doc))
+                    .collect::<Option<Vec<&Vec<u32>>>>()
+                else {
+                    return false;
+                };
+                positions[0].iter().any(|&start| {
+                    positions[1..]
+                        .iter()
+                        .enumerate()
+                        .all(|(offset, next)| next.binary_search(&(start + offset as u32 + 1)).is_ok())
+                })
+            })
+            .copied()
+            .collect()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Prefix(String),
+    Phrase(Vec<String>),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+fn lex(query: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = query.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '-' => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            '"' => {
+                chars.next();
+                let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+                let words = tokenize(&phrase);
+                if words.is_empty() {
+                    return Err("Empty phrase in query".to_string());
+                }
+                tokens.push(Token::Phrase(words));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()\"".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                match word.as_str() {
+                    "AND" => tokens.push(Token::And),
+                    "OR" => tokens.push(Token::Or),
+                    "NOT" => tokens.push(Token::Not),
+                    _ => {
+                        let (text, is_prefix) = match word.strip_suffix('*') {
+                            Some(text) => (text, true),
+                            None => (word.as_str(), false),
+                        };
+                        let mut words = tokenize(text);
+                        match (words.len(), is_prefix) {
+                            (0, _) => return Err(format!("Nothing searchable in '{}'", word)),
+                            (1, true) => tokens.push(Token::Prefix(words.remove(0))),
+                            (1, false) => tokens.push(Token::Word(words.remove(0))),
+                            // `10.0.0.5` or `user_id=42` split into several tokens match as a phrase
+                            (_, false) => tokens.push(Token::Phrase(words)),
+                            (_, true) => return Err(format!("Prefix '{}' must be a single word", word)),
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses a search expression: words, `"quoted phrases"` and `prefix*`
+/// terms combined with `AND`, `OR`, `NOT` (or `-term`) and parentheses.
+/// Adjacent terms are ANDed; `NOT` binds tightest, then `AND`, then `OR`.
+pub fn parse_query(query: &str) -> Result<SearchQuery, String> {
+    let tokens = lex(query)?;
+    if tokens.is_empty() {
+        return Err("Empty query".to_string());
+    }
+    let mut parser = Parser { tokens, position: 0 };
+    let parsed = parser.or()?;
+    match parser.tokens.get(parser.position) {
+        None => Ok(parsed),
+        Some(token) => Err(format!("Unexpected {:?} in query", token)),
+    }
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn or(&mut self) -> Result<SearchQuery, String> {
+        let mut parts = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            parts.push(self.and()?);
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { SearchQuery::Or(parts) })
+    }
+
+    fn and(&mut self) -> Result<SearchQuery, String> {
+        let mut parts = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.position += 1;
+                    parts.push(self.unary()?);
+                }
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(_) => parts.push(self.unary()?),
+            }
+        }
+        Ok(if parts.len() == 1 { parts.remove(0) } else { SearchQuery::And(parts) })
+    }
+
+    fn unary(&mut self) -> Result<SearchQuery, String> {
+        let Some(token) = self.tokens.get(self.position) else {
+            return Err("Query ends unexpectedly".to_string());
+        };
+        self.position += 1;
+        match token {
+            Token::Not => Ok(SearchQuery::Not(Box::new(self.unary()?))),
+            Token::Word(word) => Ok(SearchQuery::Term(word.clone())),
+            Token::Prefix(prefix) => Ok(SearchQuery::Prefix(prefix.clone())),
+            Token::Phrase(words) => Ok(SearchQuery::Phrase(words.clone())),
+            Token::Open => {
+                let inner = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err("Missing ')' in query".to_string());
+                }
+                self.position += 1;
+                Ok(inner)
+            }
+            other => Err(format!("Unexpected {:?} in query", other)),
+        }
+    }
+}
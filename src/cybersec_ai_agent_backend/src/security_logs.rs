@@ -0,0 +1,568 @@
+use crate::log_chain::{
+    self, ChainHead, ChainProof, ChainState, ChainVerification, Checkpoint, PrunedRange, CHECKPOINT_INTERVAL,
+    GENESIS_HASH,
+};
+use crate::{certified, SecurityLog};
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::entities::extract_entities;
+use ic_certification::hash_tree::Hash;
+use ic_certification::{AsHashTree, HashTree, RbTree};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::iter::Peekable;
+use std::ops::Bound;
+
+const MAX_PAGE: u32 = 1_000;
+// Entries one verification call rehashes at most, to stay within the instruction limit
+const MAX_VERIFY_RANGE: u64 = 100_000;
+// Entries one proof holds at most, to stay within the response size limit
+const MAX_PROOF_ENTRIES: u64 = 500;
+
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum SortOrder {
+    #[default]
+    OldestFirst,
+    NewestFirst,
+}
+
+/// Filters for `query_security_logs`; unset filters match everything.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct SecurityLogQuery {
+    /// Inclusive lower bound on the timestamp, in nanoseconds
+    pub from_time: Option<u64>,
+    /// Exclusive upper bound on the timestamp, in nanoseconds
+    pub to_time: Option<u64>,
+    /// Any of these severities, case-insensitively
+    pub severities: Vec<String>,
+    pub event_type: Option<String>,
+    /// Case-insensitive substring of the details
+    pub text: Option<String>,
+    /// The detection's entity, or an IP, domain, URL, hash or other entity in the details
+    pub entity: Option<String>,
+    pub order: SortOrder,
+    /// Page size, at most 1000
+    pub limit: u32,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SecurityLogPage {
+    pub logs: Vec<SecurityLog>,
+    /// Present when more logs match; pass it back to get the next page
+    pub next_cursor: Option<String>,
+}
+
+// Position in (timestamp, sequence) order; sequence numbers break ties between equal timestamps
+type Key = (u64, u64);
+
+/// Security logs as a tamper-evident hash chain, with secondary indexes on
+/// time, severity, event type and entities.
+///
+/// Every log gets the next sequence number and a chain hash covering its
+/// contents and the hash of the log before it, so editing, reordering or
+/// dropping a log breaks every hash after it. Logs retention evicts leave a
+/// pruned range behind that keeps the chain verifiable across the gap.
+pub struct SecurityLogStore {
+    logs: BTreeMap<u64, SecurityLog>,
+    length: u64,
+    head_hash: String,
+    checkpoints: Vec<Checkpoint>,
+    // By first sequence number; adjacent ranges are merged
+    pruned: BTreeMap<u64, PrunedRange>,
+    // Leaf per stored log by big-endian sequence number, for certified queries
+    certified: RbTree<[u8; 8], Hash>,
+    by_time: BTreeSet<Key>,
+    by_severity: HashMap<String, BTreeSet<Key>>,
+    by_event_type: HashMap<String, BTreeSet<Key>>,
+    by_entity: HashMap<String, BTreeSet<Key>>,
+}
+
+fn entity_keys(log: &SecurityLog) -> Vec<String> {
+    let entities = extract_entities(&log.details);
+    let mut keys: Vec<String> = log.entity.iter().cloned().collect();
+    for list in [
+        entities.ipv4,
+        entities.ipv6,
+        entities.cidrs,
+        entities.domains,
+        entities.urls,
+        entities.emails,
+        entities.hashes,
+        entities.cve_ids,
+        entities.principals,
+        entities.accounts,
+    ] {
+        keys.extend(list);
+    }
+    let mut keys: Vec<String> = keys.into_iter().map(|key| key.to_lowercase()).collect();
+    keys.sort_unstable();
+    keys.dedup();
+    keys
+}
+
+impl Default for SecurityLogStore {
+    fn default() -> Self {
+        SecurityLogStore {
+            logs: BTreeMap::new(),
+            length: 0,
+            head_hash: GENESIS_HASH.to_string(),
+            checkpoints: Vec::new(),
+            pruned: BTreeMap::new(),
+            certified: RbTree::new(),
+            by_time: BTreeSet::new(),
+            by_severity: HashMap::new(),
+            by_event_type: HashMap::new(),
+            by_entity: HashMap::new(),
+        }
+    }
+}
+
+impl SecurityLogStore {
+    /// A new chain of `logs`, appended in order.
+    pub fn from_logs(logs: Vec<SecurityLog>) -> Self {
+        SecurityLogStore::restore(logs, ChainState::default())
+    }
+
+    /// Reassembles a store persisted with `into_parts`.
+    pub fn restore(logs: Vec<SecurityLog>, chain: ChainState) -> Self {
+        let mut store = SecurityLogStore {
+            length: chain.length,
+            head_hash: chain.head_hash,
+            checkpoints: chain.checkpoints,
+            pruned: chain.pruned.into_iter().map(|range| (range.first, range)).collect(),
+            ..SecurityLogStore::default()
+        };
+        for log in logs {
+            match log.sequence {
+                Some(sequence) if sequence < store.length => store.insert(sequence, log),
+                _ => {
+                    store.push(log);
+                }
+            }
+        }
+        store
+    }
+
+    /// The logs in sequence order and the chain state, as persisted across upgrades.
+    pub fn into_parts(self) -> (Vec<SecurityLog>, ChainState) {
+        let chain = ChainState {
+            length: self.length,
+            head_hash: self.head_hash,
+            checkpoints: self.checkpoints,
+            pruned: self.pruned.into_values().collect(),
+        };
+        (self.logs.into_values().collect(), chain)
+    }
+
+    /// The chain state `into_parts` would return, leaving the store as it is.
+    pub fn chain_state(&self) -> ChainState {
+        ChainState {
+            length: self.length,
+            head_hash: self.head_hash.clone(),
+            checkpoints: self.checkpoints.clone(),
+            pruned: self.pruned.values().cloned().collect(),
+        }
+    }
+
+    /// Appends `log` to the chain, replacing any sequence number and hash it
+    /// carries, and returns its sequence number.
+    pub fn push(&mut self, mut log: SecurityLog) -> u64 {
+        let sequence = self.length;
+        let hash = log_chain::entry_hash(&self.head_hash, sequence, &log).expect("the chain head is a valid hash");
+        if (sequence + 1).is_multiple_of(CHECKPOINT_INTERVAL) {
+            self.checkpoints.push(Checkpoint {
+                sequence,
+                timestamp: log.timestamp,
+                hash: hash.clone(),
+            });
+        }
+        log.sequence = Some(sequence);
+        log.hash = Some(hash.clone());
+        self.length += 1;
+        self.head_hash = hash;
+        self.insert(sequence, log);
+        sequence
+    }
+
+    fn insert(&mut self, id: u64, log: SecurityLog) {
+        if let Ok(leaf) = certified::security_log_leaf(&log) {
+            self.certified.insert(id.to_be_bytes(), leaf);
+        }
+        let position = (log.timestamp, id);
+        self.by_time.insert(position);
+        self.by_severity.entry(log.severity.to_uppercase()).or_default().insert(position);
+        self.by_event_type.entry(log.event_type.clone()).or_default().insert(position);
+        for key in entity_keys(&log) {
+            self.by_entity.entry(key).or_default().insert(position);
+        }
+        self.logs.insert(id, log);
+    }
+
+    pub fn len(&self) -> usize {
+        self.logs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logs.is_empty()
+    }
+
+    /// The logs in sequence order.
+    pub fn iter(&self) -> impl Iterator<Item = &SecurityLog> {
+        self.logs.values()
+    }
+
+    /// Removes the logs whose position in sequence order is marked in `evict`.
+    pub fn evict(&mut self, evict: &[bool]) {
+        let ids: Vec<u64> = self
+            .logs
+            .keys()
+            .zip(evict)
+            .filter(|(_, evicted)| **evicted)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in ids {
+            self.remove(id);
+        }
+    }
+
+    /// Removes the logs with the given sequence numbers, as `evict` does.
+    pub fn remove_logs(&mut self, sequences: &[u64]) {
+        for sequence in sequences {
+            self.remove(*sequence);
+        }
+    }
+
+    /// How many runs of evicted logs the chain keeps a hash for.
+    pub fn pruned_ranges(&self) -> u64 {
+        self.pruned.len() as u64
+    }
+
+    /// Stored logs with sequence numbers in `range`.
+    pub fn range(&self, range: std::ops::Range<u64>) -> impl Iterator<Item = &SecurityLog> {
+        self.logs.range(range).map(|(_, log)| log)
+    }
+
+    /// Removes every stored log up to sequence number `last`, as `evict` does,
+    /// and returns how many there were.
+    pub fn remove_through(&mut self, last: u64) -> u64 {
+        let ids: Vec<u64> = self.logs.range(..=last).map(|(id, _)| *id).collect();
+        for id in &ids {
+            self.remove(*id);
+        }
+        ids.len() as u64
+    }
+
+    fn remove(&mut self, id: u64) {
+        let Some(log) = self.logs.remove(&id) else {
+            return;
+        };
+        if let Some(hash) = &log.hash {
+            self.prune(id, hash.clone());
+        }
+        self.certified.delete(&id.to_be_bytes());
+        let position = (log.timestamp, id);
+        self.by_time.remove(&position);
+        let unindex = |index: &mut HashMap<String, BTreeSet<Key>>, key: String| {
+            if let Some(positions) = index.get_mut(&key) {
+                positions.remove(&position);
+                if positions.is_empty() {
+                    index.remove(&key);
+                }
+            }
+        };
+        unindex(&mut self.by_severity, log.severity.to_uppercase());
+        unindex(&mut self.by_event_type, log.event_type.clone());
+        for key in entity_keys(&log) {
+            unindex(&mut self.by_entity, key);
+        }
+    }
+
+    // Records an evicted entry, merging it with the pruned ranges either side
+    fn prune(&mut self, sequence: u64, hash: String) {
+        let mut range = PrunedRange {
+            first: sequence,
+            last: sequence,
+            last_hash: hash,
+        };
+        let before = self.pruned.range(..sequence).next_back().map(|(first, range)| (*first, range.last));
+        if let Some((first, last)) = before {
+            if last + 1 == sequence {
+                self.pruned.remove(&first);
+                range.first = first;
+            }
+        }
+        if let Some(after) = self.pruned.remove(&(sequence + 1)) {
+            range.last = after.last;
+            range.last_hash = after.last_hash;
+        }
+        self.pruned.insert(range.first, range);
+    }
+
+    fn pruned_containing(&self, sequence: u64) -> Option<&PrunedRange> {
+        self.pruned.range(..=sequence).next_back().map(|(_, range)| range).filter(|range| range.last >= sequence)
+    }
+
+    // Chain hash of entry `sequence - 1`, unknown only when it was evicted
+    // together with `sequence`
+    fn hash_before(&self, sequence: u64) -> Option<String> {
+        if sequence == 0 {
+            return Some(GENESIS_HASH.to_string());
+        }
+        match self.logs.get(&(sequence - 1)) {
+            Some(log) => log.hash.clone(),
+            None => self
+                .pruned_containing(sequence - 1)
+                .filter(|range| range.last == sequence - 1)
+                .map(|range| range.last_hash.clone()),
+        }
+    }
+
+    /// Root hash of the certified tree of stored logs.
+    pub fn certified_root(&self) -> Hash {
+        self.certified.root_hash()
+    }
+
+    /// Up to `limit` logs from sequence number `from` on, the last sequence
+    /// number they cover (`u64::MAX` once the store runs out) and a witness
+    /// over that range of the certified tree.
+    pub fn certified_page(&self, from: u64, limit: usize) -> (Vec<SecurityLog>, u64, HashTree) {
+        let logs: Vec<SecurityLog> = self.logs.range(from..).take(limit).map(|(_, log)| log.clone()).collect();
+        let to = match logs.last() {
+            Some(last) if logs.len() == limit => last.sequence.unwrap_or(u64::MAX),
+            _ => u64::MAX,
+        };
+        let witness = self.certified.value_range(&from.to_be_bytes(), &to.to_be_bytes());
+        (logs, to, witness)
+    }
+
+    pub fn head(&self) -> ChainHead {
+        ChainHead {
+            length: self.length,
+            hash: self.head_hash.clone(),
+        }
+    }
+
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    fn check_range(&self, from: u64, to: u64, max: u64) -> Result<(), String> {
+        if from > to {
+            return Err(format!("Empty range {}..={}", from, to));
+        }
+        if to >= self.length {
+            return Err(format!("The chain has {} entries; {} is past its end", self.length, to));
+        }
+        if to - from >= max {
+            return Err(format!("At most {} entries per call; split the range", max));
+        }
+        Ok(())
+    }
+
+    /// Recomputes the hashes of entries `from..=to`, following pruned ranges
+    /// across evicted entries, and checks them against the stored hashes,
+    /// checkpoints and, when `to` is the last entry, the head.
+    pub fn verify(&self, from: u64, to: u64) -> Result<ChainVerification, String> {
+        self.check_range(from, to, MAX_VERIFY_RANGE)?;
+        let mut verification = ChainVerification {
+            from,
+            to,
+            verified: 0,
+            pruned: 0,
+            valid: true,
+            error: None,
+            head: self.head(),
+        };
+        let checkpoint = |sequence: u64| {
+            self.checkpoints
+                .binary_search_by_key(&sequence, |checkpoint| checkpoint.sequence)
+                .ok()
+                .map(|index| &self.checkpoints[index].hash)
+        };
+
+        let mut hash = self.hash_before(from);
+        let mut sequence = from;
+        let mut error = None;
+        while sequence <= to {
+            if let Some(log) = self.logs.get(&sequence) {
+                let computed = hash.as_deref().map(|previous| log_chain::entry_hash(previous, sequence, log));
+                let Some(Ok(computed)) = computed else {
+                    error = Some(format!("Entry {} has no valid previous hash", sequence));
+                    break;
+                };
+                if log.sequence != Some(sequence) || log.hash.as_ref() != Some(&computed) {
+                    error = Some(format!("Entry {} does not match its hash", sequence));
+                    break;
+                }
+                if checkpoint(sequence).is_some_and(|expected| expected != &computed) {
+                    error = Some(format!("Entry {} does not match its checkpoint", sequence));
+                    break;
+                }
+                hash = Some(computed);
+                verification.verified += 1;
+                sequence += 1;
+            } else if let Some(range) = self.pruned_containing(sequence) {
+                if checkpoint(range.last).is_some_and(|expected| expected != &range.last_hash) {
+                    error = Some(format!("Pruned entry {} does not match its checkpoint", range.last));
+                    break;
+                }
+                verification.pruned += range.last.min(to) - sequence + 1;
+                hash = Some(range.last_hash.clone());
+                sequence = range.last + 1;
+            } else {
+                error = Some(format!("Entry {} is missing", sequence));
+                break;
+            }
+        }
+        if error.is_none() && to + 1 == self.length && hash.as_ref() != Some(&self.head_hash) {
+            error = Some(format!("Entry {} does not match the chain head", to));
+        }
+        verification.valid = error.is_none();
+        verification.error = error;
+        Ok(verification)
+    }
+
+    /// A proof of entries `from..=to` for `log_chain::verify_chain_proof`.
+    /// Neither end may fall inside a run of evicted entries, whose hashes are gone.
+    pub fn proof(&self, from: u64, to: u64) -> Result<ChainProof, String> {
+        self.check_range(from, to, MAX_PROOF_ENTRIES)?;
+        let previous_hash = self.hash_before(from).ok_or_else(|| format!("Entry {} was evicted", from - 1))?;
+        if let Some(range) = self.pruned_containing(to).filter(|range| range.last > to) {
+            return Err(format!(
+                "Entries {}..={} were evicted; end the proof at {} or before {}",
+                range.first, range.last, range.last, range.first
+            ));
+        }
+        Ok(ChainProof {
+            from,
+            to,
+            previous_hash,
+            entries: self.logs.range(from..=to).map(|(_, log)| log.clone()).collect(),
+            pruned: self
+                .pruned
+                .range(from..=to)
+                .map(|(_, range)| range.clone())
+                .collect(),
+            checkpoints: self
+                .checkpoints
+                .iter()
+                .filter(|checkpoint| (from..=to).contains(&checkpoint.sequence))
+                .cloned()
+                .collect(),
+        })
+    }
+
+    /// One page of the logs matching `query`, in the requested order.
+    ///
+    /// The narrowest of the severity, event type and entity indexes drives the
+    /// scan, or the time index without any of those filters. Indexes are kept
+    /// in (timestamp, sequence) order, so the scan starts at the cursor or time
+    /// bound and stops as soon as the page is full.
+    pub fn query(&self, query: &SecurityLogQuery) -> Result<SecurityLogPage, String> {
+        let limit = query.limit.clamp(1, MAX_PAGE) as usize;
+        let after = query.cursor.as_deref().map(decode_cursor).transpose()?;
+        let newest_first = query.order == SortOrder::NewestFirst;
+        let mut lower = Bound::Included((query.from_time.unwrap_or(0), 0));
+        let mut upper = Bound::Excluded((query.to_time.unwrap_or(u64::MAX), 0));
+        match after {
+            Some(after) if newest_first => upper = Bound::Excluded(after.min(bound_key(upper))),
+            Some(after) if after >= bound_key(lower) => lower = Bound::Excluded(after),
+            _ => {}
+        }
+        if bound_key(lower) >= bound_key(upper) {
+            return Ok(SecurityLogPage {
+                logs: Vec::new(),
+                next_cursor: None,
+            });
+        }
+        let text = query.text.as_deref().map(str::to_lowercase);
+
+        // Each filter's index entries, as the union of one or more sets
+        let mut candidates: Vec<Vec<&BTreeSet<Key>>> = Vec::new();
+        let empty = BTreeSet::new();
+        if let Some(event_type) = &query.event_type {
+            candidates.push(vec![self.by_event_type.get(event_type).unwrap_or(&empty)]);
+        }
+        if let Some(entity) = &query.entity {
+            candidates.push(vec![self.by_entity.get(&entity.to_lowercase()).unwrap_or(&empty)]);
+        }
+        if !query.severities.is_empty() {
+            let severities: BTreeSet<String> =
+                query.severities.iter().map(|severity| severity.to_uppercase()).collect();
+            candidates.push(severities.iter().filter_map(|severity| self.by_severity.get(severity)).collect());
+        }
+        let driver = candidates
+            .into_iter()
+            .min_by_key(|sets| sets.iter().map(|set| set.len()).sum::<usize>())
+            .unwrap_or_else(|| vec![&self.by_time]);
+
+        let matches = |(_, id): &Key| {
+            let log = &self.logs[id];
+            query.event_type.as_ref().is_none_or(|event_type| &log.event_type == event_type)
+                && (query.severities.is_empty()
+                    || query.severities.iter().any(|severity| severity.eq_ignore_ascii_case(&log.severity)))
+                && query.entity.as_ref().is_none_or(|entity| {
+                    self.by_entity
+                        .get(&entity.to_lowercase())
+                        .is_some_and(|positions| positions.contains(&(log.timestamp, *id)))
+                })
+                && text.as_ref().is_none_or(|text| log.details.to_lowercase().contains(text))
+        };
+        let mut keys: Vec<Key> = merged(driver, (lower, upper), newest_first)
+            .filter(matches)
+            .take(limit + 1)
+            .collect();
+
+        let next_cursor = (keys.len() > limit).then(|| encode_cursor(keys[limit - 1]));
+        keys.truncate(limit);
+        Ok(SecurityLogPage {
+            logs: keys.iter().map(|(_, id)| self.logs[id].clone()).collect(),
+            next_cursor,
+        })
+    }
+}
+
+fn bound_key(bound: Bound<Key>) -> Key {
+    match bound {
+        Bound::Included(key) | Bound::Excluded(key) => key,
+        Bound::Unbounded => (0, 0),
+    }
+}
+
+// Walks `range` of each set at once, yielding their keys in one ordered scan
+fn merged<'a>(
+    sets: Vec<&'a BTreeSet<Key>>,
+    range: (Bound<Key>, Bound<Key>),
+    newest_first: bool,
+) -> impl Iterator<Item = Key> + 'a {
+    let mut scans: Vec<Peekable<Box<dyn Iterator<Item = &Key> + 'a>>> = sets
+        .into_iter()
+        .map(|set| {
+            let scan = set.range(range);
+            let scan: Box<dyn Iterator<Item = &Key>> = if newest_first { Box::new(scan.rev()) } else { Box::new(scan) };
+            scan.peekable()
+        })
+        .collect();
+    std::iter::from_fn(move || {
+        let (next, _) = scans
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, scan)| scan.peek().map(|key| (index, **key)))
+            .min_by(|(_, a), (_, b)| if newest_first { b.cmp(a) } else { a.cmp(b) })?;
+        scans[next].next().copied()
+    })
+}
+
+fn encode_cursor((timestamp, id): Key) -> String {
+    format!("{:016x}{:016x}", timestamp, id)
+}
+
+fn decode_cursor(cursor: &str) -> Result<Key, String> {
+    let invalid = || format!("Invalid cursor: {}", cursor);
+    if cursor.len() != 32 || !cursor.is_ascii() {
+        return Err(invalid());
+    }
+    let timestamp = u64::from_str_radix(&cursor[..16], 16).map_err(|_| invalid())?;
+    let id = u64::from_str_radix(&cursor[16..], 16).map_err(|_| invalid())?;
+    Ok((timestamp, id))
+}
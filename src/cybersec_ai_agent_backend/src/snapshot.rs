@@ -0,0 +1,522 @@
+use crate::persistence::{install, StateReader, StateV1, StateWriter};
+use crate::upload;
+use crate::wallet::WALLET_STORE;
+use crate::{require_controller, HASH_IOCS, LOGS, SECURITY_LOGS, THREATS};
+use candid::{CandidType, Deserialize};
+use ic_cdk::{query, update};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::collections::{btree_map, BTreeMap};
+use std::io::Read;
+use std::thread::LocalKey;
+use std::time::Duration;
+
+/// Snapshot format written by this release. The state inside carries its
+/// own layout version, so snapshots of older layouts import as upgrades do.
+pub const SNAPSHOT_VERSION: u32 = 1;
+// Stay well below the 2 MiB message limit
+pub const SNAPSHOT_CHUNK_BYTES: u64 = 1_800_000;
+// Parts of the state encoded or decoded per timer tick, so no message nears
+// the instruction limit
+const PARTS_PER_TICK: usize = 10;
+
+// Neither survives an upgrade; an export or import in progress is started over
+thread_local! {
+    static STATE_EXPORT: RefCell<Option<StateExport>> = const { RefCell::new(None) };
+    static STATE_IMPORT: RefCell<Option<StateImport>> = const { RefCell::new(None) };
+    static EXPORT_TICK_SCHEDULED: Cell<bool> = const { Cell::new(false) };
+    static IMPORT_TICK_SCHEDULED: Cell<bool> = const { Cell::new(false) };
+}
+
+enum StateExport {
+    Writing(SnapshotWriter),
+    Ready(Snapshot),
+    Failed(String),
+}
+
+enum StateImport {
+    Receiving(SnapshotImport),
+    // Boxed; the decoded state is large
+    Loading(Box<SnapshotLoader>),
+    Installed,
+    Failed(String),
+}
+
+/// Where the last export taken with `export_state_snapshot` stands.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum SnapshotExportStatus {
+    None,
+    /// Being encoded; `bytes` so far
+    Writing { bytes: u64 },
+    Ready(SnapshotManifest),
+    Failed(String),
+}
+
+/// Where the import started with `begin_state_import` stands.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum SnapshotImportStatus {
+    None,
+    Receiving { chunks: u32 },
+    /// Being decoded after `commit_state_import`; `bytes` read so far
+    Loading { bytes: u64 },
+    Installed,
+    Failed(String),
+}
+
+/// Describes a snapshot: enough to fetch it chunk by chunk and to check
+/// every chunk and the whole on the way.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct SnapshotManifest {
+    pub version: u32,
+    /// When the snapshot was taken, in nanoseconds since the epoch
+    pub created_at: u64,
+    pub total_bytes: u64,
+    /// Size of every chunk but the last
+    pub chunk_bytes: u64,
+    /// SHA-256 of each chunk, in order
+    pub chunk_sha256: Vec<ByteBuf>,
+    /// SHA-256 of the whole snapshot
+    pub sha256: ByteBuf,
+}
+
+impl SnapshotManifest {
+    pub fn chunk_count(&self) -> u32 {
+        self.chunk_sha256.len() as u32
+    }
+
+    fn chunk_range(&self, index: u32) -> Result<std::ops::Range<usize>, String> {
+        if index >= self.chunk_count() {
+            return Err(format!("The snapshot has {} chunks; there is no chunk {}", self.chunk_count(), index));
+        }
+        let start = index as u64 * self.chunk_bytes;
+        Ok(start as usize..(start + self.chunk_bytes).min(self.total_bytes) as usize)
+    }
+}
+
+/// The encoded state of the canister, split into chunks.
+pub struct Snapshot {
+    manifest: SnapshotManifest,
+    bytes: Vec<u8>,
+}
+
+impl Snapshot {
+    pub fn new(bytes: Vec<u8>, created_at: u64) -> Self {
+        SnapshotHasher::default().finish(bytes, created_at)
+    }
+
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    pub fn chunk(&self, index: u32) -> Result<&[u8], String> {
+        Ok(&self.bytes[self.manifest.chunk_range(index)?])
+    }
+}
+
+// Checksums of the chunks of a snapshot as it grows, so no one message hashes all of it
+#[derive(Default)]
+struct SnapshotHasher {
+    chunk_sha256: Vec<ByteBuf>,
+    sha256: Sha256,
+    hashed: usize,
+}
+
+impl SnapshotHasher {
+    // Hashes the chunks of `bytes` that are complete and not yet hashed
+    fn update(&mut self, bytes: &[u8]) {
+        while bytes.len() - self.hashed >= SNAPSHOT_CHUNK_BYTES as usize {
+            self.hash_chunk(&bytes[self.hashed..self.hashed + SNAPSHOT_CHUNK_BYTES as usize]);
+        }
+    }
+
+    fn hash_chunk(&mut self, chunk: &[u8]) {
+        self.chunk_sha256.push(ByteBuf::from(Sha256::digest(chunk).to_vec()));
+        self.sha256.update(chunk);
+        self.hashed += chunk.len();
+    }
+
+    fn finish(mut self, bytes: Vec<u8>, created_at: u64) -> Snapshot {
+        self.update(&bytes);
+        if self.hashed < bytes.len() {
+            self.hash_chunk(&bytes[self.hashed..]);
+        }
+        let manifest = SnapshotManifest {
+            version: SNAPSHOT_VERSION,
+            created_at,
+            total_bytes: bytes.len() as u64,
+            chunk_bytes: SNAPSHOT_CHUNK_BYTES,
+            chunk_sha256: self.chunk_sha256,
+            sha256: ByteBuf::from(self.sha256.finalize().to_vec()),
+        };
+        Snapshot { manifest, bytes }
+    }
+}
+
+/// A snapshot of the live state being encoded a few parts at a time.
+pub struct SnapshotWriter {
+    writer: StateWriter,
+    hasher: SnapshotHasher,
+    bytes: Vec<u8>,
+    created_at: u64,
+}
+
+impl SnapshotWriter {
+    /// Encodes everything but the logs, which `step` adds.
+    pub fn new(created_at: u64) -> Result<Self, String> {
+        let mut bytes = Vec::new();
+        let writer = StateWriter::begin(&mut bytes)?;
+        Ok(SnapshotWriter {
+            writer,
+            hasher: SnapshotHasher::default(),
+            bytes,
+            created_at,
+        })
+    }
+
+    /// Encodes up to `max_parts` more parts and returns whether the snapshot is complete.
+    pub fn step(&mut self, max_parts: usize) -> Result<bool, String> {
+        let done = self.writer.write_next(&mut self.bytes, max_parts)?;
+        self.hasher.update(&self.bytes);
+        Ok(done)
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes.len() as u64
+    }
+
+    /// The snapshot, once `step` has reported it complete.
+    pub fn finish(self) -> Snapshot {
+        self.hasher.finish(self.bytes, self.created_at)
+    }
+}
+
+/// A snapshot being received chunk by chunk, in any order.
+pub struct SnapshotImport {
+    manifest: SnapshotManifest,
+    chunks: BTreeMap<u32, Vec<u8>>,
+}
+
+impl SnapshotImport {
+    pub fn new(manifest: SnapshotManifest) -> Result<Self, String> {
+        if manifest.version != SNAPSHOT_VERSION {
+            return Err(format!(
+                "Snapshot format {} is not supported; this release reads format {}",
+                manifest.version, SNAPSHOT_VERSION
+            ));
+        }
+        let expected_chunks = manifest.total_bytes.div_ceil(manifest.chunk_bytes.max(1));
+        if manifest.chunk_bytes == 0
+            || manifest.chunk_bytes > SNAPSHOT_CHUNK_BYTES
+            || manifest.chunk_count() as u64 != expected_chunks
+        {
+            return Err("The manifest's chunk sizes and checksums do not add up".to_string());
+        }
+        Ok(SnapshotImport {
+            manifest,
+            chunks: BTreeMap::new(),
+        })
+    }
+
+    pub fn manifest(&self) -> &SnapshotManifest {
+        &self.manifest
+    }
+
+    /// Stores chunk `index` after checking it against the manifest. Re-sending
+    /// a chunk is accepted, so clients can retry after a timeout.
+    pub fn add_chunk(&mut self, index: u32, data: Vec<u8>) -> Result<u32, String> {
+        let range = self.manifest.chunk_range(index)?;
+        if data.len() != range.len() {
+            return Err(format!("Chunk {} should be {} bytes, not {}", index, range.len(), data.len()));
+        }
+        if Sha256::digest(&data).as_slice() != self.manifest.chunk_sha256[index as usize].as_slice() {
+            return Err(format!("SHA-256 mismatch for chunk {}", index));
+        }
+        self.chunks.insert(index, data);
+        Ok(self.chunks.len() as u32)
+    }
+
+    /// The first chunk that has not arrived yet, if any.
+    pub fn missing_chunk(&self) -> Option<u32> {
+        (0..self.manifest.chunk_count()).find(|index| !self.chunks.contains_key(index))
+    }
+
+    /// Starts decoding the snapshot once every chunk has arrived.
+    pub fn into_loader(self) -> Result<SnapshotLoader, String> {
+        if let Some(missing) = self.missing_chunk() {
+            return Err(format!("Chunk {} has not been received", missing));
+        }
+        let mut input = ChunkReader {
+            chunks: self.chunks.into_values(),
+            chunk: Vec::new(),
+            offset: 0,
+            bytes_read: 0,
+            sha256: Sha256::new(),
+        };
+        let reader = StateReader::begin(&mut input)?;
+        Ok(SnapshotLoader {
+            expected_sha256: self.manifest.sha256,
+            input,
+            reader,
+        })
+    }
+}
+
+// Reads the received chunks in order, dropping and hashing each as it is reached
+struct ChunkReader {
+    chunks: btree_map::IntoValues<u32, Vec<u8>>,
+    chunk: Vec<u8>,
+    offset: usize,
+    bytes_read: u64,
+    sha256: Sha256,
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.offset == self.chunk.len() {
+            let Some(chunk) = self.chunks.next() else {
+                return Ok(0);
+            };
+            self.sha256.update(&chunk);
+            (self.chunk, self.offset) = (chunk, 0);
+        }
+        let read = buf.len().min(self.chunk.len() - self.offset);
+        buf[..read].copy_from_slice(&self.chunk[self.offset..self.offset + read]);
+        self.offset += read;
+        self.bytes_read += read as u64;
+        Ok(read)
+    }
+}
+
+/// A received snapshot being decoded a few parts at a time.
+pub struct SnapshotLoader {
+    expected_sha256: ByteBuf,
+    input: ChunkReader,
+    reader: StateReader,
+}
+
+impl SnapshotLoader {
+    /// Decodes up to `max_parts` more parts and returns whether the state is complete.
+    pub fn step(&mut self, max_parts: usize) -> Result<bool, String> {
+        self.reader.read_next(&mut self.input, max_parts)
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.input.bytes_read
+    }
+
+    /// The decoded state, once `step` has reported it complete and the whole
+    /// snapshot matches its checksum.
+    pub fn finish(mut self) -> Result<StateV1, String> {
+        let state = self.reader.into_state()?;
+        if self.input.read(&mut [0]).map_err(|e| format!("Failed to read state: {}", e))? > 0 {
+            return Err("The snapshot has data after the state".to_string());
+        }
+        if self.input.sha256.finalize().as_slice() != self.expected_sha256.as_slice() {
+            return Err("SHA-256 mismatch for the assembled snapshot".to_string());
+        }
+        Ok(state)
+    }
+}
+
+/// Snapshot of everything the canister keeps across upgrades, encoded in
+/// one go; `export_state_snapshot` spreads the same encoding across timer ticks.
+pub fn export_state(now: u64) -> Result<Snapshot, String> {
+    let mut writer = SnapshotWriter::new(now)?;
+    writer.step(usize::MAX)?;
+    Ok(writer.finish())
+}
+
+/// Whether the canister holds no logs, detections or wallet records, as
+/// when freshly installed; only such a canister accepts an import.
+pub fn is_fresh() -> bool {
+    LOGS.with(|logs| logs.borrow().is_empty())
+        && SECURITY_LOGS.with(|logs| logs.borrow().head().length == 0)
+        && THREATS.with(|threats| threats.borrow().is_empty())
+        && WALLET_STORE.with(|wallets| wallets.borrow().is_empty())
+        && HASH_IOCS.with(|store| store.borrow().is_empty())
+}
+
+fn require_fresh() -> Result<(), String> {
+    if is_fresh() {
+        Ok(())
+    } else {
+        Err("Snapshots are only imported into a freshly installed canister".to_string())
+    }
+}
+
+/// Whether an export is being encoded. Retention and archiving hold off
+/// meanwhile, so the logs it has yet to write stay where they are.
+pub fn export_in_progress() -> bool {
+    STATE_EXPORT.with(|export| matches!(*export.borrow(), Some(StateExport::Writing(_))))
+}
+
+/// Replaces the state of a fresh canister with a decoded snapshot. The
+/// archives it lists belong to the canister it was taken from, so they are
+/// only read from here on.
+pub fn import_state(mut state: StateV1) -> Result<(), String> {
+    require_fresh()?;
+    state.archive.mark_imported();
+    install(state);
+    Ok(())
+}
+
+fn schedule_tick(scheduled: &'static LocalKey<Cell<bool>>, tick: fn()) {
+    if scheduled.with(|scheduled| scheduled.replace(true)) {
+        return;
+    }
+    ic_cdk_timers::set_timer(Duration::ZERO, move || {
+        scheduled.with(|scheduled| scheduled.set(false));
+        tick();
+    });
+}
+
+// Encodes PARTS_PER_TICK parts of the export, then reschedules itself until it is done
+fn export_tick() {
+    let Some(StateExport::Writing(mut writer)) = STATE_EXPORT.with(|export| export.borrow_mut().take()) else {
+        return;
+    };
+    let export = match writer.step(PARTS_PER_TICK) {
+        Ok(true) => StateExport::Ready(writer.finish()),
+        Ok(false) => {
+            schedule_tick(&EXPORT_TICK_SCHEDULED, export_tick);
+            StateExport::Writing(writer)
+        }
+        Err(error) => StateExport::Failed(error),
+    };
+    STATE_EXPORT.with(|current| *current.borrow_mut() = Some(export));
+}
+
+// Decodes PARTS_PER_TICK parts of the import and installs the state once it is complete
+fn import_tick() {
+    let Some(StateImport::Loading(mut loader)) = STATE_IMPORT.with(|import| import.borrow_mut().take()) else {
+        return;
+    };
+    let import = match loader.step(PARTS_PER_TICK) {
+        Ok(true) => match (*loader).finish().and_then(import_state) {
+            Ok(()) => {
+                crate::certify();
+                upload::resume_processing();
+                StateImport::Installed
+            }
+            Err(error) => StateImport::Failed(error),
+        },
+        Ok(false) => {
+            schedule_tick(&IMPORT_TICK_SCHEDULED, import_tick);
+            StateImport::Loading(loader)
+        }
+        Err(error) => StateImport::Failed(error),
+    };
+    STATE_IMPORT.with(|current| *current.borrow_mut() = Some(import));
+}
+
+/// Starts encoding a snapshot for `get_state_export_chunk` to serve,
+/// replacing any earlier one; `get_state_export_status` tells when it is ready.
+#[update]
+fn export_state_snapshot() -> Result<(), String> {
+    require_controller()?;
+    if crate::archive::is_running() {
+        return Err("Archiving is running; export once it has finished".to_string());
+    }
+    let writer = SnapshotWriter::new(ic_cdk::api::time())?;
+    STATE_EXPORT.with(|export| *export.borrow_mut() = Some(StateExport::Writing(writer)));
+    schedule_tick(&EXPORT_TICK_SCHEDULED, export_tick);
+    Ok(())
+}
+
+#[query]
+fn get_state_export_status() -> Result<SnapshotExportStatus, String> {
+    require_controller()?;
+    Ok(STATE_EXPORT.with(|export| match &*export.borrow() {
+        None => SnapshotExportStatus::None,
+        Some(StateExport::Writing(writer)) => SnapshotExportStatus::Writing {
+            bytes: writer.bytes_written(),
+        },
+        Some(StateExport::Ready(snapshot)) => SnapshotExportStatus::Ready(snapshot.manifest().clone()),
+        Some(StateExport::Failed(error)) => SnapshotExportStatus::Failed(error.clone()),
+    }))
+}
+
+#[query]
+fn get_state_export_chunk(index: u32) -> Result<serde_bytes::ByteBuf, String> {
+    require_controller()?;
+    STATE_EXPORT.with(|export| match &*export.borrow() {
+        Some(StateExport::Ready(snapshot)) => Ok(ByteBuf::from(snapshot.chunk(index)?.to_vec())),
+        _ => Err("No state export is ready".to_string()),
+    })
+}
+
+/// Frees the last export once it has been downloaded, or stops one being encoded.
+#[update]
+fn discard_state_export() -> Result<(), String> {
+    require_controller()?;
+    STATE_EXPORT.with(|export| export.borrow_mut().take());
+    Ok(())
+}
+
+/// Starts importing the snapshot `manifest` describes, replacing any import in progress.
+#[update]
+fn begin_state_import(manifest: SnapshotManifest) -> Result<(), String> {
+    require_controller()?;
+    require_fresh()?;
+    let import = SnapshotImport::new(manifest)?;
+    STATE_IMPORT.with(|current| *current.borrow_mut() = Some(StateImport::Receiving(import)));
+    Ok(())
+}
+
+/// Returns the number of chunks received so far.
+#[update]
+fn append_state_import_chunk(index: u32, data: serde_bytes::ByteBuf) -> Result<u32, String> {
+    require_controller()?;
+    STATE_IMPORT.with(|import| match import.borrow_mut().as_mut() {
+        Some(StateImport::Receiving(import)) => import.add_chunk(index, data.into_vec()),
+        _ => Err("No state import is receiving chunks".to_string()),
+    })
+}
+
+/// Starts decoding the snapshot once every chunk has arrived; it is
+/// installed if it matches its checksum, as `get_state_import_status` reports.
+#[update]
+fn commit_state_import() -> Result<(), String> {
+    require_controller()?;
+    require_fresh()?;
+    STATE_IMPORT.with(|current| {
+        let mut current = current.borrow_mut();
+        match current.take() {
+            Some(StateImport::Receiving(import)) => {
+                if let Some(missing) = import.missing_chunk() {
+                    *current = Some(StateImport::Receiving(import));
+                    return Err(format!("Chunk {} has not been received", missing));
+                }
+                *current = Some(match import.into_loader() {
+                    Ok(loader) => StateImport::Loading(Box::new(loader)),
+                    Err(error) => StateImport::Failed(error),
+                });
+                Ok(())
+            }
+            other => {
+                *current = other;
+                Err("No state import is receiving chunks".to_string())
+            }
+        }
+    })?;
+
+    schedule_tick(&IMPORT_TICK_SCHEDULED, import_tick);
+    Ok(())
+}
+
+#[query]
+fn get_state_import_status() -> Result<SnapshotImportStatus, String> {
+    require_controller()?;
+    Ok(STATE_IMPORT.with(|import| match &*import.borrow() {
+        None => SnapshotImportStatus::None,
+        Some(StateImport::Receiving(import)) => SnapshotImportStatus::Receiving {
+            chunks: import.chunks.len() as u32,
+        },
+        Some(StateImport::Loading(loader)) => SnapshotImportStatus::Loading {
+            bytes: loader.bytes_read(),
+        },
+        Some(StateImport::Installed) => SnapshotImportStatus::Installed,
+        Some(StateImport::Failed(error)) => SnapshotImportStatus::Failed(error.clone()),
+    }))
+}
@@ -0,0 +1,135 @@
+use crate::decode::flatten_json;
+use crate::ThreatOutput;
+use cybersec_log_model::timestamp::parse_rfc3339;
+use cybersec_log_model::LogRecord;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+pub const SOURCE: &str = "suricata";
+
+const EVENT_TYPES: [&str; 5] = ["alert", "http", "dns", "tls", "flow"];
+
+/// Parses Suricata EVE JSON (one event object per line) into log records.
+///
+/// Only the alert, http, dns, tls and flow event types are kept; stats and
+/// other housekeeping events are skipped.
+pub fn parse_eve(text: &str) -> Result<Vec<LogRecord>, String> {
+    let mut records = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let event: Value = serde_json::from_str(line)
+            .map_err(|e| format!("Invalid EVE JSON on line {}: {}", index + 1, e))?;
+        let event_type = event["event_type"].as_str().unwrap_or_default();
+        if EVENT_TYPES.contains(&event_type) {
+            records.push(to_record(&event, event_type));
+        }
+    }
+
+    Ok(records)
+}
+
+fn to_record(event: &Value, event_type: &str) -> LogRecord {
+    let mut attributes = BTreeMap::new();
+    flatten_json("", event, &mut attributes);
+
+    let text = |path: &str| attributes.get(path).cloned();
+    let message = match event_type {
+        "alert" => format!(
+            "Suricata alert {}: {}",
+            text("alert.signature_id").unwrap_or_default(),
+            text("alert.signature").unwrap_or_default()
+        ),
+        "http" => format!(
+            "HTTP {} {}{} {}",
+            text("http.http_method").unwrap_or_default(),
+            text("http.hostname").unwrap_or_default(),
+            text("http.url").unwrap_or_default(),
+            text("http.status").unwrap_or_default()
+        ),
+        "dns" => format!(
+            "DNS {} {} {} {}",
+            text("dns.type").unwrap_or_default(),
+            text("dns.rrname").unwrap_or_default(),
+            text("dns.rrtype").unwrap_or_default(),
+            text("dns.rcode").unwrap_or_default()
+        ),
+        "tls" => format!(
+            "TLS {} {} subject {}",
+            text("tls.sni").unwrap_or_default(),
+            text("tls.version").unwrap_or_default(),
+            text("tls.subject").unwrap_or_default()
+        ),
+        _ => format!(
+            "Flow {} {}:{} -> {}:{} {} bytes",
+            text("proto").unwrap_or_default(),
+            text("src_ip").unwrap_or_default(),
+            text("src_port").unwrap_or_default(),
+            text("dest_ip").unwrap_or_default(),
+            text("dest_port").unwrap_or_default(),
+            total_bytes(&attributes)
+        ),
+    };
+
+    // EVE severity runs from 1 (most severe) to 3
+    let severity = match text("alert.severity").as_deref() {
+        Some("1") => Some("HIGH".to_string()),
+        Some("2") => Some("MEDIUM".to_string()),
+        Some(_) => Some("LOW".to_string()),
+        None => None,
+    };
+
+    LogRecord {
+        timestamp: text("timestamp").as_deref().and_then(parse_rfc3339),
+        original_timestamp: text("timestamp"),
+        message,
+        source: Some(SOURCE.to_string()),
+        source_ip: text("src_ip"),
+        destination_ip: text("dest_ip"),
+        user: None,
+        host: text("host"),
+        action: Some(event_type.to_string()),
+        outcome: text("alert.action"),
+        severity,
+        attributes,
+        entities: None,
+    }
+}
+
+fn total_bytes(attributes: &BTreeMap<String, String>) -> u64 {
+    ["flow.bytes_toserver", "flow.bytes_toclient"]
+        .iter()
+        .filter_map(|key| attributes.get(*key)?.parse::<u64>().ok())
+        .sum()
+}
+
+/// Turns a Suricata alert record into a detection; other event types yield `None`.
+pub fn alert_detection(record: &LogRecord) -> Option<ThreatOutput> {
+    if record.source.as_deref() != Some(SOURCE) || record.action.as_deref() != Some("alert") {
+        return None;
+    }
+
+    let severity = record.severity.clone().unwrap_or_else(|| "LOW".to_string());
+    let confidence = match severity.as_str() {
+        "HIGH" => 0.9,
+        "MEDIUM" => 0.75,
+        _ => 0.6,
+    };
+    let source = record.source_ip.as_deref().unwrap_or("unknown");
+    Some(ThreatOutput::detection(
+        "ids_alert",
+        &severity,
+        confidence,
+        source,
+        format!(
+            "{} ({}) from {} to {}",
+            record.attribute("alert.signature").unwrap_or("Suricata alert"),
+            record.attribute("alert.category").unwrap_or("uncategorized"),
+            source,
+            record.destination_ip.as_deref().unwrap_or("unknown")
+        ),
+    ))
+}
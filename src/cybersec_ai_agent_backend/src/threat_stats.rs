@@ -0,0 +1,251 @@
+use candid::{CandidType, Deserialize};
+use std::collections::BTreeMap;
+
+const NANOS_PER_MINUTE: u64 = 60_000_000_000;
+/// Most series counts a reply holds, across all its buckets.
+pub const MAX_PAGE_COUNTS: usize = 1_000;
+
+/// Width of the buckets a query reads.
+#[derive(CandidType, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Granularity {
+    Minute,
+    #[default]
+    Hour,
+    Day,
+}
+
+impl Granularity {
+    pub fn width_nanos(self) -> u64 {
+        match self {
+            Granularity::Minute => NANOS_PER_MINUTE,
+            Granularity::Hour => 60 * NANOS_PER_MINUTE,
+            Granularity::Day => 24 * 60 * NANOS_PER_MINUTE,
+        }
+    }
+
+    /// Start of the bucket holding `time`.
+    pub fn bucket_start(self, time: u64) -> u64 {
+        time - time % self.width_nanos()
+    }
+}
+
+/// How many of the most recent buckets each granularity keeps.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThreatStatsConfig {
+    pub minutes_kept: u64,
+    pub hours_kept: u64,
+    pub days_kept: u64,
+}
+
+impl Default for ThreatStatsConfig {
+    fn default() -> Self {
+        ThreatStatsConfig {
+            minutes_kept: 24 * 60,
+            hours_kept: 30 * 24,
+            days_kept: 365,
+        }
+    }
+}
+
+/// What a detection is counted by.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ThreatSeries {
+    pub category: String,
+    /// Upper-cased
+    pub severity: String,
+    /// The producer of the record the detection came from, or the detector
+    pub source: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThreatCount {
+    pub series: ThreatSeries,
+    pub count: u64,
+}
+
+/// Detections counted in the bucket starting at `start`, by series.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThreatStatsBucket {
+    pub start: u64,
+    pub total: u64,
+    pub counts: Vec<ThreatCount>,
+}
+
+/// Where a page of buckets stopped: after `series` in the bucket at `start`.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThreatStatsCursor {
+    pub start: u64,
+    pub series: ThreatSeries,
+}
+
+/// Buckets of one granularity overlapping `from_time..=to_time`, counting
+/// only the series the filters match. A filter left out matches any value;
+/// `severity` matches case-insensitively.
+#[derive(CandidType, Deserialize, Clone, Debug, Default, PartialEq)]
+pub struct ThreatStatsQuery {
+    pub granularity: Granularity,
+    pub from_time: Option<u64>,
+    pub to_time: Option<u64>,
+    pub category: Option<String>,
+    pub severity: Option<String>,
+    pub source: Option<String>,
+    /// Series counts per page, at most 1000; 0 for the most
+    pub limit: u32,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<ThreatStatsCursor>,
+}
+
+/// A page of buckets. A bucket whose series did not all fit is continued on
+/// the next page; its `total` always covers every matching series.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ThreatStatsPage {
+    pub buckets: Vec<ThreatStatsBucket>,
+    /// Present when more series match; pass it back to get the next page
+    pub next_cursor: Option<ThreatStatsCursor>,
+}
+
+type Buckets = BTreeMap<u64, BTreeMap<ThreatSeries, u64>>;
+
+/// Detection counts over time. Every detection is counted into its minute,
+/// hour and day bucket at once, so each coarser level is the rollup of the
+/// finer ones and can be kept for longer; buckets past a level's retention
+/// are dropped as new detections arrive or `prune` runs.
+#[derive(CandidType, Deserialize, Default)]
+pub struct ThreatStats {
+    config: ThreatStatsConfig,
+    minutes: Buckets,
+    hours: Buckets,
+    days: Buckets,
+}
+
+impl ThreatStats {
+    pub fn new(config: ThreatStatsConfig) -> Self {
+        ThreatStats {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &ThreatStatsConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ThreatStatsConfig, now: u64) {
+        self.config = config;
+        self.prune(now);
+    }
+
+    pub fn record(&mut self, category: &str, severity: &str, source: &str, time: u64) {
+        let series = ThreatSeries {
+            category: category.to_string(),
+            severity: severity.to_uppercase(),
+            source: if source.is_empty() { "unknown" } else { source }.to_string(),
+        };
+        for granularity in [Granularity::Minute, Granularity::Hour, Granularity::Day] {
+            let bucket = self.level_mut(granularity).entry(granularity.bucket_start(time)).or_default();
+            *bucket.entry(series.clone()).or_insert(0) += 1;
+        }
+        self.prune(time);
+    }
+
+    /// Drops the buckets that fell out of each granularity's retention by `now`.
+    pub fn prune(&mut self, now: u64) {
+        for granularity in [Granularity::Minute, Granularity::Hour, Granularity::Day] {
+            let kept = match granularity {
+                Granularity::Minute => self.config.minutes_kept,
+                Granularity::Hour => self.config.hours_kept,
+                Granularity::Day => self.config.days_kept,
+            };
+            // The current bucket counts as one of those kept
+            let oldest = granularity
+                .bucket_start(now)
+                .saturating_sub(kept.saturating_sub(1).saturating_mul(granularity.width_nanos()));
+            let level = self.level_mut(granularity);
+            *level = if kept == 0 { BTreeMap::new() } else { level.split_off(&oldest) };
+        }
+    }
+
+    /// Non-empty buckets matching `query`, oldest first and series in order
+    /// within each; missing buckets counted nothing.
+    pub fn query(&self, query: &ThreatStatsQuery) -> Result<ThreatStatsPage, String> {
+        let (from, to) = (query.from_time.unwrap_or(0), query.to_time.unwrap_or(u64::MAX));
+        if from > to {
+            return Err(format!("from_time {} is after to_time {}", from, to));
+        }
+        let limit = match query.limit as usize {
+            0 => MAX_PAGE_COUNTS,
+            limit => limit.min(MAX_PAGE_COUNTS),
+        };
+        let mut from = query.granularity.bucket_start(from);
+        if let Some(cursor) = &query.cursor {
+            from = from.max(cursor.start);
+        }
+        let matches = |series: &ThreatSeries| {
+            query.category.as_ref().is_none_or(|category| &series.category == category)
+                && query.severity.as_ref().is_none_or(|severity| series.severity.eq_ignore_ascii_case(severity))
+                && query.source.as_ref().is_none_or(|source| &series.source == source)
+        };
+
+        let mut page = ThreatStatsPage {
+            buckets: Vec::new(),
+            next_cursor: None,
+        };
+        // The bucket start and series of the last count returned
+        let mut last: Option<(u64, &ThreatSeries)> = None;
+        let mut returned = 0;
+        for (start, counts) in self.level(query.granularity).range(from..=to) {
+            let total = counts.iter().filter(|(series, _)| matches(series)).map(|(_, count)| count).sum();
+            if total == 0 {
+                continue;
+            }
+            let after = query.cursor.as_ref().filter(|cursor| cursor.start == *start).map(|cursor| &cursor.series);
+            let mut bucket = ThreatStatsBucket {
+                start: *start,
+                total,
+                counts: Vec::new(),
+            };
+            for (series, count) in counts {
+                if !matches(series) || after.is_some_and(|after| series <= after) {
+                    continue;
+                }
+                if returned == limit {
+                    page.next_cursor = last.map(|(start, series)| ThreatStatsCursor {
+                        start,
+                        series: series.clone(),
+                    });
+                    break;
+                }
+                bucket.counts.push(ThreatCount {
+                    series: series.clone(),
+                    count: *count,
+                });
+                last = Some((*start, series));
+                returned += 1;
+            }
+            if !bucket.counts.is_empty() {
+                page.buckets.push(bucket);
+            }
+            if page.next_cursor.is_some() {
+                break;
+            }
+        }
+        Ok(page)
+    }
+
+    fn level(&self, granularity: Granularity) -> &Buckets {
+        match granularity {
+            Granularity::Minute => &self.minutes,
+            Granularity::Hour => &self.hours,
+            Granularity::Day => &self.days,
+        }
+    }
+
+    fn level_mut(&mut self, granularity: Granularity) -> &mut Buckets {
+        match granularity {
+            Granularity::Minute => &mut self.minutes,
+            Granularity::Hour => &mut self.hours,
+            Granularity::Day => &mut self.days,
+        }
+    }
+}
+
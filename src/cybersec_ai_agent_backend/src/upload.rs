@@ -0,0 +1,426 @@
+use crate::{ingest_log_records, ThreatOutput};
+use candid::{CandidType, Deserialize, Principal};
+use cybersec_log_model::LogRecord;
+use ic_cdk::{query, update};
+use sha2::{Digest, Sha256};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+// Stay well below the 2 MiB ingress message limit
+const MAX_CHUNK_BYTES: usize = 1_800_000;
+const MAX_SESSION_BYTES: u64 = 256 * 1024 * 1024;
+// Log lines classified per timer tick, so one tick never exhausts the instruction limit
+const LINES_PER_TICK: usize = 2_000;
+const MAX_RESULTS_PAGE: u64 = 1_000;
+// Sessions one caller may have open or processing at once
+const MAX_ACTIVE_SESSIONS_PER_CALLER: usize = 4;
+// Completed and failed sessions kept for their results; the earliest beyond this are dropped
+const MAX_FINISHED_SESSIONS: usize = 256;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum UploadState {
+    Open,
+    Processing,
+    Completed,
+    Failed(String),
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UploadProgress {
+    pub session_id: u64,
+    pub source: String,
+    pub state: UploadState,
+    pub chunks_received: u32,
+    pub bytes_received: u64,
+    pub total_lines: u64,
+    pub processed_lines: u64,
+    pub threats_found: u64,
+}
+
+/// A detection produced while processing an upload, with the line it came from.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct UploadDetection {
+    /// 1-based line number in the uploaded file
+    pub line: u64,
+    pub detection: ThreatOutput,
+}
+
+#[derive(CandidType, Deserialize)]
+struct UploadSession {
+    owner: Principal,
+    source: String,
+    state: UploadState,
+    chunks: BTreeMap<u32, Vec<u8>>,
+    chunks_received: u32,
+    bytes_received: u64,
+    lines: Vec<String>,
+    /// Line number in the uploaded file of each of `lines`
+    line_numbers: Vec<u64>,
+    total_lines: u64,
+    processed_lines: u64,
+    results: Vec<UploadDetection>,
+}
+
+impl UploadSession {
+    fn is_active(&self) -> bool {
+        matches!(self.state, UploadState::Open | UploadState::Processing)
+    }
+
+    fn check_owner(&self, caller: Principal, session_id: u64) -> Result<(), String> {
+        if self.owner == caller {
+            Ok(())
+        } else {
+            Err(format!("Upload session {} belongs to another caller", session_id))
+        }
+    }
+
+    fn progress(&self, session_id: u64) -> UploadProgress {
+        UploadProgress {
+            session_id,
+            source: self.source.clone(),
+            state: self.state.clone(),
+            chunks_received: self.chunks_received,
+            bytes_received: self.bytes_received,
+            total_lines: self.total_lines,
+            processed_lines: self.processed_lines,
+            threats_found: self.results.len() as u64,
+        }
+    }
+}
+
+thread_local! {
+    static UPLOAD_SESSIONS: RefCell<BTreeMap<u64, UploadSession>> = const { RefCell::new(BTreeMap::new()) };
+    static NEXT_SESSION_ID: Cell<u64> = const { Cell::new(1) };
+    static TICK_SCHEDULED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Upload sessions as kept across upgrades.
+#[derive(CandidType, Deserialize, Default)]
+pub struct UploadSnapshot {
+    sessions: BTreeMap<u64, UploadSession>,
+    next_session_id: u64,
+}
+
+pub(crate) fn take_snapshot() -> UploadSnapshot {
+    UploadSnapshot {
+        sessions: UPLOAD_SESSIONS.with(|sessions| std::mem::take(&mut *sessions.borrow_mut())),
+        next_session_id: NEXT_SESSION_ID.with(Cell::get),
+    }
+}
+
+// Encodes like `UploadSnapshot`, borrowing the live sessions
+#[derive(CandidType, Clone, Copy)]
+pub(crate) struct UploadSnapshotRef<'a> {
+    sessions: &'a BTreeMap<u64, UploadSession>,
+    next_session_id: u64,
+}
+
+pub(crate) fn with_snapshot_ref<R>(f: impl FnOnce(UploadSnapshotRef) -> R) -> R {
+    UPLOAD_SESSIONS.with(|sessions| {
+        f(UploadSnapshotRef {
+            sessions: &sessions.borrow(),
+            next_session_id: NEXT_SESSION_ID.with(Cell::get),
+        })
+    })
+}
+
+pub(crate) fn restore_snapshot(snapshot: UploadSnapshot) {
+    UPLOAD_SESSIONS.with(|sessions| *sessions.borrow_mut() = snapshot.sessions);
+    NEXT_SESSION_ID.with(|next| next.set(snapshot.next_session_id.max(1)));
+}
+
+/// Picks processing back up after an upgrade, which drops pending timers.
+pub(crate) fn resume_processing() {
+    TICK_SCHEDULED.with(|scheduled| scheduled.set(false));
+    let processing = UPLOAD_SESSIONS
+        .with(|sessions| sessions.borrow().values().any(|session| session.state == UploadState::Processing));
+    if processing {
+        schedule_tick();
+    }
+}
+
+// Runs `f` on a session owned by `caller`
+fn with_own_session<T>(
+    caller: Principal,
+    session_id: u64,
+    f: impl FnOnce(&mut UploadSession) -> Result<T, String>,
+) -> Result<T, String> {
+    UPLOAD_SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        let session = sessions
+            .get_mut(&session_id)
+            .ok_or_else(|| format!("Unknown upload session {}", session_id))?;
+        session.check_owner(caller, session_id)?;
+        f(session)
+    })
+}
+
+#[update]
+fn begin_upload_session(source: String) -> Result<u64, String> {
+    begin_session(ic_cdk::caller(), source)
+}
+
+/// Opens a session for a log batch; every line of the finalized upload is one message from `source`.
+pub(crate) fn begin_session(caller: Principal, source: String) -> Result<u64, String> {
+    let active = UPLOAD_SESSIONS.with(|sessions| {
+        let sessions = sessions.borrow();
+        sessions.values().filter(|session| session.owner == caller && session.is_active()).count()
+    });
+    if active >= MAX_ACTIVE_SESSIONS_PER_CALLER {
+        return Err(format!(
+            "At most {} upload sessions per caller may be open or processing",
+            MAX_ACTIVE_SESSIONS_PER_CALLER
+        ));
+    }
+
+    let session_id = NEXT_SESSION_ID.with(|next| {
+        let id = next.get();
+        next.set(id + 1);
+        id
+    });
+
+    let session = UploadSession {
+        owner: caller,
+        source,
+        state: UploadState::Open,
+        chunks: BTreeMap::new(),
+        chunks_received: 0,
+        bytes_received: 0,
+        lines: Vec::new(),
+        line_numbers: Vec::new(),
+        total_lines: 0,
+        processed_lines: 0,
+        results: Vec::new(),
+    };
+    UPLOAD_SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        expire_finished(&mut sessions);
+        sessions.insert(session_id, session);
+    });
+    Ok(session_id)
+}
+
+// Sessions only finish after being begun, so trimming here bounds them all
+fn expire_finished(sessions: &mut BTreeMap<u64, UploadSession>) {
+    let finished: Vec<u64> = sessions
+        .iter()
+        .filter(|(_, session)| !session.is_active())
+        .map(|(session_id, _)| *session_id)
+        .collect();
+    for session_id in finished.iter().take(finished.len().saturating_sub(MAX_FINISHED_SESSIONS)) {
+        sessions.remove(session_id);
+    }
+}
+
+#[update]
+fn append_upload_chunk(
+    session_id: u64,
+    sequence: u32,
+    data: serde_bytes::ByteBuf,
+    sha256: serde_bytes::ByteBuf,
+) -> Result<(), String> {
+    append_chunk(ic_cdk::caller(), session_id, sequence, data.into_vec(), &sha256)
+}
+
+/// Stores chunk `sequence` after checking it against its SHA-256. Re-sending an
+/// identical chunk is accepted, so clients can retry after a timeout.
+pub(crate) fn append_chunk(
+    caller: Principal,
+    session_id: u64,
+    sequence: u32,
+    data: Vec<u8>,
+    sha256: &[u8],
+) -> Result<(), String> {
+    if data.len() > MAX_CHUNK_BYTES {
+        return Err(format!("Chunk exceeds {} bytes", MAX_CHUNK_BYTES));
+    }
+    if Sha256::digest(&data).as_slice() != sha256 {
+        return Err(format!("SHA-256 mismatch for chunk {}", sequence));
+    }
+
+    with_own_session(caller, session_id, |session| {
+        if session.state != UploadState::Open {
+            return Err(format!("Upload session {} is no longer accepting chunks", session_id));
+        }
+        if let Some(existing) = session.chunks.get(&sequence) {
+            return if *existing == data {
+                Ok(())
+            } else {
+                Err(format!("Chunk {} was already received with different content", sequence))
+            };
+        }
+        if session.bytes_received + data.len() as u64 > MAX_SESSION_BYTES {
+            return Err(format!("Upload session exceeds {} bytes", MAX_SESSION_BYTES));
+        }
+
+        session.chunks_received += 1;
+        session.bytes_received += data.len() as u64;
+        session.chunks.insert(sequence, data);
+        Ok(())
+    })
+}
+
+#[update]
+fn finalize_upload_session(session_id: u64, total_chunks: u32) -> Result<UploadProgress, String> {
+    let progress = finalize_session(ic_cdk::caller(), session_id, total_chunks)?;
+    schedule_tick();
+    Ok(progress)
+}
+
+/// Closes the session once chunks `0..total_chunks` have all arrived and
+/// readies it for `process_batch`.
+pub(crate) fn finalize_session(
+    caller: Principal,
+    session_id: u64,
+    total_chunks: u32,
+) -> Result<UploadProgress, String> {
+    with_own_session(caller, session_id, |session| {
+        if session.state != UploadState::Open {
+            return Err(format!("Upload session {} was already finalized", session_id));
+        }
+        if let Some(missing) = (0..total_chunks).find(|seq| !session.chunks.contains_key(seq)) {
+            return Err(format!("Chunk {} has not been received", missing));
+        }
+        if session.chunks.len() as u32 != total_chunks {
+            return Err(format!(
+                "Received {} chunks but {} were declared",
+                session.chunks.len(),
+                total_chunks
+            ));
+        }
+
+        let mut payload = Vec::with_capacity(session.bytes_received as usize);
+        for chunk in std::mem::take(&mut session.chunks).into_values() {
+            payload.extend(chunk);
+        }
+        match String::from_utf8(payload) {
+            Ok(text) => {
+                let (numbers, lines) = text
+                    .lines()
+                    .zip(1..)
+                    .map(|(line, number)| (number, line.trim()))
+                    .filter(|(_, line)| !line.is_empty())
+                    .map(|(number, line)| (number, line.to_string()))
+                    .unzip();
+                session.line_numbers = numbers;
+                session.lines = lines;
+                session.total_lines = session.lines.len() as u64;
+                session.state = UploadState::Processing;
+            }
+            Err(_) => session.state = UploadState::Failed("Upload is not valid UTF-8".to_string()),
+        }
+        Ok(session.progress(session_id))
+    })
+}
+
+#[query]
+fn get_upload_progress(session_id: u64) -> Option<UploadProgress> {
+    session_progress(ic_cdk::caller(), session_id)
+}
+
+/// Progress of a session owned by `caller`.
+pub(crate) fn session_progress(caller: Principal, session_id: u64) -> Option<UploadProgress> {
+    UPLOAD_SESSIONS.with(|sessions| {
+        let sessions = sessions.borrow();
+        let session = sessions.get(&session_id)?;
+        session.check_owner(caller, session_id).ok()?;
+        Some(session.progress(session_id))
+    })
+}
+
+#[query]
+fn get_upload_results(session_id: u64, offset: u64, limit: u64) -> Result<Vec<UploadDetection>, String> {
+    session_results(ic_cdk::caller(), session_id, offset, limit)
+}
+
+/// A page of the detections found so far in a session owned by `caller`.
+pub(crate) fn session_results(
+    caller: Principal,
+    session_id: u64,
+    offset: u64,
+    limit: u64,
+) -> Result<Vec<UploadDetection>, String> {
+    let limit = limit.min(MAX_RESULTS_PAGE) as usize;
+    UPLOAD_SESSIONS.with(|sessions| {
+        let sessions = sessions.borrow();
+        let session = sessions
+            .get(&session_id)
+            .ok_or_else(|| format!("Unknown upload session {}", session_id))?;
+        session.check_owner(caller, session_id)?;
+        Ok(session.results.iter().skip(offset as usize).take(limit).cloned().collect())
+    })
+}
+
+#[update]
+fn delete_upload_session(session_id: u64) -> Result<(), String> {
+    delete_session(ic_cdk::caller(), session_id)
+}
+
+pub(crate) fn delete_session(caller: Principal, session_id: u64) -> Result<(), String> {
+    with_own_session(caller, session_id, |_| Ok(()))?;
+    UPLOAD_SESSIONS.with(|sessions| sessions.borrow_mut().remove(&session_id));
+    Ok(())
+}
+
+fn schedule_tick() {
+    if TICK_SCHEDULED.with(|scheduled| scheduled.replace(true)) {
+        return;
+    }
+    ic_cdk_timers::set_timer(Duration::ZERO, process_tick);
+}
+
+// Ingests up to LINES_PER_TICK lines of the oldest processing session, then reschedules itself
+fn process_tick() {
+    TICK_SCHEDULED.with(|scheduled| scheduled.set(false));
+    if process_batch(ingest_log_records) {
+        schedule_tick();
+    }
+}
+
+/// Runs the next LINES_PER_TICK lines of the oldest processing session
+/// through `ingest`, and returns whether any session still has lines left.
+pub(crate) fn process_batch(ingest: impl FnOnce(Vec<LogRecord>) -> Vec<Vec<ThreatOutput>>) -> bool {
+    // Copied out, so the sessions are not borrowed while the detectors run
+    let batch = UPLOAD_SESSIONS.with(|sessions| {
+        let sessions = sessions.borrow();
+        let (session_id, session) = sessions.iter().find(|(_, s)| s.state == UploadState::Processing)?;
+        let start = session.processed_lines as usize;
+        let end = (start + LINES_PER_TICK).min(session.lines.len());
+        let records: Vec<LogRecord> = session.lines[start..end]
+            .iter()
+            .map(|line| LogRecord {
+                source: Some(session.source.clone()),
+                ..LogRecord::new(line.clone())
+            })
+            .collect();
+        Some((*session_id, start, records))
+    });
+    let Some((session_id, start, records)) = batch else {
+        return false;
+    };
+    let end = start + records.len();
+    let findings = ingest(records);
+
+    UPLOAD_SESSIONS.with(|sessions| {
+        let mut sessions = sessions.borrow_mut();
+        if let Some(session) = sessions.get_mut(&session_id) {
+            for (index, findings) in findings.into_iter().enumerate() {
+                for output in findings {
+                    session.results.push(UploadDetection {
+                        line: session.line_numbers[start + index],
+                        detection: output,
+                    });
+                }
+            }
+            session.processed_lines = end as u64;
+
+            if end == session.lines.len() {
+                session.state = UploadState::Completed;
+                session.lines = Vec::new();
+                session.line_numbers = Vec::new();
+            }
+        }
+        sessions.values().any(|s| s.state == UploadState::Processing)
+    })
+}
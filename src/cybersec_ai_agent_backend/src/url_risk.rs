@@ -0,0 +1,351 @@
+use crate::decode::percent_decode;
+use crate::dns::split_domain;
+use crate::ThreatOutput;
+use candid::{CandidType, Deserialize};
+pub use cybersec_log_model::entities::extract_urls;
+use cybersec_log_model::LogRecord;
+use std::net::IpAddr;
+
+/// Brands most often impersonated, by the label they are registered under.
+pub const DEFAULT_BRANDS: [&str; 14] = [
+    "paypal", "microsoft", "office365", "apple", "amazon", "google", "netflix", "dhl", "fedex", "docusign",
+    "dropbox", "linkedin", "facebook", "wellsfargo",
+];
+// Score at which a URL is reported
+const RISK_THRESHOLD: f64 = 0.5;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct UrlRiskConfig {
+    /// Brand labels whose lookalikes are flagged, e.g. `paypal`
+    pub brands: Vec<String>,
+    /// Top-level domains mostly seen in abuse
+    pub suspicious_tlds: Vec<String>,
+    /// Hosts of URL shortening services
+    pub shorteners: Vec<String>,
+    /// Query parameters that commonly carry a redirect target
+    pub redirect_parameters: Vec<String>,
+    /// Subdomain labels beyond which a host is suspicious
+    pub max_subdomains: u32,
+}
+
+impl Default for UrlRiskConfig {
+    fn default() -> Self {
+        let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        UrlRiskConfig {
+            brands: strings(&DEFAULT_BRANDS),
+            suspicious_tlds: strings(&[
+                "zip", "mov", "top", "xyz", "tk", "ml", "ga", "cf", "gq", "work", "click", "country", "kim", "loan",
+                "men", "rest", "support", "cam", "icu", "buzz",
+            ]),
+            shorteners: strings(&[
+                "bit.ly", "tinyurl.com", "t.co", "goo.gl", "ow.ly", "is.gd", "buff.ly", "rebrand.ly", "cutt.ly",
+                "shorturl.at", "tiny.cc", "rb.gy", "s.id", "t.ly", "bl.ink",
+            ]),
+            redirect_parameters: strings(&[
+                "url", "redirect", "redirect_uri", "redirect_url", "next", "return", "returnurl", "return_to",
+                "goto", "dest", "destination", "continue", "target", "rurl", "out",
+            ]),
+            max_subdomains: 3,
+        }
+    }
+}
+
+/// One reason a URL looks risky.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct UrlFinding {
+    pub indicator: String,
+    pub details: String,
+    pub weight: f64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct UrlAnalysis {
+    pub url: String,
+    pub host: String,
+    /// Sum of the finding weights, capped below 1
+    pub score: f64,
+    pub is_threat: bool,
+    pub severity: String,
+    pub findings: Vec<UrlFinding>,
+}
+
+struct ParsedUrl<'a> {
+    userinfo: Option<&'a str>,
+    host: String,
+    query: Option<&'a str>,
+}
+
+fn parse_url(url: &str) -> Option<ParsedUrl<'_>> {
+    let (scheme, rest) = url.trim().split_once("://")?;
+    if scheme.is_empty() || !scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) {
+        return None;
+    }
+    let authority_end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+    let authority = &rest[..authority_end];
+    let (userinfo, host_port) = match authority.rsplit_once('@') {
+        Some((userinfo, host_port)) => (Some(userinfo), host_port),
+        None => (None, authority),
+    };
+    let host = match host_port.strip_prefix('[') {
+        Some(v6) => v6.split(']').next().unwrap_or_default(),
+        None => host_port.split(':').next().unwrap_or_default(),
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let query = rest[authority_end..]
+        .split_once('?')
+        .map(|(_, query)| query.split('#').next().unwrap_or_default());
+
+    Some(ParsedUrl {
+        userinfo,
+        host: host.trim_end_matches('.').to_lowercase(),
+        query,
+    })
+}
+
+/// Scores a URL against every indicator and lists the ones it trips.
+pub fn analyze_url(url: &str, config: &UrlRiskConfig) -> Result<UrlAnalysis, String> {
+    let parsed = parse_url(url).ok_or_else(|| format!("Not an absolute URL: {}", url))?;
+    let host = parsed.host.as_str();
+    let mut findings = Vec::new();
+    let mut finding = |indicator: &str, weight: f64, details: String| {
+        findings.push(UrlFinding {
+            indicator: indicator.to_string(),
+            details,
+            weight,
+        })
+    };
+
+    if let Some(ip) = ip_literal(host) {
+        finding("ip_literal_host", 0.4, format!("host is the IP address {}", ip));
+    } else {
+        for (label, brand) in homographs(host, &config.brands) {
+            finding("homograph", 0.6, format!("{} imitates {}", label, brand));
+        }
+        let tld = host.rsplit('.').next().unwrap_or_default();
+        if config.suspicious_tlds.iter().any(|suspicious| suspicious.eq_ignore_ascii_case(tld)) {
+            finding("suspicious_tld", 0.25, format!("top-level domain .{}", tld));
+        }
+        let (subdomain, _) = split_domain(host);
+        let subdomains = if subdomain.is_empty() { 0 } else { subdomain.split('.').count() as u32 };
+        if subdomains > config.max_subdomains {
+            finding("excessive_subdomains", 0.25, format!("{} subdomain levels in {}", subdomains, host));
+        }
+        if config.shorteners.iter().any(|shortener| shortener.eq_ignore_ascii_case(host)) {
+            finding("url_shortener", 0.2, format!("{} hides the destination", host));
+        }
+    }
+    if let Some(userinfo) = parsed.userinfo {
+        let details = if userinfo.contains(':') {
+            "URL embeds a username and password".to_string()
+        } else {
+            format!("{}@ before the host disguises it", userinfo)
+        };
+        finding("credentials_in_url", 0.4, details);
+    }
+    for (name, target) in redirect_targets(parsed.query.unwrap_or_default(), &config.redirect_parameters) {
+        finding("open_redirect", 0.35, format!("parameter {} redirects to {}", name, target));
+    }
+
+    let score = findings.iter().map(|finding| finding.weight).sum::<f64>().min(0.99);
+    Ok(UrlAnalysis {
+        url: url.trim().to_string(),
+        host: parsed.host.clone(),
+        score,
+        is_threat: score >= RISK_THRESHOLD,
+        severity: match score {
+            s if s >= 0.8 => "HIGH",
+            s if s >= RISK_THRESHOLD => "MEDIUM",
+            s if s > 0.0 => "LOW",
+            _ => "INFO",
+        }
+        .to_string(),
+        findings,
+    })
+}
+
+// Dotted quads, IPv6 and the single-number forms browsers still resolve (3232235777, 0xc0a80101)
+fn ip_literal(host: &str) -> Option<String> {
+    if let Ok(ip) = host.parse::<IpAddr>() {
+        return Some(ip.to_string());
+    }
+    let number = match host.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None if host.chars().all(|c| c.is_ascii_digit()) => host.parse::<u32>().ok(),
+        None => None,
+    }?;
+    Some(std::net::Ipv4Addr::from(number).to_string())
+}
+
+// Internationalized labels that read as a brand once lookalike characters are mapped to ASCII
+fn homographs(host: &str, brands: &[String]) -> Vec<(String, String)> {
+    let mut found = Vec::new();
+    for label in host.split('.') {
+        let decoded = match label.strip_prefix("xn--") {
+            Some(encoded) => match punycode_decode(encoded) {
+                Some(decoded) => decoded,
+                None => continue,
+            },
+            None => label.to_string(),
+        };
+        if decoded.is_ascii() {
+            continue;
+        }
+        let skeleton: String = decoded.chars().map(confusable).collect();
+        let skeleton = skeleton.replace('-', "");
+        if let Some(brand) = brands.iter().find(|brand| skeleton.contains(&brand.to_ascii_lowercase())) {
+            let shown = if decoded == label { decoded } else { format!("{} ({})", label, decoded) };
+            found.push((shown, brand.clone()));
+        }
+    }
+    found
+}
+
+// The ASCII letter a Cyrillic, Greek or Latin-extended character is mistaken for
+fn confusable(c: char) -> char {
+    match c {
+        'а' | 'α' | 'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ɑ' => 'a',
+        'ь' | 'Ƅ' => 'b',
+        'с' | 'ϲ' | 'ç' => 'c',
+        'ԁ' | 'ɗ' => 'd',
+        'е' | 'ё' | 'è' | 'é' | 'ê' | 'ë' | 'ε' => 'e',
+        'ɡ' | 'ġ' => 'g',
+        'һ' => 'h',
+        'і' | 'ı' | 'ì' | 'í' | 'î' | 'ï' | 'ι' => 'i',
+        'ј' => 'j',
+        'κ' | 'к' => 'k',
+        'ӏ' | 'ł' | 'ǀ' => 'l',
+        'м' => 'm',
+        'ո' | 'п' | 'ñ' => 'n',
+        'о' | 'ο' | 'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'օ' => 'o',
+        'р' | 'ρ' => 'p',
+        'ԛ' => 'q',
+        'г' => 'r',
+        'ѕ' | 'ş' => 's',
+        'т' | 'τ' => 't',
+        'υ' | 'ս' | 'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'ν' | 'ѵ' => 'v',
+        'ԝ' | 'ѡ' | 'ω' => 'w',
+        'х' | 'χ' => 'x',
+        'у' | 'ý' | 'ÿ' | 'γ' => 'y',
+        'ᴢ' | 'ž' => 'z',
+        c => c.to_lowercase().next().unwrap_or(c),
+    }
+}
+
+/// Decodes the part of an IDNA label after `xn--` (RFC 3492).
+pub fn punycode_decode(encoded: &str) -> Option<String> {
+    const BASE: u32 = 36;
+    const T_MIN: u32 = 1;
+    const T_MAX: u32 = 26;
+    const SKEW: u32 = 38;
+    const DAMP: u32 = 700;
+
+    let adapt = |delta: u32, points: u32, first: bool| {
+        let mut delta = if first { delta / DAMP } else { delta / 2 };
+        delta += delta / points;
+        let mut k = 0;
+        while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+            delta /= BASE - T_MIN;
+            k += BASE;
+        }
+        k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+    };
+
+    let (basic, extended) = match encoded.rfind('-') {
+        Some(position) => (&encoded[..position], &encoded[position + 1..]),
+        None => ("", encoded),
+    };
+    if !basic.is_ascii() {
+        return None;
+    }
+    let mut output: Vec<char> = basic.chars().collect();
+    let (mut n, mut i, mut bias) = (128u32, 0u32, 72u32);
+    let mut digits = extended.bytes().peekable();
+    while digits.peek().is_some() {
+        let old_i = i;
+        let mut weight = 1u32;
+        let mut k = BASE;
+        loop {
+            let digit = match digits.next()? {
+                byte @ b'a'..=b'z' => byte - b'a',
+                byte @ b'A'..=b'Z' => byte - b'A',
+                byte @ b'0'..=b'9' => byte - b'0' + 26,
+                _ => return None,
+            } as u32;
+            i = i.checked_add(digit.checked_mul(weight)?)?;
+            let threshold = if k <= bias {
+                T_MIN
+            } else if k >= bias + T_MAX {
+                T_MAX
+            } else {
+                k - bias
+            };
+            if digit < threshold {
+                break;
+            }
+            weight = weight.checked_mul(BASE - threshold)?;
+            k += BASE;
+        }
+        let points = output.len() as u32 + 1;
+        bias = adapt(i - old_i, points, old_i == 0);
+        n = n.checked_add(i / points)?;
+        i %= points;
+        output.insert(i as usize, char::from_u32(n)?);
+        i += 1;
+    }
+    Some(output.into_iter().collect())
+}
+
+// Redirect parameters whose value is an absolute or protocol-relative URL
+fn redirect_targets(query: &str, parameters: &[String]) -> Vec<(String, String)> {
+    query
+        .split('&')
+        .filter_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            if !parameters.iter().any(|parameter| parameter.eq_ignore_ascii_case(name)) {
+                return None;
+            }
+            let target = percent_decode(value);
+            let lowered = target.to_ascii_lowercase();
+            let external = lowered.starts_with("http://") || lowered.starts_with("https://") || lowered.starts_with("//");
+            external.then(|| (name.to_string(), target))
+        })
+        .collect()
+}
+
+/// Analyzes the URLs in a record's message and attributes and reports the
+/// riskiest one, with every finding in the details; other risky URLs in the
+/// same record are appended. Records without a risky URL yield `None`.
+pub fn detection(record: &LogRecord, config: &UrlRiskConfig) -> Option<ThreatOutput> {
+    let mut urls = extract_urls(&record.message);
+    for value in record.attributes.values().filter(|value| value.contains("://")) {
+        for url in extract_urls(value) {
+            if !urls.contains(&url) {
+                urls.push(url);
+            }
+        }
+    }
+
+    let mut risky: Vec<UrlAnalysis> = urls
+        .iter()
+        .filter_map(|url| analyze_url(url, config).ok())
+        .filter(|analysis| analysis.is_threat)
+        .collect();
+    risky.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let leading = risky.first()?;
+    let describe = |analysis: &UrlAnalysis| {
+        let findings: Vec<&str> = analysis.findings.iter().map(|finding| finding.details.as_str()).collect();
+        format!("Risky URL {}: {}", analysis.url, findings.join("; "))
+    };
+    let details: Vec<String> = risky.iter().map(describe).collect();
+
+    Some(ThreatOutput::detection(
+        "malicious_url",
+        &leading.severity,
+        leading.score,
+        &leading.url,
+        details.join("; "),
+    ))
+}
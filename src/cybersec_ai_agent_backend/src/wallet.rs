@@ -7,7 +7,7 @@ use std::fmt;
 
 // Use thread_local instead of lazy_static for IC canisters
 thread_local! {
-    static WALLET_STORE: RefCell<HashMap<Principal, String>> = RefCell::new(HashMap::new());
+    pub(crate) static WALLET_STORE: RefCell<HashMap<Principal, String>> = RefCell::new(HashMap::new());
 }
 
 // Define a custom error type that implements both Error and CandidType
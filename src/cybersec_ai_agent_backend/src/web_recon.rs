@@ -0,0 +1,313 @@
+use crate::detection::NANOS_PER_SECOND;
+use crate::ThreatOutput;
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::timestamp::{parse_timestamp, TimestampConfig};
+use cybersec_log_model::LogRecord;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+pub const SOURCE: &str = "access_log";
+
+const MAX_QUERIED_PATHS: usize = 256;
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct WebReconConfig {
+    /// Each client is judged over consecutive windows of this length
+    pub window_secs: u64,
+    /// Requests in a window before the 404 ratio counts
+    pub min_requests: u64,
+    /// Share of 404 responses that indicates blind enumeration
+    pub not_found_ratio: f64,
+    /// Distinct sensitive paths in a window that indicate enumeration
+    pub sensitive_path_threshold: u64,
+    /// Distinct query strings against one path in a window that indicate fuzzing
+    pub fuzzing_threshold: u64,
+    /// Case-insensitive substrings of scanner user-agents
+    pub tool_user_agents: Vec<String>,
+    /// Case-insensitive path substrings worth probing for
+    pub sensitive_paths: Vec<String>,
+}
+
+impl Default for WebReconConfig {
+    fn default() -> Self {
+        let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        WebReconConfig {
+            window_secs: 300,
+            min_requests: 20,
+            not_found_ratio: 0.5,
+            sensitive_path_threshold: 3,
+            fuzzing_threshold: 20,
+            tool_user_agents: strings(&[
+                "sqlmap", "nikto", "nuclei", "gobuster", "masscan", "dirbuster", "dirb", "wfuzz", "ffuf",
+                "feroxbuster", "wpscan", "acunetix", "nmap", "zgrab", "nessus", "openvas", "hydra",
+            ]),
+            sensitive_paths: strings(&[
+                "/.git/", "/.env", "/.svn/", "/.hg/", "/.ds_store", "/.htaccess", "/.htpasswd", "/.aws/",
+                "/.ssh/", "/wp-admin", "/wp-login.php", "/xmlrpc.php", "/phpmyadmin", "/server-status",
+                "/actuator", "/config.php", "/web.config", "/backup", "/etc/passwd", "/cgi-bin/",
+            ]),
+        }
+    }
+}
+
+/// Parses Apache/nginx access logs in the Common or Combined Log Format,
+/// reading times without a zone in `config`'s.
+pub fn parse_access_log(text: &str, config: &TimestampConfig) -> Result<Vec<LogRecord>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            parse_line(line, config).ok_or_else(|| format!("Access log line {} is not in Common or Combined Log Format", index + 1))
+        })
+        .collect()
+}
+
+// host ident user [time] "request" status bytes ["referer" "user-agent"]
+fn parse_line(line: &str, config: &TimestampConfig) -> Option<LogRecord> {
+    let mut rest = line.trim();
+    let mut word = || {
+        let (word, tail) = rest.split_once(' ').unwrap_or((rest, ""));
+        rest = tail.trim_start();
+        word.to_string()
+    };
+    let client = word();
+    let _ident = word();
+    let user = word();
+
+    let time_start = rest.strip_prefix('[')?;
+    let (time, tail) = time_start.split_once(']')?;
+    let (request, tail) = quoted(tail.trim_start())?;
+    let mut fields = tail.split_whitespace();
+    let status = fields.next()?.to_string();
+    let bytes = fields.next().unwrap_or("-").to_string();
+    let tail = tail.trim_start().splitn(3, ' ').nth(2).unwrap_or_default();
+    let (referer, user_agent) = match quoted(tail.trim_start()) {
+        Some((referer, tail)) => (Some(referer), quoted(tail.trim_start()).map(|(agent, _)| agent)),
+        None => (None, None),
+    };
+
+    status.parse::<u16>().ok()?;
+    let mut parts = request.splitn(3, ' ');
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let protocol = parts.next().unwrap_or_default().to_string();
+
+    let mut attributes = BTreeMap::new();
+    for (key, value) in [
+        ("method", Some(method.clone())),
+        ("path", Some(path.clone())),
+        ("protocol", Some(protocol)),
+        ("status", Some(status.clone())),
+        ("bytes", Some(bytes)),
+        ("referer", referer),
+        ("user_agent", user_agent),
+    ] {
+        if let Some(value) = value.filter(|value| !value.is_empty() && value != "-") {
+            attributes.insert(key.to_string(), value);
+        }
+    }
+
+    Some(LogRecord {
+        timestamp: parse_timestamp(time, config),
+        original_timestamp: Some(time.to_string()),
+        message: format!("HTTP {} {} {} from {}", method, path, status, client),
+        source: Some(SOURCE.to_string()),
+        source_ip: Some(client),
+        user: (user != "-").then_some(user),
+        action: Some(method),
+        outcome: Some(status),
+        attributes,
+        ..Default::default()
+    })
+}
+
+// A double-quoted field with backslash escapes, and what follows it
+fn quoted(text: &str) -> Option<(String, &str)> {
+    let body = text.strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = body.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+            '"' => return Some((value, &body[index + 1..])),
+            c => value.push(c),
+        }
+    }
+    None
+}
+
+/// An HTTP request as seen in an access log, Zeek http.log or Suricata http event.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WebRequest {
+    pub client: String,
+    pub path: String,
+    pub status: Option<u16>,
+    pub user_agent: Option<String>,
+}
+
+/// Pulls the HTTP request out of a web log record; other records yield `None`.
+pub fn web_request(record: &LogRecord) -> Option<WebRequest> {
+    let (path, status, user_agent) = match (record.source.as_deref(), record.action.as_deref()) {
+        (Some(SOURCE), _) => ("path", "status", "user_agent"),
+        (Some(crate::zeek::SOURCE), Some("http")) => ("uri", "status_code", "user_agent"),
+        (Some(crate::suricata::SOURCE), Some("http")) => ("http.url", "http.status", "http.http_user_agent"),
+        _ => return None,
+    };
+
+    Some(WebRequest {
+        client: record.source_ip.clone()?,
+        path: record.attribute(path)?.to_string(),
+        status: record.attribute(status).and_then(|status| status.parse().ok()),
+        user_agent: record.attribute(user_agent).map(str::to_string),
+    })
+}
+
+#[derive(CandidType, Deserialize, Default)]
+struct ClientWindow {
+    start: u64,
+    requests: u64,
+    not_found: u64,
+    tools: BTreeSet<String>,
+    // Capped at the sensitive path threshold
+    sensitive_paths: BTreeSet<String>,
+    // Distinct query strings per path, capped at the fuzzing threshold, for
+    // at most MAX_QUERIED_PATHS paths
+    queries: HashMap<String, HashSet<String>>,
+    reported: bool,
+}
+
+/// Aggregates each client's web requests over fixed windows and reports a
+/// single reconnaissance detection per client and window, as soon as any
+/// indicator trips, listing every indicator seen by then.
+#[derive(CandidType, Deserialize, Default)]
+pub struct WebReconDetector {
+    config: WebReconConfig,
+    watermark: u64,
+    last_eviction: u64,
+    clients: HashMap<String, ClientWindow>,
+}
+
+impl WebReconDetector {
+    pub fn new(config: WebReconConfig) -> Self {
+        WebReconDetector {
+            config,
+            ..Default::default()
+        }
+    }
+
+    pub fn config(&self) -> &WebReconConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: WebReconConfig) {
+        self.config = config;
+    }
+
+    /// Observes `record` if it is a web request; records without an event
+    /// time are taken to have happened at `now`.
+    pub fn observe(&mut self, record: &LogRecord, now: u64) -> Option<ThreatOutput> {
+        let request = web_request(record)?;
+        let time = record.timestamp.unwrap_or(now);
+        let window = self.config.window_secs.max(1).saturating_mul(NANOS_PER_SECOND);
+        let window_start = time - time % window;
+        if time > self.watermark {
+            self.watermark = time;
+            if self.watermark - self.last_eviction >= window {
+                self.last_eviction = self.watermark;
+                let horizon = self.watermark.saturating_sub(window);
+                self.clients.retain(|_, client| client.start >= horizon);
+            }
+        }
+
+        let state = self.clients.entry(request.client.clone()).or_default();
+        if window_start > state.start {
+            *state = ClientWindow {
+                start: window_start,
+                ..Default::default()
+            };
+        } else if window_start < state.start {
+            // Too late for the window it belongs to
+            return None;
+        }
+
+        state.requests += 1;
+        if request.status == Some(404) {
+            state.not_found += 1;
+        }
+        if let Some(agent) = &request.user_agent {
+            let agent = agent.to_ascii_lowercase();
+            if let Some(tool) = self.config.tool_user_agents.iter().find(|tool| agent.contains(&tool.to_ascii_lowercase())) {
+                state.tools.insert(tool.clone());
+            }
+        }
+        let (path, query) = request.path.split_once('?').unwrap_or((&request.path, ""));
+        let lowered = path.to_ascii_lowercase();
+        if (state.sensitive_paths.len() as u64) < self.config.sensitive_path_threshold
+            && self.config.sensitive_paths.iter().any(|sensitive| lowered.contains(&sensitive.to_ascii_lowercase()))
+        {
+            state.sensitive_paths.insert(path.to_string());
+        }
+        let tracked = state.queries.contains_key(path) || state.queries.len() < MAX_QUERIED_PATHS;
+        if !query.is_empty() && self.config.fuzzing_threshold > 0 && tracked {
+            let variants = state.queries.entry(path.to_string()).or_default();
+            if (variants.len() as u64) < self.config.fuzzing_threshold {
+                variants.insert(query.to_string());
+            }
+        }
+
+        if state.reported {
+            return None;
+        }
+        let indicators = indicators(state, &self.config);
+        if indicators.is_empty() {
+            return None;
+        }
+        state.reported = true;
+
+        let has_tool = !state.tools.is_empty();
+        let confidence = if has_tool { 0.9 } else { (0.5 + 0.15 * (indicators.len() - 1) as f64).min(0.85) };
+        Some(ThreatOutput::detection(
+            "web_reconnaissance",
+            if has_tool || indicators.len() >= 3 { "HIGH" } else { "MEDIUM" },
+            confidence,
+            &request.client,
+            format!(
+                "Web reconnaissance from {} within {}s: {}",
+                request.client,
+                self.config.window_secs,
+                indicators.join("; ")
+            ),
+        ))
+    }
+}
+
+fn indicators(state: &ClientWindow, config: &WebReconConfig) -> Vec<String> {
+    let mut indicators = Vec::new();
+    if !state.tools.is_empty() {
+        let tools: Vec<&str> = state.tools.iter().map(String::as_str).collect();
+        indicators.push(format!("scanner user-agent {}", tools.join(", ")));
+    }
+    if state.requests >= config.min_requests
+        && state.not_found as f64 >= state.requests as f64 * config.not_found_ratio
+    {
+        indicators.push(format!("{} of {} requests returned 404", state.not_found, state.requests));
+    }
+    if config.sensitive_path_threshold > 0 && state.sensitive_paths.len() as u64 >= config.sensitive_path_threshold {
+        let paths: Vec<&str> = state.sensitive_paths.iter().map(String::as_str).collect();
+        indicators.push(format!("probed sensitive paths {}", paths.join(", ")));
+    }
+    let mut fuzzed: Vec<&str> = state
+        .queries
+        .iter()
+        .filter(|(_, variants)| config.fuzzing_threshold > 0 && variants.len() as u64 >= config.fuzzing_threshold)
+        .map(|(path, _)| path.as_str())
+        .collect();
+    if !fuzzed.is_empty() {
+        fuzzed.sort_unstable();
+        indicators.push(format!(
+            "fuzzed parameters of {} ({}+ variants each)",
+            fuzzed.join(", "),
+            config.fuzzing_threshold
+        ));
+    }
+    indicators
+}
@@ -0,0 +1,384 @@
+use crate::detection::{first_report, NANOS_PER_SECOND};
+use crate::event_time::{EventTimeStats, SlidingWindowCounter};
+use crate::ThreatOutput;
+use candid::{CandidType, Deserialize};
+use cybersec_log_model::timestamp::parse_rfc3339;
+use cybersec_log_model::LogRecord;
+use std::collections::{BTreeMap, HashMap};
+
+// Failed logons (4625) from the same source within this window count as one burst
+const FAILED_LOGON_WINDOW_NS: u64 = 5 * 60 * NANOS_PER_SECOND;
+const FAILED_LOGON_THRESHOLD: usize = 5;
+// A source's burst is reported again only after this many seconds
+const FAILED_LOGON_SUPPRESSION_SECS: u64 = 3_600;
+
+// Command-line fragments that make a 4688 process launch worth flagging
+const SUSPICIOUS_COMMAND_LINES: [(&str, &str); 14] = [
+    ("-encodedcommand", "encoded PowerShell command"),
+    (" -enc ", "encoded PowerShell command"),
+    ("-nop -w hidden", "hidden PowerShell window"),
+    ("downloadstring(", "PowerShell download cradle"),
+    ("certutil -urlcache", "certutil used as downloader"),
+    ("bitsadmin /transfer", "bitsadmin used as downloader"),
+    ("regsvr32 /s /n /u /i:http", "regsvr32 remote scriptlet (squiblydoo)"),
+    ("mshta http", "mshta remote script"),
+    ("rundll32 javascript:", "rundll32 script execution"),
+    ("vssadmin delete shadows", "shadow copy deletion"),
+    ("wmic shadowcopy delete", "shadow copy deletion"),
+    ("net user /add", "local account created from command line"),
+    ("sekurlsa::", "mimikatz credential dumping"),
+    ("lsass", "LSASS access"),
+];
+
+const SUSPICIOUS_PROCESSES: [&str; 3] = ["mimikatz.exe", "procdump.exe", "psexesvc.exe"];
+
+/// One Windows Security event, flattened from the Event XML schema.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct WindowsEvent {
+    pub event_id: u32,
+    pub record_id: Option<u64>,
+    pub provider: String,
+    pub computer: String,
+    pub time_created: String,
+    pub target_user_name: Option<String>,
+    pub subject_user_name: Option<String>,
+    pub ip_address: Option<String>,
+    pub logon_type: Option<u32>,
+    pub process_name: Option<String>,
+    pub command_line: Option<String>,
+    // Every named <Data> (or <UserData>) field, in document order
+    pub data: Vec<(String, String)>,
+}
+
+impl WindowsEvent {
+    pub fn field(&self, name: &str) -> Option<&str> {
+        self.data
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl From<&WindowsEvent> for LogRecord {
+    fn from(event: &WindowsEvent) -> Self {
+        let mut attributes: BTreeMap<String, String> = event.data.iter().cloned().collect();
+        attributes.insert("EventID".to_string(), event.event_id.to_string());
+        if let Some(record_id) = event.record_id {
+            attributes.insert("EventRecordID".to_string(), record_id.to_string());
+        }
+
+        LogRecord {
+            timestamp: parse_rfc3339(&event.time_created),
+            original_timestamp: Some(event.time_created.clone()),
+            message: format!("Windows event {} from {}", event.event_id, event.provider),
+            source: Some(event.provider.clone()),
+            source_ip: event.ip_address.clone(),
+            destination_ip: None,
+            user: event.target_user_name.clone().or_else(|| event.subject_user_name.clone()),
+            host: Some(event.computer.clone()),
+            action: Some(event.event_id.to_string()),
+            outcome: match event.event_id {
+                4625 => Some("failure".to_string()),
+                4624 => Some("success".to_string()),
+                _ => None,
+            },
+            severity: None,
+            attributes,
+            entities: None,
+        }
+    }
+}
+
+/// Parses a wevtutil export or a forwarded-events file. Both a bare sequence
+/// of `<Event>` elements and a document wrapped in `<Events>` are accepted.
+pub fn parse_events(xml: &str) -> Result<Vec<WindowsEvent>, String> {
+    let body = strip_xml_declaration(xml);
+    let wrapped = format!("<Events>{}</Events>", body);
+    let doc = roxmltree::Document::parse(&wrapped)
+        .map_err(|e| format!("Invalid Windows event XML: {}", e))?;
+
+    doc.descendants()
+        .filter(|node| node.tag_name().name() == "Event")
+        .map(parse_event)
+        .collect()
+}
+
+fn strip_xml_declaration(xml: &str) -> &str {
+    let trimmed = xml.trim_start_matches('\u{feff}').trim_start();
+    if trimmed.starts_with("<?xml") {
+        match trimmed.find("?>") {
+            Some(end) => &trimmed[end + 2..],
+            None => trimmed,
+        }
+    } else {
+        trimmed
+    }
+}
+
+fn parse_event(event: roxmltree::Node) -> Result<WindowsEvent, String> {
+    let system = child(event, "System").ok_or("Event is missing its <System> element")?;
+
+    let event_id = child(system, "EventID")
+        .and_then(|node| node.text())
+        .and_then(|text| text.trim().parse::<u32>().ok())
+        .ok_or("Event is missing a numeric <EventID>")?;
+
+    let record_id = child(system, "EventRecordID")
+        .and_then(|node| node.text())
+        .and_then(|text| text.trim().parse::<u64>().ok());
+    let provider = child(system, "Provider")
+        .and_then(|node| node.attribute("Name"))
+        .unwrap_or_default()
+        .to_string();
+    let computer = child(system, "Computer")
+        .and_then(|node| node.text())
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+    let time_created = child(system, "TimeCreated")
+        .and_then(|node| node.attribute("SystemTime"))
+        .unwrap_or_default()
+        .to_string();
+
+    let mut data = Vec::new();
+    if let Some(event_data) = child(event, "EventData") {
+        for item in event_data.children().filter(|n| n.tag_name().name() == "Data") {
+            if let Some(name) = item.attribute("Name") {
+                data.push((name.to_string(), item.text().unwrap_or_default().trim().to_string()));
+            }
+        }
+    }
+    // Events such as 1102 carry their payload as <UserData><LogFileCleared>...</LogFileCleared>
+    if let Some(user_data) = child(event, "UserData") {
+        for item in user_data.descendants().filter(|n| n.is_element() && !has_element_children(*n)) {
+            if item == user_data {
+                continue;
+            }
+            data.push((
+                item.tag_name().name().to_string(),
+                item.text().unwrap_or_default().trim().to_string(),
+            ));
+        }
+    }
+
+    let mut parsed = WindowsEvent {
+        event_id,
+        record_id,
+        provider,
+        computer,
+        time_created,
+        target_user_name: None,
+        subject_user_name: None,
+        ip_address: None,
+        logon_type: None,
+        process_name: None,
+        command_line: None,
+        data,
+    };
+    parsed.target_user_name = present(parsed.field("TargetUserName"));
+    parsed.subject_user_name = present(parsed.field("SubjectUserName"));
+    parsed.ip_address = present(parsed.field("IpAddress"));
+    parsed.logon_type = parsed.field("LogonType").and_then(|v| v.parse().ok());
+    // 4688 names the launched image NewProcessName; logon events use ProcessName
+    parsed.process_name = present(parsed.field("NewProcessName")).or_else(|| present(parsed.field("ProcessName")));
+    parsed.command_line = present(parsed.field("CommandLine"));
+
+    Ok(parsed)
+}
+
+fn child<'a, 'input>(node: roxmltree::Node<'a, 'input>, name: &str) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children().find(|n| n.tag_name().name() == name)
+}
+
+fn has_element_children(node: roxmltree::Node) -> bool {
+    node.children().any(|n| n.is_element())
+}
+
+// Windows writes "-" for fields that do not apply to the event
+fn present(value: Option<&str>) -> Option<String> {
+    match value {
+        Some(v) if !v.is_empty() && v != "-" => Some(v.to_string()),
+        _ => None,
+    }
+}
+
+/// Counts failed logons per source across batches, so bursts spanning
+/// several of them are still recognised, and reports each source's burst
+/// once per suppression period.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct FailedLogonDetector {
+    window: SlidingWindowCounter,
+    // When each source's burst was last reported
+    reported: HashMap<String, u64>,
+}
+
+impl FailedLogonDetector {
+    pub fn new(allowed_lateness_ns: u64) -> Self {
+        FailedLogonDetector {
+            window: SlidingWindowCounter::new(FAILED_LOGON_WINDOW_NS, allowed_lateness_ns),
+            reported: HashMap::new(),
+        }
+    }
+
+    pub fn set_allowed_lateness(&mut self, allowed_lateness_ns: u64) {
+        self.window.set_allowed_lateness(allowed_lateness_ns);
+    }
+
+    pub fn stats(&self) -> EventTimeStats {
+        self.window.stats()
+    }
+
+    /// Runs the built-in Windows detections. Failed logons whose
+    /// `TimeCreated` does not parse are counted as happening at `now`.
+    pub fn detect(&mut self, events: &[WindowsEvent], now: u64) -> Vec<ThreatOutput> {
+        let mut results = self.failed_logon_bursts(events, now);
+
+        for event in events {
+            let detection = match event.event_id {
+                4720 => Some(account_created(event)),
+                4732 => Some(group_member_added(event)),
+                1102 => Some(log_cleared(event)),
+                4688 => suspicious_process(event),
+                _ => None,
+            };
+            results.extend(detection);
+        }
+
+        results
+    }
+
+    fn failed_logon_bursts(&mut self, events: &[WindowsEvent], now: u64) -> Vec<ThreatOutput> {
+        // Group failures by source address, falling back to the targeted account
+        let mut failures: Vec<(u64, String)> = events
+            .iter()
+            .filter(|e| e.event_id == 4625)
+            .filter_map(|event| {
+                let key = event.ip_address.clone().or_else(|| event.target_user_name.clone())?;
+                Some((parse_rfc3339(&event.time_created).unwrap_or(now), key))
+            })
+            .collect();
+        // Feed the window in event-time order, whatever order the export used
+        failures.sort();
+
+        // Peak count per source, and the time of the failure that reached it
+        let mut peaks: HashMap<String, (usize, u64)> = HashMap::new();
+        for (time, key) in failures {
+            if let Some(count) = self.window.observe(&key, time) {
+                let peak = peaks.entry(key).or_default();
+                if count > peak.0 {
+                    *peak = (count, time);
+                }
+            }
+        }
+
+        let mut keys: Vec<_> = peaks.keys().cloned().collect();
+        keys.sort();
+        let mut results = Vec::new();
+        for key in keys {
+            let (peak, time) = peaks[&key];
+            if peak < FAILED_LOGON_THRESHOLD
+                || !first_report(&mut self.reported, key.clone(), time, FAILED_LOGON_SUPPRESSION_SECS)
+            {
+                continue;
+            }
+            results.push(ThreatOutput::detection(
+                "windows_failed_logon_burst",
+                if peak >= FAILED_LOGON_THRESHOLD * 4 { "HIGH" } else { "MEDIUM" },
+                0.8,
+                &key,
+                format!("{} failed logons (4625) from {} within 5 minutes", peak, key),
+            ));
+        }
+
+        let suppression_horizon = self
+            .window
+            .stats()
+            .watermark
+            .saturating_sub(FAILED_LOGON_SUPPRESSION_SECS * NANOS_PER_SECOND);
+        self.reported.retain(|_, last| *last >= suppression_horizon);
+        results
+    }
+}
+
+/// Runs the built-in Windows detections over a single, self-contained batch
+/// received at `now`.
+pub fn detect(events: &[WindowsEvent], now: u64) -> Vec<ThreatOutput> {
+    FailedLogonDetector::new(0).detect(events, now)
+}
+
+fn account_created(event: &WindowsEvent) -> ThreatOutput {
+    let account = event.target_user_name.clone().unwrap_or_else(|| "unknown".to_string());
+    ThreatOutput::detection(
+        "windows_account_created",
+        "MEDIUM",
+        0.6,
+        &account,
+        format!(
+            "Account {} created (4720) by {} on {}",
+            account,
+            event.subject_user_name.as_deref().unwrap_or("unknown"),
+            event.computer
+        ),
+    )
+}
+
+fn group_member_added(event: &WindowsEvent) -> ThreatOutput {
+    let group = event.target_user_name.clone().unwrap_or_else(|| "unknown".to_string());
+    let member = present(event.field("MemberName"))
+        .or_else(|| present(event.field("MemberSid")))
+        .unwrap_or_else(|| "unknown".to_string());
+    let privileged = group.eq_ignore_ascii_case("Administrators");
+    ThreatOutput::detection(
+        "windows_group_member_added",
+        if privileged { "HIGH" } else { "MEDIUM" },
+        if privileged { 0.85 } else { 0.6 },
+        &member,
+        format!(
+            "{} added to local group {} (4732) by {} on {}",
+            member,
+            group,
+            event.subject_user_name.as_deref().unwrap_or("unknown"),
+            event.computer
+        ),
+    )
+}
+
+fn log_cleared(event: &WindowsEvent) -> ThreatOutput {
+    let actor = event.subject_user_name.clone().unwrap_or_else(|| "unknown".to_string());
+    ThreatOutput::detection(
+        "windows_audit_log_cleared",
+        "CRITICAL",
+        0.95,
+        &actor,
+        format!("Security audit log cleared (1102) by {} on {}", actor, event.computer),
+    )
+}
+
+fn suspicious_process(event: &WindowsEvent) -> Option<ThreatOutput> {
+    let process = event.process_name.clone().unwrap_or_default();
+    let command_line = event.command_line.clone().unwrap_or_default();
+    let image = process.rsplit('\\').next().unwrap_or_default().to_lowercase();
+    // Pad so patterns anchored on spaces also match at either end
+    let haystack = format!(" {} ", command_line.to_lowercase());
+
+    let reason = if SUSPICIOUS_PROCESSES.contains(&image.as_str()) {
+        format!("known offensive tool {}", image)
+    } else {
+        SUSPICIOUS_COMMAND_LINES
+            .iter()
+            .find(|(pattern, _)| haystack.contains(pattern))
+            .map(|(_, reason)| reason.to_string())?
+    };
+
+    let actor = event.subject_user_name.clone().unwrap_or_else(|| "unknown".to_string());
+    Some(ThreatOutput::detection(
+        "windows_suspicious_process",
+        "HIGH",
+        0.75,
+        &actor,
+        format!(
+            "Suspicious process launch (4688) on {}: {} ({}) by {}: {}",
+            event.computer, process, reason, actor, command_line
+        ),
+    ))
+}
@@ -0,0 +1,199 @@
+use crate::decode::flatten_json;
+use crate::ThreatOutput;
+use cybersec_log_model::timestamp::{parse_timestamp, TimestampConfig};
+use cybersec_log_model::LogRecord;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+pub const SOURCE: &str = "zeek";
+
+/// Parses a Zeek log in either its default TSV form (with `#fields` headers)
+/// or JSON lines. TSV logs name their own type in `#path`; for JSON the type
+/// comes from a `_path` field or, failing that, from `path`. `ts` values
+/// without a zone are read in `config`'s.
+pub fn parse_log(text: &str, path: Option<&str>, config: &TimestampConfig) -> Result<Vec<LogRecord>, String> {
+    let first = text.lines().map(str::trim).find(|line| !line.is_empty());
+    match first {
+        Some(line) if line.starts_with('{') => parse_json(text, path, config),
+        Some(_) => parse_tsv(text, path, config),
+        None => Ok(Vec::new()),
+    }
+}
+
+fn parse_tsv(text: &str, path: Option<&str>, config: &TimestampConfig) -> Result<Vec<LogRecord>, String> {
+    let mut separator = "\t".to_string();
+    let mut set_separator = ",".to_string();
+    let mut empty_field = "(empty)".to_string();
+    let mut unset_field = "-".to_string();
+    let mut log_path = path.map(str::to_string);
+    let mut fields: Vec<String> = Vec::new();
+    let mut records = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('#') {
+            if let Some(value) = header.strip_prefix("separator ") {
+                separator = unescape(value);
+                continue;
+            }
+            let mut parts = header.split(separator.as_str());
+            let directive = parts.next().unwrap_or_default();
+            let values: Vec<String> = parts.map(str::to_string).collect();
+            match directive {
+                "set_separator" => set_separator = values.first().cloned().unwrap_or(set_separator),
+                "empty_field" => empty_field = values.first().cloned().unwrap_or(empty_field),
+                "unset_field" => unset_field = values.first().cloned().unwrap_or(unset_field),
+                "path" => log_path = values.first().cloned().or(log_path),
+                "fields" => fields = values,
+                _ => {}
+            }
+            continue;
+        }
+
+        if fields.is_empty() {
+            return Err(format!("Zeek log line {} precedes its #fields header", index + 1));
+        }
+        let values: Vec<&str> = line.split(separator.as_str()).collect();
+        if values.len() != fields.len() {
+            return Err(format!(
+                "Zeek log line {} has {} columns but #fields declares {}",
+                index + 1,
+                values.len(),
+                fields.len()
+            ));
+        }
+
+        let attributes = fields
+            .iter()
+            .zip(values)
+            .filter(|(_, value)| *value != unset_field && *value != empty_field)
+            .map(|(field, value)| (field.clone(), value.replace(set_separator.as_str(), ",")))
+            .collect();
+        records.push(to_record(log_path.as_deref().unwrap_or("unknown"), attributes, config));
+    }
+
+    Ok(records)
+}
+
+fn parse_json(text: &str, path: Option<&str>, config: &TimestampConfig) -> Result<Vec<LogRecord>, String> {
+    let mut records = Vec::new();
+
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let entry: Value = serde_json::from_str(line)
+            .map_err(|e| format!("Invalid Zeek JSON on line {}: {}", index + 1, e))?;
+
+        let mut attributes = BTreeMap::new();
+        flatten_json("", &entry, &mut attributes);
+        let log_path = attributes
+            .remove("_path")
+            .or_else(|| path.map(str::to_string))
+            .unwrap_or_else(|| "unknown".to_string());
+        records.push(to_record(&log_path, attributes, config));
+    }
+
+    Ok(records)
+}
+
+// Zeek escapes the separator in its header, e.g. "\x09" for a tab
+fn unescape(value: &str) -> String {
+    match value.strip_prefix("\\x").and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+        Some(byte) => (byte as char).to_string(),
+        None => value.to_string(),
+    }
+}
+
+fn to_record(log_path: &str, attributes: BTreeMap<String, String>, config: &TimestampConfig) -> LogRecord {
+    let text = |key: &str| attributes.get(key).cloned();
+    let field = |key: &str| attributes.get(key).map(String::as_str).unwrap_or_default();
+
+    let endpoints = format!(
+        "{}:{} -> {}:{}",
+        field("id.orig_h"),
+        field("id.orig_p"),
+        field("id.resp_h"),
+        field("id.resp_p")
+    );
+    let message = match log_path {
+        "conn" => format!(
+            "conn {} {} {} state {}",
+            endpoints,
+            field("proto"),
+            field("service"),
+            field("conn_state")
+        ),
+        "dns" => format!(
+            "DNS query {} ({}) from {} -> {}",
+            field("query"),
+            field("qtype_name"),
+            field("id.orig_h"),
+            field("rcode_name")
+        ),
+        "http" => format!(
+            "HTTP {} {}{} {} from {}",
+            field("method"),
+            field("host"),
+            field("uri"),
+            field("status_code"),
+            field("id.orig_h")
+        ),
+        "ssl" => format!(
+            "TLS {} {} {} validation {}",
+            endpoints,
+            field("server_name"),
+            field("version"),
+            field("validation_status")
+        ),
+        "notice" => format!("Zeek notice {}: {}", field("note"), field("msg")),
+        other => format!("Zeek {} {}", other, endpoints),
+    };
+
+    // notice.log also carries src/dst outside the connection id
+    let source_ip = text("id.orig_h").or_else(|| text("src"));
+    let destination_ip = text("id.resp_h").or_else(|| text("dst"));
+    let original_timestamp = text("ts");
+
+    LogRecord {
+        timestamp: original_timestamp
+            .as_deref()
+            .and_then(|ts| parse_timestamp(ts, config)),
+        original_timestamp,
+        message,
+        source: Some(SOURCE.to_string()),
+        source_ip,
+        destination_ip,
+        user: text("username"),
+        host: None,
+        action: Some(log_path.to_string()),
+        outcome: text("conn_state").or_else(|| text("rcode_name")).or_else(|| text("status_code")),
+        severity: (log_path == "notice").then(|| "MEDIUM".to_string()),
+        attributes,
+        entities: None,
+    }
+}
+
+/// Turns a Zeek notice record into a detection; other log types yield `None`.
+pub fn notice_detection(record: &LogRecord) -> Option<ThreatOutput> {
+    if record.source.as_deref() != Some(SOURCE) || record.action.as_deref() != Some("notice") {
+        return None;
+    }
+
+    let source = record.source_ip.as_deref().unwrap_or("unknown");
+    Some(ThreatOutput::detection(
+        "ids_notice",
+        "MEDIUM",
+        0.7,
+        source,
+        format!(
+            "{}: {} (source {})",
+            record.attribute("note").unwrap_or("Zeek notice"),
+            record.attribute("msg").unwrap_or_default(),
+            source
+        ),
+    ))
+}
@@ -0,0 +1,119 @@
+// tests/archive.rs
+use candid::Principal;
+use cybersec_ai_agent_backend::archive::{batches, log_range, ArchiveIndex, ArchiveOptions, SecurityLogRange};
+use cybersec_ai_agent_backend::security_logs::SecurityLogStore;
+use cybersec_ai_agent_backend::SecurityLog;
+
+fn log(index: u64) -> SecurityLog {
+    SecurityLog {
+        timestamp: index,
+        event_type: "port_scan".to_string(),
+        details: format!("Scan {}", index),
+        severity: "MEDIUM".to_string(),
+        entity: None,
+        source: Some("netflow".to_string()),
+        sequence: None,
+        hash: None,
+    }
+}
+
+fn archive(n: u8) -> Principal {
+    Principal::from_slice(&[n])
+}
+
+fn options() -> ArchiveOptions {
+    ArchiveOptions {
+        trigger_threshold: 10,
+        num_logs_to_archive: 4,
+        max_logs_per_archive: 6,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_block_size_follows_threshold_and_capacity() {
+    let mut index = ArchiveIndex::new(options());
+    assert_eq!(index.block_size(9), 0);
+    assert_eq!(index.block_size(10), 4);
+
+    index.add_node(archive(1));
+    index.record_append(4, 5);
+    // One slot left in the first archive
+    assert_eq!(index.block_size(12), 1);
+    index.record_append(5, 6);
+    assert!(index.writable().is_none());
+    assert_eq!(index.block_size(12), 4);
+
+    index.set_options(ArchiveOptions {
+        trigger_threshold: 0,
+        ..options()
+    });
+    assert_eq!(index.block_size(1_000), 0);
+}
+
+#[test]
+fn test_ranges_point_at_archives() {
+    let mut index = ArchiveIndex::new(options());
+    index.add_node(archive(1));
+    index.record_append(5, 6);
+    index.add_node(archive(2));
+    // Logs 7 and 8 were evicted before they were archived
+    index.record_append(9, 2);
+    assert_eq!(index.archived_through(), 10);
+    assert_eq!((index.nodes()[1].from, index.nodes()[1].length, index.nodes()[1].stored), (6, 4, 2));
+
+    let ranges = index.locate(4, 8);
+    let spans: Vec<_> = ranges.iter().map(|range| (range.callback.0.principal, range.from, range.length)).collect();
+    assert_eq!(spans, vec![(archive(1), 4, 2), (archive(2), 6, 4)]);
+    assert_eq!(ranges[0].callback.0.method, "get_logs");
+    assert!(index.locate(10, 5).is_empty());
+}
+
+#[test]
+fn test_imported_archives_are_only_read() {
+    let mut index = ArchiveIndex::new(options());
+    index.add_node(archive(1));
+    index.record_append(3, 4);
+    index.mark_imported();
+    assert!(index.writable().is_none());
+    assert_eq!(index.block_size(12), 4);
+
+    // The next block goes to an archive of this canister's own, after the imported ones
+    index.add_node(archive(2));
+    assert_eq!(index.writable().map(|node| (node.canister_id, node.from)), Some((archive(2), 4)));
+    assert_eq!(index.locate(0, 4)[0].callback.0.principal, archive(1));
+}
+
+#[test]
+fn test_archived_logs_leave_the_chain_verifiable() {
+    let mut store = SecurityLogStore::from_logs((0..10).map(log).collect());
+    let mut index = ArchiveIndex::new(options());
+    index.add_node(archive(1));
+    assert_eq!(store.remove_through(3), 4);
+    index.record_append(3, 4);
+
+    let range = log_range(&store, &index, 2, 5);
+    assert_eq!(range.chain_length, 10);
+    assert_eq!(range.logs.iter().map(|log| log.sequence.unwrap()).collect::<Vec<_>>(), vec![4, 5, 6]);
+    assert_eq!((range.archived[0].from, range.archived[0].length), (2, 2));
+
+    // The range goes out over candid, callbacks included
+    let bytes = candid::encode_one(&range).unwrap();
+    let decoded: SecurityLogRange = candid::decode_one(&bytes).unwrap();
+    assert_eq!(decoded.logs.len(), 3);
+    assert_eq!(decoded.archived[0].callback, range.archived[0].callback);
+
+    let verification = store.verify(0, 9).unwrap();
+    assert!(verification.valid, "{:?}", verification.error);
+    assert_eq!((verification.verified, verification.pruned), (6, 4));
+}
+
+#[test]
+fn test_batches_stay_under_message_limit() {
+    let mut logs: Vec<SecurityLog> = (0..4).map(log).collect();
+    logs[1].details = "x".repeat(1_000_000);
+    logs[2].details = "x".repeat(1_000_000);
+    let sizes: Vec<usize> = batches(&logs).iter().map(|batch| batch.len()).collect();
+    assert_eq!(sizes, vec![2, 2]);
+    assert!(batches(&[]).is_empty());
+}
@@ -0,0 +1,212 @@
+// tests/certified.rs
+use bls12_381::{G1Affine, G2Affine, Scalar};
+use candid::Principal;
+use cybersec_ai_agent_backend::certified::{encode_witness, security_logs_witness, threat_summary_witness};
+use cybersec_ai_agent_backend::security_logs::SecurityLogStore;
+use cybersec_ai_agent_backend::SecurityLog;
+use cybersec_log_model::certified::{
+    hash_to_g1, root_hash, threat_summary_leaf, verify_security_logs, verify_threat_summary, CertifiedSecurityLogs,
+    CertifiedThreatSummary,
+};
+use ic_certification::hash_tree::Hash;
+use ic_certification::{fork, labeled, leaf, Certificate, Delegation, HashTree};
+use serde_bytes::ByteBuf;
+
+const NOW: u64 = 1_700_000_000_000_000_000;
+const MAX_AGE: u64 = 300_000_000_000;
+const DER_PREFIX: &str = "308182301d060d2b0601040182dc7c0503010201060c2b0601040182dc7c05030201036100";
+
+fn canister() -> Principal {
+    Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap()
+}
+
+fn public_key(secret: &Scalar) -> Vec<u8> {
+    let prefix: Vec<u8> = (0..DER_PREFIX.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&DER_PREFIX[i..i + 2], 16).unwrap())
+        .collect();
+    [prefix, G2Affine::from(G2Affine::generator() * secret).to_compressed().to_vec()].concat()
+}
+
+fn sign(secret: &Scalar, tree: &HashTree) -> Vec<u8> {
+    let message = [&b"\x0dic-state-root"[..], &tree.digest()[..]].concat();
+    G1Affine::from(hash_to_g1(&message) * secret).to_compressed().to_vec()
+}
+
+fn leb128(mut value: u64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            bytes.push(byte);
+            return bytes;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn certificate(secret: &Scalar, certified_data: Hash, time: u64, delegation: Option<Delegation>) -> ByteBuf {
+    let tree = fork(
+        labeled(
+            "canister",
+            labeled(canister().as_slice(), labeled("certified_data", leaf(certified_data.to_vec()))),
+        ),
+        labeled("time", leaf(leb128(time))),
+    );
+    let certificate = Certificate {
+        signature: sign(secret, &tree),
+        tree,
+        delegation,
+    };
+    ByteBuf::from(serde_cbor::to_vec(&certificate).unwrap())
+}
+
+fn log(index: u64) -> SecurityLog {
+    SecurityLog {
+        timestamp: index,
+        event_type: "phishing".to_string(),
+        details: format!("Phishing mail {}", index),
+        severity: "HIGH".to_string(),
+        entity: None,
+        source: None,
+        sequence: None,
+        hash: None,
+    }
+}
+
+fn summary() -> Vec<(String, u32)> {
+    vec![("dns_tunneling".to_string(), 2), ("phishing".to_string(), 5)]
+}
+
+fn certified_logs(store: &SecurityLogStore, from: u64, limit: usize, root_secret: &Scalar) -> CertifiedSecurityLogs {
+    let (logs, to, range) = store.certified_page(from, limit);
+    let summary_leaf = threat_summary_leaf(&summary());
+    let root = root_hash(&store.certified_root(), &summary_leaf);
+    let witness = security_logs_witness(range, &summary_leaf);
+    assert_eq!(witness.digest(), root);
+    CertifiedSecurityLogs {
+        from,
+        to,
+        logs,
+        certificate: certificate(root_secret, root, NOW, None),
+        witness: encode_witness(&witness).unwrap(),
+    }
+}
+
+#[test]
+fn test_security_log_pages_verify() {
+    let secret = Scalar::from(7_u64);
+    let mut store = SecurityLogStore::from_logs((0..10).map(log).collect());
+    store.evict(&[false, false, false, true, true]);
+
+    let page = certified_logs(&store, 2, 3, &secret);
+    assert_eq!(page.logs.iter().map(|log| log.sequence.unwrap()).collect::<Vec<_>>(), vec![2, 5, 6]);
+    assert_eq!(page.to, 6);
+    assert_eq!(verify_security_logs(&page, &canister(), &public_key(&secret), NOW, MAX_AGE), Ok(()));
+
+    let last = certified_logs(&store, 8, 5, &secret);
+    assert_eq!(last.to, u64::MAX);
+    assert_eq!(verify_security_logs(&last, &canister(), &public_key(&secret), NOW, MAX_AGE), Ok(()));
+}
+
+#[test]
+fn test_tampered_or_incomplete_logs_rejected() {
+    let secret = Scalar::from(7_u64);
+    let store = SecurityLogStore::from_logs((0..6).map(log).collect());
+    let key = public_key(&secret);
+
+    let mut edited = certified_logs(&store, 0, 6, &secret);
+    edited.logs[1].severity = "LOW".to_string();
+    assert_eq!(
+        verify_security_logs(&edited, &canister(), &key, NOW, MAX_AGE),
+        Err("Log 1 does not match its certified leaf".to_string())
+    );
+
+    let mut omitted = certified_logs(&store, 0, 6, &secret);
+    omitted.logs.remove(2);
+    assert_eq!(
+        verify_security_logs(&omitted, &canister(), &key, NOW, MAX_AGE),
+        Err("Log 2 is certified but was not returned".to_string())
+    );
+
+    let mut widened = certified_logs(&store, 0, 3, &secret);
+    widened.to = 4;
+    assert!(verify_security_logs(&widened, &canister(), &key, NOW, MAX_AGE).is_err());
+}
+
+#[test]
+fn test_certificate_checks() {
+    let secret = Scalar::from(7_u64);
+    let store = SecurityLogStore::from_logs((0..3).map(log).collect());
+    let page = certified_logs(&store, 0, 3, &secret);
+
+    let other_key = public_key(&Scalar::from(8_u64));
+    assert_eq!(
+        verify_security_logs(&page, &canister(), &other_key, NOW, MAX_AGE),
+        Err("Certificate signature does not verify".to_string())
+    );
+    assert!(verify_security_logs(&page, &canister(), &public_key(&secret), NOW + 2 * MAX_AGE, MAX_AGE)
+        .unwrap_err()
+        .contains("too far from now"));
+    let other_canister = Principal::from_text("ryjl3-tyaaa-aaaaa-aaaba-cai").unwrap();
+    assert!(verify_security_logs(&page, &other_canister, &public_key(&secret), NOW, MAX_AGE).is_err());
+}
+
+#[test]
+fn test_threat_summary_verifies() {
+    let secret = Scalar::from(11_u64);
+    let store = SecurityLogStore::from_logs((0..3).map(log).collect());
+    let summary_leaf = threat_summary_leaf(&summary());
+    let root = root_hash(&store.certified_root(), &summary_leaf);
+    let mut response = CertifiedThreatSummary {
+        summary: summary(),
+        certificate: certificate(&secret, root, NOW, None),
+        witness: encode_witness(&threat_summary_witness(&store.certified_root(), &summary_leaf)).unwrap(),
+    };
+    assert_eq!(verify_threat_summary(&response, &canister(), &public_key(&secret), NOW, MAX_AGE), Ok(()));
+
+    response.summary[1].1 = 4;
+    assert!(verify_threat_summary(&response, &canister(), &public_key(&secret), NOW, MAX_AGE).is_err());
+}
+
+#[test]
+fn test_subnet_delegation() {
+    let (root_secret, subnet_secret) = (Scalar::from(3_u64), Scalar::from(5_u64));
+    let subnet_id = b"test-subnet".to_vec();
+    let ranges = |low: Principal, high: Principal| {
+        serde_cbor::to_vec(&vec![(ByteBuf::from(low.as_slice()), ByteBuf::from(high.as_slice()))]).unwrap()
+    };
+    let delegation = |ranges: Vec<u8>| {
+        let tree = labeled(
+            "subnet",
+            labeled(
+                subnet_id.clone(),
+                fork(labeled("canister_ranges", leaf(ranges)), labeled("public_key", leaf(public_key(&subnet_secret)))),
+            ),
+        );
+        let parent = Certificate {
+            signature: sign(&root_secret, &tree),
+            tree,
+            delegation: None,
+        };
+        Delegation {
+            subnet_id: subnet_id.clone(),
+            certificate: serde_cbor::to_vec(&parent).unwrap(),
+        }
+    };
+
+    let store = SecurityLogStore::from_logs((0..2).map(log).collect());
+    let mut page = certified_logs(&store, 0, 2, &subnet_secret);
+    let root = root_hash(&store.certified_root(), &threat_summary_leaf(&summary()));
+    let hosting = ranges(Principal::from_slice(&[0]), Principal::from_slice(&[0xff; 10]));
+    page.certificate = certificate(&subnet_secret, root, NOW, Some(delegation(hosting)));
+    assert_eq!(verify_security_logs(&page, &canister(), &public_key(&root_secret), NOW, MAX_AGE), Ok(()));
+
+    let elsewhere = ranges(Principal::from_slice(&[0xfe]), Principal::from_slice(&[0xff]));
+    page.certificate = certificate(&subnet_secret, root, NOW, Some(delegation(elsewhere)));
+    assert_eq!(
+        verify_security_logs(&page, &canister(), &public_key(&root_secret), NOW, MAX_AGE),
+        Err("The delegated subnet does not host this canister".to_string())
+    );
+}
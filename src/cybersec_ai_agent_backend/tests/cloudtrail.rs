@@ -0,0 +1,50 @@
+// tests/cloudtrail.rs
+use cybersec_ai_agent_backend::cloudtrail::{detection, detections, grants_everything, parse_cloudtrail};
+
+#[test]
+fn test_parse_records_envelope_and_single_event() {
+    let records = parse_cloudtrail(include_str!("fixtures/cloudtrail/records.json")).unwrap();
+    assert_eq!(records.len(), 7);
+    assert_eq!(records[1].action.as_deref(), Some("StopLogging"));
+    assert_eq!(records[1].user.as_deref(), Some("arn:aws:iam::123456789012:user/mallory"));
+    assert_eq!(records[1].timestamp, Some(1_709_287_260_000_000_000));
+    assert_eq!(records[1].outcome.as_deref(), Some("success"));
+
+    let single = r#"{"eventName": "GetCallerIdentity", "userIdentity": {"type": "IAMUser", "userName": "bob"}}"#;
+    let records = parse_cloudtrail(single).unwrap();
+    assert_eq!(records[0].user.as_deref(), Some("bob"));
+
+    assert!(parse_cloudtrail(r#"{"Records": [{"eventSource": "s3.amazonaws.com"}]}"#).is_err());
+}
+
+#[test]
+fn test_cloudtrail_detections() {
+    let records = parse_cloudtrail(include_str!("fixtures/cloudtrail/records.json")).unwrap();
+
+    let categories: Vec<Vec<String>> = records
+        .iter()
+        .map(|record| detections(record).into_iter().map(|d| d.category).collect())
+        .collect();
+    assert_eq!(categories[0], vec!["cloudtrail_root_usage", "cloudtrail_console_login_without_mfa"]);
+    assert_eq!(categories[1], vec!["cloudtrail_logging_disabled"]);
+    assert_eq!(categories[2], vec!["cloudtrail_admin_policy"]);
+    assert_eq!(categories[3], vec!["cloudtrail_access_key_for_other_user"]);
+    assert_eq!(categories[4], vec!["cloudtrail_security_group_open"]);
+    assert!(categories[5].is_empty());
+    assert_eq!(categories[6], vec!["cloudtrail_admin_policy"]);
+
+    let open = detection(&records[4]).unwrap();
+    assert_eq!(open.severity, "HIGH");
+    assert_eq!(open.entity.as_deref(), Some("arn:aws:sts::123456789012:assumed-role/deployer/deploy"));
+    assert!(open.details.ends_with("on port 22"));
+    // Both root findings end up in one detection
+    assert!(detection(&records[0]).unwrap().details.contains("without MFA"));
+}
+
+#[test]
+fn test_policy_documents() {
+    assert!(grants_everything(r#"{"Statement": {"Effect": "Allow", "Action": ["s3:*", "*"], "Resource": "*"}}"#));
+    assert!(grants_everything(r#"{"Statement": [{"Effect": "Allow", "Action": "*:*", "Resource": "*"}]}"#));
+    assert!(!grants_everything(r#"{"Statement": [{"Effect": "Allow", "Action": "s3:*", "Resource": "*"}]}"#));
+    assert!(!grants_everything(r#"{"Statement": [{"Effect": "Deny", "Action": "*", "Resource": "*"}]}"#));
+}
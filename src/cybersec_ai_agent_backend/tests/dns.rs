@@ -0,0 +1,115 @@
+// tests/dns.rs
+use cybersec_ai_agent_backend::dns::{dga_score, split_domain, DnsAnalyzer, DnsDetectorConfig};
+use cybersec_ai_agent_backend::zeek;
+use cybersec_log_model::LogRecord;
+
+const SECOND: u64 = 1_000_000_000;
+
+fn query(client: &str, domain: &str, qtype: &str, rcode: &str, secs: u64) -> LogRecord {
+    LogRecord {
+        timestamp: Some(secs * SECOND),
+        source: Some(zeek::SOURCE.to_string()),
+        source_ip: Some(client.to_string()),
+        action: Some("dns".to_string()),
+        ..LogRecord::new(format!("DNS query {}", domain))
+    }
+    .with_attribute("query", domain)
+    .with_attribute("qtype_name", qtype)
+    .with_attribute("rcode_name", rcode)
+}
+
+#[test]
+fn test_split_domain() {
+    assert_eq!(split_domain("a.b.example.com"), ("a.b", "example.com"));
+    assert_eq!(split_domain("www.example.co.uk"), ("www", "example.co.uk"));
+    assert_eq!(split_domain("example.com"), ("", "example.com"));
+}
+
+#[test]
+fn test_dga_score() {
+    for benign in ["www.google.com", "stackoverflow.com", "update.example-cdn.net", "bbc.co.uk"] {
+        assert!(dga_score(benign).score < 0.4, "{}", benign);
+    }
+    for generated in ["xjw3kq9zpt1vbn.com", "qzkxvbnmtrwp.net", "rlkqmpgqzz.cc"] {
+        assert!(dga_score(generated).score >= 0.65, "{}", generated);
+    }
+    assert!(dga_score("qzkxvbnmtrwp.net").explanation.contains("longest consonant run 12"));
+}
+
+#[test]
+fn test_dga_domain_reported_once() {
+    let mut analyzer = DnsAnalyzer::default();
+
+    let finding = analyzer.observe(&query("10.0.0.44", "qzkxvbnmtrwp.net", "A", "NOERROR", 0), 0).unwrap();
+    assert_eq!(finding.category, "dns_dga_domain");
+    assert_eq!(finding.entity.as_deref(), Some("10.0.0.44"));
+    assert!(analyzer.observe(&query("10.0.0.44", "qzkxvbnmtrwp.net", "A", "NOERROR", 5), 0).is_none());
+    assert!(analyzer.observe(&query("10.0.0.44", "www.google.com", "A", "NOERROR", 6), 0).is_none());
+}
+
+#[test]
+fn test_tunneling_indicators() {
+    let mut analyzer = DnsAnalyzer::default();
+
+    let mut findings = Vec::new();
+    for i in 0..60 {
+        let domain = format!("{:08x}{}.t.tunnel-example.com", i * 7919, "a".repeat(i % 3));
+        findings.extend(analyzer.observe(&query("10.0.0.50", &domain, "TXT", "NOERROR", i as u64), 0));
+    }
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].category, "dns_tunneling");
+    assert!(findings[0].details.contains("tunnel-example.com"));
+    assert!(findings[0].details.contains("TXT/NULL"));
+
+    let label = "a".repeat(60);
+    let finding = analyzer.observe(&query("10.0.0.51", &format!("{}.exfil.net", label), "A", "NOERROR", 70), 0);
+    assert!(finding.unwrap().details.contains("60-character label"));
+}
+
+#[test]
+fn test_tunneling_counts_only_the_window() {
+    let mut analyzer = DnsAnalyzer::default();
+
+    // 100 distinct A lookups, two seconds apart: never 50 within 60 seconds
+    let findings: Vec<_> = (0..100)
+        .filter_map(|i| {
+            let domain = format!("host{}.cdn.example.net", i);
+            analyzer.observe(&query("10.0.0.52", &domain, "A", "NOERROR", i * 2), 0)
+        })
+        .collect();
+    assert!(findings.is_empty());
+}
+
+#[test]
+fn test_nxdomain_burst() {
+    let mut analyzer = DnsAnalyzer::new(DnsDetectorConfig {
+        nxdomain_burst_threshold: 5,
+        ..DnsDetectorConfig::default()
+    });
+
+    let findings: Vec<_> = (0..8)
+        .filter_map(|i| analyzer.observe(&query("10.0.0.60", &format!("host{}.corp.example.com", i), "A", "NXDOMAIN", i), 0))
+        .collect();
+    assert_eq!(findings.len(), 1);
+    assert_eq!(findings[0].category, "dns_nxdomain_burst");
+    assert!(findings[0].details.contains("5 NXDOMAIN answers"));
+}
+
+#[test]
+fn test_nxdomains_allowed_lateness() {
+    let mut analyzer = DnsAnalyzer::default();
+    analyzer.set_allowed_lateness(10 * SECOND);
+    analyzer.observe(&query("10.0.0.60", "a.corp.example.com", "A", "NXDOMAIN", 100), 0);
+
+    // Within the window, but later than the lateness allows
+    analyzer.observe(&query("10.0.0.60", "b.corp.example.com", "A", "NXDOMAIN", 80), 0);
+    analyzer.observe(&query("10.0.0.60", "c.corp.example.com", "A", "NXDOMAIN", 95), 0);
+    assert_eq!(analyzer.event_time_stats().dropped_late_events, 1);
+    assert_eq!(analyzer.event_time_stats().watermark, 100 * SECOND);
+
+    // Kept when the config changes
+    analyzer.set_config(DnsDetectorConfig::default());
+    analyzer.observe(&query("10.0.0.60", "d.corp.example.com", "A", "NXDOMAIN", 200), 0);
+    analyzer.observe(&query("10.0.0.60", "e.corp.example.com", "A", "NXDOMAIN", 185), 0);
+    assert_eq!(analyzer.event_time_stats().dropped_late_events, 1);
+}
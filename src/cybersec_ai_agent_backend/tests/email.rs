@@ -0,0 +1,66 @@
+// tests/email.rs
+use cybersec_ai_agent_backend::email::{analyze, decode_encoded_words, parse_mailbox, parse_message};
+
+#[test]
+fn test_parse_phishing_message() {
+    let message = parse_message(include_str!("fixtures/email/phishing.eml")).unwrap();
+
+    assert_eq!(message.subject.as_deref(), Some("Your account is on hold"));
+    let from = message.from.as_ref().unwrap();
+    assert_eq!(from.display_name.as_deref(), Some("PayPal Support"));
+    assert_eq!(from.address, "service@paypa1-secure.ru");
+    assert_eq!(message.return_path.as_deref(), Some("bounce@mailer.paypa1-secure.ru"));
+    assert_eq!(message.received.len(), 2);
+    assert_eq!(message.received[1].from.as_deref(), Some("smtp.paypa1-secure.ru"));
+    assert_eq!(message.originating_ip(), Some("203.0.113.45"));
+    assert_eq!(
+        (message.spf.as_deref(), message.dkim.as_deref(), message.dmarc.as_deref()),
+        (Some("fail"), Some("none"), Some("fail"))
+    );
+    assert_eq!(message.links.len(), 2);
+    assert_eq!(message.links[1].href, "http://paypal.account-verify.top/login?u=alice");
+    assert_eq!(message.links[1].text.as_deref(), Some("https://www.paypal.com/signin"));
+    assert_eq!(message.attachments, vec!["invoice.pdf.exe"]);
+}
+
+#[test]
+fn test_phishing_verdict() {
+    let verdict = analyze(&parse_message(include_str!("fixtures/email/phishing.eml")).unwrap());
+
+    assert!(verdict.is_threat);
+    assert_eq!(verdict.category, "phishing");
+    assert_eq!(verdict.severity, "HIGH");
+    assert_eq!(verdict.entity.as_deref(), Some("service@paypa1-secure.ru"));
+    for reason in [
+        "DMARC failed",
+        "SPF failed",
+        "Reply-To billing-desk@protonmail.com differs",
+        "display name claims paypal",
+        "link to a bare IP address",
+        "link text shows www.paypal.com but points to paypal.account-verify.top",
+        "disguised executable attachment invoice.pdf.exe",
+    ] {
+        assert!(verdict.details.contains(reason), "missing '{}' in {}", reason, verdict.details);
+    }
+}
+
+#[test]
+fn test_legitimate_newsletter() {
+    let verdict = analyze(&parse_message(include_str!("fixtures/email/newsletter.eml")).unwrap());
+
+    assert!(!verdict.is_threat);
+    assert_eq!(verdict.severity, "INFO");
+    assert_eq!(verdict.details, "No phishing indicators (originating IP 198.51.100.20)");
+}
+
+#[test]
+fn test_display_name_with_embedded_address() {
+    let from = parse_mailbox("\"security@microsoft.com\" <alerts@mail-notify.xyz>").unwrap();
+    assert_eq!(from.display_name.as_deref(), Some("security@microsoft.com"));
+    assert_eq!(decode_encoded_words("=?utf-8?q?Caf=C3=A9?= =?utf-8?b?IG1lbnU=?="), "Café menu");
+
+    let raw = "From: \"security@microsoft.com\" <alerts@mail-notify.xyz>\nSubject: test\n\nhello";
+    let verdict = analyze(&parse_message(raw).unwrap());
+    assert!(verdict.details.contains("display name shows security@microsoft.com"));
+    assert!(parse_message("").is_err());
+}
@@ -0,0 +1,88 @@
+// tests/event_time.rs
+use cybersec_ai_agent_backend::event_time::{EventTimeConfig, SlidingWindowCounter};
+use cybersec_ai_agent_backend::flows::parse_flows;
+use cybersec_log_model::timestamp::{parse_timestamp, TimestampConfig};
+use cybersec_log_model::LogRecord;
+
+const SECOND: u64 = 1_000_000_000;
+// 2024-03-01T10:00:00Z
+const BASE: u64 = 1_709_287_200 * SECOND;
+
+#[test]
+fn test_parse_timestamp_formats() {
+    let config = TimestampConfig::default();
+
+    assert_eq!(parse_timestamp("2024-03-01T10:00:00Z", &config), Some(BASE));
+    assert_eq!(parse_timestamp("2024-03-01T12:00:00.5+02:00", &config), Some(BASE + SECOND / 2));
+    assert_eq!(parse_timestamp("2024-03-01 10:00:00", &config), Some(BASE));
+    assert_eq!(parse_timestamp("1709287200", &config), Some(BASE));
+    assert_eq!(parse_timestamp("1709287200.25", &config), Some(BASE + SECOND / 4));
+    assert_eq!(parse_timestamp("1709287200000", &config), Some(BASE));
+    assert_eq!(parse_timestamp("1709287200000000000", &config), Some(BASE));
+    assert_eq!(parse_timestamp("[01/Mar/2024:12:00:00 +0200]", &config), Some(BASE));
+    assert_eq!(parse_timestamp("not a timestamp", &config), None);
+    // Days past the end of the month, leap years included
+    assert_eq!(parse_timestamp("2024-02-31T10:00:00Z", &config), None);
+    assert_eq!(parse_timestamp("2023-02-29T10:00:00Z", &config), None);
+    assert_eq!(parse_timestamp("2024-04-31T10:00:00Z", &config), None);
+    assert!(parse_timestamp("2024-02-29T10:00:00Z", &config).is_some());
+    assert!(parse_timestamp("2000-02-29T10:00:00Z", &config).is_some());
+    assert_eq!(parse_timestamp("1900-02-29T10:00:00Z", &config), None);
+}
+
+#[test]
+fn test_default_offset_for_zoneless_timestamps() {
+    let config = TimestampConfig {
+        default_utc_offset_minutes: 60,
+        reference_time: None,
+    };
+
+    assert_eq!(parse_timestamp("2024-03-01T11:00:00", &config), Some(BASE));
+    assert_eq!(parse_timestamp("2024-03-01T10:00:00Z", &config), Some(BASE));
+}
+
+#[test]
+fn test_configured_zone_applies_at_ingest() {
+    let config = EventTimeConfig {
+        default_utc_offset_minutes: -300,
+        ..Default::default()
+    }
+    .timestamp_config(BASE);
+
+    let mut record = LogRecord {
+        original_timestamp: Some("2024-03-01T05:00:00".to_string()),
+        ..LogRecord::new("Accepted password for alice")
+    };
+    assert_eq!(record.resolve_timestamp(&config), Some(BASE));
+
+    let flows = parse_flows("src_ip,dst_ip,start\n10.0.0.1,10.0.0.2,2024-03-01T05:00:00", &config).unwrap();
+    assert_eq!(flows[0].start, Some(BASE));
+    let utc = parse_flows("src_ip,dst_ip,start\n10.0.0.1,10.0.0.2,2024-03-01T05:00:00", &TimestampConfig::default());
+    assert_eq!(utc.unwrap()[0].start, Some(BASE - 5 * 3600 * SECOND));
+}
+
+#[test]
+fn test_syslog_year_inference() {
+    let config = TimestampConfig {
+        default_utc_offset_minutes: 0,
+        reference_time: Some(BASE),
+    };
+
+    assert_eq!(parse_timestamp("Mar  1 10:00:00", &config), Some(BASE));
+    // December entries read in early March belong to the previous year
+    assert_eq!(parse_timestamp("Dec 31 23:59:59", &config), Some(1_704_067_199 * SECOND));
+}
+
+#[test]
+fn test_window_orders_late_events_and_drops_too_late() {
+    let mut window = SlidingWindowCounter::new(60 * SECOND, 30 * SECOND);
+
+    assert_eq!(window.observe("10.0.0.1", BASE + 50 * SECOND), Some(1));
+    // Arrives late but within the allowed lateness, so it still shares the window
+    assert_eq!(window.observe("10.0.0.1", BASE + 30 * SECOND), Some(2));
+    assert_eq!(window.observe("10.0.0.1", BASE), None);
+
+    let stats = window.stats();
+    assert_eq!(stats.watermark, BASE + 50 * SECOND);
+    assert_eq!(stats.dropped_late_events, 1);
+}
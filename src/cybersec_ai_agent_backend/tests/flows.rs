@@ -0,0 +1,126 @@
+// tests/flows.rs
+use cybersec_ai_agent_backend::flows::{parse_flows, Flow, FlowAnalyzer, FlowDetectorConfig};
+use cybersec_log_model::timestamp::TimestampConfig;
+
+const SECOND: u64 = 1_000_000_000;
+
+fn flow(src: &str, dst: &str, port: u16, bytes: u64, start_secs: u64) -> Flow {
+    Flow {
+        src_ip: src.to_string(),
+        dst_ip: dst.to_string(),
+        src_port: Some(50_000),
+        dst_port: Some(port),
+        protocol: "tcp".to_string(),
+        bytes,
+        packets: 1,
+        start: Some(start_secs * SECOND),
+        end: None,
+    }
+}
+
+#[test]
+fn test_parse_flow_csv_and_json() {
+    let flows = parse_flows(include_str!("fixtures/flows/flows.csv"), &TimestampConfig::default()).unwrap();
+    assert_eq!(flows.len(), 2);
+    assert_eq!(flows[0].src_ip, "10.0.0.31");
+    assert_eq!(flows[0].dst_port, Some(443));
+    assert_eq!(flows[0].protocol, "tcp");
+    assert_eq!(flows[0].bytes, 18_320);
+    assert_eq!(flows[0].start, Some(1_709_287_200 * SECOND));
+    assert_eq!(flows[1].packets, 1);
+
+    let json = r#"{"sourceIPv4Address":"10.0.0.8","destinationIPv4Address":"203.0.113.9","destinationTransportPort":53,"protocolIdentifier":17,"octetDeltaCount":120,"flowStartMilliseconds":1709287200500}"#;
+    let flows = parse_flows(json, &TimestampConfig::default()).unwrap();
+    assert_eq!(flows[0].protocol, "udp");
+    assert_eq!(flows[0].start, Some(1_709_287_200_500_000_000));
+
+    assert!(parse_flows("srcaddr,dstaddr\n10.0.0.1", &TimestampConfig::default()).is_err());
+}
+
+#[test]
+fn test_port_scans_reported_once() {
+    let mut analyzer = FlowAnalyzer::new(FlowDetectorConfig::default());
+    let mut flows: Vec<Flow> = (1..=25).map(|host| flow("203.0.113.45", &format!("10.0.0.{}", host), 22, 60, 100)).collect();
+    flows.extend((1..=30).map(|port| flow("203.0.113.46", "10.0.0.5", port, 60, 100 + port as u64)));
+
+    let detections = analyzer.analyze(&flows, 0);
+    let categories: Vec<_> = detections.iter().map(|d| d.category.as_str()).collect();
+    assert_eq!(categories, vec!["flow_horizontal_scan", "flow_vertical_scan"]);
+    assert_eq!(detections[0].entity.as_deref(), Some("203.0.113.45"));
+    assert!(detections[1].details.contains("20 ports on 10.0.0.5"));
+}
+
+#[test]
+fn test_slow_scans_stay_under_the_window() {
+    let mut analyzer = FlowAnalyzer::new(FlowDetectorConfig::default());
+    // One host every 10 seconds: never more than 7 within the 60 second window
+    let flows: Vec<Flow> = (1..=40)
+        .map(|host| flow("203.0.113.45", &format!("10.0.0.{}", host), 22, 60, host * 10))
+        .collect();
+    assert!(analyzer.analyze(&flows, 0).is_empty());
+
+    // The same hosts probed quickly are a scan
+    let flows: Vec<Flow> = (1..=20)
+        .map(|host| flow("203.0.113.45", &format!("10.0.1.{}", host), 22, 60, 500))
+        .collect();
+    assert_eq!(analyzer.analyze(&flows, 0)[0].category, "flow_horizontal_scan");
+}
+
+#[test]
+fn test_beaconing_and_large_outbound_transfer() {
+    let mut analyzer = FlowAnalyzer::new(FlowDetectorConfig::default());
+    // Every 60 seconds, give or take a second
+    let flows: Vec<Flow> = [0, 60, 121, 180, 241, 300, 360]
+        .iter()
+        .map(|t| flow("10.0.0.31", "198.51.100.7", 443, 900, *t))
+        .collect();
+    let detections = analyzer.analyze(&flows, 0);
+    assert_eq!(detections.len(), 1);
+    assert_eq!(detections[0].category, "flow_beaconing");
+
+    let detections = analyzer.analyze(&[flow("10.0.0.31", "203.0.113.9", 443, 2_000_000_000, 400)], 0);
+    assert_eq!(detections[0].category, "flow_large_outbound_transfer");
+    assert_eq!(detections[0].severity, "HIGH");
+    // Internal to internal is not exfiltration
+    assert!(analyzer.analyze(&[flow("10.0.0.31", "10.0.0.40", 445, 2_000_000_000, 401)], 0).is_empty());
+}
+
+#[test]
+fn test_rare_port_and_network_view() {
+    let mut analyzer = FlowAnalyzer::new(FlowDetectorConfig {
+        rare_port_baseline_flows: 100,
+        rare_port_max_share: 0.01,
+        ..FlowDetectorConfig::default()
+    });
+    let mut baseline: Vec<Flow> = (0..100).map(|i| flow("10.0.0.31", "10.0.0.5", 443, 900, i * 120)).collect();
+    baseline[0].packets = 12;
+    assert!(analyzer.analyze(&baseline, 0).iter().all(|d| d.category != "flow_rare_port"));
+
+    let detections = analyzer.analyze(&[flow("10.0.0.31", "198.51.100.7", 4444, 300, 20_000)], 0);
+    assert_eq!(detections[0].category, "flow_rare_port");
+
+    let view = analyzer.network_view(0, 100);
+    assert_eq!(view.len(), 1);
+    assert_eq!(view[0].target, "10.0.0.5");
+    assert_eq!(view[0].open_ports, vec![443]);
+    assert_eq!(view[0].services, vec!["https"]);
+}
+
+#[test]
+fn test_network_view_is_paged_and_bounded() {
+    let mut analyzer = FlowAnalyzer::new(FlowDetectorConfig::default());
+    let flows: Vec<Flow> = (0..10_050u64)
+        .map(|i| Flow {
+            packets: 12,
+            ..flow("10.0.0.31", &format!("10.1.{}.{}", i / 250, i % 250), 443, 900, i)
+        })
+        .collect();
+    analyzer.analyze(&flows, 0);
+
+    // Once full, the hosts seen least recently make room
+    let view: Vec<_> = (0..20).flat_map(|page| analyzer.network_view(page * 1_000, 1_000)).collect();
+    assert!(view.len() <= 10_000);
+    assert!(view.iter().any(|scan| scan.target == "10.1.40.49"));
+    assert!(view.iter().all(|scan| scan.target != "10.1.0.0"));
+    assert_eq!(analyzer.network_view(0, 5_000).len(), 1_000);
+}
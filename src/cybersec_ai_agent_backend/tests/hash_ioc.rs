@@ -0,0 +1,104 @@
+// tests/hash_ioc.rs
+use cybersec_ai_agent_backend::hash_ioc::{detection, hash_tokens, HashIoc, HashIocStore};
+use cybersec_ai_agent_backend::{classify_record, suricata, HASH_IOCS};
+use cybersec_log_model::LogRecord;
+use std::collections::BTreeMap;
+
+const EICAR_MD5: &str = "44d88612fea8a8f36de82e1278abb02f";
+const EICAR_SHA1: &str = "3395856ce81f2b7382dee72602f798b642f14140";
+const EICAR_SHA256: &str = "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f";
+
+fn ioc(hash: &str, label: &str) -> HashIoc {
+    HashIoc {
+        hash: hash.to_string(),
+        label: label.to_string(),
+        source: "test-feed".to_string(),
+    }
+}
+
+#[test]
+fn test_register_and_lookup() {
+    let mut store = HashIocStore::default();
+    let registered = store.register(&[ioc(EICAR_MD5, "EICAR"), ioc(&EICAR_SHA256.to_uppercase(), "EICAR")]);
+    assert_eq!(registered, Ok(2));
+
+    let found = store.lookup(&format!(" {} ", EICAR_SHA256)).unwrap();
+    assert_eq!((found.hash.as_str(), found.algorithm.as_str()), (EICAR_SHA256, "SHA-256"));
+    assert_eq!((found.label.as_str(), found.source.as_str()), ("EICAR", "test-feed"));
+    assert_eq!(store.lookup(&EICAR_MD5.to_uppercase()).unwrap().algorithm, "MD5");
+    assert!(store.lookup(EICAR_SHA1).is_none());
+
+    assert_eq!(store.remove(&[EICAR_MD5.to_string(), EICAR_SHA1.to_string()]), 1);
+    assert_eq!(store.stats().md5, 0);
+    assert_eq!(store.stats().sha256, 1);
+}
+
+#[test]
+fn test_invalid_hash_rejects_whole_batch() {
+    let mut store = HashIocStore::default();
+    let result = store.register(&[ioc(EICAR_MD5, "EICAR"), ioc("+4d88612fea8a8f36de82e1278abb02f", "bad")]);
+    assert_eq!(result, Err("Not an MD5, SHA-1 or SHA-256 hash: +4d88612fea8a8f36de82e1278abb02f".to_string()));
+    assert!(store.is_empty());
+}
+
+#[test]
+fn test_hash_tokens() {
+    let text = format!("Hashes=SHA1={},MD5={} id=deadbeef {}ff", EICAR_SHA1.to_uppercase(), EICAR_MD5, EICAR_MD5);
+    let tokens: Vec<&str> = hash_tokens(&text).collect();
+    assert_eq!(tokens, vec![EICAR_SHA1.to_uppercase().as_str(), EICAR_MD5]);
+}
+
+#[test]
+fn test_detection_in_edr_event() {
+    let mut store = HashIocStore::default();
+    store.register(&[ioc(EICAR_SHA1, "EICAR test file")]).unwrap();
+
+    let mut attributes = BTreeMap::new();
+    attributes.insert("Image".to_string(), "C:\\Users\\bob\\Downloads\\eicar.com".to_string());
+    attributes.insert("Hashes".to_string(), format!("SHA1={},MD5={}", EICAR_SHA1.to_uppercase(), EICAR_MD5));
+    let record = LogRecord {
+        message: "Process Create".to_string(),
+        source: Some("windows".to_string()),
+        attributes,
+        ..Default::default()
+    };
+    let threat = detection(&record, &store).unwrap();
+    assert_eq!(threat.category, "malicious_file_hash");
+    assert_eq!(threat.entity.as_deref(), Some(EICAR_SHA1));
+    assert_eq!(
+        threat.details,
+        format!("Malicious file hash: EICAR test file SHA-1 {} (source test-feed)", EICAR_SHA1)
+    );
+
+    let clean = LogRecord {
+        message: format!("Scanned {}", EICAR_MD5),
+        ..Default::default()
+    };
+    assert!(detection(&clean, &store).is_none());
+}
+
+#[test]
+fn test_large_registry() {
+    let mut store = HashIocStore::default();
+    let hashes: Vec<HashIoc> = (0..300_000u64)
+        .map(|i| {
+            let hash = format!("{:064x}", (i as u128).wrapping_mul(0x9e37_79b9_7f4a_7c15_f39c_c060_5ced_c835));
+            ioc(&hash, if i % 2 == 0 { "family-a" } else { "family-b" })
+        })
+        .collect();
+    store.register(&hashes).unwrap();
+
+    assert_eq!(store.stats().sha256, 300_000);
+    assert_eq!(store.lookup(&hashes[123_456].hash).unwrap().label, "family-a");
+    assert!(store.lookup(&format!("{:064x}", 1)).is_none());
+}
+
+#[test]
+fn test_hashes_reported_alongside_sensor_rules() {
+    HASH_IOCS.with(|store| store.borrow_mut().register(&[ioc(EICAR_MD5, "EICAR test file")]).unwrap());
+    let mut alert = suricata::parse_eve(include_str!("fixtures/suricata/eve.json")).unwrap().remove(0);
+    alert.attributes.insert("fileinfo.md5".to_string(), EICAR_MD5.to_string());
+
+    let categories: Vec<String> = classify_record(&alert).into_iter().map(|finding| finding.category).collect();
+    assert_eq!(categories, vec!["ids_alert", "malicious_file_hash"]);
+}
@@ -0,0 +1,65 @@
+// tests/k8s_audit.rs
+use cybersec_ai_agent_backend::k8s_audit::{parse_audit_log, K8sAuditConfig, K8sAuditDetector};
+
+#[test]
+fn test_parse_audit_log() {
+    let records = parse_audit_log(include_str!("fixtures/k8s/audit.log")).unwrap();
+    assert_eq!(records.len(), 9);
+    assert_eq!(records[1].user.as_deref(), Some("alice@example.com"));
+    assert_eq!(records[1].source_ip.as_deref(), Some("10.0.0.31"));
+    assert_eq!(records[1].outcome.as_deref(), Some("101"));
+    assert_eq!(records[1].timestamp, Some(1_709_287_200_000_000_000));
+    assert_eq!(records[3].message, "Kubernetes create pods default/debug by mallory");
+
+    let list = r#"{"kind":"EventList","apiVersion":"audit.k8s.io/v1","items":[{"verb":"get","user":{"username":"bob"}}]}"#;
+    assert_eq!(parse_audit_log(list).unwrap()[0].user.as_deref(), Some("bob"));
+    assert!(parse_audit_log(r#"{"kind":"Event","user":{"username":"bob"}}"#).is_err());
+}
+
+#[test]
+fn test_audit_detections() {
+    let records = parse_audit_log(include_str!("fixtures/k8s/audit.log")).unwrap();
+    let mut detector = K8sAuditDetector::new(K8sAuditConfig::default());
+
+    let categories: Vec<Vec<String>> = records
+        .iter()
+        .map(|record| detector.detections(record).into_iter().map(|d| d.category).collect())
+        .collect();
+    // RequestReceived is skipped in favour of the later stages
+    assert!(categories[0].is_empty());
+    assert_eq!(categories[1], vec!["k8s_pod_exec"]);
+    // The same exec session completing is not reported again
+    assert!(categories[2].is_empty());
+    assert_eq!(categories[3], vec!["k8s_privileged_pod"]);
+    assert_eq!(categories[4], vec!["k8s_unusual_secret_read"]);
+    // kube-system service accounts are allowlisted
+    assert!(categories[5].is_empty());
+    assert_eq!(categories[6], vec!["k8s_cluster_admin_binding"]);
+    assert_eq!(categories[7], vec!["k8s_anonymous_request"]);
+    // Anonymous health checks are expected
+    assert!(categories[8].is_empty());
+
+    let exec = detector.detection(&records[1]).unwrap();
+    assert_eq!(exec.entity.as_deref(), Some("alice@example.com"));
+    assert!(exec.details.ends_with("sh -c cat /etc/shadow"));
+    assert!(detector.detection(&records[3]).unwrap().details.contains("privileged container, hostPath /"));
+    assert!(detector.detection(&records[6]).unwrap().details.contains("ServiceAccount default"));
+    let anonymous = detector.detection(&records[7]).unwrap();
+    assert_eq!(anonymous.severity, "MEDIUM");
+    assert!(anonymous.details.ends_with("(denied: 403)"));
+}
+
+#[test]
+fn test_exec_reported_without_response_started() {
+    let records = parse_audit_log(include_str!("fixtures/k8s/audit.log")).unwrap();
+    let mut detector = K8sAuditDetector::new(K8sAuditConfig::default());
+
+    // A policy omitting ResponseStarted only logs the session as it completes
+    let exec = detector.detection(&records[2]).unwrap();
+    assert_eq!(exec.category, "k8s_pod_exec");
+    assert_eq!(exec.severity, "MEDIUM");
+
+    // Once reported at ResponseStarted, the completion stays quiet
+    assert!(detector.detection(&records[1]).is_some());
+    assert!(detector.detection(&records[2]).is_none());
+}
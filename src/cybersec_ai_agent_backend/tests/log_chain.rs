@@ -0,0 +1,137 @@
+// tests/log_chain.rs
+use cybersec_ai_agent_backend::security_logs::SecurityLogStore;
+use cybersec_ai_agent_backend::SecurityLog;
+use cybersec_log_model::log_chain::{verify_chain_proof, CHECKPOINT_INTERVAL, GENESIS_HASH};
+
+fn log(index: u64) -> SecurityLog {
+    SecurityLog {
+        timestamp: index * 1_000,
+        event_type: "ids_alert".to_string(),
+        details: format!("Alert {}", index),
+        severity: if index.is_multiple_of(2) { "INFO" } else { "HIGH" }.to_string(),
+        entity: None,
+        source: None,
+        sequence: None,
+        hash: None,
+    }
+}
+
+fn store(count: u64) -> SecurityLogStore {
+    SecurityLogStore::from_logs((0..count).map(log).collect())
+}
+
+#[test]
+fn test_push_assigns_sequence_and_chains_hashes() {
+    let store = store(3);
+    let logs: Vec<&SecurityLog> = store.iter().collect();
+    assert_eq!(logs.iter().map(|log| log.sequence).collect::<Vec<_>>(), vec![Some(0), Some(1), Some(2)]);
+    assert_ne!(logs[0].hash, logs[1].hash);
+    assert_eq!(store.head().length, 3);
+    assert_eq!(store.head().hash, logs[2].hash.clone().unwrap());
+    assert_eq!(SecurityLogStore::default().head().hash, GENESIS_HASH);
+
+    let verification = store.verify(0, 2).unwrap();
+    assert!(verification.valid, "{:?}", verification.error);
+    assert_eq!(verification.verified, 3);
+}
+
+#[test]
+fn test_edit_detected() {
+    let (mut logs, chain) = store(5).into_parts();
+    logs[2].details = "Alert 2 (edited)".to_string();
+    let store = SecurityLogStore::restore(logs, chain);
+    let verification = store.verify(0, 4).unwrap();
+    assert!(!verification.valid);
+    assert_eq!(verification.error.as_deref(), Some("Entry 2 does not match its hash"));
+    assert!(store.verify(3, 4).unwrap().valid);
+}
+
+#[test]
+fn test_truncation_detected() {
+    let (mut logs, chain) = store(5).into_parts();
+    logs.pop();
+    let store = SecurityLogStore::restore(logs.clone(), chain);
+    assert_eq!(store.verify(0, 4).unwrap().error.as_deref(), Some("Entry 4 is missing"));
+
+    logs.remove(1);
+    let (_, chain) = self::store(5).into_parts();
+    let store = SecurityLogStore::restore(logs, chain);
+    assert_eq!(store.verify(0, 3).unwrap().error.as_deref(), Some("Entry 1 is missing"));
+}
+
+#[test]
+fn test_eviction_leaves_verifiable_pruned_ranges() {
+    let mut store = store(6);
+    store.evict(&[false, true, true, false, false, true]);
+    let verification = store.verify(0, 5).unwrap();
+    assert!(verification.valid, "{:?}", verification.error);
+    assert_eq!((verification.verified, verification.pruned), (3, 3));
+
+    // The chain keeps growing from the evicted head
+    store.push(log(6));
+    assert!(store.verify(0, 6).unwrap().valid);
+    assert!(store.verify(3, 6).unwrap().valid);
+}
+
+#[test]
+fn test_proof_verifies_offline_against_head() {
+    let mut store = store(10);
+    store.evict(&[false, false, true, true]);
+    let head = store.head();
+
+    let proof = store.proof(0, 9).unwrap();
+    assert_eq!(verify_chain_proof(&proof, &head.hash), Ok(8));
+    let tail = store.proof(4, 9).unwrap();
+    assert_eq!(verify_chain_proof(&tail, &head.hash), Ok(6));
+    assert!(verify_chain_proof(&tail, GENESIS_HASH).is_err());
+
+    let mut forged = proof.clone();
+    forged.entries[5].severity = "LOW".to_string();
+    assert!(verify_chain_proof(&forged, &head.hash).is_err());
+    let mut dropped = proof;
+    dropped.entries.remove(3);
+    assert!(verify_chain_proof(&dropped, &head.hash).is_err());
+
+    assert!(store.proof(3, 9).is_err());
+    assert!(store.proof(0, 2).is_err());
+}
+
+#[test]
+fn test_checkpoints_and_restore_keep_the_chain() {
+    let store = store(2 * CHECKPOINT_INTERVAL + 5);
+    let checkpoints = store.checkpoints().to_vec();
+    assert_eq!(checkpoints.len(), 2);
+    assert_eq!(checkpoints[1].sequence, 2 * CHECKPOINT_INTERVAL - 1);
+
+    let head = store.head();
+    let (logs, chain) = store.into_parts();
+    let mut restored = SecurityLogStore::restore(logs, chain);
+    assert_eq!(restored.head(), head);
+    assert_eq!(restored.checkpoints(), checkpoints.as_slice());
+    restored.push(log(0));
+    assert_eq!(restored.head().length, head.length + 1);
+    assert!(restored.verify(head.length - 10, head.length).unwrap().valid);
+}
+
+#[test]
+fn test_source_is_chained() {
+    let mut store = SecurityLogStore::default();
+    store.push(SecurityLog {
+        source: Some("suricata".to_string()),
+        ..log(0)
+    });
+    store.push(log(1));
+    assert!(store.verify(0, 1).unwrap().valid);
+
+    let (mut logs, chain) = store.into_parts();
+    logs[0].source = Some("zeek".to_string());
+    let store = SecurityLogStore::restore(logs, chain);
+    assert_eq!(store.verify(0, 1).unwrap().error.as_deref(), Some("Entry 0 does not match its hash"));
+
+    // An empty source is told apart from none
+    let (mut logs, chain) = store.into_parts();
+    logs[0].source = Some("suricata".to_string());
+    logs[1].source = Some(String::new());
+    let store = SecurityLogStore::restore(logs, chain);
+    assert_eq!(store.verify(0, 1).unwrap().error.as_deref(), Some("Entry 1 does not match its hash"));
+}
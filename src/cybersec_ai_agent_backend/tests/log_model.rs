@@ -0,0 +1,52 @@
+// tests/log_model.rs
+use cybersec_ai_agent_backend::api::LogEntry;
+use cybersec_ai_agent_backend::windows_events::parse_events;
+use cybersec_ai_agent_backend::ThreatInput;
+use cybersec_log_model::LogRecord;
+
+#[test]
+fn test_message_only_entry() {
+    let record = LogRecord::from("Malware detected");
+
+    assert_eq!(record.message, "Malware detected");
+    assert_eq!(record, LogRecord::new("Malware detected"));
+}
+
+#[test]
+fn test_threat_input_adapter() {
+    let record = LogRecord::from(ThreatInput {
+        source: "nginx".to_string(),
+        message: "GET /login?id=1' OR '1'='1".to_string(),
+    });
+
+    assert_eq!(record.source.as_deref(), Some("nginx"));
+    assert_eq!(record.message, "GET /login?id=1' OR '1'='1");
+}
+
+#[test]
+fn test_legacy_entry_round_trip() {
+    let entry = LogEntry {
+        message: "Malware detected".to_string(),
+        timestamp: 1_709_287_210_000_000_000,
+        source: "edr".to_string(),
+    };
+    let record = LogRecord::from(entry.clone());
+
+    assert_eq!(record.timestamp, Some(1_709_287_210_000_000_000));
+    assert_eq!(record.source.as_deref(), Some("edr"));
+    assert_eq!(LogEntry::from(record), entry);
+}
+
+#[test]
+fn test_windows_event_adapter() {
+    let events = parse_events(include_str!("fixtures/windows/failed_logons.xml")).unwrap();
+    let record = LogRecord::from(&events[0]);
+
+    assert_eq!(record.original_timestamp.as_deref(), Some("2024-03-01T10:00:10.1234567Z"));
+    assert_eq!(record.timestamp, Some(1_709_287_210_123_456_700));
+    assert_eq!(record.source_ip.as_deref(), Some("203.0.113.45"));
+    assert_eq!(record.user.as_deref(), Some("administrator"));
+    assert_eq!(record.outcome.as_deref(), Some("failure"));
+    assert_eq!(record.attribute("LogonType"), Some("3"));
+    assert_eq!(record.attribute("EventID"), Some("4625"));
+}
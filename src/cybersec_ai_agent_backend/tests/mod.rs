@@ -0,0 +1,26 @@
+// tests/mod.rs
+// Compiled into the library's unit test harness: the canister only links
+// as a wasm cdylib, so these cannot run as integration tests
+mod archive;
+mod certified;
+mod cloudtrail;
+mod dns;
+mod email;
+mod event_time;
+mod flows;
+mod hash_ioc;
+mod k8s_audit;
+mod log_chain;
+mod log_model;
+mod noise_reduction;
+mod persistence;
+mod retention;
+mod search;
+mod security_logs;
+mod sensor_logs;
+mod snapshot;
+mod threat_stats;
+mod upload;
+mod url_risk;
+mod web_recon;
+mod windows_events;
@@ -0,0 +1,75 @@
+// tests/noise_reduction.rs
+use cybersec_ai_agent_backend::noise_reduction::{
+    mask_message, NoiseReducer, NoiseReductionConfig, OCCURRENCES, SAMPLE_RATE,
+};
+use cybersec_log_model::LogRecord;
+
+const SECOND: u64 = 1_000_000_000;
+
+fn record(source: &str, message: &str) -> LogRecord {
+    LogRecord {
+        source: Some(source.to_string()),
+        ..LogRecord::new(message)
+    }
+}
+
+#[test]
+fn test_mask_message() {
+    assert_eq!(
+        mask_message("session 9f8e7d6c for user 1042 from 10.0.0.8"),
+        "session * for user # from #.#.#.#"
+    );
+}
+
+#[test]
+fn test_near_identical_messages_collapse() {
+    let mut reducer = NoiseReducer::new(NoiseReductionConfig::default());
+    let mut logs = Vec::new();
+
+    reducer.admit(record("app", "user 1042 logged in"), false, 0, &mut logs);
+    reducer.admit(record("app", "user 7 logged in"), false, 10 * SECOND, &mut logs);
+    reducer.admit(record("app", "user 7 logged out"), false, 20 * SECOND, &mut logs);
+    // Outside the 60 second window, so it starts a new record
+    reducer.admit(record("app", "user 8 logged in"), false, 90 * SECOND, &mut logs);
+
+    assert_eq!(logs.len(), 3);
+    assert_eq!(logs[0].attribute(OCCURRENCES), Some("2"));
+    assert_eq!(logs[0].attribute("dedup.last_seen"), Some("10000000000"));
+    assert_eq!(reducer.stats().deduplicated, 1);
+}
+
+#[test]
+fn test_flagged_records_are_not_folded() {
+    let mut reducer = NoiseReducer::new(NoiseReductionConfig::default());
+    let mut logs = Vec::new();
+
+    reducer.admit(record("web", "GET /search from 10.0.0.8"), false, 0, &mut logs);
+    reducer.admit(record("web", "GET /search from 203.0.113.7"), true, SECOND, &mut logs);
+    // Nor do later benign duplicates fold into the flagged record
+    reducer.admit(record("web", "GET /search from 10.0.0.9"), false, 2 * SECOND, &mut logs);
+
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0].attribute(OCCURRENCES), Some("2"));
+    assert_eq!(logs[1].message, "GET /search from 203.0.113.7");
+    assert_eq!(logs[1].attribute(OCCURRENCES), None);
+}
+
+#[test]
+fn test_sampling_spares_flagged_records() {
+    let mut reducer = NoiseReducer::new(NoiseReductionConfig {
+        dedup_window_secs: 0,
+        source_quota_per_minute: 10,
+    });
+    let mut logs = Vec::new();
+
+    for i in 0..40 {
+        reducer.admit(record("noisy", &format!("heartbeat {}", i)), false, 0, &mut logs);
+    }
+    reducer.admit(record("noisy", "sql injection attempt"), true, 0, &mut logs);
+
+    let stats = reducer.stats();
+    assert_eq!(logs.len() as u64 + stats.sampled_out, 41);
+    assert_eq!(stats.sampled_out, 20);
+    assert!(logs[10].attribute(SAMPLE_RATE).is_some());
+    assert_eq!(logs.last().unwrap().message, "sql injection attempt");
+}
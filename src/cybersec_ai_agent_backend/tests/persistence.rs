@@ -0,0 +1,190 @@
+// tests/persistence.rs
+use candid::Principal;
+use cybersec_ai_agent_backend::flows::{parse_flows, FlowAnalyzer, FlowDetectorConfig};
+use cybersec_ai_agent_backend::hash_ioc::HashIoc;
+use cybersec_ai_agent_backend::noise_reduction::{NoiseReducer, NoiseReductionConfig};
+use cybersec_ai_agent_backend::persistence::{
+    decode_state, install, load_state, restore_from_stable_memory, save_state, save_to_stable_memory, take_state,
+    StateV1,
+};
+use cybersec_ai_agent_backend::threat_stats::ThreatStatsQuery;
+use cybersec_ai_agent_backend::{SecurityLog, SECURITY_LOGS};
+use cybersec_log_model::timestamp::TimestampConfig;
+use cybersec_log_model::LogRecord;
+use ic_cdk::api::stable::{StableMemory, StableMemoryError, WASM_PAGE_SIZE_IN_BYTES};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const SECOND: u64 = 1_000_000_000;
+const PAGE: u64 = WASM_PAGE_SIZE_IN_BYTES as u64;
+
+fn populated_state() -> StateV1 {
+    let mut state = StateV1::default();
+
+    let mut record = LogRecord::new("Blocked 203.0.113.7 probing /wp-login.php").with_attribute("status", "403");
+    record.extract_entities();
+    let mut reducer = NoiseReducer::new(NoiseReductionConfig::default());
+    for _ in 0..3 {
+        reducer.admit(record.clone(), false, 10 * SECOND, &mut state.logs);
+    }
+    state.noise_reducer = reducer;
+
+    state.security_logs.push(SecurityLog {
+        timestamp: 10 * SECOND,
+        event_type: "web_reconnaissance".to_string(),
+        details: "Web reconnaissance from 203.0.113.7".to_string(),
+        severity: "HIGH".to_string(),
+        entity: Some("203.0.113.7".to_string()),
+        source: None,
+        sequence: None,
+        hash: None,
+    });
+    state.threats.insert("web_reconnaissance".to_string(), 1);
+    state.threat_stats.record("web_reconnaissance", "HIGH", "access_log", 10 * SECOND);
+    state.wallets.insert(Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap(), "Verified".to_string());
+
+    let mut flows = FlowAnalyzer::new(FlowDetectorConfig::default());
+    flows.analyze(&parse_flows(include_str!("fixtures/flows/flows.csv"), &TimestampConfig::default()).unwrap(), 0);
+    state.flow_analyzer = flows;
+
+    state
+        .hash_iocs
+        .register(&[HashIoc {
+            hash: "44d88612fea8a8f36de82e1278abb02f".to_string(),
+            label: "EICAR".to_string(),
+            source: "test-feed".to_string(),
+        }])
+        .unwrap();
+    state.url_risk_config.max_subdomains = 5;
+    state
+}
+
+// Stable memory that lives on the heap, for running the upgrade hooks' storage in tests
+#[derive(Clone, Default)]
+struct HeapMemory(Rc<RefCell<Vec<u8>>>);
+
+impl HeapMemory {
+    fn with_bytes(bytes: &[u8]) -> Self {
+        let memory = HeapMemory::default();
+        memory.stable_grow((bytes.len() as u64).div_ceil(PAGE) as u32).unwrap();
+        memory.stable_write(0, bytes);
+        memory
+    }
+}
+
+impl StableMemory for HeapMemory {
+    fn stable_size(&self) -> u32 {
+        (self.0.borrow().len() as u64 / PAGE) as u32
+    }
+
+    fn stable64_size(&self) -> u64 {
+        self.stable_size() as u64
+    }
+
+    fn stable_grow(&self, new_pages: u32) -> Result<u32, StableMemoryError> {
+        let previous = self.stable_size();
+        let mut bytes = self.0.borrow_mut();
+        let size = bytes.len() + new_pages as usize * PAGE as usize;
+        bytes.resize(size, 0);
+        Ok(previous)
+    }
+
+    fn stable64_grow(&self, new_pages: u64) -> Result<u64, StableMemoryError> {
+        self.stable_grow(new_pages as u32).map(u64::from)
+    }
+
+    fn stable_write(&self, offset: u32, buf: &[u8]) {
+        self.0.borrow_mut()[offset as usize..offset as usize + buf.len()].copy_from_slice(buf);
+    }
+
+    fn stable64_write(&self, offset: u64, buf: &[u8]) {
+        self.stable_write(offset as u32, buf)
+    }
+
+    fn stable_read(&self, offset: u32, buf: &mut [u8]) {
+        buf.copy_from_slice(&self.0.borrow()[offset as usize..offset as usize + buf.len()]);
+    }
+
+    fn stable64_read(&self, offset: u64, buf: &mut [u8]) {
+        self.stable_read(offset as u32, buf)
+    }
+}
+
+#[test]
+fn test_state_survives_upgrade() {
+    let expected_view = format!("{:?}", populated_state().flow_analyzer.network_view(0, 100));
+    install(populated_state());
+
+    // pre_upgrade writes the live state to stable memory
+    let memory = HeapMemory::default();
+    save_to_stable_memory(memory.clone()).unwrap();
+    // The heap does not survive the upgrade
+    take_state();
+
+    // post_upgrade: the new code reads it back
+    restore_from_stable_memory(memory).unwrap();
+    let restored = take_state();
+
+    assert_eq!(restored.logs.len(), 1);
+    assert_eq!(restored.logs[0].message, "Blocked 203.0.113.7 probing /wp-login.php");
+    assert_eq!(restored.logs[0].entities.as_ref().unwrap().ipv4, vec!["203.0.113.7"]);
+    assert_eq!(restored.noise_reducer.stats().deduplicated, 2);
+    assert_eq!(restored.security_logs.len(), 1);
+    assert_eq!(restored.security_logs[0].event_type, "web_reconnaissance");
+    assert_eq!(restored.threats.get("web_reconnaissance"), Some(&1));
+    let stats = restored.threat_stats.query(&ThreatStatsQuery::default()).unwrap().buckets;
+    assert_eq!((stats[0].start, stats[0].total), (0, 1));
+    assert_eq!(
+        restored.wallets.get(&Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap()).map(String::as_str),
+        Some("Verified")
+    );
+    assert_eq!(format!("{:?}", restored.flow_analyzer.network_view(0, 100)), expected_view);
+    assert_eq!(restored.hash_iocs.lookup("44D88612FEA8A8F36DE82E1278ABB02F").unwrap().label, "EICAR");
+    assert_eq!(restored.url_risk_config.max_subdomains, 5);
+}
+
+#[test]
+fn test_logs_are_saved_in_chunks() {
+    let state = StateV1 {
+        logs: (0..2_500).map(|index| LogRecord::new(format!("log {}", index))).collect(),
+        security_logs: (0..1_200)
+            .map(|index| SecurityLog {
+                timestamp: index * SECOND,
+                event_type: "flow_beaconing".to_string(),
+                details: format!("detection {}", index),
+                severity: "MEDIUM".to_string(),
+                entity: None,
+                source: None,
+                sequence: None,
+                hash: None,
+            })
+            .collect(),
+        ..StateV1::default()
+    };
+    install(state);
+    let head = SECURITY_LOGS.with(|logs| logs.borrow().head());
+
+    let mut bytes = Vec::new();
+    save_state(&mut bytes).unwrap();
+    // Nothing is taken out of the canister to save it
+    assert_eq!(take_state().logs.len(), 2_500);
+
+    let restored = load_state(&mut bytes.as_slice()).unwrap();
+    assert_eq!(restored.logs.len(), 2_500);
+    assert_eq!(restored.logs[2_499].message, "log 2499");
+    assert_eq!(restored.security_logs.len(), 1_200);
+    assert_eq!(restored.security_logs[1_199].sequence, Some(1_199));
+    install(restored);
+    assert_eq!(SECURITY_LOGS.with(|logs| logs.borrow().head()), head);
+
+    // A save cut short is refused rather than restored without its logs
+    assert!(load_state(&mut &bytes[..bytes.len() - 8]).is_err());
+}
+
+#[test]
+fn test_rejects_foreign_state() {
+    assert!(decode_state(b"not candid").is_err());
+    assert!(decode_state(&candid::encode_one(42u64).unwrap()).is_err());
+    assert!(restore_from_stable_memory(HeapMemory::with_bytes(b"not a saved state")).is_err());
+    assert!(restore_from_stable_memory(HeapMemory::default()).is_err());
+}
@@ -0,0 +1,188 @@
+// tests/retention.rs
+use cybersec_ai_agent_backend::retention::{
+    severity_rank, RetentionConfig, RetentionLedger, RetentionRule, Retained, PRUNED_RANGE_BYTES,
+};
+use cybersec_ai_agent_backend::security_logs::SecurityLogStore;
+use cybersec_ai_agent_backend::SecurityLog;
+use cybersec_log_model::LogRecord;
+
+const SECOND: u64 = 1_000_000_000;
+const DAY: u64 = 24 * 3600 * SECOND;
+
+fn record(message: &str, severity: &str, source: &str, time: u64) -> LogRecord {
+    LogRecord {
+        timestamp: Some(time),
+        severity: Some(severity.to_string()),
+        source: Some(source.to_string()),
+        ..LogRecord::new(message)
+    }
+}
+
+fn security_log(event_type: &str, severity: &str, time: u64) -> SecurityLog {
+    SecurityLog {
+        timestamp: time,
+        event_type: event_type.to_string(),
+        details: format!("{} detected", event_type),
+        severity: severity.to_string(),
+        entity: None,
+        source: None,
+        sequence: None,
+        hash: None,
+    }
+}
+
+fn config(rules: Vec<RetentionRule>) -> RetentionConfig {
+    RetentionConfig {
+        rules,
+        memory_budget_bytes: u64::MAX,
+        max_evictions_per_run: 1_000,
+    }
+}
+
+#[test]
+fn test_max_age_per_severity() {
+    let now = 30 * DAY;
+    let mut logs = vec![
+        record("debug chatter", "info", "app", now - 10 * DAY),
+        record("disk failing", "error", "app", now - 10 * DAY),
+        record("recent chatter", "INFO", "app", now - DAY),
+    ];
+    let mut security_logs = SecurityLogStore::from_logs(vec![security_log("phishing", "HIGH", now - 20 * DAY)]);
+    let rules = vec![
+        RetentionRule {
+            severity: Some("info".to_string()),
+            max_age_secs: Some(7 * 24 * 3600),
+            ..Default::default()
+        },
+        RetentionRule {
+            max_age_secs: Some(90 * 24 * 3600),
+            ..Default::default()
+        },
+    ];
+
+    let summary = RetentionLedger::default().enforce(&mut logs, &mut security_logs, &config(rules), now);
+    assert_eq!((summary.logs, summary.security_logs, summary.by_age), (1, 0, 1));
+    let messages: Vec<&str> = logs.iter().map(|log| log.message.as_str()).collect();
+    assert_eq!(messages, vec!["disk failing", "recent chatter"]);
+}
+
+#[test]
+fn test_max_count_and_bytes_per_event_type() {
+    let mut logs: Vec<LogRecord> = (0..5).map(|i| record(&format!("flow {}", i), "info", "netflow", i * SECOND)).collect();
+    logs.push(record("login", "info", "auth", SECOND));
+    let mut security_logs =
+        SecurityLogStore::from_logs((0..4).map(|i| security_log("dns_tunneling", "MEDIUM", i * SECOND)).collect());
+    let one_alert = security_logs.iter().next().unwrap().estimated_bytes();
+    let rules = vec![
+        RetentionRule {
+            event_type: Some("netflow".to_string()),
+            max_count: Some(2),
+            ..Default::default()
+        },
+        RetentionRule {
+            event_type: Some("dns_tunneling".to_string()),
+            max_bytes: Some(one_alert * 3),
+            ..Default::default()
+        },
+    ];
+
+    let summary = RetentionLedger::default().enforce(&mut logs, &mut security_logs, &config(rules), 10 * SECOND);
+    assert_eq!((summary.by_count, summary.by_bytes), (3, 1));
+    let messages: Vec<&str> = logs.iter().map(|log| log.message.as_str()).collect();
+    assert_eq!(messages, vec!["flow 3", "flow 4", "login"]);
+    let times: Vec<u64> = security_logs.iter().map(|log| log.timestamp / SECOND).collect();
+    assert_eq!(times, vec![1, 2, 3]);
+}
+
+#[test]
+fn test_memory_pressure_evicts_low_severity_first() {
+    let mut logs = vec![
+        record("old warning", "warning", "app", SECOND),
+        record("old notice", "notice", "app", 2 * SECOND),
+        record("newer notice", "notice", "app", 3 * SECOND),
+    ];
+    let mut security_logs = SecurityLogStore::from_logs(vec![security_log("k8s_privileged_pod", "HIGH", 0)]);
+    let kept = logs[0].estimated_bytes() + security_logs.iter().next().unwrap().estimated_bytes();
+    let mut config = config(Vec::new());
+    // Getting under 90% of the budget leaves room for the warning and the alert only
+    config.memory_budget_bytes = kept * 10 / 9 + 10;
+
+    let summary = RetentionLedger::default().enforce(&mut logs, &mut security_logs, &config, 10 * SECOND);
+    assert_eq!(summary.by_memory_pressure, 2);
+    assert_eq!(logs.len(), 1);
+    assert_eq!(logs[0].message, "old warning");
+    assert_eq!(security_logs.len(), 1);
+    assert!(summary.remaining_bytes <= config.memory_budget_bytes / 10 * 9);
+    assert!(summary.describe().starts_with("Evicted 2 log records and 0 security logs"));
+}
+
+#[test]
+fn test_pruned_ranges_count_towards_memory() {
+    let severities = ["LOW", "HIGH", "LOW", "HIGH", "LOW"];
+    let mut security_logs = SecurityLogStore::from_logs(
+        severities.iter().enumerate().map(|(i, severity)| security_log("port_scan", severity, i as u64)).collect(),
+    );
+    let high = security_logs.iter().nth(1).unwrap().estimated_bytes();
+    let mut config = config(Vec::new());
+    // Getting under 90% of the budget leaves room for the two kept logs and three ranges
+    config.memory_budget_bytes = (2 * high + 3 * PRUNED_RANGE_BYTES) * 10 / 9 + 10;
+
+    // Each low-severity log evicted between two kept ones leaves a range of its own
+    let summary = RetentionLedger::default().enforce(&mut Vec::new(), &mut security_logs, &config, SECOND);
+    assert_eq!(summary.security_logs, 3);
+    assert_eq!(security_logs.pruned_ranges(), 3);
+    assert_eq!(summary.remaining_bytes, 2 * high + 3 * PRUNED_RANGE_BYTES);
+}
+
+#[test]
+fn test_evictions_are_bounded_per_run() {
+    let mut logs: Vec<LogRecord> = (0..10).map(|i| record("old", "info", "app", i)).collect();
+    let mut config = config(vec![RetentionRule {
+        max_age_secs: Some(1),
+        ..Default::default()
+    }]);
+    config.max_evictions_per_run = 4;
+
+    let (mut ledger, mut security_logs) = (RetentionLedger::default(), SecurityLogStore::default());
+    assert_eq!(ledger.enforce(&mut logs, &mut security_logs, &config, DAY).logs, 4);
+    assert_eq!(ledger.enforce(&mut logs, &mut security_logs, &config, DAY).logs, 4);
+    assert_eq!(ledger.enforce(&mut logs, &mut security_logs, &config, DAY).logs, 2);
+    assert!(logs.is_empty());
+}
+
+#[test]
+fn test_ledger_keeps_running_totals_between_runs() {
+    let mut ledger = RetentionLedger::default();
+    let mut logs: Vec<LogRecord> = (0..3).map(|i| record(&format!("flow {}", i), "info", "netflow", i)).collect();
+    let mut security_logs = SecurityLogStore::from_logs(vec![security_log("port_scan", "LOW", 0)]);
+    let config = config(vec![RetentionRule {
+        event_type: Some("netflow".to_string()),
+        max_count: Some(3),
+        ..Default::default()
+    }]);
+    assert_eq!(ledger.enforce(&mut logs, &mut security_logs, &config, DAY).total(), 0);
+
+    // Only what was appended since is new to the ledger
+    logs.push(record("login", "info", "auth", 2));
+    logs.extend((3..5).map(|i| record(&format!("flow {}", i), "info", "netflow", i)));
+    security_logs.push(security_log("port_scan", "LOW", 1));
+    let summary = ledger.enforce(&mut logs, &mut security_logs, &config, DAY);
+    assert_eq!((summary.logs, summary.by_count), (2, 2));
+    let messages: Vec<&str> = logs.iter().map(|log| log.message.as_str()).collect();
+    assert_eq!(messages, vec!["flow 2", "login", "flow 3", "flow 4"]);
+
+    // A security log moved to an archive no longer counts, bar its pruned range
+    security_logs.remove_through(0);
+    let stored = security_logs.iter().next().unwrap().estimated_bytes()
+        + logs.iter().map(Retained::estimated_bytes).sum::<u64>()
+        + PRUNED_RANGE_BYTES;
+    assert_eq!(ledger.enforce(&mut logs, &mut security_logs, &config, DAY).remaining_bytes, stored);
+}
+
+#[test]
+fn test_severity_rank() {
+    assert!(severity_rank(Some("CRITICAL")) > severity_rank(Some("error")));
+    assert!(severity_rank(Some("warn")) > severity_rank(Some("notice")));
+    assert_eq!(severity_rank(Some("debug")), severity_rank(None));
+    assert!(RetentionConfig::default().rules.iter().all(|rule| rule.max_age_secs.is_some()));
+}
@@ -0,0 +1,130 @@
+// tests/search.rs
+use cybersec_ai_agent_backend::search::{parse_query, tokenize, SearchConfig, SearchIndex, SearchQuery};
+use cybersec_log_model::LogRecord;
+
+fn record(message: &str, source: &str) -> LogRecord {
+    LogRecord {
+        source: Some(source.to_string()),
+        ..LogRecord::new(message)
+    }
+}
+
+fn index() -> (Vec<LogRecord>, SearchIndex) {
+    let logs = vec![
+        record("Failed password for root from 203.0.113.7 port 22", "sshd"),
+        record("Accepted password for alice from 10.0.0.5 port 22", "sshd"),
+        record("Failed password for admin from 203.0.113.7 port 22", "sshd"),
+        record("CRON session opened for user root", "cron"),
+        record("GET /wp-login.php 404 from 198.51.100.9", "nginx"),
+    ];
+    let mut index = SearchIndex::new(SearchConfig::default());
+    index.sync(&logs);
+    (logs, index)
+}
+
+#[test]
+fn test_tokenize_lowercases_and_splits() {
+    assert_eq!(tokenize("Failed PASSWORD for user_42@host"), vec!["failed", "password", "for", "user_42", "host"]);
+}
+
+#[test]
+fn test_boolean_operators() {
+    let (_, index) = index();
+    assert_eq!(index.search("password root").unwrap(), vec![0]);
+    assert_eq!(index.search("root OR alice").unwrap(), vec![0, 1, 3]);
+    assert_eq!(index.search("password NOT failed").unwrap(), vec![1]);
+    assert_eq!(index.search("root -cron").unwrap(), vec![0]);
+    assert_eq!(index.search("(root OR admin) AND failed").unwrap(), vec![0, 2]);
+    assert_eq!(index.search("NOT sshd").unwrap(), vec![3, 4]);
+}
+
+#[test]
+fn test_phrase_and_prefix() {
+    let (_, index) = index();
+    assert_eq!(index.search("\"failed password\"").unwrap(), vec![0, 2]);
+    assert_eq!(index.search("\"password failed\"").unwrap(), Vec::<usize>::new());
+    assert_eq!(index.search("203.0.113.7").unwrap(), vec![0, 2]);
+    assert_eq!(index.search("adm*").unwrap(), vec![2]);
+    assert_eq!(index.search("wp-login*").unwrap_err(), "Prefix 'wp-login*' must be a single word");
+    assert!(index.search("a*").is_err());
+}
+
+#[test]
+fn test_phrase_does_not_span_fields() {
+    let (_, index) = index();
+    // "user root" ends the message and "cron" is the source
+    assert_eq!(index.search("\"user root\"").unwrap(), vec![3]);
+    assert!(index.search("\"root cron\"").unwrap().is_empty());
+}
+
+#[test]
+fn test_parse_precedence() {
+    let parsed = parse_query("a b OR -c").unwrap();
+    assert_eq!(
+        parsed,
+        SearchQuery::Or(vec![
+            SearchQuery::And(vec![SearchQuery::Term("a".to_string()), SearchQuery::Term("b".to_string())]),
+            SearchQuery::Not(Box::new(SearchQuery::Term("c".to_string()))),
+        ])
+    );
+    assert!(parse_query("(a OR b").is_err());
+    assert!(parse_query("   ").is_err());
+}
+
+#[test]
+fn test_evict_keeps_positions_in_step() {
+    let (mut logs, mut index) = index();
+    let evict = [true, false, true, false, false];
+    let mut position = 0;
+    logs.retain(|_| {
+        position += 1;
+        !evict[position - 1]
+    });
+    index.evict(&evict);
+    assert_eq!(index.search("password").unwrap(), vec![0]);
+    assert_eq!(index.search("root").unwrap(), vec![1]);
+
+    logs.push(record("Failed password for root again", "sshd"));
+    index.sync(&logs);
+    assert_eq!(index.search("failed root").unwrap(), vec![3]);
+    assert_eq!(index.stats().indexed_records, 4);
+}
+
+#[test]
+fn test_memory_budget_drops_oldest() {
+    let (logs, mut index) = index();
+    let full = index.stats().estimated_bytes;
+    index.set_config(SearchConfig {
+        memory_budget_bytes: full / 2,
+    });
+    let stats = index.stats();
+    assert!(stats.estimated_bytes <= full / 2);
+    assert!(stats.unindexed_records > 0);
+    assert_eq!(stats.indexed_records + stats.unindexed_records, logs.len() as u64);
+    // The newest record is still searchable, the oldest no longer is
+    assert_eq!(index.search("nginx").unwrap(), vec![4]);
+    assert!(index.search("\"root from 203.0.113.7\"").unwrap().is_empty());
+
+    index.rebuild(&[]);
+    assert_eq!(index.stats().estimated_bytes, 0);
+}
+
+#[test]
+fn test_rebuild_indexes_newest_within_budget() {
+    let (logs, full) = index();
+    let mut index = SearchIndex::new(SearchConfig {
+        memory_budget_bytes: full.stats().estimated_bytes / 2,
+    });
+    index.rebuild(&logs);
+
+    let stats = index.stats();
+    assert!(stats.estimated_bytes <= full.stats().estimated_bytes / 2);
+    assert_eq!(stats.indexed_records + stats.unindexed_records, logs.len() as u64);
+    assert_eq!(index.search("nginx").unwrap(), vec![4]);
+    assert!(index.search("sshd").unwrap().len() < 3);
+    // Later records still line up with their positions in the store
+    let mut logs = logs;
+    logs.push(record("Failed password for root from 198.51.100.9 port 22", "sshd"));
+    index.sync(&logs);
+    assert_eq!(index.search("198.51.100.9").unwrap(), vec![4, 5]);
+}
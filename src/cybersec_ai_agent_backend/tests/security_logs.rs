@@ -0,0 +1,159 @@
+// tests/security_logs.rs
+use cybersec_ai_agent_backend::security_logs::{SecurityLogQuery, SecurityLogStore, SortOrder};
+use cybersec_ai_agent_backend::SecurityLog;
+
+fn log(timestamp: u64, event_type: &str, severity: &str, details: &str, entity: Option<&str>) -> SecurityLog {
+    SecurityLog {
+        timestamp,
+        event_type: event_type.to_string(),
+        details: details.to_string(),
+        severity: severity.to_string(),
+        entity: entity.map(str::to_string),
+        source: None,
+        sequence: None,
+        hash: None,
+    }
+}
+
+fn store() -> SecurityLogStore {
+    SecurityLogStore::from_logs(vec![
+        log(10, "phishing", "HIGH", "Phishing mail from evil.example.com", Some("attacker@evil.example.com")),
+        log(20, "dns_tunneling", "MEDIUM", "Long TXT queries to tunnel.example.net", Some("10.0.0.5")),
+        log(30, "ids_alert", "high", "ET SCAN from 203.0.113.7 to 10.0.0.5", Some("203.0.113.7")),
+        log(30, "ids_alert", "LOW", "ET POLICY curl user agent", None),
+        log(40, "retention_eviction", "INFO", "Evicted 3 log records", None),
+    ])
+}
+
+fn times(logs: &[SecurityLog]) -> Vec<u64> {
+    logs.iter().map(|log| log.timestamp).collect()
+}
+
+#[test]
+fn test_time_range_and_order() {
+    let store = store();
+    let query = SecurityLogQuery {
+        from_time: Some(20),
+        to_time: Some(40),
+        limit: 10,
+        ..Default::default()
+    };
+    assert_eq!(times(&store.query(&query).unwrap().logs), vec![20, 30, 30]);
+
+    let newest = SecurityLogQuery {
+        order: SortOrder::NewestFirst,
+        ..query
+    };
+    assert_eq!(times(&store.query(&newest).unwrap().logs), vec![30, 30, 20]);
+}
+
+#[test]
+fn test_filters_combine() {
+    let store = store();
+    let high = SecurityLogQuery {
+        severities: vec!["HIGH".to_string()],
+        limit: 10,
+        ..Default::default()
+    };
+    assert_eq!(times(&store.query(&high).unwrap().logs), vec![10, 30]);
+
+    let ids_text = SecurityLogQuery {
+        event_type: Some("ids_alert".to_string()),
+        text: Some("et policy".to_string()),
+        limit: 10,
+        ..Default::default()
+    };
+    let page = store.query(&ids_text).unwrap();
+    assert_eq!(page.logs.len(), 1);
+    assert_eq!(page.logs[0].severity, "LOW");
+}
+
+#[test]
+fn test_entity_matches_field_and_details() {
+    let store = store();
+    let by_ip = SecurityLogQuery {
+        entity: Some("10.0.0.5".to_string()),
+        limit: 10,
+        ..Default::default()
+    };
+    assert_eq!(times(&store.query(&by_ip).unwrap().logs), vec![20, 30]);
+
+    let by_domain = SecurityLogQuery {
+        entity: Some("Evil.Example.com".to_string()),
+        limit: 10,
+        ..Default::default()
+    };
+    assert_eq!(times(&store.query(&by_domain).unwrap().logs), vec![10]);
+}
+
+#[test]
+fn test_cursor_pages_through_ties() {
+    let store = store();
+    let mut query = SecurityLogQuery {
+        limit: 2,
+        ..Default::default()
+    };
+    let mut seen = Vec::new();
+    loop {
+        let page = store.query(&query).unwrap();
+        seen.extend(page.logs.iter().map(|log| log.details.clone()));
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    assert_eq!(seen.len(), 5);
+    assert_eq!(seen[2], "ET SCAN from 203.0.113.7 to 10.0.0.5");
+    assert_eq!(seen[3], "ET POLICY curl user agent");
+}
+
+#[test]
+fn test_severity_union_pages_in_order() {
+    let store = store();
+    let mut query = SecurityLogQuery {
+        severities: vec!["high".to_string(), "LOW".to_string(), "low".to_string()],
+        to_time: Some(35),
+        order: SortOrder::NewestFirst,
+        limit: 1,
+        ..Default::default()
+    };
+    let mut seen = Vec::new();
+    loop {
+        let page = store.query(&query).unwrap();
+        seen.extend(page.logs.iter().map(|log| (log.timestamp, log.severity.clone())));
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    let expected = [(30, "LOW"), (30, "high"), (10, "HIGH")];
+    assert_eq!(seen, expected.map(|(time, severity)| (time, severity.to_string())));
+}
+
+#[test]
+fn test_invalid_cursor_rejected() {
+    let query = SecurityLogQuery {
+        cursor: Some("not-a-cursor".to_string()),
+        ..Default::default()
+    };
+    assert!(store().query(&query).is_err());
+}
+
+#[test]
+fn test_evict_updates_indexes() {
+    let mut store = store();
+    store.evict(&[true, false, true, false, false]);
+    assert_eq!(store.len(), 3);
+    let high = SecurityLogQuery {
+        severities: vec!["high".to_string()],
+        limit: 10,
+        ..Default::default()
+    };
+    assert!(store.query(&high).unwrap().logs.is_empty());
+    let by_ip = SecurityLogQuery {
+        entity: Some("203.0.113.7".to_string()),
+        limit: 10,
+        ..Default::default()
+    };
+    assert!(store.query(&by_ip).unwrap().logs.is_empty());
+}
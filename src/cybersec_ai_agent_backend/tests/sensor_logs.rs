@@ -0,0 +1,65 @@
+// tests/sensor_logs.rs
+use cybersec_ai_agent_backend::{suricata, zeek};
+use cybersec_log_model::timestamp::TimestampConfig;
+
+#[test]
+fn test_parse_suricata_eve() {
+    let records = suricata::parse_eve(include_str!("fixtures/suricata/eve.json")).unwrap();
+
+    // The stats event is skipped
+    let actions: Vec<_> = records.iter().map(|r| r.action.clone().unwrap()).collect();
+    assert_eq!(actions, vec!["alert", "http", "dns", "tls", "flow"]);
+
+    let alert = &records[0];
+    assert_eq!(alert.timestamp, Some(1_709_287_200_123_456_000));
+    assert_eq!(alert.source_ip.as_deref(), Some("203.0.113.45"));
+    assert_eq!(alert.destination_ip.as_deref(), Some("10.0.0.20"));
+    assert_eq!(alert.severity.as_deref(), Some("MEDIUM"));
+    assert_eq!(alert.attribute("alert.signature_id"), Some("2013028"));
+    assert_eq!(records[1].attribute("http.http_user_agent"), Some("curl/8.4.0"));
+    assert_eq!(records[3].attribute("tls.ja3.hash"), Some("e7d705a3286e19ea42f587b344ee6865"));
+}
+
+#[test]
+fn test_suricata_alert_detection() {
+    let records = suricata::parse_eve(include_str!("fixtures/suricata/eve.json")).unwrap();
+
+    let detection = suricata::alert_detection(&records[0]).unwrap();
+    assert_eq!(detection.category, "ids_alert");
+    assert_eq!(detection.entity.as_deref(), Some("203.0.113.45"));
+    assert!(suricata::alert_detection(&records[1]).is_none());
+}
+
+#[test]
+fn test_parse_zeek_tsv() {
+    let records = zeek::parse_log(include_str!("fixtures/zeek/conn.log"), None, &TimestampConfig::default()).unwrap();
+
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].action.as_deref(), Some("conn"));
+    assert_eq!(records[0].timestamp, Some(1_709_287_200_123_456_000));
+    assert_eq!(records[0].source_ip.as_deref(), Some("10.0.0.31"));
+    assert_eq!(records[0].attribute("service"), Some("ssl"));
+    // Unset fields ("-") are left out
+    assert_eq!(records[1].attribute("resp_bytes"), None);
+}
+
+#[test]
+fn test_parse_zeek_json_with_path_hint() {
+    let records = zeek::parse_log(include_str!("fixtures/zeek/dns.json"), Some("dns"), &TimestampConfig::default()).unwrap();
+
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].action.as_deref(), Some("dns"));
+    assert_eq!(records[1].attribute("query"), Some("qz7x9k2m4p.badexample.top"));
+    assert_eq!(records[1].outcome.as_deref(), Some("NXDOMAIN"));
+    assert_eq!(records[0].attribute("answers.0"), Some("198.51.100.7"));
+}
+
+#[test]
+fn test_zeek_notice_detection() {
+    let records = zeek::parse_log(include_str!("fixtures/zeek/notice.log"), None, &TimestampConfig::default()).unwrap();
+
+    let detection = zeek::notice_detection(&records[0]).unwrap();
+    assert_eq!(detection.category, "ids_notice");
+    assert_eq!(detection.entity.as_deref(), Some("203.0.113.45"));
+    assert_eq!(records[0].attribute("actions"), Some("Notice::ACTION_LOG,Notice::ACTION_EMAIL"));
+}
@@ -0,0 +1,164 @@
+// tests/snapshot.rs
+use candid::Principal;
+use cybersec_ai_agent_backend::persistence::{install, take_state, StateV1};
+use cybersec_ai_agent_backend::snapshot::{
+    export_state, import_state, is_fresh, Snapshot, SnapshotImport, SnapshotLoader, SnapshotWriter,
+    SNAPSHOT_CHUNK_BYTES,
+};
+use cybersec_ai_agent_backend::{SecurityLog, LOGS};
+use cybersec_log_model::LogRecord;
+
+fn bytes(len: u64) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+fn chunks(snapshot: &Snapshot) -> Vec<Vec<u8>> {
+    (0..snapshot.manifest().chunk_count())
+        .map(|index| snapshot.chunk(index).unwrap().to_vec())
+        .collect()
+}
+
+fn receive(snapshot: &Snapshot) -> SnapshotLoader {
+    let mut import = SnapshotImport::new(snapshot.manifest().clone()).unwrap();
+    for (index, chunk) in chunks(snapshot).into_iter().enumerate() {
+        import.add_chunk(index as u32, chunk).unwrap();
+    }
+    import.into_loader().unwrap()
+}
+
+fn populated_state() -> StateV1 {
+    let mut state = StateV1::default();
+    state.logs.push(LogRecord::new("Failed password for root from 203.0.113.7"));
+    state.security_logs.push(SecurityLog {
+        timestamp: 5,
+        event_type: "brute_force".to_string(),
+        details: "Repeated failed logons from 203.0.113.7".to_string(),
+        severity: "HIGH".to_string(),
+        entity: Some("203.0.113.7".to_string()),
+        source: Some("sshd".to_string()),
+        sequence: None,
+        hash: None,
+    });
+    state.threats.insert("brute_force".to_string(), 1);
+    state.wallets.insert(Principal::from_text("rrkah-fqaaa-aaaaa-aaaaq-cai").unwrap(), "Verified".to_string());
+    state.url_risk_config.max_subdomains = 3;
+    state.archive.add_node(Principal::from_slice(&[1]));
+    state
+}
+
+#[test]
+fn test_chunks_reassemble_in_any_order() {
+    let snapshot = Snapshot::new(bytes(2 * SNAPSHOT_CHUNK_BYTES + 10), 7);
+    let manifest = snapshot.manifest().clone();
+    assert_eq!((manifest.chunk_count(), manifest.created_at), (3, 7));
+    let chunks = chunks(&snapshot);
+    assert_eq!(chunks[2].len(), 10);
+
+    let mut import = SnapshotImport::new(manifest).unwrap();
+    assert_eq!(import.add_chunk(2, chunks[2].clone()), Ok(1));
+    assert_eq!(import.add_chunk(0, chunks[0].clone()), Ok(2));
+    assert_eq!(import.missing_chunk(), Some(1));
+    assert_eq!(import.add_chunk(1, chunks[1].clone()), Ok(3));
+    // Retries after a timeout are accepted
+    assert_eq!(import.add_chunk(1, chunks[1].clone()), Ok(3));
+    assert_eq!(import.missing_chunk(), None);
+}
+
+#[test]
+fn test_corrupt_chunks_and_manifests_rejected() {
+    let snapshot = Snapshot::new(bytes(100), 0);
+    let mut import = SnapshotImport::new(snapshot.manifest().clone()).unwrap();
+    let mut corrupt = snapshot.chunk(0).unwrap().to_vec();
+    corrupt[10] ^= 1;
+    assert_eq!(import.add_chunk(0, corrupt), Err("SHA-256 mismatch for chunk 0".to_string()));
+    assert!(import.add_chunk(0, bytes(99)).is_err());
+    assert!(import.add_chunk(1, bytes(100)).is_err());
+
+    let mut newer = snapshot.manifest().clone();
+    newer.version += 1;
+    assert!(SnapshotImport::new(newer).is_err());
+    let mut inconsistent = snapshot.manifest().clone();
+    inconsistent.total_bytes = 3 * SNAPSHOT_CHUNK_BYTES;
+    assert!(SnapshotImport::new(inconsistent).is_err());
+
+    // Chunks that each match but do not make up the snapshot
+    take_state();
+    let snapshot = export_state(0).unwrap();
+    let mut forged = snapshot.manifest().clone();
+    forged.sha256 = Snapshot::new(bytes(101), 0).manifest().sha256.clone();
+    let mut import = SnapshotImport::new(forged).unwrap();
+    import.add_chunk(0, snapshot.chunk(0).unwrap().to_vec()).unwrap();
+    let mut loader = import.into_loader().unwrap();
+    assert_eq!(loader.step(usize::MAX), Ok(true));
+    assert_eq!(loader.finish().err().as_deref(), Some("SHA-256 mismatch for the assembled snapshot"));
+}
+
+#[test]
+fn test_export_then_import_into_fresh_canister() {
+    install(populated_state());
+    let snapshot = export_state(42).unwrap();
+    // Exporting leaves the live state in place
+    assert!(!is_fresh());
+    assert!(import_state(StateV1::default()).is_err());
+
+    take_state();
+    assert!(is_fresh());
+    let mut loader = receive(&snapshot);
+    while !loader.step(1).unwrap() {}
+    import_state(loader.finish().unwrap()).unwrap();
+
+    let restored = take_state();
+    assert_eq!(restored.logs[0].message, "Failed password for root from 203.0.113.7");
+    assert_eq!(restored.security_logs[0].sequence, Some(0));
+    assert_eq!(restored.security_logs[0].source.as_deref(), Some("sshd"));
+    assert_eq!(restored.security_log_chain.length, 1);
+    assert_eq!(restored.threats.get("brute_force"), Some(&1));
+    assert_eq!(restored.wallets.len(), 1);
+    assert_eq!(restored.url_risk_config.max_subdomains, 3);
+    // The source canister's archive is still listed, but no longer written to
+    let archive = restored.archive;
+    assert_eq!(archive.nodes().len(), 1);
+    assert!(archive.writable().is_none());
+}
+
+#[test]
+fn test_export_and_import_spread_over_steps() {
+    let mut state = populated_state();
+    for index in 0..2_500 {
+        state.logs.push(LogRecord::new(format!("Accepted publickey for deploy from 198.51.100.{}", index % 250)));
+    }
+    install(state);
+
+    let mut writer = SnapshotWriter::new(42).unwrap();
+    assert_eq!(writer.step(1), Ok(false));
+    // Logs ingested while the export is underway are left for the next one
+    LOGS.with(|logs| logs.borrow_mut().push(LogRecord::new("Ingested during the export")));
+    let mut steps = 1;
+    while !writer.step(1).unwrap() {
+        steps += 1;
+    }
+    // Three chunks of logs and the end of them, then one chunk of security logs
+    assert_eq!(steps, 5);
+    let snapshot = writer.finish();
+
+    // Clearing the logs it has yet to write fails the export
+    let mut writer = SnapshotWriter::new(43).unwrap();
+    LOGS.with(|logs| logs.borrow_mut().clear());
+    assert_eq!(
+        writer.step(1).err().as_deref(),
+        Some("The logs were cleared while the state was being written")
+    );
+
+    take_state();
+    let mut loader = receive(&snapshot);
+    let mut steps = 0;
+    while !loader.step(1).unwrap() {
+        steps += 1;
+    }
+    assert_eq!(steps, 5);
+    assert_eq!(loader.bytes_read(), snapshot.manifest().total_bytes);
+    import_state(loader.finish().unwrap()).unwrap();
+    let restored = take_state();
+    assert_eq!(restored.logs.len(), 2_501);
+    assert_eq!(restored.security_logs.len(), 1);
+}
@@ -0,0 +1,142 @@
+// tests/threat_stats.rs
+use cybersec_ai_agent_backend::threat_stats::{
+    Granularity, ThreatStats, ThreatStatsConfig, ThreatStatsQuery, MAX_PAGE_COUNTS,
+};
+
+const MINUTE: u64 = 60_000_000_000;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+
+fn query(granularity: Granularity) -> ThreatStatsQuery {
+    ThreatStatsQuery {
+        granularity,
+        ..Default::default()
+    }
+}
+
+fn totals(stats: &ThreatStats, query: &ThreatStatsQuery) -> Vec<(u64, u64)> {
+    stats.query(query).unwrap().buckets.iter().map(|bucket| (bucket.start, bucket.total)).collect()
+}
+
+#[test]
+fn test_detections_roll_up() {
+    let mut stats = ThreatStats::default();
+    let start = 10 * DAY;
+    stats.record("phishing", "high", "email", start + 5 * MINUTE + 1);
+    stats.record("phishing", "HIGH", "email", start + 5 * MINUTE + 2);
+    stats.record("port_scan", "MEDIUM", "netflow", start + 61 * MINUTE);
+    stats.record("port_scan", "MEDIUM", "netflow", start + DAY);
+
+    assert_eq!(
+        totals(&stats, &query(Granularity::Minute)),
+        vec![(start + 5 * MINUTE, 2), (start + 61 * MINUTE, 1), (start + DAY, 1)]
+    );
+    assert_eq!(totals(&stats, &query(Granularity::Hour)), vec![(start, 2), (start + HOUR, 1), (start + DAY, 1)]);
+    assert_eq!(totals(&stats, &query(Granularity::Day)), vec![(start, 3), (start + DAY, 1)]);
+
+    let buckets = stats.query(&query(Granularity::Day)).unwrap().buckets;
+    assert_eq!(buckets[0].counts.len(), 2);
+    assert_eq!(buckets[0].counts[0].series.severity, "HIGH");
+    assert_eq!(buckets[0].counts[0].count, 2);
+}
+
+#[test]
+fn test_range_and_filters() {
+    let mut stats = ThreatStats::default();
+    for hour in 0..5 {
+        stats.record("port_scan", "MEDIUM", "netflow", DAY + hour * HOUR);
+        stats.record("phishing", "HIGH", "email", DAY + hour * HOUR);
+    }
+    let range = ThreatStatsQuery {
+        from_time: Some(DAY + HOUR + 30 * MINUTE),
+        to_time: Some(DAY + 3 * HOUR),
+        severity: Some("high".to_string()),
+        ..query(Granularity::Hour)
+    };
+    assert_eq!(totals(&stats, &range), vec![(DAY + HOUR, 1), (DAY + 2 * HOUR, 1), (DAY + 3 * HOUR, 1)]);
+
+    let by_source = ThreatStatsQuery {
+        source: Some("netflow".to_string()),
+        ..query(Granularity::Day)
+    };
+    assert_eq!(totals(&stats, &by_source), vec![(DAY, 5)]);
+    let by_category = ThreatStatsQuery {
+        category: Some("dns_tunneling".to_string()),
+        ..query(Granularity::Day)
+    };
+    assert!(stats.query(&by_category).unwrap().buckets.is_empty());
+
+    let inverted = ThreatStatsQuery {
+        from_time: Some(2 * DAY),
+        to_time: Some(DAY),
+        ..Default::default()
+    };
+    assert!(stats.query(&inverted).is_err());
+}
+
+#[test]
+fn test_retention_per_granularity() {
+    let mut stats = ThreatStats::new(ThreatStatsConfig {
+        minutes_kept: 60,
+        hours_kept: 24,
+        days_kept: 2,
+    });
+    let start = 100 * DAY;
+    stats.record("phishing", "HIGH", "email", start);
+    stats.prune(start + 2 * HOUR);
+    assert!(stats.query(&query(Granularity::Minute)).unwrap().buckets.is_empty());
+    assert_eq!(totals(&stats, &query(Granularity::Hour)), vec![(start, 1)]);
+
+    stats.record("phishing", "HIGH", "email", start + DAY + HOUR);
+    assert_eq!(totals(&stats, &query(Granularity::Hour)), vec![(start + DAY + HOUR, 1)]);
+    assert_eq!(totals(&stats, &query(Granularity::Day)), vec![(start, 1), (start + DAY, 1)]);
+
+    stats.set_config(
+        ThreatStatsConfig {
+            days_kept: 1,
+            ..stats.config().clone()
+        },
+        start + DAY + HOUR,
+    );
+    assert_eq!(totals(&stats, &query(Granularity::Day)), vec![(start + DAY, 1)]);
+}
+
+#[test]
+fn test_series_are_paged() {
+    let mut stats = ThreatStats::default();
+    for source in 0..5 {
+        stats.record("port_scan", "MEDIUM", &format!("sensor-{}", source), DAY);
+    }
+    stats.record("port_scan", "MEDIUM", "sensor-0", DAY + HOUR);
+
+    let mut page_query = ThreatStatsQuery {
+        limit: 2,
+        ..query(Granularity::Hour)
+    };
+    let mut pages = Vec::new();
+    loop {
+        let page = stats.query(&page_query).unwrap();
+        pages.push(
+            page.buckets
+                .iter()
+                .map(|bucket| (bucket.start, bucket.total, bucket.counts.len()))
+                .collect::<Vec<_>>(),
+        );
+        match page.next_cursor {
+            Some(cursor) => page_query.cursor = Some(cursor),
+            None => break,
+        }
+    }
+    // The first bucket's five series span three pages, each giving the bucket's full total
+    assert_eq!(
+        pages,
+        vec![vec![(DAY, 5, 2)], vec![(DAY, 5, 2)], vec![(DAY, 5, 1), (DAY + HOUR, 1, 1)]]
+    );
+
+    for source in 0..2 * MAX_PAGE_COUNTS {
+        stats.record("port_scan", "MEDIUM", &format!("fuzz-{}", source), DAY);
+    }
+    let page = stats.query(&query(Granularity::Day)).unwrap();
+    assert_eq!(page.buckets[0].counts.len(), MAX_PAGE_COUNTS);
+    assert!(page.next_cursor.is_some());
+}
@@ -0,0 +1,141 @@
+// tests/upload.rs
+use candid::Principal;
+use cybersec_ai_agent_backend::upload::{
+    append_chunk, begin_session, delete_session, finalize_session, process_batch, session_progress, session_results,
+    UploadState,
+};
+use cybersec_ai_agent_backend::ThreatOutput;
+use cybersec_log_model::LogRecord;
+use sha2::{Digest, Sha256};
+
+fn owner() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn stranger() -> Principal {
+    Principal::from_slice(&[2])
+}
+
+fn send(caller: Principal, session_id: u64, sequence: u32, data: &[u8]) -> Result<(), String> {
+    append_chunk(caller, session_id, sequence, data.to_vec(), &Sha256::digest(data))
+}
+
+// Flags every line mentioning an attack, standing in for the detectors
+fn flag_attacks(records: Vec<LogRecord>) -> Vec<Vec<ThreatOutput>> {
+    records
+        .iter()
+        .map(|record| {
+            let flagged = record.message.contains("attack");
+            flagged
+                .then(|| ThreatOutput::detection("test", "LOW", 0.5, &record.message, record.message.clone()))
+                .into_iter()
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_upload_reports_file_line_numbers() {
+    let session_id = begin_session(owner(), "sshd".to_string()).unwrap();
+    // Lines may straddle chunks, and blank lines still count
+    send(owner(), session_id, 0, b"benign\n\nattack one\nbe").unwrap();
+    send(owner(), session_id, 1, b"nign\n   \nattack two\n").unwrap();
+
+    let progress = finalize_session(owner(), session_id, 2).unwrap();
+    assert_eq!(progress.state, UploadState::Processing);
+    assert_eq!(progress.total_lines, 4);
+
+    assert!(!process_batch(flag_attacks));
+    let progress = session_progress(owner(), session_id).unwrap();
+    assert_eq!(progress.state, UploadState::Completed);
+    assert_eq!(progress.processed_lines, 4);
+    assert_eq!(progress.threats_found, 2);
+
+    let results = session_results(owner(), session_id, 0, 10).unwrap();
+    let lines: Vec<u64> = results.iter().map(|result| result.line).collect();
+    assert_eq!(lines, vec![3, 6]);
+    assert_eq!(session_results(owner(), session_id, 1, 10).unwrap().len(), 1);
+}
+
+#[test]
+fn test_chunks_are_checked_and_retries_accepted() {
+    let session_id = begin_session(owner(), "sshd".to_string()).unwrap();
+
+    let wrong_hash = append_chunk(owner(), session_id, 0, b"line\n".to_vec(), &[0; 32]);
+    assert_eq!(wrong_hash, Err("SHA-256 mismatch for chunk 0".to_string()));
+
+    send(owner(), session_id, 0, b"line\n").unwrap();
+    // A retried chunk is accepted once, a conflicting one is not
+    send(owner(), session_id, 0, b"line\n").unwrap();
+    assert!(send(owner(), session_id, 0, b"other\n").is_err());
+
+    let progress = session_progress(owner(), session_id).unwrap();
+    assert_eq!(progress.chunks_received, 1);
+    assert_eq!(progress.bytes_received, 5);
+}
+
+#[test]
+fn test_finalize_needs_every_chunk() {
+    let session_id = begin_session(owner(), "sshd".to_string()).unwrap();
+    send(owner(), session_id, 0, b"first\n").unwrap();
+    send(owner(), session_id, 2, b"third\n").unwrap();
+
+    assert_eq!(
+        finalize_session(owner(), session_id, 3).unwrap_err(),
+        "Chunk 1 has not been received"
+    );
+    assert!(finalize_session(owner(), session_id, 1).is_err());
+
+    send(owner(), session_id, 1, b"second\n").unwrap();
+    finalize_session(owner(), session_id, 3).unwrap();
+    assert!(finalize_session(owner(), session_id, 3).is_err());
+    assert!(send(owner(), session_id, 3, b"late\n").is_err());
+}
+
+#[test]
+fn test_sessions_belong_to_their_owner() {
+    let session_id = begin_session(owner(), "sshd".to_string()).unwrap();
+    send(owner(), session_id, 0, b"attack\n").unwrap();
+
+    assert!(send(stranger(), session_id, 1, b"more\n").is_err());
+    assert!(finalize_session(stranger(), session_id, 1).is_err());
+    assert!(session_progress(stranger(), session_id).is_none());
+    assert!(session_results(stranger(), session_id, 0, 10).is_err());
+    assert!(delete_session(stranger(), session_id).is_err());
+
+    delete_session(owner(), session_id).unwrap();
+    assert!(session_progress(owner(), session_id).is_none());
+}
+
+#[test]
+fn test_active_sessions_are_capped_per_caller() {
+    let sessions: Vec<u64> = (0..4)
+        .map(|_| begin_session(owner(), "sshd".to_string()).unwrap())
+        .collect();
+    assert!(begin_session(owner(), "sshd".to_string()).is_err());
+    assert!(begin_session(stranger(), "sshd".to_string()).is_ok());
+
+    // Processing still counts; a completed session no longer does
+    finalize_session(owner(), sessions[0], 0).unwrap();
+    assert!(begin_session(owner(), "sshd".to_string()).is_err());
+    process_batch(flag_attacks);
+    assert!(begin_session(owner(), "sshd".to_string()).is_ok());
+}
+
+#[test]
+fn test_earliest_finished_sessions_expire() {
+    let finished: Vec<u64> = (0..300)
+        .map(|_| {
+            let session_id = begin_session(owner(), "sshd".to_string()).unwrap();
+            send(owner(), session_id, 0, &[0xff]).unwrap();
+            let progress = finalize_session(owner(), session_id, 1).unwrap();
+            assert!(matches!(progress.state, UploadState::Failed(_)));
+            session_id
+        })
+        .collect();
+    let open = begin_session(owner(), "sshd".to_string()).unwrap();
+
+    assert!(session_progress(owner(), finished[0]).is_none());
+    assert!(session_progress(owner(), finished[299]).is_some());
+    assert!(session_progress(owner(), open).is_some());
+}
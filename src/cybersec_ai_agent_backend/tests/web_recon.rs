@@ -0,0 +1,93 @@
+// tests/web_recon.rs
+use cybersec_ai_agent_backend::web_recon::{parse_access_log, WebReconConfig, WebReconDetector};
+use cybersec_log_model::timestamp::TimestampConfig;
+use cybersec_log_model::LogRecord;
+
+const SECOND: u64 = 1_000_000_000;
+
+fn request(client: &str, path: &str, status: u16, secs: u64) -> LogRecord {
+    let line = format!(
+        "{} - - [01/Mar/2024:10:{:02}:{:02} +0000] \"GET {} HTTP/1.1\" {} 0 \"-\" \"Mozilla/5.0\"",
+        client,
+        secs / 60,
+        secs % 60,
+        path,
+        status
+    );
+    parse_access_log(&line, &TimestampConfig::default()).unwrap().remove(0)
+}
+
+#[test]
+fn test_parse_access_log() {
+    let records = parse_access_log(include_str!("fixtures/web/access.log"), &TimestampConfig::default()).unwrap();
+    assert_eq!(records.len(), 7);
+    assert_eq!(records[0].source_ip.as_deref(), Some("10.0.0.31"));
+    assert_eq!(records[0].user.as_deref(), Some("alice"));
+    assert_eq!(records[0].timestamp, Some(1_709_287_200 * SECOND));
+    assert_eq!(records[0].attribute("user_agent"), Some("Mozilla/5.0 (X11; Linux x86_64)"));
+    assert_eq!(records[5].attribute("path"), Some("/search?q=1%27%20AND%201=1"));
+    // Common Log Format lines have no referer or user-agent
+    assert_eq!(records[6].outcome.as_deref(), Some("401"));
+    assert_eq!(records[6].attribute("user_agent"), None);
+
+    assert!(parse_access_log("not an access log line", &TimestampConfig::default()).is_err());
+}
+
+#[test]
+fn test_one_detection_per_source_and_window() {
+    let records = parse_access_log(include_str!("fixtures/web/access.log"), &TimestampConfig::default()).unwrap();
+    let mut detector = WebReconDetector::new(WebReconConfig::default());
+
+    let findings: Vec<_> = records.iter().filter_map(|record| detector.observe(record, 0)).collect();
+    assert_eq!(findings.len(), 2);
+    assert_eq!(findings[0].entity.as_deref(), Some("203.0.113.45"));
+    assert!(findings[0].details.contains("probed sensitive paths /.env, /.git/config, /wp-admin/"));
+    assert_eq!(findings[1].entity.as_deref(), Some("198.51.100.9"));
+    assert!(findings[1].details.contains("scanner user-agent sqlmap"));
+    assert_eq!(findings[1].severity, "HIGH");
+
+    // Same client, next window: reported again
+    let later = request("203.0.113.45", "/.htpasswd", 404, 400);
+    assert!(detector.observe(&later, 0).is_none());
+    for (i, path) in ["/.aws/credentials", "/server-status"].iter().enumerate() {
+        let finding = detector.observe(&request("203.0.113.45", path, 404, 401 + i as u64), 0);
+        assert_eq!(finding.is_some(), i == 1);
+    }
+}
+
+#[test]
+fn test_not_found_ratio_and_fuzzing() {
+    let mut detector = WebReconDetector::new(WebReconConfig::default());
+
+    let findings: Vec<_> = (0..30)
+        .filter_map(|i| detector.observe(&request("203.0.113.7", &format!("/page{}", i), 404, i), 0))
+        .collect();
+    assert_eq!(findings.len(), 1);
+    assert!(findings[0].details.contains("20 of 20 requests returned 404"));
+
+    let findings: Vec<_> = (0..25)
+        .filter_map(|i| detector.observe(&request("203.0.113.8", &format!("/item?id={}", i), 200, i), 0))
+        .collect();
+    assert_eq!(findings.len(), 1);
+    assert!(findings[0].details.contains("fuzzed parameters of /item"));
+}
+
+#[test]
+fn test_fuzzing_tracked_paths_are_capped() {
+    let mut detector = WebReconDetector::new(WebReconConfig::default());
+
+    // Paths past the cap are not tracked, the earlier ones still are
+    for i in 0..300 {
+        let path = format!("/p{}?id=0", i);
+        assert!(detector.observe(&request("203.0.113.9", &path, 200, i / 10), 0).is_none());
+    }
+    let findings: Vec<_> = (1..20)
+        .filter_map(|i| detector.observe(&request("203.0.113.9", &format!("/p0?id={}", i), 200, 30), 0))
+        .collect();
+    assert_eq!(findings.len(), 1);
+    assert!(findings[0].details.contains("fuzzed parameters of /p0 "));
+    let late: Vec<_> = (1..20)
+        .filter_map(|i| detector.observe(&request("203.0.113.9", &format!("/p299?id={}", i), 200, 31), 0))
+        .collect();
+    assert!(late.is_empty());
+}
@@ -0,0 +1,76 @@
+// tests/windows_events.rs
+use cybersec_ai_agent_backend::windows_events::{detect, parse_events, FailedLogonDetector};
+
+const SECOND: u64 = 1_000_000_000;
+// 2024-03-01T10:05:00Z, shortly after the fixtures were exported
+const NOW: u64 = 1_709_287_500 * SECOND;
+
+#[test]
+fn test_parse_wevtutil_export() {
+    let events = parse_events(include_str!("fixtures/windows/failed_logons.xml")).unwrap();
+
+    assert_eq!(events.len(), 7);
+    assert_eq!(events[0].event_id, 4625);
+    assert_eq!(events[0].record_id, Some(1000));
+    assert_eq!(events[0].target_user_name.as_deref(), Some("administrator"));
+    assert_eq!(events[0].subject_user_name, None);
+    assert_eq!(events[0].ip_address.as_deref(), Some("203.0.113.45"));
+    assert_eq!(events[0].logon_type, Some(3));
+    assert_eq!(events[0].process_name.as_deref(), Some("C:\\Windows\\System32\\svchost.exe"));
+}
+
+#[test]
+fn test_failed_logon_burst() {
+    let events = parse_events(include_str!("fixtures/windows/failed_logons.xml")).unwrap();
+    let detections = detect(&events, NOW);
+
+    assert_eq!(detections.len(), 1);
+    assert_eq!(detections[0].category, "windows_failed_logon_burst");
+    assert_eq!(detections[0].entity.as_deref(), Some("203.0.113.45"));
+}
+
+#[test]
+fn test_failed_logon_burst_reported_once() {
+    let events = parse_events(include_str!("fixtures/windows/failed_logons.xml")).unwrap();
+    let mut detector = FailedLogonDetector::new(300 * SECOND);
+
+    assert_eq!(detector.detect(&events[..5], NOW).len(), 1);
+    // The burst goes on in the next batch, but was already reported
+    assert!(detector.detect(&events[5..], NOW).is_empty());
+}
+
+#[test]
+fn test_failed_logons_without_time_count_at_ingest() {
+    let mut events = parse_events(include_str!("fixtures/windows/failed_logons.xml")).unwrap();
+    events.truncate(5);
+    for event in &mut events {
+        event.time_created = "not a time".to_string();
+    }
+
+    let detections = detect(&events, NOW);
+    assert_eq!(detections.len(), 1);
+    assert_eq!(detections[0].entity.as_deref(), Some("203.0.113.45"));
+}
+
+#[test]
+fn test_forwarded_events_detections() {
+    let events = parse_events(include_str!("fixtures/windows/forwarded_events.xml")).unwrap();
+    assert_eq!(events.len(), 5);
+    assert_eq!(events[4].subject_user_name.as_deref(), Some("jdoe"));
+
+    let categories: Vec<String> = detect(&events, NOW).into_iter().map(|d| d.category).collect();
+    assert_eq!(
+        categories,
+        vec![
+            "windows_account_created",
+            "windows_group_member_added",
+            "windows_suspicious_process",
+            "windows_audit_log_cleared",
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_xml_is_rejected() {
+    assert!(parse_events("<Event><System>").is_err());
+}
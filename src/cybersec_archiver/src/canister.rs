@@ -0,0 +1,124 @@
+use candid::{CandidType, Decode, Deserialize, Encode, Principal};
+use cybersec_log_model::certified::{verify_security_logs, CertifiedSecurityLogs};
+use ic_agent::Agent;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use cybersec_log_model::SecurityLog;
+
+candid::define_function!(GetLogsCallback : (u64, u64) -> (Vec<SecurityLog>) query);
+
+// The parts of `SecurityLogRange` the archiver reads: where the logs the
+// backend has moved to archive canisters can be fetched
+#[derive(CandidType, Deserialize)]
+struct ArchivedRange {
+    from: u64,
+    length: u64,
+    callback: GetLogsCallback,
+}
+
+#[derive(CandidType, Deserialize)]
+struct SecurityLogRange {
+    archived: Vec<ArchivedRange>,
+}
+
+// Archive canisters return at most this many logs per `get_logs` call
+const ARCHIVE_PAGE: u64 = 1_000;
+// How far a certificate's time may be from the local clock
+const MAX_CERTIFICATE_AGE_NS: u64 = 5 * 60 * 1_000_000_000;
+
+pub struct Page {
+    pub logs: Vec<SecurityLog>,
+    /// Sequence number the next page starts at, or `None` once the page
+    /// reached the newest log
+    pub next: Option<u64>,
+}
+
+pub struct Backend {
+    agent: Agent,
+    canister_id: Principal,
+}
+
+impl Backend {
+    pub async fn connect(url: &str, canister_id: Principal) -> Result<Self, String> {
+        let agent = Agent::builder()
+            .with_url(url)
+            .build()
+            .map_err(|e| format!("Failed to create agent for {}: {}", url, e))?;
+        // A local replica signs with its own root key rather than the IC's
+        agent
+            .fetch_root_key()
+            .await
+            .map_err(|e| format!("Failed to fetch the root key from {}: {}", url, e))?;
+        Ok(Backend { agent, canister_id })
+    }
+
+    /// Stored logs from sequence number `from` on, at most `limit` of them.
+    /// Logs the backend has moved to archive canisters are read from those;
+    /// logs retention evicted are skipped over. Logs read from the backend
+    /// are checked against its certificate before they are returned.
+    pub async fn security_logs(&self, from: u64, limit: u64) -> Result<Page, String> {
+        if let Some(range) = self.archived_range(from, limit).await? {
+            return self.archived_logs(range, limit).await;
+        }
+
+        let arg = Encode!(&from, &limit).map_err(|e| format!("Failed to encode arguments: {}", e))?;
+        let response = self
+            .agent
+            .query(&self.canister_id, "get_certified_security_logs")
+            .with_arg(arg)
+            .call()
+            .await
+            .map_err(|e| format!("get_certified_security_logs failed: {}", e))?;
+        let page = Decode!(&response, Result<CertifiedSecurityLogs, String>)
+            .map_err(|e| format!("Failed to decode security logs: {}", e))??;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| format!("The system clock is before the Unix epoch: {}", e))?
+            .as_nanos() as u64;
+        verify_security_logs(&page, &self.canister_id, &self.agent.read_root_key(), now, MAX_CERTIFICATE_AGE_NS)
+            .map_err(|e| format!("Security logs {}..={} failed verification: {}", page.from, page.to, e))?;
+        Ok(Page {
+            logs: page.logs,
+            // `to` is u64::MAX on the last page
+            next: page.to.checked_add(1),
+        })
+    }
+
+    // The archived part of `from..from + limit`, if any. Archives hold a
+    // contiguous prefix of the chain, so it starts at `from`
+    async fn archived_range(&self, from: u64, limit: u64) -> Result<Option<ArchivedRange>, String> {
+        let arg = Encode!(&from, &limit).map_err(|e| format!("Failed to encode arguments: {}", e))?;
+        let response = self
+            .agent
+            .query(&self.canister_id, "get_security_log_range")
+            .with_arg(arg)
+            .call()
+            .await
+            .map_err(|e| format!("get_security_log_range failed: {}", e))?;
+        let range = Decode!(&response, SecurityLogRange)
+            .map_err(|e| format!("Failed to decode the security log range: {}", e))?;
+        Ok(range.archived.into_iter().next())
+    }
+
+    async fn archived_logs(&self, range: ArchivedRange, limit: u64) -> Result<Page, String> {
+        let length = range.length.min(limit).min(ARCHIVE_PAGE);
+        let callback = range.callback.0;
+        let arg = Encode!(&range.from, &length).map_err(|e| format!("Failed to encode arguments: {}", e))?;
+        let response = self
+            .agent
+            .query(&callback.principal, &callback.method)
+            .with_arg(arg)
+            .call()
+            .await
+            .map_err(|e| format!("{} on archive {} failed: {}", callback.method, callback.principal, e))?;
+        let logs = Decode!(&response, Vec<SecurityLog>)
+            .map_err(|e| format!("Failed to decode archived security logs: {}", e))?;
+        // Archives cut replies short by size, so carry on after the last log
+        // returned; the backend holds whatever follows the last archive
+        let next = logs
+            .last()
+            .and_then(|log| log.sequence)
+            .map_or(range.from + length, |sequence| sequence + 1);
+        Ok(Page { logs, next: Some(next) })
+    }
+}
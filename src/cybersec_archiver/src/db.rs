@@ -0,0 +1,77 @@
+use crate::canister::SecurityLog;
+use crate::schema::threats;
+use chrono::{DateTime, NaiveDateTime};
+use diesel::prelude::*;
+use diesel::upsert::excluded;
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+
+const MIGRATIONS: EmbeddedMigrations = embed_migrations!("../../migrations");
+
+/// A `threats` row for one security log.
+#[derive(Insertable, Debug)]
+#[diesel(table_name = threats)]
+pub struct NewThreat {
+    message: String,
+    threat_type: String,
+    timestamp: Option<NaiveDateTime>,
+    severity: Option<String>,
+    source: Option<String>,
+    sequence: Option<i64>,
+}
+
+impl NewThreat {
+    pub fn from_log(log: &SecurityLog) -> Result<Self, String> {
+        let sequence = log.sequence.ok_or("The canister returned a security log without a sequence number")?;
+        let sequence = i64::try_from(sequence).map_err(|_| format!("Sequence number {} does not fit a BIGINT", sequence))?;
+        Ok(NewThreat {
+            message: log.details.clone(),
+            threat_type: log.event_type.clone(),
+            timestamp: i64::try_from(log.timestamp)
+                .ok()
+                .map(|nanos| DateTime::from_timestamp_nanos(nanos).naive_utc()),
+            severity: Some(log.severity.to_uppercase()),
+            source: log.source.clone(),
+            sequence: Some(sequence),
+        })
+    }
+}
+
+/// Connects to `database_url` and brings the schema up to date.
+pub fn connect(database_url: &str) -> Result<PgConnection, String> {
+    let mut connection =
+        PgConnection::establish(database_url).map_err(|e| format!("Failed to connect to {}: {}", database_url, e))?;
+    connection
+        .run_pending_migrations(MIGRATIONS)
+        .map_err(|e| format!("Failed to run migrations: {}", e))?;
+    Ok(connection)
+}
+
+/// Highest sequence number archived so far.
+pub fn last_synced(connection: &mut PgConnection) -> Result<Option<u64>, String> {
+    let last: Option<i64> = threats::table
+        .select(diesel::dsl::max(threats::sequence))
+        .first(connection)
+        .map_err(|e| format!("Failed to read the last archived sequence number: {}", e))?;
+    Ok(last.map(|sequence| sequence as u64))
+}
+
+/// Inserts `rows`, overwriting rows already archived with the same sequence
+/// number, so pages can be archived again after an interrupted run.
+pub fn upsert(connection: &mut PgConnection, rows: &[NewThreat]) -> Result<usize, String> {
+    if rows.is_empty() {
+        return Ok(0);
+    }
+    diesel::insert_into(threats::table)
+        .values(rows)
+        .on_conflict(threats::sequence)
+        .do_update()
+        .set((
+            threats::message.eq(excluded(threats::message)),
+            threats::threat_type.eq(excluded(threats::threat_type)),
+            threats::timestamp.eq(excluded(threats::timestamp)),
+            threats::severity.eq(excluded(threats::severity)),
+            threats::source.eq(excluded(threats::source)),
+        ))
+        .execute(connection)
+        .map_err(|e| format!("Failed to write threats: {}", e))
+}
@@ -0,0 +1,105 @@
+mod canister;
+mod db;
+#[path = "../../schema.rs"]
+mod schema;
+
+use canister::Backend;
+use candid::Principal;
+use diesel::PgConnection;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: cybersec_archiver [--url URL] [--canister ID] [--batch N] [--follow SECONDS]
+
+Copies the backend canister's security logs into the threats table of the
+Postgres database at DATABASE_URL, resuming after the highest sequence number
+already archived. With --follow it keeps polling for new logs.
+
+  --url       replica to query (default http://127.0.0.1:4943)
+  --canister  backend canister id (default $CANISTER_ID_CYBERSEC_AI_AGENT_BACKEND)
+  --batch     logs fetched per query (default 500)";
+
+struct Options {
+    url: String,
+    canister_id: Principal,
+    database_url: String,
+    batch: u64,
+    follow: Option<Duration>,
+}
+
+fn parse_options() -> Result<Options, String> {
+    let mut url = "http://127.0.0.1:4943".to_string();
+    let mut canister = std::env::var("CANISTER_ID_CYBERSEC_AI_AGENT_BACKEND").ok();
+    let mut batch = 500;
+    let mut follow = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value\n\n{}", arg, USAGE));
+        match arg.as_str() {
+            "--url" => url = value()?,
+            "--canister" => canister = Some(value()?),
+            "--batch" => batch = value()?.parse().map_err(|_| format!("--batch must be a number\n\n{}", USAGE))?,
+            "--follow" => {
+                let secs = value()?.parse().map_err(|_| format!("--follow must be a number of seconds\n\n{}", USAGE))?;
+                follow = Some(Duration::from_secs(secs));
+            }
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            _ => return Err(format!("Unknown argument {}\n\n{}", arg, USAGE)),
+        }
+    }
+    let canister = canister.ok_or(format!("No backend canister id given\n\n{}", USAGE))?;
+    Ok(Options {
+        url,
+        canister_id: Principal::from_text(&canister).map_err(|e| format!("Invalid canister id {}: {}", canister, e))?,
+        database_url: std::env::var("DATABASE_URL").map_err(|_| "DATABASE_URL is not set".to_string())?,
+        batch,
+        follow,
+    })
+}
+
+// Archives pages from `*from` on until the newest log, leaving `*from` at the
+// sequence number to resume at
+async fn archive(backend: &Backend, connection: &mut PgConnection, from: &mut u64, batch: u64) -> Result<usize, String> {
+    let mut archived = 0;
+    loop {
+        let page = backend.security_logs(*from, batch).await?;
+        let rows = page.logs.iter().map(db::NewThreat::from_log).collect::<Result<Vec<_>, _>>()?;
+        archived += db::upsert(connection, &rows)?;
+        match page.next {
+            Some(next) => *from = next,
+            None => {
+                if let Some(last) = page.logs.last().and_then(|log| log.sequence) {
+                    *from = last + 1;
+                }
+                return Ok(archived);
+            }
+        }
+    }
+}
+
+async fn run() -> Result<(), String> {
+    let options = parse_options()?;
+    let backend = Backend::connect(&options.url, options.canister_id).await?;
+    let mut connection = db::connect(&options.database_url)?;
+    let mut from = db::last_synced(&mut connection)?.map_or(0, |sequence| sequence + 1);
+    loop {
+        match archive(&backend, &mut connection, &mut from, options.batch).await {
+            Ok(archived) => println!("Archived {} security logs; resuming at sequence {}", archived, from),
+            // While following, a failed poll is retried at the next one
+            Err(e) if options.follow.is_some() => eprintln!("{}", e),
+            Err(e) => return Err(e),
+        }
+        match options.follow {
+            Some(interval) => tokio::time::sleep(interval).await,
+            None => return Ok(()),
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    if let Err(e) = run().await {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,262 @@
+use candid::{CandidType, Deserialize, Principal};
+use ic_cdk::api::stable::{
+    BufferedStableReader, BufferedStableWriter, CanisterStableMemory, StableMemory, StableReader, StableWriter,
+};
+use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+const MAX_PAGE: u64 = 1_000;
+// Stay well below the 2 MiB reply limit
+const MAX_PAGE_BYTES: usize = 1_500_000;
+// Candid framing of a log on top of its strings
+const LOG_OVERHEAD_BYTES: usize = 96;
+
+// What pre_upgrade leaves in stable memory: STABLE_MAGIC, then the main
+// canister and capacity, then the logs in chunks of LOGS_PER_CHUNK. Every
+// part is a candid blob after its length as a little-endian u64, and an
+// empty part ends the logs, so neither hook holds more than one chunk's
+// encoding at a time.
+const STABLE_MAGIC: &[u8; 4] = b"CLA1";
+const LOGS_PER_CHUNK: usize = 1_000;
+// Stable memory is written and read through a buffer of this size
+const STABLE_BUFFER_BYTES: usize = 1024 * 1024;
+
+/// A security log as the backend stores it.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SecurityLog {
+    pub timestamp: u64,
+    pub event_type: String,
+    pub details: String,
+    pub severity: String,
+    pub entity: Option<String>,
+    pub source: Option<String>,
+    pub sequence: Option<u64>,
+    pub hash: Option<String>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct ArchiveInit {
+    /// The backend canister, the only one allowed to append
+    pub main_canister: Principal,
+    /// Logs this archive holds at most
+    pub capacity: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ArchiveStatus {
+    pub main_canister: Principal,
+    pub capacity: u64,
+    pub stored: u64,
+    pub first_sequence: Option<u64>,
+    pub last_sequence: Option<u64>,
+}
+
+struct State {
+    main_canister: Principal,
+    capacity: u64,
+    logs: BTreeMap<u64, SecurityLog>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = const {
+        RefCell::new(State {
+            main_canister: Principal::anonymous(),
+            capacity: 0,
+            logs: BTreeMap::new(),
+        })
+    };
+}
+
+#[init]
+fn init(args: ArchiveInit) {
+    STATE.with(|state| {
+        let mut state = state.borrow_mut();
+        state.main_canister = args.main_canister;
+        state.capacity = args.capacity;
+    });
+}
+
+/// Stores `logs` after those already held; each must carry a sequence number
+/// above the last one stored. Logs already held are skipped, so the main
+/// canister can resend a batch whose reply it never got. Returns the number
+/// of logs stored.
+#[update]
+fn append_logs(logs: Vec<SecurityLog>) -> Result<u64, String> {
+    STATE.with(|state| append(&mut state.borrow_mut(), ic_cdk::caller(), logs))
+}
+
+fn append(state: &mut State, caller: Principal, logs: Vec<SecurityLog>) -> Result<u64, String> {
+    if caller != state.main_canister {
+        return Err("Only the main canister appends to its archive".to_string());
+    }
+    let mut last = state.logs.last_key_value().map(|(sequence, _)| *sequence);
+    let mut new_logs = Vec::new();
+    for log in logs {
+        let sequence = log.sequence.ok_or("Only stored logs, with a sequence number, are archived")?;
+        if state.logs.contains_key(&sequence) {
+            continue;
+        }
+        if last.is_some_and(|last| sequence <= last) {
+            return Err(format!("Log {} does not follow the last archived log", sequence));
+        }
+        last = Some(sequence);
+        new_logs.push((sequence, log));
+    }
+    if state.logs.len() as u64 + new_logs.len() as u64 > state.capacity {
+        return Err(format!("The archive holds at most {} logs", state.capacity));
+    }
+    state.logs.extend(new_logs);
+    Ok(state.logs.len() as u64)
+}
+
+fn log_bytes(log: &SecurityLog) -> usize {
+    LOG_OVERHEAD_BYTES
+        + log.event_type.len()
+        + log.details.len()
+        + log.severity.len()
+        + log.entity.as_ref().map_or(0, String::len)
+        + log.source.as_ref().map_or(0, String::len)
+        + log.hash.as_ref().map_or(0, String::len)
+}
+
+/// Stored logs with sequence numbers in `from..from + length`, at most 1000
+/// and about 1.5 MB of them. A reply cut short by size ends with the log
+/// before the next one to ask for.
+#[query]
+fn get_logs(from: u64, length: u64) -> Vec<SecurityLog> {
+    STATE.with(|state| page(&state.borrow().logs, from, length))
+}
+
+// The first log in range is returned whatever its size
+fn page(logs: &BTreeMap<u64, SecurityLog>, from: u64, length: u64) -> Vec<SecurityLog> {
+    let end = from.saturating_add(length.min(MAX_PAGE));
+    let (mut page, mut bytes) = (Vec::new(), 0);
+    for log in logs.range(from..end).map(|(_, log)| log) {
+        bytes += log_bytes(log);
+        if !page.is_empty() && bytes > MAX_PAGE_BYTES {
+            break;
+        }
+        page.push(log.clone());
+    }
+    page
+}
+
+#[query]
+fn get_archive_status() -> ArchiveStatus {
+    STATE.with(|state| {
+        let state = state.borrow();
+        ArchiveStatus {
+            main_canister: state.main_canister,
+            capacity: state.capacity,
+            stored: state.logs.len() as u64,
+            first_sequence: state.logs.first_key_value().map(|(sequence, _)| *sequence),
+            last_sequence: state.logs.last_key_value().map(|(sequence, _)| *sequence),
+        }
+    })
+}
+
+fn write_part(writer: &mut impl Write, part: &[u8]) -> Result<(), String> {
+    writer
+        .write_all(&(part.len() as u64).to_le_bytes())
+        .and_then(|_| writer.write_all(part))
+        .map_err(|e| format!("Failed to write the archive: {}", e))
+}
+
+fn read_part(reader: &mut impl Read) -> Result<Vec<u8>, String> {
+    let mut length = [0; 8];
+    reader
+        .read_exact(&mut length)
+        .map_err(|e| format!("Failed to read the archive: {}", e))?;
+    let mut part = vec![0; u64::from_le_bytes(length) as usize];
+    reader.read_exact(&mut part).map_err(|e| format!("Failed to read the archive: {}", e))?;
+    Ok(part)
+}
+
+fn encode<T: CandidType>(value: &T) -> Result<Vec<u8>, String> {
+    candid::encode_one(value).map_err(|e| format!("Failed to encode the archive: {}", e))
+}
+
+fn decode<T: for<'de> Deserialize<'de> + CandidType>(bytes: &[u8]) -> Result<T, String> {
+    candid::decode_one(bytes).map_err(|e| format!("Failed to decode the archive: {}", e))
+}
+
+/// Writes the archive to `writer` in the layout pre_upgrade uses.
+fn save_state(writer: &mut impl Write) -> Result<(), String> {
+    STATE.with(|state| {
+        let state = state.borrow();
+        writer
+            .write_all(STABLE_MAGIC)
+            .map_err(|e| format!("Failed to write the archive: {}", e))?;
+        write_part(writer, &encode(&(state.main_canister, state.capacity))?)?;
+        let mut logs = state.logs.values();
+        loop {
+            let chunk: Vec<&SecurityLog> = logs.by_ref().take(LOGS_PER_CHUNK).collect();
+            if chunk.is_empty() {
+                break;
+            }
+            write_part(writer, &encode(&chunk)?)?;
+        }
+        write_part(writer, &[])
+    })
+}
+
+/// Replaces the archive with one `save_state` wrote to `reader`.
+fn load_state(reader: &mut impl Read) -> Result<(), String> {
+    let mut magic = [0; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|e| format!("Failed to read the archive: {}", e))?;
+    if &magic != STABLE_MAGIC {
+        return Err("Stable memory does not hold a saved archive".to_string());
+    }
+    let (main_canister, capacity) = decode(&read_part(reader)?)?;
+    let mut logs = BTreeMap::new();
+    loop {
+        let part = read_part(reader)?;
+        if part.is_empty() {
+            break;
+        }
+        let chunk: Vec<SecurityLog> = decode(&part)?;
+        logs.extend(chunk.into_iter().filter_map(|log| Some((log.sequence?, log))));
+    }
+    STATE.with(|state| {
+        *state.borrow_mut() = State {
+            main_canister,
+            capacity,
+            logs,
+        }
+    });
+    Ok(())
+}
+
+fn save_to_stable_memory<M: StableMemory>(memory: M) -> Result<(), String> {
+    let mut writer = BufferedStableWriter::with_writer(STABLE_BUFFER_BYTES, StableWriter::with_memory(memory, 0));
+    save_state(&mut writer)?;
+    writer.flush().map_err(|e| format!("Failed to write the archive: {}", e))
+}
+
+fn restore_from_stable_memory<M: StableMemory>(memory: M) -> Result<(), String> {
+    let mut reader = BufferedStableReader::with_reader(STABLE_BUFFER_BYTES, StableReader::with_memory(memory, 0));
+    load_state(&mut reader)
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    save_to_stable_memory(CanisterStableMemory::default()).expect("Failed to save the archive");
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    restore_from_stable_memory(CanisterStableMemory::default()).expect("Failed to restore the archive");
+}
+
+ic_cdk::export_candid!();
+
+// The tests under tests/ name the crate as an integration test would
+#[cfg(test)]
+extern crate self as cybersec_log_archive;
+#[cfg(test)]
+#[path = "../tests/mod.rs"]
+mod tests;
@@ -0,0 +1,106 @@
+// tests/archive.rs
+use candid::Principal;
+use cybersec_log_archive::{append, load_state, page, save_state, SecurityLog, State, STATE};
+use std::collections::BTreeMap;
+
+fn main_canister() -> Principal {
+    Principal::from_slice(&[1])
+}
+
+fn log(sequence: u64) -> SecurityLog {
+    SecurityLog {
+        timestamp: sequence * 1_000,
+        event_type: "port_scan".to_string(),
+        details: format!("Scan {}", sequence),
+        severity: "MEDIUM".to_string(),
+        entity: Some("203.0.113.7".to_string()),
+        source: Some("netflow".to_string()),
+        sequence: Some(sequence),
+        hash: Some(format!("{:064x}", sequence)),
+    }
+}
+
+fn archive(capacity: u64) -> State {
+    State {
+        main_canister: main_canister(),
+        capacity,
+        logs: BTreeMap::new(),
+    }
+}
+
+fn sequences(logs: &[SecurityLog]) -> Vec<u64> {
+    logs.iter().filter_map(|log| log.sequence).collect()
+}
+
+#[test]
+fn test_appends_in_order_and_skips_resent_logs() {
+    let mut state = archive(6);
+    assert_eq!(
+        append(&mut state, Principal::anonymous(), vec![log(0)]),
+        Err("Only the main canister appends to its archive".to_string())
+    );
+    assert_eq!(append(&mut state, main_canister(), (0..3).map(log).collect()), Ok(3));
+    // A batch resent after a lost reply only adds what is new
+    assert_eq!(append(&mut state, main_canister(), (1..4).map(log).collect()), Ok(4));
+    // Gaps left by retention are fine, going back is not
+    assert_eq!(append(&mut state, main_canister(), vec![log(6)]), Ok(5));
+    assert_eq!(
+        append(&mut state, main_canister(), vec![log(5)]),
+        Err("Log 5 does not follow the last archived log".to_string())
+    );
+    let unsequenced = SecurityLog { sequence: None, ..log(7) };
+    assert!(append(&mut state, main_canister(), vec![unsequenced]).is_err());
+    assert_eq!(
+        append(&mut state, main_canister(), vec![log(7), log(8)]),
+        Err("The archive holds at most 6 logs".to_string())
+    );
+    assert_eq!(sequences(&page(&state.logs, 0, 100)), vec![0, 1, 2, 3, 6]);
+}
+
+#[test]
+fn test_pages_are_capped_by_count_and_size() {
+    let mut state = archive(5_000);
+    append(&mut state, main_canister(), (0..2_000).map(log).collect()).unwrap();
+    assert_eq!(page(&state.logs, 0, 5_000).len(), 1_000);
+    assert_eq!(sequences(&page(&state.logs, 1_998, 10)), vec![1_998, 1_999]);
+
+    let large = |sequence| SecurityLog {
+        details: "x".repeat(400_000),
+        ..log(sequence)
+    };
+    let mut state = archive(10);
+    append(&mut state, main_canister(), (0..5).map(large).collect()).unwrap();
+    assert_eq!(sequences(&page(&state.logs, 0, 10)), vec![0, 1, 2]);
+    // Asking again after the last log returned carries on
+    assert_eq!(sequences(&page(&state.logs, 3, 7)), vec![3, 4]);
+
+    // A log over the cap on its own still comes back
+    let mut state = archive(10);
+    let huge = SecurityLog {
+        details: "x".repeat(2_000_000),
+        ..log(0)
+    };
+    append(&mut state, main_canister(), vec![huge, log(1)]).unwrap();
+    assert_eq!(sequences(&page(&state.logs, 0, 10)), vec![0]);
+}
+
+#[test]
+fn test_archive_survives_upgrade() {
+    let mut saved = archive(5_000);
+    append(&mut saved, main_canister(), (0..2_500).map(log).collect()).unwrap();
+    STATE.with(|state| *state.borrow_mut() = saved);
+    let mut bytes = Vec::new();
+    save_state(&mut bytes).unwrap();
+
+    STATE.with(|state| *state.borrow_mut() = archive(0));
+    load_state(&mut bytes.as_slice()).unwrap();
+    STATE.with(|state| {
+        let state = state.borrow();
+        assert_eq!((state.main_canister, state.capacity), (main_canister(), 5_000));
+        assert_eq!(state.logs.len(), 2_500);
+        assert_eq!(state.logs[&2_499].details, "Scan 2499");
+    });
+
+    assert!(load_state(&mut &b"not a saved archive"[..]).is_err());
+    assert!(load_state(&mut &bytes[..bytes.len() - 1]).is_err());
+}
@@ -0,0 +1,74 @@
+use crate::{log_chain, SecurityLog};
+use candid::{CandidType, Deserialize};
+use ic_certification::hash_tree::{fork_hash, labeled_hash, leaf_hash, Hash};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "verify")]
+mod verify;
+
+#[cfg(feature = "verify")]
+pub use verify::{hash_to_g1, verify_certificate, verify_security_logs, verify_threat_summary};
+
+/// Label of the subtree holding a leaf per stored security log, keyed by
+/// big-endian sequence number.
+pub const SECURITY_LOGS_LABEL: &[u8] = b"security_logs";
+/// Label of the leaf holding the digest of the threat summary.
+pub const THREAT_SUMMARY_LABEL: &[u8] = b"threat_summary";
+
+/// Security logs `from..=to` by sequence number, with the proof that they
+/// are all the canister stores in that range.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CertifiedSecurityLogs {
+    pub from: u64,
+    pub to: u64,
+    pub logs: Vec<SecurityLog>,
+    /// CBOR certificate of the subnet over the canister's certified data
+    pub certificate: ByteBuf,
+    /// CBOR hash tree whose root hash is the certified data
+    pub witness: ByteBuf,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CertifiedThreatSummary {
+    /// Sorted by category
+    pub summary: Vec<(String, u32)>,
+    pub certificate: ByteBuf,
+    pub witness: ByteBuf,
+}
+
+/// The leaf certifying one stored log: SHA-256 over its chain encoding and
+/// its chain hash, length-prefixed.
+pub fn security_log_leaf(log: &SecurityLog) -> Result<Hash, String> {
+    let (Some(sequence), Some(hash)) = (log.sequence, &log.hash) else {
+        return Err("Only stored security logs, with a sequence number and hash, are certified".to_string());
+    };
+    let mut hasher = Sha256::new();
+    log_chain::hash_contents(&mut hasher, sequence, log);
+    hasher.update((hash.len() as u64).to_be_bytes());
+    hasher.update(hash.as_bytes());
+    Ok(hasher.finalize().into())
+}
+
+/// The leaf certifying the threat summary: its entries sorted by category,
+/// each the length-prefixed category then the count as a big-endian u32.
+pub fn threat_summary_leaf(summary: &[(String, u32)]) -> Hash {
+    let mut entries: Vec<&(String, u32)> = summary.iter().collect();
+    entries.sort();
+    let mut hasher = Sha256::new();
+    for (category, count) in entries {
+        hasher.update((category.len() as u64).to_be_bytes());
+        hasher.update(category.as_bytes());
+        hasher.update(count.to_be_bytes());
+    }
+    hasher.finalize().into()
+}
+
+/// The certified data: the root hash of
+/// `fork(security_logs -> <logs tree>, threat_summary -> leaf)`.
+pub fn root_hash(logs_root: &Hash, summary_leaf: &Hash) -> Hash {
+    fork_hash(
+        &labeled_hash(SECURITY_LOGS_LABEL, logs_root),
+        &labeled_hash(THREAT_SUMMARY_LABEL, &leaf_hash(summary_leaf)),
+    )
+}
@@ -0,0 +1,219 @@
+use super::{security_log_leaf, threat_summary_leaf, CertifiedSecurityLogs, CertifiedThreatSummary};
+use super::{SECURITY_LOGS_LABEL, THREAT_SUMMARY_LABEL};
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine};
+use candid::Principal;
+use ic_certification::{Certificate, HashTree, LookupResult};
+use serde_bytes::ByteBuf;
+use sha2::{Digest, Sha256};
+
+// DER header of an IC BLS12-381 public key, followed by the 96-byte key
+const BLS_KEY_DER_PREFIX: [u8; 37] = [
+    0x30, 0x81, 0x82, 0x30, 0x1d, 0x06, 0x0d, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03, 0x01, 0x02,
+    0x01, 0x06, 0x0c, 0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0xdc, 0x7c, 0x05, 0x03, 0x02, 0x01, 0x03, 0x61, 0x00,
+];
+const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_";
+const STATE_ROOT_DOMAIN: &[u8] = b"\x0dic-state-root";
+
+// bls12_381 hashes to the curve through the digest 0.9 traits; this feeds
+// them from the sha2 hasher used everywhere else
+#[derive(Clone, Default)]
+struct XmdSha256(Sha256);
+
+impl digest09::Update for XmdSha256 {
+    fn update(&mut self, data: impl AsRef<[u8]>) {
+        Digest::update(&mut self.0, data);
+    }
+}
+
+impl digest09::BlockInput for XmdSha256 {
+    type BlockSize = digest09::consts::U64;
+}
+
+impl digest09::FixedOutputDirty for XmdSha256 {
+    type OutputSize = digest09::consts::U32;
+
+    fn finalize_into_dirty(&mut self, out: &mut digest09::generic_array::GenericArray<u8, Self::OutputSize>) {
+        out.copy_from_slice(&self.0.finalize_reset());
+    }
+}
+
+impl digest09::Reset for XmdSha256 {
+    fn reset(&mut self) {
+        Digest::reset(&mut self.0);
+    }
+}
+
+/// The curve point a BLS signature over `message` signs, as the IC derives
+/// it: hash-to-curve with SHA-256 and the standard G1 domain separator.
+pub fn hash_to_g1(message: &[u8]) -> G1Affine {
+    G1Affine::from(<G1Projective as HashToCurve<ExpandMsgXmd<XmdSha256>>>::hash_to_curve(message, BLS_DST))
+}
+
+fn verify_bls(signature: &[u8], message: &[u8], der_key: &[u8]) -> Result<(), String> {
+    let key = der_key
+        .strip_prefix(&BLS_KEY_DER_PREFIX[..])
+        .and_then(|key| <[u8; 96]>::try_from(key).ok())
+        .ok_or("Not a DER-encoded BLS public key")?;
+    let key = Option::<G2Affine>::from(G2Affine::from_compressed(&key)).ok_or("Invalid BLS public key")?;
+    let signature = <[u8; 48]>::try_from(signature).map_err(|_| "Invalid BLS signature length")?;
+    let signature = Option::<G1Affine>::from(G1Affine::from_compressed(&signature)).ok_or("Invalid BLS signature")?;
+    if pairing(&signature, &G2Affine::generator()) != pairing(&hash_to_g1(message), &key) {
+        return Err("Certificate signature does not verify".to_string());
+    }
+    Ok(())
+}
+
+fn lookup<'a>(tree: &'a HashTree, path: &[&[u8]]) -> Result<&'a [u8], String> {
+    match tree.lookup_path(path.iter().copied()) {
+        LookupResult::Found(value) => Ok(value),
+        _ => Err(format!("Certificate has no {}", path_text(path))),
+    }
+}
+
+fn path_text(path: &[&[u8]]) -> String {
+    path.iter()
+        .map(|label| String::from_utf8(label.to_vec()).unwrap_or_else(|_| format!("{:02x?}", label)))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn decode_leb128(bytes: &[u8]) -> Result<u64, String> {
+    let mut value: u64 = 0;
+    for (index, byte) in bytes.iter().enumerate() {
+        if index >= 10 {
+            break;
+        }
+        value |= ((byte & 0x7f) as u64) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err("Invalid certificate time".to_string())
+}
+
+// The key the subnet signed with: the root key, or one it delegated to the
+// subnet that hosts `canister_id`
+fn signing_key(certificate: &Certificate, canister_id: &Principal, root_key: &[u8]) -> Result<Vec<u8>, String> {
+    let Some(delegation) = &certificate.delegation else {
+        return Ok(root_key.to_vec());
+    };
+    let parent: Certificate = serde_cbor::from_slice(&delegation.certificate)
+        .map_err(|e| format!("Invalid delegation certificate: {}", e))?;
+    if parent.delegation.is_some() {
+        return Err("Delegations may not be nested".to_string());
+    }
+    let message = [STATE_ROOT_DOMAIN, &parent.tree.digest()[..]].concat();
+    verify_bls(&parent.signature, &message, root_key)?;
+
+    let subnet = delegation.subnet_id.as_slice();
+    let ranges = lookup(&parent.tree, &[b"subnet", subnet, b"canister_ranges"])?;
+    let ranges: Vec<(ByteBuf, ByteBuf)> =
+        serde_cbor::from_slice(ranges).map_err(|e| format!("Invalid canister ranges: {}", e))?;
+    let canister = canister_id.as_slice();
+    if !ranges.iter().any(|(low, high)| low.as_slice() <= canister && canister <= high.as_slice()) {
+        return Err("The delegated subnet does not host this canister".to_string());
+    }
+    Ok(lookup(&parent.tree, &[b"subnet", subnet, b"public_key"])?.to_vec())
+}
+
+/// Checks that `certificate` is signed by the IC for `root_key` (the
+/// DER-encoded root public key), is no more than `max_age_ns` away from
+/// `now`, and certifies `witness` as `canister_id`'s certified data.
+/// Returns the decoded witness.
+pub fn verify_certificate(
+    certificate: &[u8],
+    witness: &[u8],
+    canister_id: &Principal,
+    root_key: &[u8],
+    now: u64,
+    max_age_ns: u64,
+) -> Result<HashTree, String> {
+    let certificate: Certificate =
+        serde_cbor::from_slice(certificate).map_err(|e| format!("Invalid certificate: {}", e))?;
+    let key = signing_key(&certificate, canister_id, root_key)?;
+    let message = [STATE_ROOT_DOMAIN, &certificate.tree.digest()[..]].concat();
+    verify_bls(&certificate.signature, &message, &key)?;
+
+    let time = decode_leb128(lookup(&certificate.tree, &[b"time"])?)?;
+    if now.abs_diff(time) > max_age_ns {
+        return Err(format!("Certificate time {} is too far from now ({})", time, now));
+    }
+    let certified_data = lookup(
+        &certificate.tree,
+        &[b"canister", canister_id.as_slice(), b"certified_data"],
+    )?;
+    let witness: HashTree = serde_cbor::from_slice(witness).map_err(|e| format!("Invalid witness: {}", e))?;
+    if certified_data != witness.digest() {
+        return Err("The witness does not match the certified data".to_string());
+    }
+    Ok(witness)
+}
+
+/// Verifies a `get_certified_security_logs` response: the certificate, that
+/// every log matches its certified leaf, and that no stored log in
+/// `from..=to` was left out.
+pub fn verify_security_logs(
+    response: &CertifiedSecurityLogs,
+    canister_id: &Principal,
+    root_key: &[u8],
+    now: u64,
+    max_age_ns: u64,
+) -> Result<(), String> {
+    let witness = verify_certificate(&response.certificate, &response.witness, canister_id, root_key, now, max_age_ns)?;
+    let (from, to) = (response.from, response.to);
+
+    let mut returned = Vec::with_capacity(response.logs.len());
+    for log in &response.logs {
+        let sequence = log.sequence.ok_or("A log has no sequence number")?;
+        if sequence < from || sequence > to || returned.last().is_some_and(|last| *last >= sequence) {
+            return Err(format!("Log {} is out of order or outside {}..={}", sequence, from, to));
+        }
+        let key = sequence.to_be_bytes();
+        let certified = lookup(&witness, &[SECURITY_LOGS_LABEL, &key])?;
+        if certified != security_log_leaf(log)? {
+            return Err(format!("Log {} does not match its certified leaf", sequence));
+        }
+        returned.push(sequence);
+    }
+
+    // Every log the witness reveals in range must have been returned ...
+    for path in witness.list_paths() {
+        let [label, key] = path.as_slice() else {
+            continue;
+        };
+        let Ok(key) = <[u8; 8]>::try_from(key.as_bytes()) else {
+            continue;
+        };
+        let sequence = u64::from_be_bytes(key);
+        let in_range = (from..=to).contains(&sequence);
+        if label.as_bytes() == SECURITY_LOGS_LABEL && in_range && returned.binary_search(&sequence).is_err() {
+            return Err(format!("Log {} is certified but was not returned", sequence));
+        }
+    }
+    // ... and proven absence after each returned log (and at `from`) rules
+    // out logs hidden in pruned parts of the witness
+    let gaps = std::iter::once(from).chain(returned.iter().map(|sequence| sequence + 1));
+    for sequence in gaps.filter(|sequence| *sequence <= to && returned.binary_search(sequence).is_err()) {
+        let key = sequence.to_be_bytes();
+        if witness.lookup_path([SECURITY_LOGS_LABEL, &key[..]]) != LookupResult::Absent {
+            return Err(format!("The witness does not prove log {} absent", sequence));
+        }
+    }
+    Ok(())
+}
+
+/// Verifies a `get_certified_threat_summary` response.
+pub fn verify_threat_summary(
+    response: &CertifiedThreatSummary,
+    canister_id: &Principal,
+    root_key: &[u8],
+    now: u64,
+    max_age_ns: u64,
+) -> Result<(), String> {
+    let witness = verify_certificate(&response.certificate, &response.witness, canister_id, root_key, now, max_age_ns)?;
+    if lookup(&witness, &[THREAT_SUMMARY_LABEL])? != threat_summary_leaf(&response.summary) {
+        return Err("The threat summary does not match its certified leaf".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use candid::{CandidType, Deserialize, Principal};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+const URL_SCHEMES: [&str; 3] = ["http", "https", "ftp"];
+// Delimiters around tokens in free text; URLs are taken out before splitting
+const TOKEN_DELIMITERS: &str = ",;\"'`()[]{}<>|=";
+// Generic top-level domains a bare hostname is recognised by. Two-letter
+// country codes are accepted too, apart from common file extensions.
+const GENERIC_TLDS: [&str; 36] = [
+    "com", "net", "org", "edu", "gov", "mil", "int", "info", "biz", "name", "pro", "mobi", "app", "dev", "cloud",
+    "online", "site", "website", "tech", "store", "shop", "live", "xyz", "top", "club", "work", "click", "link",
+    "icu", "buzz", "support", "local", "internal", "onion", "arpa", "zip",
+];
+const FILE_EXTENSIONS: [&str; 12] = ["js", "py", "sh", "cs", "db", "gz", "md", "rb", "ps", "so", "7z", "rs"];
+
+/// Entities found in a record's free text, each list in order of first
+/// appearance and without repeats. Like ECS `related.*`, but typed.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct Entities {
+    pub ipv4: Vec<String>,
+    pub ipv6: Vec<String>,
+    /// Networks in CIDR notation, e.g. `10.0.0.0/8`
+    pub cidrs: Vec<String>,
+    /// Hostnames, including those of URLs and email addresses
+    pub domains: Vec<String>,
+    pub urls: Vec<String>,
+    pub emails: Vec<String>,
+    /// MD5, SHA-1 and SHA-256 digests, lowercase
+    pub hashes: Vec<String>,
+    /// `CVE-YYYY-NNNN`, uppercase
+    pub cve_ids: Vec<String>,
+    /// Internet Computer principals, canister ids included
+    pub principals: Vec<String>,
+    /// ICP ledger account identifiers, AWS ARNs and Windows `DOMAIN\user` accounts
+    pub accounts: Vec<String>,
+}
+
+impl Entities {
+    pub fn is_empty(&self) -> bool {
+        self.ipv4.is_empty()
+            && self.ipv6.is_empty()
+            && self.cidrs.is_empty()
+            && self.domains.is_empty()
+            && self.urls.is_empty()
+            && self.emails.is_empty()
+            && self.hashes.is_empty()
+            && self.cve_ids.is_empty()
+            && self.principals.is_empty()
+            && self.accounts.is_empty()
+    }
+}
+
+fn push(list: &mut Vec<String>, value: String) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+/// Pulls every recognised entity out of `text`.
+pub fn extract_entities(text: &str) -> Entities {
+    let mut entities = Entities::default();
+
+    let urls = extract_urls(text);
+    let mut rest = text.to_string();
+    for url in &urls {
+        rest = rest.replace(url.as_str(), " ");
+        if let Some(host) = url_host(url) {
+            classify_host(&host, &mut entities);
+        }
+    }
+    entities.urls = urls;
+
+    for token in rest.split(|c: char| c.is_whitespace() || TOKEN_DELIMITERS.contains(c)) {
+        let token = token
+            .trim_start_matches(['.', ':', '!', '?', '*'])
+            .trim_end_matches(['.', ':', '!', '?', '*']);
+        if !token.is_empty() {
+            classify_token(token, &mut entities);
+        }
+    }
+    entities
+}
+
+fn classify_token(token: &str, entities: &mut Entities) {
+    if let Some(cidr) = parse_cidr(token) {
+        return push(&mut entities.cidrs, cidr);
+    }
+    if let Some(ip) = parse_ip(token) {
+        return match ip {
+            IpAddr::V4(ip) => push(&mut entities.ipv4, ip.to_string()),
+            IpAddr::V6(ip) => push(&mut entities.ipv6, ip.to_string()),
+        };
+    }
+    if let Some(cve) = parse_cve(token) {
+        return push(&mut entities.cve_ids, cve);
+    }
+    if matches!(token.len(), 32 | 40 | 64) && token.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        let hex = token.to_ascii_lowercase();
+        return if is_icp_account_identifier(&hex) {
+            push(&mut entities.accounts, hex)
+        } else {
+            push(&mut entities.hashes, hex)
+        };
+    }
+    if token.starts_with("arn:") && token.split(':').count() >= 6 {
+        return push(&mut entities.accounts, token.to_string());
+    }
+    if let Some((domain, user)) = token.split_once('\\') {
+        let is_name = |part: &str| {
+            !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || "-_.$".contains(c))
+        };
+        if is_name(domain) && is_name(user) && !domain.contains('.') {
+            return push(&mut entities.accounts, token.to_string());
+        }
+    }
+    if token.contains('-') && Principal::from_text(token).is_ok() {
+        return push(&mut entities.principals, token.to_ascii_lowercase());
+    }
+    if let Some((local, domain)) = token.split_once('@') {
+        if !local.is_empty() && !local.contains(':') && is_domain(domain) {
+            push(&mut entities.emails, token.to_ascii_lowercase());
+            push(&mut entities.domains, domain.to_ascii_lowercase());
+        }
+        return;
+    }
+    classify_host(token, entities);
+}
+
+fn classify_host(host: &str, entities: &mut Entities) {
+    match parse_ip(host) {
+        Some(IpAddr::V4(ip)) => push(&mut entities.ipv4, ip.to_string()),
+        Some(IpAddr::V6(ip)) => push(&mut entities.ipv6, ip.to_string()),
+        None if is_domain(host) => push(&mut entities.domains, host.trim_end_matches('.').to_ascii_lowercase()),
+        None => {}
+    }
+}
+
+// Addresses alone, with a port (`1.2.3.4:443`, `[::1]:8080`) or in brackets
+fn parse_ip(token: &str) -> Option<IpAddr> {
+    if let Ok(ip) = token.parse::<Ipv4Addr>() {
+        return Some(IpAddr::V4(ip));
+    }
+    if let Some(bracketed) = token.strip_prefix('[') {
+        let address = bracketed.split(']').next()?;
+        return address.parse::<Ipv6Addr>().ok().map(IpAddr::V6);
+    }
+    if token != "::" {
+        if let Ok(ip) = token.parse::<Ipv6Addr>() {
+            return Some(IpAddr::V6(ip));
+        }
+    }
+    let (address, port) = token.rsplit_once(':')?;
+    port.parse::<u16>().ok()?;
+    address.parse::<Ipv4Addr>().ok().map(IpAddr::V4)
+}
+
+fn parse_cidr(token: &str) -> Option<String> {
+    let (address, prefix) = token.split_once('/')?;
+    let prefix: u8 = prefix.parse().ok()?;
+    match address.parse::<IpAddr>().ok()? {
+        IpAddr::V4(ip) if prefix <= 32 => Some(format!("{}/{}", ip, prefix)),
+        IpAddr::V6(ip) if prefix <= 128 => Some(format!("{}/{}", ip, prefix)),
+        _ => None,
+    }
+}
+
+fn parse_cve(token: &str) -> Option<String> {
+    let upper = token.to_ascii_uppercase();
+    let (year, number) = upper.strip_prefix("CVE-")?.split_once('-')?;
+    let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    (year.len() == 4 && digits(year) && number.len() >= 4 && digits(number)).then_some(upper)
+}
+
+fn is_domain(candidate: &str) -> bool {
+    let candidate = candidate.trim_end_matches('.');
+    let labels: Vec<&str> = candidate.split('.').collect();
+    if labels.len() < 2 || candidate.len() > 253 {
+        return false;
+    }
+    let valid_label = |label: &&str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if !labels.iter().all(valid_label) {
+        return false;
+    }
+    let tld = labels[labels.len() - 1].to_ascii_lowercase();
+    if tld.starts_with("xn--") {
+        return true;
+    }
+    tld.chars().all(|c| c.is_ascii_alphabetic())
+        && (GENERIC_TLDS.contains(&tld.as_str()) || (tld.len() == 2 && !FILE_EXTENSIONS.contains(&tld.as_str())))
+}
+
+fn url_host(url: &str) -> Option<String> {
+    let authority = url.split_once("://")?.1.split(['/', '?', '#']).next()?;
+    let host_port = authority.rsplit('@').next()?;
+    let host = match host_port.strip_prefix('[') {
+        Some(v6) => v6.split(']').next()?,
+        None => host_port.split(':').next()?,
+    };
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// The http, https and ftp URLs in free text, in order of appearance and
+/// without duplicates. Trailing punctuation is left out.
+pub fn extract_urls(text: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    let mut offset = 0;
+    while let Some(found) = text[offset..].find("://") {
+        let separator = offset + found;
+        let scheme_start = text[..separator]
+            .char_indices()
+            .rev()
+            .take_while(|(_, c)| c.is_ascii_alphanumeric())
+            .last()
+            .map_or(separator, |(index, _)| index);
+        let rest = &text[separator + 3..];
+        let end = rest
+            .find(|c: char| c.is_whitespace() || "<>\"'()[]{}".contains(c))
+            .unwrap_or(rest.len());
+        offset = separator + 3 + end;
+
+        let scheme = text[scheme_start..separator].to_ascii_lowercase();
+        if !URL_SCHEMES.contains(&scheme.as_str()) || end == 0 {
+            continue;
+        }
+        let url = text[scheme_start..offset].trim_end_matches(['.', ',', ';', '!', '?']);
+        if !urls.iter().any(|existing| existing == url) {
+            urls.push(url.to_string());
+        }
+    }
+    urls
+}
+
+// A ledger account identifier is a CRC-32 of the 28-byte account hash, then the hash
+fn is_icp_account_identifier(hex: &str) -> bool {
+    if hex.len() != 64 {
+        return false;
+    }
+    let bytes: Option<Vec<u8>> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect();
+    let Some(bytes) = bytes else {
+        return false;
+    };
+    crc32(&bytes[4..]).to_be_bytes() == bytes[..4]
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
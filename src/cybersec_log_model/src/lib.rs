@@ -0,0 +1,110 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+#[cfg(feature = "certified")]
+pub mod certified;
+pub mod entities;
+pub mod log_chain;
+pub mod timestamp;
+
+use entities::{extract_entities, Entities};
+use timestamp::{parse_timestamp, TimestampConfig};
+
+/// The log record shared by the agent and backend canisters.
+///
+/// Core fields follow the Elastic Common Schema where one exists; anything
+/// format-specific goes in `attributes`, keyed by the producer's own field name.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct LogRecord {
+    /// `@timestamp`: event time in nanoseconds since the Unix epoch, when known
+    pub timestamp: Option<u64>,
+    /// The timestamp exactly as the producer wrote it
+    pub original_timestamp: Option<String>,
+    /// `message`
+    pub message: String,
+    /// Producer of the record (application, sensor or log file), like `event.dataset`
+    pub source: Option<String>,
+    /// `source.ip`
+    pub source_ip: Option<String>,
+    /// `destination.ip`
+    pub destination_ip: Option<String>,
+    /// `user.name`
+    pub user: Option<String>,
+    /// `host.name`
+    pub host: Option<String>,
+    /// `event.action`
+    pub action: Option<String>,
+    /// `event.outcome`
+    pub outcome: Option<String>,
+    /// `log.level`
+    pub severity: Option<String>,
+    pub attributes: BTreeMap<String, String>,
+    /// Entities found in `message`, once extracted
+    pub entities: Option<Entities>,
+}
+
+impl LogRecord {
+    pub fn new(message: impl Into<String>) -> Self {
+        LogRecord {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn attribute(&self, key: &str) -> Option<&str> {
+        self.attributes.get(key).map(String::as_str)
+    }
+
+    /// Fills `timestamp` from `original_timestamp` when it has not been set yet.
+    pub fn resolve_timestamp(&mut self, config: &TimestampConfig) -> Option<u64> {
+        if self.timestamp.is_none() {
+            self.timestamp = self
+                .original_timestamp
+                .as_deref()
+                .and_then(|value| parse_timestamp(value, config));
+        }
+        self.timestamp
+    }
+
+    /// Extracts the entities in `message` into `entities`.
+    pub fn extract_entities(&mut self) -> &Entities {
+        self.entities.insert(extract_entities(&self.message))
+    }
+
+    pub fn with_attribute(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.attributes.insert(key.into(), value.into());
+        self
+    }
+}
+
+// Message-only entries, as older callers and tests construct them
+impl From<String> for LogRecord {
+    fn from(message: String) -> Self {
+        LogRecord::new(message)
+    }
+}
+
+impl From<&str> for LogRecord {
+    fn from(message: &str) -> Self {
+        LogRecord::new(message)
+    }
+}
+
+/// A stored security log, as the backend keeps, chains and certifies it.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct SecurityLog {
+    pub timestamp: u64,
+    pub event_type: String,
+    pub details: String,
+    pub severity: String,
+    /// The IP, domain, hash or other entity the event is about, when known
+    pub entity: Option<String>,
+    /// Producer of the record a detection came from, or the detector; absent
+    /// for events logged directly
+    pub source: Option<String>,
+    /// Position in the hash chain, set when the log is stored
+    pub sequence: Option<u64>,
+    /// Chain hash over this log and the one before it, set when the log is stored
+    pub hash: Option<String>,
+}
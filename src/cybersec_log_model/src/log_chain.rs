@@ -0,0 +1,184 @@
+use crate::SecurityLog;
+use candid::{CandidType, Deserialize};
+use sha2::{Digest, Sha256};
+
+/// The hash the first entry chains to.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// A checkpoint is kept after every this many entries.
+pub const CHECKPOINT_INTERVAL: u64 = 1_000;
+
+/// Chain hash of an entry at a given sequence number, kept permanently.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct Checkpoint {
+    pub sequence: u64,
+    pub timestamp: u64,
+    pub hash: String,
+}
+
+/// Consecutive entries retention evicted. Their contents are gone, but the
+/// chain hash of the last one lets the chain be verified across the gap.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct PrunedRange {
+    pub first: u64,
+    pub last: u64,
+    pub last_hash: String,
+}
+
+/// What the store keeps about the chain besides the entries themselves.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChainState {
+    pub length: u64,
+    pub head_hash: String,
+    pub checkpoints: Vec<Checkpoint>,
+    pub pruned: Vec<PrunedRange>,
+}
+
+impl Default for ChainState {
+    fn default() -> Self {
+        ChainState {
+            length: 0,
+            head_hash: GENESIS_HASH.to_string(),
+            checkpoints: Vec::new(),
+            pruned: Vec::new(),
+        }
+    }
+}
+
+/// The latest entry of the chain; publish `hash` to let others verify proofs.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChainHead {
+    /// Number of entries ever appended, evicted ones included
+    pub length: u64,
+    /// Chain hash of entry `length - 1`, or `GENESIS_HASH` for an empty chain
+    pub hash: String,
+}
+
+/// Result of checking the stored entries `from..=to` against their hashes.
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub struct ChainVerification {
+    pub from: u64,
+    pub to: u64,
+    /// Entries whose hash was recomputed and matched
+    pub verified: u64,
+    /// Entries evicted by retention, skipped over using the pruned range hashes
+    pub pruned: u64,
+    pub valid: bool,
+    /// What failed first, with its sequence number
+    pub error: Option<String>,
+    pub head: ChainHead,
+}
+
+/// Everything needed to check entries `from..=to` offline: recompute the
+/// chain from `previous_hash` through `entries` and `pruned`, in sequence
+/// order, and compare the result with a published hash for `to`.
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct ChainProof {
+    pub from: u64,
+    pub to: u64,
+    /// Chain hash of entry `from - 1`, or `GENESIS_HASH` when `from` is 0
+    pub previous_hash: String,
+    pub entries: Vec<SecurityLog>,
+    pub pruned: Vec<PrunedRange>,
+    /// Stored checkpoints within the range
+    pub checkpoints: Vec<Checkpoint>,
+}
+
+fn decode_hash(hex: &str) -> Result<[u8; 32], String> {
+    let invalid = || format!("Invalid chain hash: {}", hex);
+    if hex.len() != 64 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let mut bytes = [0u8; 32];
+    for (byte, pair) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).map_err(|_| invalid())?, 16).map_err(|_| invalid())?;
+    }
+    Ok(bytes)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Chain hash of `log` at `sequence`: SHA-256 over the previous hash, then
+/// the sequence number and timestamp as big-endian u64s, then event type,
+/// details, severity, entity and source, each prefixed with its length as
+/// a big-endian u64 (a missing entity or source is a single zero byte, a
+/// present one has a one byte before it).
+pub fn entry_hash(previous_hash: &str, sequence: u64, log: &SecurityLog) -> Result<String, String> {
+    let mut hasher = Sha256::new();
+    hasher.update(decode_hash(previous_hash)?);
+    hash_contents(&mut hasher, sequence, log);
+    Ok(to_hex(&hasher.finalize()))
+}
+
+// The encoding of a log `entry_hash` describes, after the previous hash
+pub(crate) fn hash_contents(hasher: &mut Sha256, sequence: u64, log: &SecurityLog) {
+    hasher.update(sequence.to_be_bytes());
+    hasher.update(log.timestamp.to_be_bytes());
+    for field in [&log.event_type, &log.details, &log.severity] {
+        hasher.update((field.len() as u64).to_be_bytes());
+        hasher.update(field.as_bytes());
+    }
+    for field in [&log.entity, &log.source] {
+        match field {
+            Some(value) => {
+                hasher.update([1]);
+                hasher.update((value.len() as u64).to_be_bytes());
+                hasher.update(value.as_bytes());
+            }
+            None => hasher.update([0]),
+        }
+    }
+}
+
+/// Checks a proof offline and returns the number of entries it verified.
+/// `expected_hash` is the published chain hash of entry `proof.to`, such as
+/// a head hash or checkpoint recorded when it was taken.
+pub fn verify_chain_proof(proof: &ChainProof, expected_hash: &str) -> Result<u64, String> {
+    if proof.from > proof.to {
+        return Err(format!("Empty range {}..={}", proof.from, proof.to));
+    }
+    if proof.from == 0 && proof.previous_hash != GENESIS_HASH {
+        return Err("The first entry must chain to the genesis hash".to_string());
+    }
+    let mut entries = proof.entries.iter().peekable();
+    let mut pruned = proof.pruned.iter().peekable();
+    let mut hash = proof.previous_hash.clone();
+    let mut sequence = proof.from;
+    let mut verified = 0;
+    while sequence <= proof.to {
+        if let Some(entry) = entries.next_if(|entry| entry.sequence == Some(sequence)) {
+            let computed = entry_hash(&hash, sequence, entry)?;
+            if entry.hash.as_deref() != Some(computed.as_str()) {
+                return Err(format!("Entry {} does not match its hash", sequence));
+            }
+            hash = computed;
+            sequence += 1;
+            verified += 1;
+        } else if let Some(range) = pruned.next_if(|range| range.first <= sequence && sequence <= range.last) {
+            if range.last > proof.to {
+                return Err(format!("Pruned range {}..={} runs past the proof", range.first, range.last));
+            }
+            hash = range.last_hash.clone();
+            sequence = range.last + 1;
+        } else {
+            return Err(format!("Entry {} is missing", sequence));
+        }
+    }
+    if entries.next().is_some() || pruned.next().is_some() {
+        return Err("The proof holds entries outside its range".to_string());
+    }
+    for checkpoint in &proof.checkpoints {
+        let stored = match proof.entries.iter().find(|entry| entry.sequence == Some(checkpoint.sequence)) {
+            Some(entry) => entry.hash.as_ref(),
+            None => proof.pruned.iter().find(|range| range.last == checkpoint.sequence).map(|range| &range.last_hash),
+        };
+        if stored.is_some_and(|hash| hash != &checkpoint.hash) {
+            return Err(format!("Entry {} does not match its checkpoint", checkpoint.sequence));
+        }
+    }
+    if !hash.eq_ignore_ascii_case(expected_hash) {
+        return Err(format!("Chain hash of entry {} does not match the expected hash", proof.to));
+    }
+    Ok(verified)
+}
@@ -0,0 +1,221 @@
+use candid::{CandidType, Deserialize};
+use serde::Serialize;
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+pub struct TimestampConfig {
+    /// Zone assumed for ISO 8601 and syslog timestamps that carry none, in minutes east of UTC
+    pub default_utc_offset_minutes: i32,
+    /// Current time in nanoseconds, used to pick the year of syslog dates
+    pub reference_time: Option<u64>,
+}
+
+/// Parses any supported timestamp into nanoseconds since the Unix epoch.
+///
+/// Accepted forms are RFC 3339, ISO 8601 with or without a zone, Unix epoch
+/// seconds, milliseconds, microseconds or nanoseconds (told apart by
+/// magnitude), RFC 3164 syslog dates such as `Mar  1 10:00:01` and the
+/// Common Log Format dates of web access logs, `01/Mar/2024:10:00:01 +0000`.
+pub fn parse_timestamp(value: &str, config: &TimestampConfig) -> Option<u64> {
+    let value = value.trim().trim_start_matches('[').trim_end_matches(']');
+    let first = value.chars().next()?;
+
+    if first.is_ascii_digit() && value.len() >= 10 && value.as_bytes().get(4) == Some(&b'-') {
+        parse_iso8601(value, config.default_utc_offset_minutes)
+    } else if first.is_ascii_digit() && value.as_bytes().get(2) == Some(&b'/') {
+        parse_common_log(value, config.default_utc_offset_minutes)
+    } else if first.is_ascii_digit() {
+        parse_epoch(value)
+    } else {
+        parse_syslog(value, config)
+    }
+}
+
+/// Parses an RFC 3339 timestamp, which must carry `Z` or a numeric offset.
+pub fn parse_rfc3339(value: &str) -> Option<u64> {
+    let (date, time) = value.trim().split_once(['T', 't', ' '])?;
+    let (clock, offset) = split_zone(time)?;
+    to_nanos(parse_date(date)?, parse_clock(clock)?, offset?)
+}
+
+fn parse_iso8601(value: &str, default_offset_minutes: i32) -> Option<u64> {
+    let (date, time) = match value.split_once(['T', 't', ' ']) {
+        Some((date, time)) => (date, time),
+        None => (value, "00:00:00"),
+    };
+    let (clock, offset) = split_zone(time)?;
+    to_nanos(parse_date(date)?, parse_clock(clock)?, offset.unwrap_or(default_offset_minutes))
+}
+
+fn parse_epoch(value: &str) -> Option<u64> {
+    let (integer, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if !integer.bytes().all(|b| b.is_ascii_digit()) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // Ten digits of seconds cover 2001-2286; longer values are finer units
+    let scale: u64 = match integer.len() {
+        0..=11 => 1_000_000_000,
+        12..=14 => 1_000_000,
+        15..=17 => 1_000,
+        _ => 1,
+    };
+    let whole = integer.parse::<u64>().ok()?.checked_mul(scale)?;
+    let fractional = if fraction.is_empty() || scale == 1 {
+        0
+    } else {
+        let digits: String = fraction.chars().chain(std::iter::repeat('0')).take(9).collect();
+        digits.parse::<u64>().ok()? / (1_000_000_000 / scale)
+    };
+    whole.checked_add(fractional)
+}
+
+// Apache and nginx access logs: "01/Mar/2024:10:00:01 +0000"
+fn parse_common_log(value: &str, default_offset_minutes: i32) -> Option<u64> {
+    let (stamp, zone) = value.split_once(' ').unwrap_or((value, ""));
+    let (date, clock) = stamp.split_once(':')?;
+    let mut parts = date.splitn(3, '/');
+    let day = parts.next()?.parse::<i64>().ok()?;
+    let month_name = parts.next()?.to_ascii_lowercase();
+    let month = MONTHS.iter().position(|m| *m == month_name)? as i64 + 1;
+    let year = parts.next()?.parse::<i64>().ok()?;
+
+    let time = format!("{}{}", clock, zone.trim());
+    let (clock, offset) = split_zone(&time)?;
+    to_nanos((year, month, day), parse_clock(clock)?, offset.unwrap_or(default_offset_minutes))
+}
+
+// RFC 3164: "Mar  1 10:00:01", optionally with a year after the day
+fn parse_syslog(value: &str, config: &TimestampConfig) -> Option<u64> {
+    let mut parts = value.split_whitespace();
+    let month_name = parts.next()?.to_ascii_lowercase();
+    let month = MONTHS.iter().position(|m| month_name.starts_with(m))? as i64 + 1;
+    let day = parts.next()?.parse::<i64>().ok()?;
+    let mut clock = parts.next()?;
+    let mut year = None;
+    if !clock.contains(':') {
+        year = Some(clock.parse::<i64>().ok()?);
+        clock = parts.next()?;
+    }
+    let clock = parse_clock(clock)?;
+    let offset = config.default_utc_offset_minutes;
+
+    if let Some(year) = year {
+        return to_nanos((year, month, day), clock, offset);
+    }
+
+    // Syslog omits the year: take the reference year unless that lands more
+    // than a day in the future, which means the entry is from last December
+    let reference = config.reference_time?;
+    let reference_year = civil_from_days((reference as i64 / NANOS_PER_SECOND).div_euclid(86_400)).0;
+    let candidate = to_nanos((reference_year, month, day), clock, offset)?;
+    if candidate > reference + 86_400 * NANOS_PER_SECOND as u64 {
+        to_nanos((reference_year - 1, month, day), clock, offset)
+    } else {
+        Some(candidate)
+    }
+}
+
+// Splits "10:00:00.5+02:00" into the clock and its offset in minutes, if any
+fn split_zone(time: &str) -> Option<(&str, Option<i32>)> {
+    if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        return Some((clock, Some(0)));
+    }
+    let Some(position) = time.rfind(['+', '-']) else {
+        return Some((time, None));
+    };
+
+    let (clock, zone) = time.split_at(position);
+    let sign = if zone.starts_with('-') { -1 } else { 1 };
+    let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+    let (hours, minutes) = match digits.len() {
+        2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (digits[..2].parse::<i32>().ok()?, digits[2..].parse::<i32>().ok()?),
+        _ => return None,
+    };
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some((clock, Some(sign * (hours * 60 + minutes))))
+}
+
+fn parse_date(date: &str) -> Option<(i64, i64, i64)> {
+    let mut parts = date.splitn(3, '-');
+    let year = parts.next()?.parse::<i64>().ok()?;
+    let month = parts.next()?.parse::<i64>().ok()?;
+    let day = parts.next()?.parse::<i64>().ok()?;
+    Some((year, month, day))
+}
+
+// "HH:MM[:SS[.fraction]]" into (hour, minute, second, nanos)
+fn parse_clock(clock: &str) -> Option<(i64, i64, i64, i64)> {
+    let (clock, fraction) = clock.split_once(['.', ',']).unwrap_or((clock, ""));
+    let mut parts = clock.splitn(3, ':');
+    let hour = parts.next()?.parse::<i64>().ok()?;
+    let minute = parts.next()?.parse::<i64>().ok()?;
+    let second = parts.next().map_or(Some(0), |s| s.parse::<i64>().ok())?;
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanos = if fraction.is_empty() {
+        0
+    } else {
+        let digits: String = fraction.chars().chain(std::iter::repeat('0')).take(9).collect();
+        digits.parse::<i64>().ok()?
+    };
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    Some((hour, minute, second, nanos))
+}
+
+fn to_nanos(
+    (year, month, day): (i64, i64, i64),
+    (hour, minute, second, nanos): (i64, i64, i64, i64),
+    offset_minutes: i32,
+) -> Option<u64> {
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return None;
+    }
+    let seconds = days_from_civil(year, month, day) * 86_400 + hour * 3_600 + minute * 60 + second
+        - offset_minutes as i64 * 60;
+    let total = seconds.checked_mul(NANOS_PER_SECOND)?.checked_add(nanos)?;
+    u64::try_from(total).ok()
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Inverse of [`days_from_civil`], returning (year, month, day).
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
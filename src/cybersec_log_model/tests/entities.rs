@@ -0,0 +1,69 @@
+// tests/entities.rs
+use cybersec_log_model::entities::{extract_entities, extract_urls};
+use cybersec_log_model::LogRecord;
+
+#[test]
+fn test_network_entities() {
+    let entities = extract_entities(
+        "Blocked 203.0.113.7:4444 -> [2001:db8::1]:443 and fe80::1%eth0; allowlist 10.0.0.0/8, 2001:db8::/32. \
+         Resolved evil-cdn.example.com. at 12:30:45",
+    );
+    assert_eq!(entities.ipv4, vec!["203.0.113.7"]);
+    assert_eq!(entities.ipv6, vec!["2001:db8::1"]);
+    assert_eq!(entities.cidrs, vec!["10.0.0.0/8", "2001:db8::/32"]);
+    assert_eq!(entities.domains, vec!["evil-cdn.example.com"]);
+}
+
+#[test]
+fn test_urls_emails_and_domains() {
+    let entities = extract_entities(
+        "User Bob@Example.org clicked https://login.paypa1.top/verify?id=1, then http://198.51.100.4/x.exe; \
+         opened report.pdf and script.js",
+    );
+    assert_eq!(entities.urls, vec!["https://login.paypa1.top/verify?id=1", "http://198.51.100.4/x.exe"]);
+    assert_eq!(entities.emails, vec!["bob@example.org"]);
+    assert_eq!(entities.domains, vec!["login.paypa1.top", "example.org"]);
+    assert_eq!(entities.ipv4, vec!["198.51.100.4"]);
+    assert_eq!(extract_urls("see ftp://files.example.net/a."), vec!["ftp://files.example.net/a"]);
+}
+
+#[test]
+fn test_hashes_cves_and_accounts() {
+    let entities = extract_entities(
+        "Quarantined sha256=275A021BBFB6489E54D471899F7DB9D1663FC695EC2FE2A2C4538AABF651FD0F (md5 44d88612fea8a8f36de82e1278abb02f) \
+         exploiting cve-2021-44228 by CORP\\svc_backup as arn:aws:iam::123456789012:user/alice \
+         paying c4bbaff590a3ed9e32b2aaf4c61c410eb925426119e1a9dc53d4286ade99a809",
+    );
+    assert_eq!(
+        entities.hashes,
+        vec![
+            "275a021bbfb6489e54d471899f7db9d1663fc695ec2fe2a2c4538aabf651fd0f",
+            "44d88612fea8a8f36de82e1278abb02f"
+        ]
+    );
+    assert_eq!(entities.cve_ids, vec!["CVE-2021-44228"]);
+    assert_eq!(
+        entities.accounts,
+        vec![
+            "CORP\\svc_backup",
+            "arn:aws:iam::123456789012:user/alice",
+            "c4bbaff590a3ed9e32b2aaf4c61c410eb925426119e1a9dc53d4286ade99a809"
+        ]
+    );
+}
+
+#[test]
+fn test_principals() {
+    let entities = extract_entities("Call from 2vxsx-fae to canister rrkah-fqaaa-aaaaa-aaaaq-cai failed on 2024-01-01");
+    assert_eq!(entities.principals, vec!["2vxsx-fae", "rrkah-fqaaa-aaaaa-aaaaq-cai"]);
+    assert!(entities.domains.is_empty());
+}
+
+#[test]
+fn test_record_extraction() {
+    let mut record = LogRecord::new("Connection from 192.0.2.10 refused");
+    assert!(record.entities.is_none());
+    assert_eq!(record.extract_entities().ipv4, vec!["192.0.2.10"]);
+    assert!(record.entities.as_ref().is_some_and(|entities| entities.domains.is_empty()));
+    assert!(extract_entities("nothing to see here").is_empty());
+}
@@ -6,5 +6,8 @@ diesel::table! {
         message -> Text,
         threat_type -> Text,
         timestamp -> Nullable<Timestamp>,
+        severity -> Nullable<Text>,
+        source -> Nullable<Text>,
+        sequence -> Nullable<Int8>,
     }
 }